@@ -0,0 +1,185 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+#include "camera.glsl"
+
+// Must match LUT sizes in world/atmosphere.rs
+const float TRANSMITTANCE_LUT_WIDTH = 256.0;
+const float TRANSMITTANCE_LUT_HEIGHT = 64.0;
+const float SCATTERING_R_SIZE = 16.0;
+const float SCATTERING_MU_SIZE = 64.0;
+const float SCATTERING_MU_S_SIZE = 32.0;
+const float SCATTERING_NU_SIZE = 8.0;
+
+const float PI = 3.14159265359;
+
+layout(binding = 0) uniform sampler2D colorTexture;
+layout(binding = 1) uniform sampler2D depthTexture;
+layout(binding = 2) uniform sampler2D transmittanceLut;
+layout(binding = 3) uniform sampler2D scatteringLut;
+
+layout(binding = 4) uniform AtmosphereUBO {
+    vec4 planetCenter; // xyz: planet center relative to camera, w: 1 if there is an atmosphere to render
+    vec4 sunDirection; // xyz: direction from planet center to the sun, w: sun intensity
+    vec4 scattering; // rgb: Rayleigh scattering coefficients, a: Mie scattering coefficient
+    vec4 radii; // x: bottom radius, y: top radius, z: Mie phase asymmetry
+} atmosphereUbo;
+
+layout(location = 0) out vec4 outColor;
+
+float bottomRadius() { return atmosphereUbo.radii.x; }
+float topRadius() { return atmosphereUbo.radii.y; }
+
+// LUT texels store values at both ends of the parameter range
+float unitToTexCoord(float u, float size) {
+    return (0.5 + clamp(u, 0.0, 1.0) * (size - 1.0)) / size;
+}
+
+float horizonLength() {
+    return sqrt(topRadius() * topRadius() - bottomRadius() * bottomRadius());
+}
+
+float rho(float r) {
+    return sqrt(max(r * r - bottomRadius() * bottomRadius(), 0.0));
+}
+
+bool rayIntersectsGround(float r, float mu) {
+    return mu < 0.0 && r * r * (mu * mu - 1.0) + bottomRadius() * bottomRadius() >= 0.0;
+}
+
+vec3 sampleTransmittance(float r, float mu) {
+    vec2 uv = vec2(
+        unitToTexCoord((mu + 1.0) * 0.5, TRANSMITTANCE_LUT_WIDTH),
+        unitToTexCoord(rho(r) / horizonLength(), TRANSMITTANCE_LUT_HEIGHT));
+    return texture(transmittanceLut, uv).rgb;
+}
+
+// Transmittance between the point at distance r from planet center and the point at distance d along the ray
+vec3 transmittanceToDistance(float r, float mu, float d, bool rayHitsGround) {
+    float rD = clamp(sqrt(d * d + 2.0 * r * mu * d + r * r), bottomRadius(), topRadius());
+    float muD = clamp((r * mu + d) / rD, -1.0, 1.0);
+    if (rayHitsGround) {
+        return min(sampleTransmittance(rD, -muD) / max(sampleTransmittance(r, -mu), vec3(1e-6)), vec3(1.0));
+    }
+    return min(sampleTransmittance(r, mu) / max(sampleTransmittance(rD, muD), vec3(1e-6)), vec3(1.0));
+}
+
+// Rays hitting the ground are stored in the lower half of mu range, rays leaving the atmosphere in the upper one
+float scatteringMuCoord(float r, float mu, bool rayHitsGround) {
+    float H = horizonLength();
+    float rhoR = rho(r);
+    float rmu = r * mu;
+    float discriminant = rmu * rmu - r * r + bottomRadius() * bottomRadius();
+    if (rayHitsGround) {
+        float d = -rmu - sqrt(max(discriminant, 0.0));
+        float dMin = r - bottomRadius();
+        float dMax = rhoR;
+        return 0.5 - 0.5 * (dMax == dMin ? 0.0 : (d - dMin) / (dMax - dMin));
+    }
+    float d = -rmu + sqrt(max(discriminant + H * H, 0.0));
+    float dMin = topRadius() - r;
+    float dMax = rhoR + H;
+    return 0.5 + 0.5 * (d - dMin) / (dMax - dMin);
+}
+
+// 4D table is packed as nu x mu_s tiles along width and r x mu tiles along height
+vec4 sampleScattering(float r, float mu, float muS, float nu, bool rayHitsGround) {
+    float uR = clamp(rho(r) / horizonLength(), 0.0, 1.0) * (SCATTERING_R_SIZE - 1.0);
+    float uNu = clamp((nu + 1.0) * 0.5, 0.0, 1.0) * (SCATTERING_NU_SIZE - 1.0);
+    float r0 = min(floor(uR), SCATTERING_R_SIZE - 2.0);
+    float nu0 = min(floor(uNu), SCATTERING_NU_SIZE - 2.0);
+
+    // Texel position inside a tile. Stays between texel centers, so tiles do not bleed into each other.
+    float x = 0.5 + clamp((muS + 1.0) * 0.5, 0.0, 1.0) * (SCATTERING_MU_S_SIZE - 1.0);
+    float y = 0.5 + clamp(scatteringMuCoord(r, mu, rayHitsGround), 0.0, 1.0) * (SCATTERING_MU_SIZE - 1.0);
+    vec2 size = vec2(SCATTERING_NU_SIZE * SCATTERING_MU_S_SIZE, SCATTERING_R_SIZE * SCATTERING_MU_SIZE);
+
+    vec4 s00 = texture(scatteringLut, vec2(nu0 * SCATTERING_MU_S_SIZE + x, r0 * SCATTERING_MU_SIZE + y) / size);
+    vec4 s10 = texture(scatteringLut, vec2((nu0 + 1.0) * SCATTERING_MU_S_SIZE + x, r0 * SCATTERING_MU_SIZE + y) / size);
+    vec4 s01 = texture(scatteringLut, vec2(nu0 * SCATTERING_MU_S_SIZE + x, (r0 + 1.0) * SCATTERING_MU_SIZE + y) / size);
+    vec4 s11 = texture(scatteringLut, vec2((nu0 + 1.0) * SCATTERING_MU_S_SIZE + x, (r0 + 1.0) * SCATTERING_MU_SIZE + y) / size);
+
+    return mix(mix(s00, s10, uNu - nu0), mix(s01, s11, uNu - nu0), uR - r0);
+}
+
+float rayleighPhase(float nu) {
+    return 3.0 / (16.0 * PI) * (1.0 + nu * nu);
+}
+
+float miePhase(float g, float nu) {
+    float k = 3.0 / (8.0 * PI) * (1.0 - g * g) / (2.0 + g * g);
+    return k * (1.0 + nu * nu) / pow(1.0 + g * g - 2.0 * g * nu, 1.5);
+}
+
+// Light scattered towards the camera along the view ray up to distance, negative distance means the ray goes to infinity
+vec3 computeScattering(vec3 camera, vec3 viewDir, float distance, out vec3 transmittance) {
+    transmittance = vec3(1.0);
+    vec3 sunDir = atmosphereUbo.sunDirection.xyz;
+    float r = length(camera);
+    float rmu = dot(camera, viewDir);
+
+    // Start the ray at the atmosphere boundary if camera is in space
+    if (r > topRadius()) {
+        float discriminant = rmu * rmu - r * r + topRadius() * topRadius();
+        if (discriminant < 0.0 || rmu > 0.0) {
+            return vec3(0.0);
+        }
+        float entry = -rmu - sqrt(discriminant);
+        if (distance >= 0.0) {
+            if (distance <= entry) {
+                return vec3(0.0);
+            }
+            distance -= entry;
+        }
+        camera += viewDir * entry;
+        r = topRadius();
+        rmu += entry;
+    }
+    r = max(r, bottomRadius());
+
+    float mu = clamp(rmu / r, -1.0, 1.0);
+    float muS = clamp(dot(camera, sunDir) / r, -1.0, 1.0);
+    float nu = clamp(dot(viewDir, sunDir), -1.0, 1.0);
+    bool rayHitsGround = rayIntersectsGround(r, mu);
+
+    vec4 scattering = sampleScattering(r, mu, muS, nu, rayHitsGround);
+    if (distance < 0.0) {
+        transmittance = rayHitsGround ? vec3(0.0) : sampleTransmittance(r, mu);
+    } else {
+        // Aerial perspective: scattering to infinity minus the attenuated scattering behind the geometry
+        float rD = clamp(sqrt(distance * distance + 2.0 * r * mu * distance + r * r), bottomRadius(), topRadius());
+        float muD = clamp((r * mu + distance) / rD, -1.0, 1.0);
+        float muSD = clamp((r * muS + distance * nu) / rD, -1.0, 1.0);
+        transmittance = transmittanceToDistance(r, mu, distance, rayHitsGround);
+        scattering = max(scattering - transmittance.rgbr * sampleScattering(rD, muD, muSD, nu, rayHitsGround), vec4(0.0));
+    }
+
+    // Mie scattering is restored from its red channel, see Bruneton and Neyret 2008
+    vec3 rayleigh = scattering.rgb;
+    vec3 mie = rayleigh * scattering.a / max(rayleigh.r, 1e-6) * (atmosphereUbo.scattering.r / atmosphereUbo.scattering.rgb);
+
+    return (rayleigh * rayleighPhase(nu) + mie * miePhase(atmosphereUbo.radii.z, nu)) * atmosphereUbo.sunDirection.w;
+}
+
+void main()
+{
+    vec2 uv = gl_FragCoord.xy / cameraUbo.viewportExtent.zw;
+    vec4 color = texture(colorTexture, uv);
+    if (atmosphereUbo.planetCenter.w == 0.0) {
+        outColor = color;
+        return;
+    }
+
+    float depth = texture(depthTexture, uv).r;
+    bool isSky = depth == cameraUbo.depthParams.y;
+    // Projection in the UBO has Y flipped, inverse projection does not
+    vec2 ndc = vec2(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+    vec4 target = cameraUbo.projInverse * vec4(ndc, isSky ? cameraUbo.depthParams.x : depth, 1.0);
+    vec3 viewPosition = target.xyz / target.w;
+    vec3 viewDir = normalize((cameraUbo.viewInverse * vec4(viewPosition, 0.0)).xyz);
+    float distance = isSky ? -1.0 : length(viewPosition);
+
+    vec3 transmittance;
+    vec3 inscatter = computeScattering(-atmosphereUbo.planetCenter.xyz, viewDir, distance, transmittance);
+    outColor = vec4(color.rgb * transmittance + inscatter, color.a);
+}
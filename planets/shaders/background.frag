@@ -10,5 +10,6 @@ layout(location = 0) out vec4 outColor;
 
 void main()
 {
-	outColor = vec4(gl_FragCoord.x / cameraUbo.viewportExtent.z, gl_FragCoord.y / cameraUbo.viewportExtent.w, sin(timerUbo.totalTimeElapsed), 1.0);
+	// Empty space, stars are drawn on top by the stars pipeline
+	outColor = vec4(0.0, 0.0, 0.0, 1.0);
 }
\ No newline at end of file
@@ -1,6 +1,8 @@
 #version 450
 #extension GL_ARB_separate_shader_objects : enable
 
+#include "camera.glsl"
+
 layout(location = 0) in vec3 inPosition;
 layout(location = 1) in vec2 inNormal;
 layout(location = 2) in vec2 inTexCoord;
@@ -12,6 +14,6 @@ out gl_PerVertex {
 };
 
 void main() {
-    gl_Position = vec4(inPosition, 1.0);
+    gl_Position = vec4(inPosition.xy, cameraUbo.depthParams.y, 1.0);
     fragTexCoord = inTexCoord;
 }
@@ -7,6 +7,7 @@ layout(binding = 16) uniform CameraUBO {
     mat4 proj;
     mat4 projInverse;
     vec4 viewportExtent;
+    vec4 depthParams; // x: near plane depth, y: far plane depth, z: near plane distance
 } cameraUbo;
 
 #endif
\ No newline at end of file
@@ -11,42 +11,25 @@ layout(location = 0) out vec4 outColor;
 
 //layout(binding = 2) uniform sampler2D texSampler;
 
+const float PI = 3.14159265359;
 const vec3 ambientColor = vec3(0.1, 0.1, 0.1);
-const vec4 specularColor = vec4(1.0, 1.0, 1.0, 1.0);
-const float specularStrength = 2.0;
+const vec3 albedo = vec3(0.5);
 
 void main() {
     vec3 lightContribution = vec3(0.0);
 
     vec3 normal = normalize(fragNormal);
-    vec3 viewDir = normalize(vec3(0.0, 0.0, -10.0) - fragPosition);
-
-//    for (int i=0; i<MAX_LIGHTS; i++)
-//    {
-//        Light light = lightsUbo.lights[i];
-//        if (isLightActive(light))
-//        {
-//            vec3 lightDir = normalize(light.position.xyz - fragPosition);
-//
-//            float diff = max(dot(normal, lightDir), 0.0);
-//            vec3 diffuse = diff * light.color.rgb;
-//
-//            vec3 reflectDir = reflect(-lightDir, normal);
-//            float spec = pow(max(dot(viewDir, reflectDir), 0.0), 64);
-//            vec3 specular = specularStrength * spec * light.color.rgb;
-//
-//            lightContribution += diffuse + specular;
-//        }
-//    }
-    //outColor = vec4(fragTexCoord, 0.0, 1.0);
-    vec3 lightDir = normalize(vec3(0.2, 0.2, 0.8));// - fragPosition);
-    float diff = max(dot(normal, lightDir), 0.0);
-    vec3 diffuse = diff * vec3(0.5);
-
-//    vec3 reflectDir = reflect(-lightDir, normal);
-//    float spec = pow(max(dot(viewDir, reflectDir), 0.0), 64);
-//    vec3 specular = specularStrength * spec * vec3(1.0, 1.0, 1.0);
-
-    lightContribution = diffuse;//+ specular;
+
+    for (int i = 0; i < MAX_LIGHTS; i++)
+    {
+        Light light = lightsUbo.lights[i];
+        if (isLightActive(light))
+        {
+            vec3 lightDir = normalize(light.position.xyz - fragPosition);
+            float diff = max(dot(normal, lightDir), 0.0);
+            lightContribution += albedo / PI * diff * getLightIlluminance(light, fragPosition);
+        }
+    }
+
     outColor = vec4((ambientColor + lightContribution)/* * texture(texSampler, fragTexCoord).rgb*/, 1.0);
-}
\ No newline at end of file
+}
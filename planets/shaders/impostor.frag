@@ -0,0 +1,55 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+#include "camera.glsl"
+#include "lights.glsl"
+
+layout(location = 0) in vec3 fragPosition;
+layout(location = 1) flat in vec4 fragSphere;
+
+layout(location = 0) out vec4 outColor;
+
+layout(binding = 0) uniform ImpostorUBO {
+    vec4 params; // x: angular size impostors start to fade out at, y: angular size they are fully faded out at
+} impostorUbo;
+
+const float PI = 3.14159265359;
+const vec3 ambientColor = vec3(0.1, 0.1, 0.1);
+// Must match gbuffer.frag, so the impostor blends into the geometry
+const vec3 albedo = vec3(0.5);
+
+// Must match ImpostorSettings::get_opacity()
+float getOpacity(float angularSize) {
+    float fade = (angularSize - impostorUbo.params.x) / (impostorUbo.params.y - impostorUbo.params.x);
+    return 1.0 - clamp(fade, 0.0, 1.0);
+}
+
+void main() {
+    vec3 center = fragSphere.xyz;
+    float radius = fragSphere.w;
+
+    // Camera sits at the origin, intersect the view ray with the body sphere
+    vec3 direction = normalize(fragPosition);
+    float b = dot(direction, center);
+    float h = b * b - (dot(center, center) - radius * radius);
+    if (h < 0.0) {
+        discard;
+    }
+    vec3 hit = direction * (b - sqrt(h));
+    vec3 normal = normalize(hit - center);
+
+    // Lit from the actual sun position, so the phase matches the geometry
+    vec3 color = ambientColor;
+    int sunIdx = findSun();
+    if (sunIdx >= 0) {
+        Light sun = lightsUbo.lights[sunIdx];
+        vec3 lightDir = normalize(sun.position.xyz - hit);
+        color += albedo / PI * max(dot(normal, lightDir), 0.0) * getLightIlluminance(sun, hit);
+    }
+
+    vec4 clip = cameraUbo.proj * cameraUbo.view * vec4(hit, 1.0);
+    gl_FragDepth = clip.z / clip.w;
+
+    float angularSize = 2.0 * asin(min(radius / length(center), 1.0));
+    outColor = vec4(color, getOpacity(angularSize));
+}
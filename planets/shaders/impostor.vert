@@ -0,0 +1,39 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+#include "camera.glsl"
+
+layout(binding = 13) readonly buffer ModelData {
+    mat4 model[1024];
+} modelData;
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inNormal;
+layout(location = 2) in vec2 inTexCoord;
+
+layout(location = 0) out vec3 fragPosition;
+layout(location = 1) flat out vec4 fragSphere;
+
+out gl_PerVertex {
+    vec4 gl_Position;
+};
+
+// Silhouettes of spheres off the view axis stretch radially, the quad is enlarged to cover them
+const float SILHOUETTE_MARGIN = 1.2;
+
+void main() {
+    mat4 modelTransform = modelData.model[gl_InstanceIndex];
+    vec3 center = modelTransform[3].xyz;
+    float radius = length(modelTransform[0].xyz);
+
+    // Half size of the quad through the sphere center covering the cone of its silhouette
+    float distance = max(length(center), radius * 1.01);
+    float halfSize = radius * distance / sqrt(distance * distance - radius * radius) * SILHOUETTE_MARGIN;
+
+    vec3 viewCenter = (cameraUbo.view * vec4(center, 1.0)).xyz;
+    vec4 viewCorner = vec4(viewCenter + vec3(inPosition.xy * halfSize, 0.0), 1.0);
+
+    fragPosition = (cameraUbo.viewInverse * viewCorner).xyz;
+    fragSphere = vec4(center, radius);
+    gl_Position = cameraUbo.proj * viewCorner;
+}
@@ -3,10 +3,18 @@
 
 const int MAX_LIGHTS = 64;
 
+// Must match LightType::as_shader_value()
+const float LIGHT_TYPE_POINT = 0.0;
+const float LIGHT_TYPE_STAR = 1.0;
+
+// Exposure of a sunlit scene at EV100 = 15, converts illuminance in lux to display values
+const float EXPOSURE = 1.0 / (1.2 * 32768.0);
+
 struct Light {
-    vec4 position;
-    vec4 color;
+    vec4 position; // xyz: position relative to camera, w: light type
+    vec4 color; // rgb: color, a: luminous intensity in candela
     vec4 isActiveRadiusPadding;
+    vec4 starParams; // x: angular radius of star disc in radians, y: star illuminance in lux
 };
 
 bool isLightActive(Light light) {
@@ -17,8 +25,47 @@ float getLightRadius(Light light) {
     return light.isActiveRadiusPadding.y;
 }
 
+bool isStar(Light light) {
+    return light.position.w == LIGHT_TYPE_STAR;
+}
+
+float getStarAngularRadius(Light light) {
+    return light.starParams.x;
+}
+
 layout(binding = 14, std140) readonly buffer LightsUBO {
     Light lights[MAX_LIGHTS];
 } lightsUbo;
 
-#endif
\ No newline at end of file
+// Index of the first active star or of the first active light if there are no stars, -1 if there are no lights
+int findSun() {
+    int firstActive = -1;
+    for (int i = 0; i < MAX_LIGHTS; i++) {
+        Light light = lightsUbo.lights[i];
+        if (isLightActive(light)) {
+            if (isStar(light)) {
+                return i;
+            }
+            if (firstActive < 0) {
+                firstActive = i;
+            }
+        }
+    }
+    return firstActive;
+}
+
+// Exposed illuminance of a surface at the given position facing the light
+vec3 getLightIlluminance(Light light, vec3 position) {
+    if (isStar(light)) {
+        return light.color.rgb * light.starParams.y * EXPOSURE;
+    }
+
+    vec3 toLight = light.position.xyz - position;
+    float distanceSquared = max(dot(toLight, toLight), 1e-4);
+    // Inverse square falloff smoothly reaching zero at the light range
+    float range = getLightRadius(light);
+    float window = clamp(1.0 - pow(distanceSquared / (range * range), 2.0), 0.0, 1.0);
+    return light.color.rgb * light.color.a / distanceSquared * window * window * EXPOSURE;
+}
+
+#endif
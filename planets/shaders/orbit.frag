@@ -0,0 +1,30 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(binding = 0) uniform OrbitUBO {
+    mat4 model;
+    vec4 color;
+    vec4 params;
+} orbitUbo;
+
+// Opacity of the orbit outside of the predicted trajectory
+const float ORBIT_OPACITY = 0.35;
+// Opacity of the predicted trajectory at its end
+const float TRAJECTORY_END_OPACITY = 0.6;
+
+layout(location = 0) in vec3 fragPosition;
+layout(location = 1) in float fragAnomaly;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    // Part of the orbit the body reaches next
+    float ahead = fract(fragAnomaly - orbitUbo.params.x);
+    float trajectory = orbitUbo.color.a;
+    float opacity = ahead < trajectory ? mix(1.0, TRAJECTORY_END_OPACITY, ahead / trajectory) : ORBIT_OPACITY;
+
+    // Camera is at the origin
+    float fade = 1.0 - smoothstep(orbitUbo.params.y, orbitUbo.params.z, length(fragPosition));
+
+    outColor = vec4(orbitUbo.color.rgb, opacity * fade);
+}
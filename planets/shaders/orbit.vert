@@ -0,0 +1,29 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+#include "camera.glsl"
+
+layout(binding = 0) uniform OrbitUBO {
+    mat4 model; // orbit frame relative to camera
+    vec4 color; // rgb: line color, a: fraction of the orbit highlighted as predicted trajectory
+    vec4 params; // x: body mean anomaly as orbit fraction, y: fade start distance, z: fade end distance
+} orbitUbo;
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inNormal;
+layout(location = 2) in vec2 inTexCoord;
+
+layout(location = 0) out vec3 fragPosition;
+layout(location = 1) out float fragAnomaly;
+
+out gl_PerVertex {
+    vec4 gl_Position;
+};
+
+void main() {
+    vec4 position = orbitUbo.model * vec4(inPosition, 1.0);
+    fragPosition = position.xyz;
+    fragAnomaly = inTexCoord.x;
+
+    gl_Position = cameraUbo.proj * cameraUbo.view * position;
+}
@@ -0,0 +1,65 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// Must match OutputEncoding::get_shader_id() in vulkan/swapchain.rs
+const uint ENCODING_SRGB_FORMAT = 0;
+const uint ENCODING_SRGB = 1;
+const uint ENCODING_SCRGB = 2;
+const uint ENCODING_HDR10 = 3;
+
+// scRGB value 1.0 and PQ value 1.0 in nits
+const float SCRGB_WHITE = 80.0;
+const float PQ_MAX = 10000.0;
+
+// BT.709 to BT.2020 primaries, columns are BT.709 red, green and blue
+const mat3 BT709_TO_BT2020 = mat3(
+    0.6274, 0.0691, 0.0164,
+    0.3293, 0.9195, 0.0880,
+    0.0433, 0.0114, 0.8956
+);
+
+// Linear final color of the previous passes. Float target, values above 1.0 are brighter than SDR white.
+layout(binding = 0) uniform sampler2D colorTexture;
+
+layout(binding = 1) uniform OutputUBO {
+    vec4 params; // x: encoding, y: SDR white in nits
+} outputUbo;
+
+layout(location = 0) out vec4 outColor;
+
+vec3 srgbEncode(vec3 linear) {
+    vec3 low = linear * 12.92;
+    vec3 high = 1.055 * pow(linear, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, lessThanEqual(linear, vec3(0.0031308)));
+}
+
+// SMPTE ST 2084 inverse EOTF of luminance normalized to 10000 nits
+vec3 pqEncode(vec3 normalized) {
+    const float m1 = 0.1593017578125;
+    const float m2 = 78.84375;
+    const float c1 = 0.8359375;
+    const float c2 = 18.8515625;
+    const float c3 = 18.6875;
+    vec3 y = pow(clamp(normalized, 0.0, 1.0), vec3(m1));
+    return pow((c1 + c2 * y) / (1.0 + c3 * y), vec3(m2));
+}
+
+void main() {
+    // Swapchain extent may differ from the rendered size by a few pixels during resize
+    ivec2 coord = min(ivec2(gl_FragCoord.xy), textureSize(colorTexture, 0) - 1);
+    vec4 color = texelFetch(colorTexture, coord, 0);
+    uint encoding = uint(outputUbo.params.x);
+    float paperWhite = outputUbo.params.y;
+
+    // Exactly one encoding is applied. sRGB formats encode on store, so linear color is written for them.
+    vec3 encoded = color.rgb;
+    if (encoding == ENCODING_SRGB) {
+        encoded = srgbEncode(color.rgb);
+    } else if (encoding == ENCODING_SCRGB) {
+        encoded = color.rgb * (paperWhite / SCRGB_WHITE);
+    } else if (encoding == ENCODING_HDR10) {
+        encoded = pqEncode(BT709_TO_BT2020 * color.rgb * (paperWhite / PQ_MAX));
+    }
+
+    outColor = vec4(encoded, color.a);
+}
@@ -0,0 +1,72 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+#include "lights.glsl"
+
+layout(location = 0) in vec3 fragNormal;
+layout(location = 1) in vec2 fragTexCoord;
+layout(location = 2) in vec3 fragPosition;
+layout(location = 3) flat in vec3 planetCenter;
+
+layout(location = 0) out vec4 outColor;
+
+// rgb: particle color, a: density
+layout(binding = 0) uniform sampler2D profileTexture;
+
+layout(binding = 1) uniform RingUBO {
+    vec4 params; // x: planet radius, y: forward scattering asymmetry
+} ringUbo;
+
+const float PI = 3.14159265359;
+const vec3 ambientColor = vec3(0.02);
+// Relative width of planet shadow penumbra
+const float penumbraWidth = 0.02;
+
+float henyeyGreenstein(float g, float cosTheta) {
+    float g2 = g * g;
+    return (1.0 - g2) / (4.0 * PI * pow(1.0 + g2 - 2.0 * g * cosTheta, 1.5));
+}
+
+// 1 in sunlight, 0 inside planet shadow
+float planetShadow(vec3 position, vec3 lightDir) {
+    float radius = ringUbo.params.x;
+    vec3 toCenter = planetCenter - position;
+    float along = dot(toCenter, lightDir);
+    if (along <= 0.0) {
+        return 1.0;
+    }
+    float distanceToAxis = length(toCenter - along * lightDir);
+    return smoothstep(radius * (1.0 - penumbraWidth), radius * (1.0 + penumbraWidth), distanceToAxis);
+}
+
+void main() {
+    vec4 profile = texture(profileTexture, vec2(fragTexCoord.x, 0.5));
+    float density = profile.a;
+    if (density <= 0.0) {
+        discard;
+    }
+
+    int sunIdx = findSun();
+
+    vec3 color = ambientColor * profile.rgb;
+    if (sunIdx >= 0) {
+        Light sun = lightsUbo.lights[sunIdx];
+        vec3 normal = normalize(fragNormal);
+        vec3 lightDir = normalize(sun.position.xyz - fragPosition);
+        // Camera is at the origin
+        vec3 viewDir = normalize(fragPosition);
+
+        // Light reflected back by particles on the lit side and diffused through the ring on the other one
+        float cosLight = dot(normal, lightDir);
+        bool litSideVisible = cosLight * dot(normal, -viewDir) > 0.0;
+        float diffuse = abs(cosLight) * (litSideVisible ? 1.0 : density);
+
+        // Small particles scatter light forward, so sparse parts of the ring glow when seen against the sun
+        float forward = henyeyGreenstein(ringUbo.params.y, dot(lightDir, viewDir)) * (1.0 - density);
+
+        float shadow = planetShadow(fragPosition, lightDir);
+        color += profile.rgb * getLightIlluminance(sun, fragPosition) / PI * shadow * (diffuse + forward);
+    }
+
+    outColor = vec4(color, density);
+}
@@ -0,0 +1,32 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+#include "camera.glsl"
+
+layout(binding = 13) readonly buffer ModelData {
+    mat4 model[1024];
+} modelData;
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inNormal;
+layout(location = 2) in vec2 inTexCoord;
+
+layout(location = 0) out vec3 fragNormal;
+layout(location = 1) out vec2 fragTexCoord;
+layout(location = 2) out vec3 fragPosition;
+layout(location = 3) flat out vec3 planetCenter;
+
+out gl_PerVertex {
+    vec4 gl_Position;
+};
+
+void main() {
+    mat4 modelTransform = modelData.model[gl_InstanceIndex];
+    fragNormal = mat3(modelTransform) * inNormal;
+    fragTexCoord = inTexCoord;
+    fragPosition = vec3(modelTransform * vec4(inPosition, 1.0));
+    // Rings are attached to the planet node, so their origin is the planet center
+    planetCenter = vec3(modelTransform * vec4(0.0, 0.0, 0.0, 1.0));
+
+    gl_Position = cameraUbo.proj * cameraUbo.view * vec4(fragPosition, 1.0);
+}
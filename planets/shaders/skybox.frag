@@ -0,0 +1,24 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+#include "camera.glsl"
+
+layout(binding = 0) uniform samplerCube skyboxTexture;
+
+layout(binding = 1) uniform SkyboxUBO {
+    mat4 orientation; // rotation of world directions to the skybox frame
+    vec4 params; // x: intensity
+} skyboxUbo;
+
+layout(location = 0) in vec2 fragTexCoord;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    vec2 ndc = vec2(fragTexCoord.x * 2.0 - 1.0, 1.0 - fragTexCoord.y * 2.0);
+    vec4 viewPosition = cameraUbo.projInverse * vec4(ndc, cameraUbo.depthParams.x, 1.0);
+    vec3 worldDirection = mat3(cameraUbo.viewInverse) * (viewPosition.xyz / viewPosition.w);
+    vec3 direction = mat3(skyboxUbo.orientation) * normalize(worldDirection);
+
+    outColor = vec4(texture(skyboxTexture, direction).rgb * skyboxUbo.params.x, 1.0);
+}
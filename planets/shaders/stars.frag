@@ -0,0 +1,12 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec3 fragColor;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    vec2 offset = gl_PointCoord * 2.0 - 1.0;
+    float falloff = exp(-4.0 * dot(offset, offset));
+    outColor = vec4(fragColor * falloff, 1.0);
+}
@@ -0,0 +1,33 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+#include "camera.glsl"
+
+layout(binding = 0) uniform StarsUBO {
+    mat4 orientation;
+    vec4 params; // x: brightness scale, y: maximal point size
+} starsUbo;
+
+// Star direction in catalog frame, its linear color and brightness
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inNormal;
+layout(location = 2) in vec2 inTexCoord;
+
+layout(location = 0) out vec3 fragColor;
+
+out gl_PerVertex {
+    vec4 gl_Position;
+    float gl_PointSize;
+};
+
+void main() {
+    // Stars are infinitely far away, so only camera rotation applies
+    vec3 direction = mat3(cameraUbo.view) * mat3(starsUbo.orientation) * inPosition;
+    vec4 position = cameraUbo.proj * vec4(direction, 1.0);
+    gl_Position = vec4(position.xy, cameraUbo.depthParams.y * position.w, position.w);
+
+    // Faint stars dim out, bright ones grow instead of saturating
+    float brightness = inTexCoord.x * starsUbo.params.x;
+    gl_PointSize = clamp(sqrt(brightness) * 2.0, 1.0, starsUbo.params.y);
+    fragColor = inNormal * min(brightness, 1.0);
+}
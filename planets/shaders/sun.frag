@@ -0,0 +1,49 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+#include "camera.glsl"
+#include "lights.glsl"
+
+const float PI = 3.14159265359;
+// Intensity of light scattered around the disc relative to star illuminance
+const float GLOW_STRENGTH = 0.15;
+// Angle in radians where the glow drops to half
+const float GLOW_WIDTH = 0.02;
+// Fraction of disc center radiance lost at its edge
+const float LIMB_DARKENING = 0.6;
+
+layout(location = 0) in vec2 fragTexCoord;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    vec2 ndc = vec2(fragTexCoord.x * 2.0 - 1.0, 1.0 - fragTexCoord.y * 2.0);
+    vec4 viewPosition = cameraUbo.projInverse * vec4(ndc, cameraUbo.depthParams.x, 1.0);
+    vec3 viewDir = normalize(mat3(cameraUbo.viewInverse) * (viewPosition.xyz / viewPosition.w));
+
+    vec3 color = vec3(0.0);
+    for (int i = 0; i < MAX_LIGHTS; i++) {
+        Light light = lightsUbo.lights[i];
+        if (!isLightActive(light) || !isStar(light)) {
+            continue;
+        }
+
+        // Camera is at the origin
+        float angle = acos(clamp(dot(viewDir, normalize(light.position.xyz)), -1.0, 1.0));
+        float angularRadius = max(getStarAngularRadius(light), 1e-5);
+        vec3 illuminance = getLightIlluminance(light, vec3(0.0));
+
+        // Disc radiance spreads star illuminance over its solid angle, limb darkening keeps the average
+        if (angle < angularRadius) {
+            float solidAngle = 2.0 * PI * (1.0 - cos(angularRadius));
+            float r = angle / angularRadius;
+            float limb = 1.0 - LIMB_DARKENING * (1.0 - sqrt(1.0 - r * r));
+            color += illuminance / solidAngle * limb / (1.0 - LIMB_DARKENING / 3.0);
+        }
+
+        float glowAngle = max(angle - angularRadius, 0.0) / GLOW_WIDTH;
+        color += illuminance * GLOW_STRENGTH / (1.0 + glowAngle * glowAngle);
+    }
+
+    outColor = vec4(color, 1.0);
+}
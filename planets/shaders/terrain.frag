@@ -0,0 +1,64 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+#include "lights.glsl"
+
+layout(location = 0) in vec3 fragNormal;
+layout(location = 1) in vec2 fragTexCoord;
+layout(location = 2) in vec3 fragPosition;
+
+layout(location = 0) out vec4 outColor;
+
+layout(binding = 0) uniform sampler2D albedoMap;
+
+layout(binding = 1) uniform SurfaceUBO {
+    mat4 worldToPlanet;
+} surfaceUbo;
+
+const float PI = 3.14159265359;
+const vec3 ambientColor = vec3(0.1, 0.1, 0.1);
+
+// Must match planet_uv() in surface.rs
+vec2 planetUv(vec3 direction) {
+    float u = 0.5 + atan(-direction.z, direction.x) / (2.0 * PI);
+    float v = acos(clamp(direction.y, -1.0, 1.0)) / PI;
+    return vec2(u, v);
+}
+
+// Sample the equirectangular map without the seam line: longitude jumps from 1 to 0 on the seam and
+// breaks mip selection, so the gradient of a second longitude wrapped on the opposite side is used there.
+vec3 sampleAlbedo(vec3 direction) {
+    vec2 uv = planetUv(direction);
+    float seamlessU = fract(uv.x + 0.5) - 0.5;
+    vec2 dx = vec2(dFdx(uv.x), dFdx(uv.y));
+    vec2 dy = vec2(dFdy(uv.x), dFdy(uv.y));
+    float dxSeamless = dFdx(seamlessU);
+    float dySeamless = dFdy(seamlessU);
+    if (abs(dxSeamless) + abs(dySeamless) < abs(dx.x) + abs(dy.x)) {
+        dx.x = dxSeamless;
+        dy.x = dySeamless;
+    }
+    return textureGrad(albedoMap, uv, dx, dy).rgb;
+}
+
+void main() {
+    vec3 planetDirection = normalize((surfaceUbo.worldToPlanet * vec4(fragPosition, 1.0)).xyz);
+    vec3 albedo = sampleAlbedo(planetDirection);
+
+    vec3 lightContribution = vec3(0.0);
+
+    vec3 normal = normalize(fragNormal);
+
+    for (int i = 0; i < MAX_LIGHTS; i++)
+    {
+        Light light = lightsUbo.lights[i];
+        if (isLightActive(light))
+        {
+            vec3 lightDir = normalize(light.position.xyz - fragPosition);
+            float diff = max(dot(normal, lightDir), 0.0);
+            lightContribution += albedo / PI * diff * getLightIlluminance(light, fragPosition);
+        }
+    }
+
+    outColor = vec4((ambientColor * albedo + lightContribution), 1.0);
+}
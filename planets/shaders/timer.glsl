@@ -4,6 +4,8 @@
 layout(binding = 15) uniform TimerUBO {
     float totalTimeElapsed;
     float frameTimeDelta;
+    float simulationTimeElapsed; // Wraps to 0 every simulated day
+    float simulationTimeDelta;
 } timerUbo;
 
 #endif
\ No newline at end of file
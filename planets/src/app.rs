@@ -1,5 +1,8 @@
-use crate::engine::camera::{Camera, CameraMutRef};
+use crate::engine::camera::{Camera, CameraMutRef, Projection, ProjectionKind};
+use crate::engine::camera_path::{CameraPath, CameraPathRecorder, PathController, PlaybackClock, CAMERA_PATH_PATH};
+use crate::engine::camera_controller::{CameraInput, FollowController, FreeFlyController, OrbitController};
 use crate::engine::gameloop::{GameLoop, GameLoopMutRef};
+use crate::engine::input::{Input, InputBindings, INPUT_CONFIG_PATH};
 use crate::engine::renderer::Renderer;
 use crate::engine::viewport::{Viewport, ViewportMutRef};
 use crate::engine::passes::gbuffer::GBufferPass;
@@ -8,17 +11,26 @@ use crate::vulkan::device::MAX_FRAMES_IN_FLIGHT;
 use std::cell::RefCell;
 use std::rc::Rc;
 use ash::vk;
-use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent, MouseButton};
+use winit::event::{DeviceEvent, Event, WindowEvent};
 use winit::event_loop::{ControlFlow, EventLoop};
+use crate::engine::passes::atmosphere::AtmospherePass;
 use crate::engine::passes::background::BackgroundPass;
+use crate::engine::passes::impostors::ImpostorsPass;
+use crate::engine::passes::orbits::OrbitsPass;
+use crate::engine::passes::output::OutputPass;
+use crate::engine::passes::rings::RingsPass;
 use crate::engine::renderpass::RenderPass;
 use crate::engine::passes::rtao::RaytracedAo;
 use crate::engine::scene::builder::build_scene;
+use crate::engine::scene::frame::ReferenceFrame;
 use crate::engine::scene::graph::{SceneGraph, SceneGraphMutRef};
-use crate::engine::window::Window;
-use crate::util::constants::{WINDOW_HEIGHT, WINDOW_TITLE, WINDOW_WIDTH};
+use crate::engine::window::{Window, WindowSettings, WINDOW_CONFIG_PATH};
+use crate::util::constants::WINDOW_TITLE;
 use crate::vulkan::img::image::ImageAccess;
 use crate::world::loader::ModelLoader;
+use crate::world::ship::ShipControls;
+use cgmath as cgm;
+use cgmath::{EuclideanSpace, InnerSpace};
 
 pub struct App {
     gameloop: GameLoopMutRef,
@@ -30,22 +42,50 @@ pub struct App {
     viewport: ViewportMutRef,
     scene: SceneGraphMutRef,
     render_passes: Vec<Box<dyn RenderPass>>,
-    onpause: bool,
+    // Encodes the final image for swapchain formats a blit can't write correctly
+    output_pass: Option<OutputPass>,
+    input: Input,
+    // Cursor stays captured for mouse look without holding the look button
+    mouse_look: bool,
+    // Camera poses being recorded while it is set
+    path_recorder: Option<CameraPathRecorder>,
+    // Frames and real time of the camera path playback in progress
+    path_playback: Option<(u64, f64)>,
+    // Camera input gathered since the last drawn frame
+    camera_input: CameraInput,
 }
 
+// Chase camera position in ship local axes: behind and slightly above the ship
+const CHASE_CAMERA_OFFSET: cgm::Point3<f64> = cgm::Point3 { x: 0.0, y: 1.5, z: 6.0 };
+// Seconds the chase camera takes to swing behind the turning ship
+const CHASE_CAMERA_SMOOTHING: f64 = 0.3;
+// Initial free fly camera speed in units per second
+const FREE_FLY_SPEED: f64 = 10.0;
+// Seconds between recorded camera path keyframes
+const CAMERA_PATH_INTERVAL: f64 = 0.25;
+// Camera path benchmark advances by this time every frame
+const CAMERA_PATH_BENCHMARK_STEP: f64 = 1.0 / 60.0;
+// Field of view or orthographic size change per key press
+const FIELD_OF_VIEW_STEP: f32 = 1.1;
+
+// Action triggering an app method when pressed
+type ActionHandler = (&'static str, fn(&mut App));
+
 impl App {
     pub fn new(event_loop: &EventLoop<()>) -> Self {
-        let os_window = Window::create_os_window(event_loop);
+        let window_settings = WindowSettings::load_or_default(WINDOW_CONFIG_PATH);
+        let os_window = Window::create_os_window(event_loop, &window_settings);
         let vulkan = vulkan::entry::Entry::new(&os_window);
-        let window = Window::new(&vulkan.get_instance().instance, vulkan.get_device(), vulkan.get_surface(), os_window);
+        let window = Window::new(&vulkan.get_instance().instance, vulkan.get_device(), vulkan.get_surface(), os_window, window_settings);
 
         let gameloop = Rc::new(RefCell::new(GameLoop::new(&mut vulkan.get_resource_manager().borrow_mut())));
-        gameloop.borrow_mut().set_max_fps(60);
+        gameloop.borrow_mut().set_max_fps(window.get_settings().max_fps);
         // TODO: remove camera instantiation from here
         let camera = Rc::new(RefCell::new(Camera::new(
             &mut vulkan.get_resource_manager().borrow_mut(),
         )));
-        let viewport = Rc::new(RefCell::new(Viewport::new(WINDOW_WIDTH, WINDOW_HEIGHT)));
+        let window_size = window.get_size();
+        let viewport = Rc::new(RefCell::new(Viewport::new(window_size.x, window_size.y)));
 
         let renderer = Renderer::new(
             vulkan.get_device(),
@@ -59,6 +99,18 @@ impl App {
 
         let scene = SceneGraph::new_mut_ref(vulkan.get_device(), vulkan.get_resource_manager());
         build_scene(&mut scene.borrow_mut(), &mut model_loader.borrow_mut());
+        let ship_node = scene.borrow().get_ship().map(|ship| Rc::clone(ship.borrow().get_node()));
+        scene.borrow_mut().add_pick_listener(Box::new(move |hit| {
+            let name = match &ship_node {
+                Some(node) if hit.is_part_of(node) => "ship",
+                _ => "object",
+            };
+            let p = hit.position;
+            log::info!("Picked {} at ({:.2}, {:.2}, {:.2}), {:.2} away", name, p.x, p.y, p.z, hit.distance);
+        }));
+
+        let mut input = Input::new(InputBindings::load_or_default(INPUT_CONFIG_PATH));
+        input.set_scale_factor(window.get_scale_factor());
 
         App {
             gameloop,
@@ -70,7 +122,12 @@ impl App {
             viewport,
             scene,
             render_passes: vec![],
-            onpause: false,
+            output_pass: None,
+            input,
+            mouse_look: false,
+            path_recorder: None,
+            path_playback: None,
+            camera_input: CameraInput::default(),
         }
     }
 
@@ -88,13 +145,29 @@ impl App {
                         self.process_window_destruction();
                         *control_flow = ControlFlow::Exit;
                     }
-                    WindowEvent::Resized(_) => self.process_resize(),
-                    WindowEvent::KeyboardInput { input, .. } => self.process_keyboard_input(&input),
+                    WindowEvent::Resized(size) => {
+                        self.window.set_size(&size);
+                        self.process_resize();
+                    },
+                    WindowEvent::ScaleFactorChanged { scale_factor, new_inner_size } => {
+                        self.window.set_scale_factor(scale_factor, new_inner_size);
+                        self.input.set_scale_factor(scale_factor);
+                        self.process_resize();
+                    },
+                    WindowEvent::Focused(false) => {
+                        self.input.release_all();
+                        self.mouse_look = false;
+                    },
+                    WindowEvent::KeyboardInput { input, .. } => self.input.process_keyboard_input(&input),
                     WindowEvent::MouseInput {button, state, ..} => {
-                        self.process_mouse_input(&state, &button)
+                        self.input.process_mouse_input(state, button)
                     },
+                    WindowEvent::MouseWheel { delta, .. } => self.input.process_mouse_wheel(&delta),
+                    WindowEvent::CursorMoved { position, .. } => self.input.process_cursor_moved(&position),
                     _ => {},
                 },
+                // Raw mouse movement keeps coming when the cursor hits window border
+                Event::DeviceEvent { event: DeviceEvent::MouseMotion { delta }, .. } => self.input.process_mouse_motion(delta),
                 // Window input events were processed - time to start game loop cycle
                 Event::MainEventsCleared => self.update_world(),
                 Event::Suspended => {
@@ -123,9 +196,19 @@ impl App {
                 // Drawing ended - finish frame
                 Event::RedrawEventsCleared => {
                     self.gameloop.borrow_mut().finish_frame();
-                    self.window.set_title(
-                        format!("{} | {:.2} FPS", WINDOW_TITLE, self.gameloop.borrow().get_fps()).as_str(),
-                    );
+                    let title = {
+                        let gameloop = self.gameloop.borrow();
+                        let simulation = gameloop.get_simulation();
+                        format!(
+                            "{} | {:.2} FPS | {} x{}{}",
+                            WINDOW_TITLE,
+                            gameloop.get_fps(),
+                            simulation.get_date_time().format("%Y-%m-%d %H:%M:%S"),
+                            simulation.get_time_warp(),
+                            if simulation.is_paused() { " (paused)" } else { "" },
+                        )
+                    };
+                    self.window.set_title(title.as_str());
                     *control_flow = ControlFlow::WaitUntil(self.gameloop.borrow().get_wait_instant());
                 }
                 
@@ -134,34 +217,50 @@ impl App {
         });
     }
 
-    fn toggle_onpause(&mut self){
-        self.onpause = !self.onpause;
-    }
-
     fn update_world(&mut self) {
         if !self.gameloop.borrow().should_start_frame() {
             return;
         }
 
         self.gameloop.borrow_mut().start_frame();
+        self.process_actions();
 
-        if !self.onpause {
-            // noop yet
+        {
+            let mut gameloop = self.gameloop.borrow_mut();
+            let simulation = gameloop.get_mut_simulation();
+            while simulation.next_step() {
+                self.scene.borrow_mut().simulate(simulation);
+            }
         }
 
         self.window.request_redraw();
     }
 
     fn draw_frame(&mut self, image_idx: usize) {
-        self.vulkan.get_device().borrow_mut().set_image_idx(image_idx);
+        // Per frame resources are indexed by frame in flight, only the final blit and present use the swapchain image
+        let frame_idx = self.window.get_swapchain().as_ref().map_or(0, |swapchain| swapchain.current_frame);
+        self.vulkan.get_device().borrow_mut().set_image_idx(frame_idx);
         self.gameloop
             .borrow_mut()
             .update_ubo(&self.vulkan.get_device().borrow());
         let window_size = self.window.get_size();
+        let mut camera_input = std::mem::take(&mut self.camera_input);
+        camera_input.movement = cgm::Vector3::new(
+            self.input.get_axis("camera_right"),
+            self.input.get_axis("camera_up"),
+            self.input.get_axis("camera_forward"),
+        );
+        let dt = self.gameloop.borrow().get_prev_frame_time().as_secs_f64();
+        self.camera
+            .borrow_mut()
+            .update_frame(&self.scene.borrow(), self.gameloop.borrow().get_simulation(), &camera_input, dt);
+        self.update_camera_path(dt);
         self.camera
             .borrow_mut()
             .update(&self.vulkan.get_device().borrow(), window_size.x, window_size.y);
-        self.scene.borrow_mut().update(&self.vulkan.get_device().borrow(), &mut self.vulkan.get_resource_manager().borrow_mut(), &self.gameloop.borrow());
+        self.scene.borrow_mut().update_terrains(&self.camera.borrow(), &mut self.vulkan.get_resource_manager().borrow_mut(), &mut self.vulkan.get_object_descriptions().borrow_mut());
+        let render_origin = self.camera.borrow().get_render_origin();
+        self.scene.borrow_mut().update(&self.vulkan.get_device().borrow(), &mut self.vulkan.get_resource_manager().borrow_mut(), &self.gameloop.borrow(), &render_origin);
         self.scene.borrow().get_light_manager().borrow_mut().update(&self.vulkan.get_device().borrow());
 
         // Game logic update here
@@ -179,7 +278,10 @@ impl App {
             outputs = pass.run(command_buffer, outputs);
         }
 
-        if let Some(swapchain) = self.window.get_mut_swapchain() {
+        self.update_output_pass();
+        if let (Some(output_pass), Some(swapchain)) = (&self.output_pass, self.window.get_mut_swapchain()) {
+            output_pass.run(command_buffer, &outputs[0], &mut swapchain.images[image_idx]);
+        } else if let Some(swapchain) = self.window.get_mut_swapchain() {
             let device = self.vulkan.get_device().borrow();
             device.blit_result(&mut outputs[0].borrow_mut(), &mut swapchain.images[image_idx]);
 
@@ -190,7 +292,7 @@ impl App {
                 dst_stage: vk::PipelineStageFlags::TRANSFER,
                 dst_access: vk::AccessFlags::TRANSFER_READ,
             };
-            let _ = swapchain.images[device.get_image_idx()].access_image(&device, &barrier_params);
+            let _ = swapchain.images[image_idx].access_image(&device, &barrier_params);
         }
 
         self.renderer.end_frame();
@@ -216,6 +318,7 @@ impl App {
             &mut self.vulkan.get_shader_manager().borrow_mut(),
             &self.viewport,
             &self.camera,
+            &self.scene,
         ));
 
         let gbuffer_pass = Box::new(GBufferPass::new(
@@ -228,7 +331,43 @@ impl App {
             &self.scene
         ));
 
-        let mut passes: Vec<Box<dyn RenderPass>> = vec![gbuffer_pass, background_pass];
+        let impostors_pass = Box::new(ImpostorsPass::new(
+            self.vulkan.get_device(),
+            self.vulkan.get_resource_manager(),
+            &mut self.vulkan.get_shader_manager().borrow_mut(),
+            &self.viewport,
+            &self.camera,
+            &self.scene,
+        ));
+
+        let rings_pass = Box::new(RingsPass::new(
+            self.vulkan.get_device(),
+            self.vulkan.get_resource_manager(),
+            &mut self.vulkan.get_shader_manager().borrow_mut(),
+            &self.viewport,
+            &self.camera,
+            &self.scene,
+        ));
+
+        let atmosphere_pass = Box::new(AtmospherePass::new(
+            self.vulkan.get_device(),
+            self.vulkan.get_resource_manager(),
+            &mut self.vulkan.get_shader_manager().borrow_mut(),
+            &self.viewport,
+            &self.camera,
+            &self.scene,
+        ));
+
+        let orbits_pass = Box::new(OrbitsPass::new(
+            self.vulkan.get_device(),
+            self.vulkan.get_resource_manager(),
+            &mut self.vulkan.get_shader_manager().borrow_mut(),
+            &self.viewport,
+            &self.camera,
+            &self.scene,
+        ));
+
+        let mut passes: Vec<Box<dyn RenderPass>> = vec![gbuffer_pass, background_pass, impostors_pass, rings_pass, atmosphere_pass, orbits_pass];
 
         if let Some(rtao_pass) = RaytracedAo::new(self.vulkan.get_device(), self.vulkan.get_resource_manager(), self.vulkan.get_object_descriptions(), &mut self.vulkan.get_shader_manager().borrow_mut(), &self.scene, &self.camera) {
             passes.push(Box::new(rtao_pass));
@@ -237,6 +376,30 @@ impl App {
         passes
     }
 
+    /// Create the output pass when the swapchain needs shader encoding, or recreate it when the swapchain changed
+    fn update_output_pass(&mut self) {
+        let swapchain = match self.window.get_swapchain() {
+            Some(swapchain) if swapchain.encoding.needs_shader() => swapchain,
+            _ => {
+                self.output_pass = None;
+                return;
+            }
+        };
+        if self.output_pass.as_ref().is_some_and(|pass| pass.is_compatible(swapchain.format, swapchain.extent, swapchain.encoding)) {
+            return;
+        }
+
+        self.output_pass = Some(OutputPass::new(
+            self.vulkan.get_device(),
+            self.vulkan.get_resource_manager(),
+            &mut self.vulkan.get_shader_manager().borrow_mut(),
+            swapchain.format,
+            swapchain.extent,
+            swapchain.encoding,
+            self.window.get_settings().present.paper_white,
+        ));
+    }
+
     fn process_resize(&mut self) {
         let window_size = self.window.get_size();
         if window_size.x == 0 || window_size.y == 0 {
@@ -252,6 +415,7 @@ impl App {
         self.window.recreate_swapchain(&self.vulkan.get_instance().instance, self.vulkan.get_device(), self.vulkan.get_surface());
         self.viewport.borrow_mut().update(window_size.x, window_size.y);
         self.render_passes = self.create_render_passes();
+        self.output_pass = None;
     }
 
     fn process_windows_close(&mut self) {
@@ -265,19 +429,231 @@ impl App {
         self.vulkan.get_device().borrow().wait_idle();
     }
 
-    fn process_keyboard_input(&mut self, keyboard_input_event: &KeyboardInput) {
-        if let KeyboardInput {
-                virtual_keycode: Some(VirtualKeyCode::P),
-                state: ElementState::Released,
-                ..
-            } = keyboard_input_event {
-            self.toggle_onpause();
+    /// Handle actions triggered since the last frame and gather camera input for the next drawn frame
+    fn process_actions(&mut self) {
+        let toggles: [ActionHandler; 14] = [
+            ("cycle_reference_frame", App::cycle_reference_frame),
+            ("chase_camera", App::toggle_chase_camera),
+            ("orbit_camera", App::set_orbit_camera),
+            ("free_fly_camera", App::set_free_fly_camera),
+            ("cycle_active_camera", App::cycle_active_camera),
+            ("toggle_orthographic", App::toggle_orthographic),
+            ("widen_field_of_view", |app| app.scale_field_of_view(FIELD_OF_VIEW_STEP)),
+            ("narrow_field_of_view", |app| app.scale_field_of_view(1.0 / FIELD_OF_VIEW_STEP)),
+            ("record_camera_path", App::toggle_camera_path_recording),
+            ("play_camera_path", |app| app.play_camera_path(PlaybackClock::Real)),
+            ("benchmark_camera_path", |app| app.play_camera_path(PlaybackClock::Fixed(CAMERA_PATH_BENCHMARK_STEP))),
+            ("pick_object", App::pick_object),
+            ("toggle_mouse_look", |app| app.mouse_look = !app.mouse_look),
+            ("toggle_fullscreen", |app| app.window.toggle_fullscreen()),
+        ];
+        for (action, toggle) in toggles.iter() {
+            if self.input.is_action_pressed(action) {
+                toggle(self);
+            }
+        }
+
+        {
+            let mut gameloop = self.gameloop.borrow_mut();
+            let simulation = gameloop.get_mut_simulation();
+            if self.input.is_action_pressed("pause") {
+                simulation.toggle_pause();
+            }
+            if self.input.is_action_pressed("time_warp_up") {
+                simulation.increase_time_warp();
+            }
+            if self.input.is_action_pressed("time_warp_down") {
+                simulation.decrease_time_warp();
+            }
+        }
+
+        // Cursor is captured while looking around, so raw mouse movement does not stop at screen borders
+        let is_looking = self.mouse_look || self.input.is_action_held("camera_look");
+        if is_looking != self.window.is_cursor_grabbed() {
+            self.window.set_cursor_grab(is_looking);
+        }
+        if is_looking {
+            self.camera_input.look += self.input.get_mouse_delta();
+        }
+        self.camera_input.zoom += self.input.get_scroll();
+        self.update_ship_controls();
+        self.input.end_frame();
+    }
+
+    /// Pick the object under the cursor as seen in the last drawn frame. Scene pick listeners get the hit.
+    fn pick_object(&mut self) {
+        let ray = self.camera.borrow().get_cursor_ray(&self.input.get_cursor_position());
+        if self.scene.borrow().pick(&ray, self.gameloop.borrow().get_simulation()).is_none() {
+            log::info!("Nothing picked");
+        }
+    }
+
+    /// Start recording camera world poses, or stop and save them to the camera path file
+    fn toggle_camera_path_recording(&mut self) {
+        match self.path_recorder.take() {
+            Some(recorder) => {
+                let path = recorder.finish();
+                match path.save(CAMERA_PATH_PATH) {
+                    Ok(()) => log::info!("Saved camera path of {:.1} s to {}", path.get_duration(), CAMERA_PATH_PATH),
+                    Err(str) => log::error!("{}", str),
+                }
+            },
+            None => {
+                log::info!("Recording camera path");
+                self.path_recorder = Some(CameraPathRecorder::new(CAMERA_PATH_INTERVAL));
+            },
+        }
+    }
+
+    /// Fly the camera along the saved camera path in the inertial frame
+    fn play_camera_path(&mut self, clock: PlaybackClock) {
+        let path = match CameraPath::load(CAMERA_PATH_PATH) {
+            Ok(path) => path,
+            Err(str) => return log::error!("{}", str),
+        };
+        let scene = self.scene.borrow();
+        let mut camera = self.camera.borrow_mut();
+        camera.set_frame(ReferenceFrame::Inertial, &scene, self.gameloop.borrow().get_simulation());
+        camera.set_controller(Box::new(PathController::new(path, clock)));
+        log::info!("Playing camera path with {:?} clock", clock);
+        self.path_playback = Some((0, 0.0));
+    }
+
+    // Record the camera pose of this frame and finish path playback that reached its end
+    fn update_camera_path(&mut self, dt: f64) {
+        if let Some(recorder) = &mut self.path_recorder {
+            recorder.record(&self.camera.borrow().get_world_pose(), dt);
+        }
+
+        if let Some((frames, time)) = &mut self.path_playback {
+            // First frame time was spent before playback started
+            if *frames > 0 {
+                *time += dt;
+            }
+            *frames += 1;
+            if self.camera.borrow().get_controller().is_finished() {
+                log::info!("Camera path played in {} frames, {:.3} ms per frame", frames, *time * 1000.0 / (*frames - 1).max(1) as f64);
+                self.path_playback = None;
+                self.set_orbit_camera();
+            }
+        }
+    }
+
+    /// Switch camera to the next reference frame: inertial, then inertial and fixed frames of every planet
+    fn cycle_reference_frame(&mut self) {
+        let scene = self.scene.borrow();
+        let mut frames = vec![ReferenceFrame::Inertial];
+        for terrain in scene.get_terrains() {
+            let node = Rc::clone(terrain.borrow().get_node());
+            frames.push(ReferenceFrame::BodyInertial(Rc::clone(&node)));
+            frames.push(ReferenceFrame::BodyFixed(node));
         }
+
+        let mut camera = self.camera.borrow_mut();
+        let current = frames.iter().position(|f| f == camera.get_frame()).unwrap_or(0);
+        let next = frames.swap_remove((current + 1) % frames.len());
+        log::info!("Camera reference frame: {}", next.get_name());
+        camera.set_frame(next, &scene, self.gameloop.borrow().get_simulation());
+    }
+
+    /// Look through the next scene camera node, then return to the free camera
+    fn cycle_active_camera(&mut self) {
+        let scene = self.scene.borrow();
+        let cameras = scene.get_cameras();
+        let mut camera = self.camera.borrow_mut();
+        let next = match camera.get_node() {
+            Some(node) => cameras.iter().position(|c| Rc::ptr_eq(c, node)).map_or(0, |i| i + 1),
+            None => 0,
+        };
+        log::info!("Active camera: {}", if next < cameras.len() { format!("scene camera {}", next) } else { String::from("free") });
+        camera.set_node(cameras.get(next).cloned(), &scene, self.gameloop.borrow().get_simulation());
     }
 
-    fn process_mouse_input(&self, state: &ElementState, button: &MouseButton){
-        if *button == MouseButton::Left && *state == ElementState::Pressed {
-            log::info!("Left button");
+    /// Follow the ship from behind or return to orbiting the inertial frame origin
+    fn toggle_chase_camera(&mut self) {
+        let following = {
+            let scene = self.scene.borrow();
+            let ship_frame = match scene.get_ship() {
+                Some(ship) => ReferenceFrame::BodyFixed(Rc::clone(ship.borrow().get_node())),
+                None => return,
+            };
+
+            let mut camera = self.camera.borrow_mut();
+            let gameloop = self.gameloop.borrow();
+            let following = *camera.get_frame() == ship_frame;
+            if following {
+                camera.set_frame(ReferenceFrame::Inertial, &scene, gameloop.get_simulation());
+            } else {
+                camera.set_frame(ship_frame, &scene, gameloop.get_simulation());
+            }
+            following
+        };
+
+        if following {
+            self.set_orbit_camera();
+        } else {
+            self.camera.borrow_mut().set_controller(Box::new(FollowController::new(CHASE_CAMERA_OFFSET, CHASE_CAMERA_SMOOTHING)));
+            log::info!("Camera controller: {}", self.camera.borrow().get_controller().get_name());
+        }
+    }
+
+    /// Rotate the camera around its reference frame origin
+    fn set_orbit_camera(&mut self) {
+        let mut camera = self.camera.borrow_mut();
+        let position = camera.get_frame_pose().position;
+        camera.set_controller(Box::new(OrbitController::new(cgm::Point3::origin(), position)));
+        log::info!("Camera controller: {}", camera.get_controller().get_name());
+    }
+
+    /// Fly the camera in its reference frame. Movement keys steer the camera instead of the ship.
+    fn set_free_fly_camera(&mut self) {
+        let mut camera = self.camera.borrow_mut();
+        let pose = camera.get_frame_pose();
+        camera.set_controller(Box::new(FreeFlyController::new(&pose, FREE_FLY_SPEED)));
+        log::info!("Camera controller: {}", camera.get_controller().get_name());
+    }
+
+    /// Switch between perspective and orthographic projection showing the same extent at the frame origin
+    fn toggle_orthographic(&mut self) {
+        let mut camera = self.camera.borrow_mut();
+        let projection = *camera.get_projection();
+        let projection = match projection.kind {
+            ProjectionKind::Perspective(_) => {
+                let distance = camera.get_frame_pose().position.to_vec().magnitude();
+                projection.to_orthographic(distance as f32)
+            },
+            ProjectionKind::Orthographic(_) => Projection { kind: Projection::default().kind, ..projection },
+        };
+        log::info!("Camera projection: {:?}", projection.kind);
+        camera.set_projection(projection);
+    }
+
+    fn scale_field_of_view(&mut self, factor: f32) {
+        let mut camera = self.camera.borrow_mut();
+        let mut projection = *camera.get_projection();
+        projection.kind = match projection.kind {
+            ProjectionKind::Perspective(fov_y) => ProjectionKind::Perspective(cgm::Deg((fov_y.0 * factor).clamp(1.0, 150.0))),
+            ProjectionKind::Orthographic(height) => ProjectionKind::Orthographic(height * factor),
+        };
+        log::info!("Camera projection: {:?}", projection.kind);
+        camera.set_projection(projection);
+    }
+
+    fn update_ship_controls(&self) {
+        let controls = if self.camera.borrow().get_controller().uses_movement_keys() {
+            ShipControls::default()
+        } else {
+            ShipControls {
+                throttle: self.input.get_axis("ship_throttle"),
+                rotation: cgm::Vector3::new(
+                    self.input.get_axis("ship_pitch"),
+                    self.input.get_axis("ship_yaw"),
+                    self.input.get_axis("ship_roll"),
+                ),
+            }
+        };
+        if let Some(ship) = self.scene.borrow().get_ship() {
+            ship.borrow().set_controls(controls);
         }
     }
 }
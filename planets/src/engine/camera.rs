@@ -1,9 +1,17 @@
 use std::cell::RefCell;
 use std::rc::Rc;
 
+use ash::vk;
 use cgmath as cgm;
-use cgmath::{SquareMatrix, Transform};
+use cgmath::{EuclideanSpace, InnerSpace, One, Rotation, SquareMatrix, Transform};
+use crate::engine::camera_controller::{self, CameraController, CameraInput, CameraPose, FixedController, OrbitController};
+use crate::engine::scene::frame::ReferenceFrame;
+use crate::engine::scene::graph::SceneGraph;
+use crate::engine::scene::node::{NodeContent, NodeMutRef};
+use crate::engine::scene::picking::Ray;
+use crate::engine::simulation::SimulationClock;
 use crate::util::constants::{WINDOW_HEIGHT, WINDOW_WIDTH};
+use crate::util::math;
 
 use crate::vulkan::device::{Device, MAX_FRAMES_IN_FLIGHT};
 use crate::vulkan::mem::StructBufferData;
@@ -17,6 +25,135 @@ pub const UP: cgm::Vector3<f32> = cgm::Vector3 {
     z: 0.0,
 }; // TODO: move this constant to some kind of World from Camera
 
+
+/// Mapping of view distance to depth buffer values
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum DepthMode {
+    // Near plane maps to 0 and far plane to 1
+    #[allow(dead_code)]
+    Standard,
+    // Near plane maps to 1 and infinity to 0. Keeps float depth precision over astronomical ranges.
+    ReversedInfinite,
+}
+
+impl DepthMode {
+    /// Depth test passing for fragments closer to the camera
+    pub fn get_compare_op(&self) -> vk::CompareOp {
+        match self {
+            DepthMode::Standard => vk::CompareOp::LESS_OR_EQUAL,
+            DepthMode::ReversedInfinite => vk::CompareOp::GREATER_OR_EQUAL,
+        }
+    }
+
+    /// Depth value at near plane
+    pub fn get_near_depth(&self) -> f32 {
+        match self {
+            DepthMode::Standard => 0.0,
+            DepthMode::ReversedInfinite => 1.0,
+        }
+    }
+
+    /// Depth value at far plane. Also used as depth buffer clear value.
+    pub fn get_far_depth(&self) -> f32 {
+        match self {
+            DepthMode::Standard => 1.0,
+            DepthMode::ReversedInfinite => 0.0,
+        }
+    }
+
+}
+
+/// Shape of the view volume
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ProjectionKind {
+    // Vertical field of view
+    Perspective(cgm::Deg<f32>),
+    // Vertical size of the view volume in world units
+    Orthographic(f32),
+}
+
+/// Camera projection. Passes take matrices from the camera UBO every frame, so it can change at runtime.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Projection {
+    pub kind: ProjectionKind,
+    pub near: f32,
+    // Perspective projection with reversed infinite depth has no far plane
+    pub far: f32,
+}
+
+impl Default for Projection {
+    fn default() -> Self {
+        Projection {
+            kind: ProjectionKind::Perspective(cgm::Deg(60.0)),
+            near: 0.1,
+            far: 1000.0,
+        }
+    }
+}
+
+impl Projection {
+    /// Projection to Vulkan clip space before Y flip
+    pub fn get_matrix(&self, aspect: f32, depth_mode: DepthMode) -> cgm::Matrix4<f32> {
+        let (near, far) = (self.near, self.far);
+        match (self.kind, depth_mode) {
+            (_, DepthMode::Standard) => {
+                // Remap OpenGL clip space depth from [-1; 1] to [0; 1] used by Vulkan
+                let clip_correction = cgm::Matrix4::new(
+                    1.0, 0.0, 0.0, 0.0,
+                    0.0, 1.0, 0.0, 0.0,
+                    0.0, 0.0, 0.5, 0.0,
+                    0.0, 0.0, 0.5, 1.0,
+                );
+                let projection = match self.kind {
+                    ProjectionKind::Perspective(fov_y) => cgm::perspective(fov_y, aspect, near, far),
+                    ProjectionKind::Orthographic(height) => {
+                        let (half_width, half_height) = (height * aspect * 0.5, height * 0.5);
+                        cgm::ortho(-half_width, half_width, -half_height, half_height, near, far)
+                    },
+                };
+                clip_correction * projection
+            },
+            (ProjectionKind::Perspective(fov_y), DepthMode::ReversedInfinite) => {
+                let f = 1.0 / (cgm::Rad::from(fov_y).0 * 0.5).tan();
+                cgm::Matrix4::new(
+                    f / aspect, 0.0, 0.0, 0.0,
+                    0.0, f, 0.0, 0.0,
+                    0.0, 0.0, 0.0, -1.0,
+                    0.0, 0.0, near, 0.0,
+                )
+            },
+            (ProjectionKind::Orthographic(height), DepthMode::ReversedInfinite) => {
+                // Orthographic depth is linear, so the far plane stays finite with near and far swapped
+                let (half_width, half_height) = (height * aspect * 0.5, height * 0.5);
+                cgm::Matrix4::new(
+                    1.0 / half_width, 0.0, 0.0, 0.0,
+                    0.0, 1.0 / half_height, 0.0, 0.0,
+                    0.0, 0.0, 1.0 / (far - near), 0.0,
+                    0.0, 0.0, far / (far - near), 1.0,
+                )
+            },
+        }
+    }
+
+    /// Size in pixels of one unit seen from unit distance. Orthographic size does not depend on distance,
+    /// its scale is given at the distance of one view volume height.
+    pub fn get_scale(&self, viewport_height: u32) -> f64 {
+        match self.kind {
+            ProjectionKind::Perspective(fov_y) => viewport_height as f64 / (2.0 * (cgm::Rad::from(fov_y).0 as f64 * 0.5).tan()),
+            ProjectionKind::Orthographic(_) => viewport_height as f64,
+        }
+    }
+
+    /// Orthographic projection showing the same extent as this one at the given distance
+    pub fn to_orthographic(self, distance: f32) -> Projection {
+        let height = match self.kind {
+            ProjectionKind::Perspective(fov_y) => 2.0 * distance * (cgm::Rad::from(fov_y).0 * 0.5).tan(),
+            ProjectionKind::Orthographic(height) => height,
+        };
+        Projection { kind: ProjectionKind::Orthographic(height), ..self }
+    }
+}
+
 #[repr(C)]
 pub struct CameraUBOInterface {
     pub view: cgm::Matrix4<f32>,
@@ -24,13 +161,28 @@ pub struct CameraUBOInterface {
     pub proj: cgm::Matrix4<f32>,
     pub proj_inverse: cgm::Matrix4<f32>,
     pub viewport_extent: cgm::Vector4<f32>,
+    // x: near plane depth, y: far plane depth, z: near plane distance
+    pub depth_params: cgm::Vector4<f32>,
 }
 
 pub struct Camera {
     viewport_size: cgm::Vector2<u32>,
-    pub position: cgm::Point3<f32>,
-    up: cgm::Vector3<f32>,
+    // World space position resolved by update_frame(). Rendering happens relative to it, so view matrix is built at the origin.
+    pub position: cgm::Point3<f64>,
+    // World space rotation from camera axes. Camera looks along -Z with +Y up.
+    orientation: cgm::Quaternion<f64>,
+    // Frame the camera is attached to and its transform to world resolved by update_frame()
+    frame: ReferenceFrame,
+    frame_to_world: cgm::Matrix4<f64>,
+    // Places the camera in its reference frame
+    controller: Box<dyn CameraController>,
+    // Scene camera node the view is taken from, None for the free camera
+    node: Option<NodeMutRef>,
     pub aspect: f32,
+    projection: Projection,
+    // Free camera projection kept while looking through a camera node
+    free_projection: Projection,
+    depth_mode: DepthMode,
     pub ubo_interface: CameraUBOInterface,
     ubo: Vec<UniformBufferObject>,
 }
@@ -42,23 +194,12 @@ impl Camera {
             y: 0.0,
             z: -8.0,
         };
-        let up = UP;
+        let controller = Box::new(OrbitController::new(cgm::Point3::origin(), position));
+        let orientation = camera_controller::look_rotation(&-position.to_vec(), &UP.cast().unwrap_or(cgm::Vector3::unit_y()));
         let aspect = WINDOW_WIDTH as f32 / WINDOW_HEIGHT as f32;
-        let look_at = cgm::Matrix4::look_at_rh(position, cgm::Point3::new(0.0, 0.0, 0.0), up);
-        let proj = cgm::perspective(cgm::Deg(60.0), aspect, 0.1, 1000.0);
-        let mut ubo_interface = CameraUBOInterface {
-            view: look_at,
-            view_inverse: cgm::Matrix4::inverse_transform(&look_at).unwrap_or(cgm::Matrix4::identity()),
-            proj,
-            proj_inverse: cgm::Matrix4::inverse_transform(&proj).unwrap_or(cgm::Matrix4::identity()),
-            viewport_extent: cgm::Vector4 {
-                x: WINDOW_WIDTH as f32,
-                y: WINDOW_HEIGHT as f32,
-                z: 0.0,
-                w: 0.0,
-            },
-        };
-        ubo_interface.proj[1][1] *= -1.0;
+        let projection = Projection::default();
+        let depth_mode = DepthMode::ReversedInfinite;
+        let ubo_interface = Self::get_ubo_interface(&orientation, &projection, depth_mode, cgm::Vector2::new(WINDOW_WIDTH, WINDOW_HEIGHT));
 
         let ubo_data = StructBufferData::new(&ubo_interface);
         let mut ubo = vec![];
@@ -69,17 +210,39 @@ impl Camera {
         Camera {
             viewport_size: cgm::Vector2::new(WINDOW_WIDTH, WINDOW_HEIGHT),
             position,
-            up,
+            orientation,
+            frame: ReferenceFrame::Inertial,
+            frame_to_world: cgm::Matrix4::identity(),
+            controller,
+            node: None,
             aspect,
+            projection,
+            free_projection: projection,
+            depth_mode,
             ubo_interface,
             ubo,
         }
     }
 
     pub fn update(&mut self, device: &Device, viewport_width: u32, viewport_height: u32) {
+        self.viewport_size = cgm::Vector2::new(viewport_width, viewport_height);
         self.aspect = viewport_width as f32 / viewport_height as f32;
-        let view = cgm::Matrix4::look_at_rh(self.position, cgm::Point3::new(0.0, 0.0, 0.0), self.up);
-        let proj = cgm::perspective(cgm::Deg(60.0), self.aspect, 0.1, 100.0);
+        self.ubo_interface = Self::get_ubo_interface(&self.orientation, &self.projection, self.depth_mode, self.viewport_size);
+
+        let ubo_data = StructBufferData::new(&self.ubo_interface);
+        self.ubo[device.get_image_idx()].buffer.borrow().update_data(device, &ubo_data, 0);
+    }
+
+    // Inverse projection is taken before Y flip, shaders unprojecting screen positions flip Y themselves
+    fn get_ubo_interface(
+        orientation: &cgm::Quaternion<f64>,
+        projection: &Projection,
+        depth_mode: DepthMode,
+        viewport_size: cgm::Vector2<u32>,
+    ) -> CameraUBOInterface {
+        let view = Self::view_from_origin(orientation);
+        let aspect = viewport_size.x as f32 / viewport_size.y as f32;
+        let proj = projection.get_matrix(aspect, depth_mode);
         let mut ubo_interface = CameraUBOInterface {
             view,
             view_inverse: cgm::Matrix4::inverse_transform(&view).unwrap_or(cgm::Matrix4::identity()),
@@ -88,14 +251,166 @@ impl Camera {
             viewport_extent: cgm::Vector4 {
                 x: 0 as f32,
                 y: 0 as f32,
-                z: viewport_width as f32,
-                w: viewport_height as f32,
+                z: viewport_size.x as f32,
+                w: viewport_size.y as f32,
             },
+            depth_params: cgm::Vector4::new(depth_mode.get_near_depth(), depth_mode.get_far_depth(), projection.near, 0.0),
         };
         ubo_interface.proj[1][1] *= -1.0;
 
-        let ubo_data = StructBufferData::new(&ubo_interface);
-        self.ubo[device.get_image_idx()].buffer.borrow().update_data(device, &ubo_data, 0);
+        ubo_interface
+    }
+
+    /// Place camera in its reference frame with the controller for this frame. dt is real time since the
+    /// previous frame. Must be called before update() and before the render origin is taken, so the
+    /// followed body stays still on screen.
+    pub fn update_frame(&mut self, scene: &SceneGraph, simulation: &SimulationClock, input: &CameraInput, dt: f64) {
+        if let Some(node) = &self.node {
+            if let NodeContent::Camera(projection) = node.borrow().content {
+                self.projection = projection;
+            }
+        }
+        self.frame_to_world = self.frame.resolve(scene, simulation);
+        let pose = self.controller.update(input, &self.frame_to_world, dt);
+        let (_, frame_rotation, _) = math::decompose_transform(&self.frame_to_world);
+        self.position = self.frame_to_world.transform_point(pose.position);
+        self.orientation = frame_rotation * pose.orientation;
+    }
+
+    /// Attach camera to another reference frame keeping its current world placement. Stops looking
+    /// through a camera node.
+    pub fn set_frame(&mut self, reference_frame: ReferenceFrame, scene: &SceneGraph, simulation: &SimulationClock) {
+        self.release_node();
+        self.frame_to_world = reference_frame.resolve(scene, simulation);
+        self.frame = reference_frame;
+        let pose = self.get_frame_pose();
+        self.controller.set_pose(&pose);
+    }
+
+    /// Look through a scene camera node or return to the free camera, which then orbits the world origin
+    pub fn set_node(&mut self, node: Option<NodeMutRef>, scene: &SceneGraph, simulation: &SimulationClock) {
+        match node {
+            Some(node) => {
+                let frame = ReferenceFrame::BodyFixed(Rc::clone(&node));
+                if self.node.is_none() {
+                    self.free_projection = self.projection;
+                }
+                self.frame_to_world = frame.resolve(scene, simulation);
+                self.frame = frame;
+                // Node is the camera frame, so the camera sits at its origin with its axes
+                self.controller = Box::new(FixedController::new(CameraPose {
+                    position: cgm::Point3::origin(),
+                    orientation: cgm::Quaternion::one(),
+                }));
+                self.node = Some(node);
+            },
+            None => {
+                self.set_frame(ReferenceFrame::Inertial, scene, simulation);
+                let position = self.get_frame_pose().position;
+                self.set_controller(Box::new(OrbitController::new(cgm::Point3::origin(), position)));
+            },
+        }
+    }
+
+    pub fn get_node(&self) -> Option<&NodeMutRef> {
+        self.node.as_ref()
+    }
+
+    // Return to the free camera projection when leaving a camera node
+    fn release_node(&mut self) {
+        if self.node.take().is_some() {
+            self.projection = self.free_projection;
+        }
+    }
+
+    pub fn get_frame(&self) -> &ReferenceFrame {
+        &self.frame
+    }
+
+    /// Switch controller. It continues from the current camera placement.
+    pub fn set_controller(&mut self, mut controller: Box<dyn CameraController>) {
+        self.release_node();
+        controller.set_pose(&self.get_frame_pose());
+        self.controller = controller;
+    }
+
+    pub fn get_controller(&self) -> &dyn CameraController {
+        self.controller.as_ref()
+    }
+
+    /// Current camera placement in world space
+    pub fn get_world_pose(&self) -> CameraPose {
+        CameraPose {
+            position: self.position,
+            orientation: self.orientation,
+        }
+    }
+
+    /// Current camera placement in its reference frame
+    pub fn get_frame_pose(&self) -> CameraPose {
+        let world_to_frame = self.frame_to_world.inverse_transform().unwrap_or(cgm::Matrix4::identity());
+        let (_, frame_rotation, _) = math::decompose_transform(&self.frame_to_world);
+        CameraPose {
+            position: world_to_frame.transform_point(self.position),
+            orientation: frame_rotation.invert() * self.orientation,
+        }
+    }
+
+    /// Passes read depth mode on creation, so they have to be recreated after it changes
+    #[allow(dead_code)]
+    pub fn set_depth_mode(&mut self, depth_mode: DepthMode) {
+        self.depth_mode = depth_mode;
+    }
+
+    pub fn get_depth_mode(&self) -> DepthMode {
+        self.depth_mode
+    }
+
+    /// Takes effect on the next update(). Camera nodes keep their own projection.
+    pub fn set_projection(&mut self, projection: Projection) {
+        if self.node.is_none() {
+            self.projection = projection;
+        }
+    }
+
+    pub fn get_projection(&self) -> &Projection {
+        &self.projection
+    }
+
+    /// Size in pixels of one unit seen from unit distance. Used to estimate screen space errors.
+    pub fn get_projection_scale(&self) -> f64 {
+        self.projection.get_scale(self.viewport_size.y)
+    }
+
+    /// World space ray through the given position in viewport pixels with the origin in the top left corner.
+    /// Rays start on the near plane, so orthographic rays are parallel.
+    pub fn get_cursor_ray(&self, cursor: &cgm::Vector2<f64>) -> Ray {
+        let proj: cgm::Matrix4<f64> = self.projection.get_matrix(self.aspect, self.depth_mode).cast().unwrap_or(cgm::Matrix4::identity());
+        let proj_inverse = proj.invert().unwrap_or(cgm::Matrix4::identity());
+        // Projection has Y up while viewport Y goes down
+        let x = cursor.x / self.viewport_size.x as f64 * 2.0 - 1.0;
+        let y = 1.0 - cursor.y / self.viewport_size.y as f64 * 2.0;
+        let near_depth = self.depth_mode.get_near_depth() as f64;
+        // Halfway to the far plane depth is at a finite distance for infinite projection too
+        let mid_depth = (near_depth + self.depth_mode.get_far_depth() as f64) * 0.5;
+        let near = proj_inverse.transform_point(cgm::Point3::new(x, y, near_depth));
+        let mid = proj_inverse.transform_point(cgm::Point3::new(x, y, mid_depth));
+
+        Ray::new(
+            self.position + self.orientation.rotate_vector(near.to_vec()),
+            self.orientation.rotate_vector((mid - near).normalize()),
+        )
+    }
+
+    /// Origin scene positions are rebased to before upload to GPU
+    pub fn get_render_origin(&self) -> cgm::Vector3<f64> {
+        self.position.to_vec()
+    }
+
+    // View is built in camera relative space where camera sits at the origin
+    fn view_from_origin(orientation: &cgm::Quaternion<f64>) -> cgm::Matrix4<f32> {
+        let orientation = orientation.cast().unwrap_or(cgm::Quaternion::one());
+        cgm::Matrix4::from(orientation.invert())
     }
 
     pub fn get_ubo(&self, image_idx: usize) -> &UniformBufferObject {
@@ -106,3 +421,42 @@ impl Camera {
         &self.viewport_size
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use cgmath as cgm;
+    use cgmath::prelude::*;
+
+    use super::{DepthMode, Projection, ProjectionKind};
+
+    #[test]
+    fn projections_map_clip_planes_to_depth_range() {
+        let perspective = Projection::default();
+        let orthographic = perspective.to_orthographic(10.0);
+        for projection in &[perspective, orthographic] {
+            for depth_mode in &[DepthMode::Standard, DepthMode::ReversedInfinite] {
+                let matrix = projection.get_matrix(16.0 / 9.0, *depth_mode);
+                let depth = |distance: f32| matrix.transform_point(cgm::Point3::new(0.0, 0.0, -distance)).z;
+                assert!((depth(projection.near) - depth_mode.get_near_depth()).abs() < 1e-5);
+                let far = if *depth_mode == DepthMode::ReversedInfinite && projection.kind == perspective.kind {
+                    1.0e9
+                } else {
+                    projection.far
+                };
+                assert!((depth(far) - depth_mode.get_far_depth()).abs() < 1e-5);
+
+                // Inverse unprojects back to view space
+                let inverse = matrix.invert().unwrap();
+                let point = cgm::Point3::new(1.0, -2.0, -5.0);
+                assert!((inverse.transform_point(matrix.transform_point(point)) - point).magnitude() < 1e-3);
+            }
+        }
+
+        // Orthographic projection keeps the extent perspective projection had at the given distance
+        let top = |projection: &Projection, distance: f32| {
+            projection.get_matrix(1.0, DepthMode::ReversedInfinite).transform_point(cgm::Point3::new(0.0, 1.0, -distance)).y
+        };
+        assert!((top(&perspective, 10.0) - top(&orthographic, 10.0)).abs() < 1e-5);
+        assert!(matches!(orthographic.kind, ProjectionKind::Orthographic(_)));
+    }
+}
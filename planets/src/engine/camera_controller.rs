@@ -0,0 +1,328 @@
+use cgmath as cgm;
+use cgmath::prelude::*;
+
+use crate::util::math;
+
+// Radians of rotation per pixel of mouse movement
+const LOOK_SENSITIVITY: f64 = 0.005;
+// Distance or speed change per mouse wheel line
+const ZOOM_STEP: f64 = 1.1;
+// Keep pitch short of the poles, where yaw is undefined
+const MAX_PITCH: f64 = std::f64::consts::FRAC_PI_2 - 0.01;
+const MIN_ORBIT_DISTANCE: f64 = 0.5;
+
+/// Mouse and keyboard input gathered since the previous frame
+#[derive(Clone, Debug)]
+pub struct CameraInput {
+    // Mouse movement in pixels while looking around
+    pub look: cgm::Vector2<f64>,
+    // Mouse wheel lines, positive when scrolling away from the user
+    pub zoom: f64,
+    // Held movement keys along camera right, up and forward axes. Values are in [-1; 1] range.
+    pub movement: cgm::Vector3<f64>,
+}
+
+impl Default for CameraInput {
+    fn default() -> Self {
+        CameraInput {
+            look: cgm::Vector2::zero(),
+            zoom: 0.0,
+            movement: cgm::Vector3::zero(),
+        }
+    }
+}
+
+/// Camera placement in its reference frame
+#[derive(Clone, Debug)]
+pub struct CameraPose {
+    pub position: cgm::Point3<f64>,
+    // Rotation from camera axes to frame axes. Camera looks along -Z with +Y up.
+    pub orientation: cgm::Quaternion<f64>,
+}
+
+impl CameraPose {
+    pub fn get_direction(&self) -> cgm::Vector3<f64> {
+        self.orientation.rotate_vector(-cgm::Vector3::unit_z())
+    }
+}
+
+/// Moves the camera inside its reference frame in response to user input
+pub trait CameraController {
+    fn get_name(&self) -> &'static str;
+    /// Camera pose after the input gathered over dt seconds of real time.
+    /// frame_to_world is the current transform of the camera reference frame.
+    fn update(&mut self, input: &CameraInput, frame_to_world: &cgm::Matrix4<f64>, dt: f64) -> CameraPose;
+    /// Continue from the given pose, e.g. after switching controller or reference frame
+    fn set_pose(&mut self, pose: &CameraPose);
+    /// Whether the controller consumes movement keys, which are then not passed to the ship
+    fn uses_movement_keys(&self) -> bool {
+        false
+    }
+    /// Whether the controller is done, e.g. path playback reached its end
+    fn is_finished(&self) -> bool {
+        false
+    }
+}
+
+/// Rotation turning camera -Z axis to the given direction with +Y as close to up as possible
+pub fn look_rotation(direction: &cgm::Vector3<f64>, up: &cgm::Vector3<f64>) -> cgm::Quaternion<f64> {
+    let forward = direction.normalize();
+    let mut right = forward.cross(*up);
+    if right.magnitude2() < 1e-12 {
+        // Looking straight along up, any right axis will do
+        right = forward.cross(cgm::Vector3::unit_x());
+    }
+    let right = right.normalize();
+    let up = right.cross(forward);
+
+    cgm::Quaternion::from(cgm::Matrix3::from_cols(right, up, -forward))
+}
+
+fn yaw_pitch_rotation(yaw: f64, pitch: f64) -> cgm::Quaternion<f64> {
+    cgm::Quaternion::from_angle_y(cgm::Rad(yaw)) * cgm::Quaternion::from_angle_x(cgm::Rad(pitch))
+}
+
+fn apply_look(yaw: &mut f64, pitch: &mut f64, look: &cgm::Vector2<f64>) {
+    *yaw -= look.x * LOOK_SENSITIVITY;
+    *pitch = (*pitch - look.y * LOOK_SENSITIVITY).clamp(-MAX_PITCH, MAX_PITCH);
+}
+
+/// Arcball style rotation around a target point. Mouse rotates, wheel zooms.
+pub struct OrbitController {
+    target: cgm::Point3<f64>,
+    distance: f64,
+    yaw: f64,
+    pitch: f64,
+}
+
+impl OrbitController {
+    pub fn new(target: cgm::Point3<f64>, position: cgm::Point3<f64>) -> Self {
+        let mut controller = OrbitController {
+            target,
+            distance: 1.0,
+            yaw: 0.0,
+            pitch: 0.0,
+        };
+        controller.set_position(&position);
+
+        controller
+    }
+
+    fn set_position(&mut self, position: &cgm::Point3<f64>) {
+        let offset = position - self.target;
+        self.distance = offset.magnitude().max(MIN_ORBIT_DISTANCE);
+        self.yaw = offset.x.atan2(offset.z);
+        self.pitch = (-offset.y / self.distance).clamp(-1.0, 1.0).asin().clamp(-MAX_PITCH, MAX_PITCH);
+    }
+}
+
+impl CameraController for OrbitController {
+    fn get_name(&self) -> &'static str {
+        "Orbit"
+    }
+
+    fn update(&mut self, input: &CameraInput, _: &cgm::Matrix4<f64>, _: f64) -> CameraPose {
+        apply_look(&mut self.yaw, &mut self.pitch, &input.look);
+        self.distance = (self.distance * ZOOM_STEP.powf(-input.zoom)).max(MIN_ORBIT_DISTANCE);
+
+        let orientation = yaw_pitch_rotation(self.yaw, self.pitch);
+        CameraPose {
+            position: self.target + orientation.rotate_vector(cgm::Vector3::unit_z()) * self.distance,
+            orientation,
+        }
+    }
+
+    fn set_pose(&mut self, pose: &CameraPose) {
+        self.set_position(&pose.position);
+    }
+}
+
+/// Free flight with movement keys and mouse look. Wheel changes speed.
+pub struct FreeFlyController {
+    position: cgm::Point3<f64>,
+    yaw: f64,
+    pitch: f64,
+    // Units per second
+    speed: f64,
+}
+
+impl FreeFlyController {
+    pub fn new(pose: &CameraPose, speed: f64) -> Self {
+        let mut controller = FreeFlyController {
+            position: pose.position,
+            yaw: 0.0,
+            pitch: 0.0,
+            speed,
+        };
+        controller.set_pose(pose);
+
+        controller
+    }
+}
+
+impl CameraController for FreeFlyController {
+    fn get_name(&self) -> &'static str {
+        "Free fly"
+    }
+
+    fn update(&mut self, input: &CameraInput, _: &cgm::Matrix4<f64>, dt: f64) -> CameraPose {
+        apply_look(&mut self.yaw, &mut self.pitch, &input.look);
+        self.speed *= ZOOM_STEP.powf(input.zoom);
+
+        let orientation = yaw_pitch_rotation(self.yaw, self.pitch);
+        let movement = cgm::Vector3::new(input.movement.x, input.movement.y, -input.movement.z);
+        self.position += orientation.rotate_vector(movement) * (self.speed * dt);
+
+        CameraPose {
+            position: self.position,
+            orientation,
+        }
+    }
+
+    fn set_pose(&mut self, pose: &CameraPose) {
+        // Roll is dropped, free flight keeps the frame up axis
+        let direction = pose.get_direction();
+        self.position = pose.position;
+        self.yaw = (-direction.x).atan2(-direction.z);
+        self.pitch = direction.y.clamp(-1.0, 1.0).asin().clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    fn uses_movement_keys(&self) -> bool {
+        true
+    }
+}
+
+/// Stays at an offset from the frame origin looking at it. Rotation of the reference frame is followed
+/// with a delay, so turns of the followed body are visible. Wheel changes the distance.
+pub struct FollowController {
+    // Desired position in the reference frame
+    offset: cgm::Point3<f64>,
+    // Seconds to cover about two thirds of the way to the desired position
+    smoothing: f64,
+    // Smoothed offset in world axes, None until the first update
+    world_offset: Option<cgm::Vector3<f64>>,
+}
+
+impl FollowController {
+    pub fn new(offset: cgm::Point3<f64>, smoothing: f64) -> Self {
+        FollowController {
+            offset,
+            smoothing,
+            world_offset: None,
+        }
+    }
+}
+
+impl CameraController for FollowController {
+    fn get_name(&self) -> &'static str {
+        "Follow"
+    }
+
+    fn update(&mut self, input: &CameraInput, frame_to_world: &cgm::Matrix4<f64>, dt: f64) -> CameraPose {
+        self.offset = cgm::Point3::from_vec(self.offset.to_vec() * ZOOM_STEP.powf(-input.zoom));
+
+        let (_, frame_rotation, _) = math::decompose_transform(frame_to_world);
+        let desired = frame_rotation.rotate_vector(self.offset.to_vec());
+        let world_offset = match self.world_offset {
+            Some(current) if self.smoothing > 0.0 => current + (desired - current) * (1.0 - (-dt / self.smoothing).exp()),
+            _ => desired,
+        };
+        self.world_offset = Some(world_offset);
+
+        let position = frame_rotation.invert().rotate_vector(world_offset);
+        CameraPose {
+            position: cgm::Point3::from_vec(position),
+            orientation: look_rotation(&-position, &cgm::Vector3::unit_y()),
+        }
+    }
+
+    fn set_pose(&mut self, _: &CameraPose) {
+        // Start from the desired position instead of flying over from the previous one
+        self.world_offset = None;
+    }
+}
+
+/// Keeps the camera at a fixed pose in its reference frame. Used to look through camera nodes, which are
+/// the reference frame themselves.
+pub struct FixedController {
+    pose: CameraPose,
+}
+
+impl FixedController {
+    pub fn new(pose: CameraPose) -> Self {
+        FixedController {
+            pose,
+        }
+    }
+}
+
+impl CameraController for FixedController {
+    fn get_name(&self) -> &'static str {
+        "Fixed"
+    }
+
+    fn update(&mut self, _: &CameraInput, _: &cgm::Matrix4<f64>, _: f64) -> CameraPose {
+        self.pose.clone()
+    }
+
+    fn set_pose(&mut self, _: &CameraPose) {
+        // Pose is given on creation and does not continue from the previous camera
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath as cgm;
+    use cgmath::prelude::*;
+
+    use super::{CameraController, CameraInput, CameraPose, FollowController, FreeFlyController, OrbitController};
+
+    #[test]
+    fn orbit_looks_at_target() {
+        let target = cgm::Point3::new(1.0, 2.0, 3.0);
+        let mut orbit = OrbitController::new(target, cgm::Point3::new(1.0, 2.0, -5.0));
+        let input = CameraInput {
+            look: cgm::Vector2::new(150.0, -80.0),
+            zoom: 3.0,
+            ..Default::default()
+        };
+        let pose = orbit.update(&input, &cgm::Matrix4::identity(), 1.0 / 60.0);
+
+        let to_target = target - pose.position;
+        assert!(to_target.magnitude() < 8.0);
+        assert!((to_target.normalize() - pose.get_direction()).magnitude() < 1e-9);
+
+        // Switching to free flight keeps the view
+        let mut free_fly = FreeFlyController::new(&pose, 10.0);
+        let same = free_fly.update(&CameraInput::default(), &cgm::Matrix4::identity(), 1.0 / 60.0);
+        assert!((same.position - pose.position).magnitude() < 1e-9);
+        assert!((same.get_direction() - pose.get_direction()).magnitude() < 1e-9);
+
+        let forward = CameraInput { movement: cgm::Vector3::new(0.0, 0.0, 1.0), ..Default::default() };
+        let moved = free_fly.update(&forward, &cgm::Matrix4::identity(), 0.5);
+        assert!((moved.position - (pose.position + pose.get_direction() * 5.0)).magnitude() < 1e-9);
+    }
+
+    #[test]
+    fn follow_catches_up_with_frame_rotation() {
+        let offset = cgm::Point3::new(0.0, 1.5, 6.0);
+        let mut follow = FollowController::new(offset, 0.3);
+        let pose = follow.update(&CameraInput::default(), &cgm::Matrix4::identity(), 1.0 / 60.0);
+        assert!((pose.position - offset).magnitude() < 1e-9);
+
+        // Followed body turns around: camera swings behind it over time, not at once
+        let turned = cgm::Matrix4::from_translation(cgm::Vector3::new(50.0, 0.0, 0.0)) * cgm::Matrix4::from_angle_y(cgm::Deg(90.0));
+        let first = follow.update(&CameraInput::default(), &turned, 1.0 / 60.0);
+        assert!((first.position - offset).magnitude() > 1.0);
+        let mut last = first.clone();
+        for _ in 0..300 {
+            last = follow.update(&CameraInput::default(), &turned, 1.0 / 60.0);
+        }
+        assert!((last.position - offset).magnitude() < 1e-3);
+        assert!((last.get_direction() - (-offset.to_vec()).normalize()).magnitude() < 1e-3);
+
+        follow.set_pose(&CameraPose { position: cgm::Point3::origin(), orientation: cgm::Quaternion::one() });
+        let reset = follow.update(&CameraInput::default(), &cgm::Matrix4::identity(), 1.0 / 60.0);
+        assert!((reset.position - offset).magnitude() < 1e-9);
+    }
+}
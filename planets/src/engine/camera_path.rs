@@ -0,0 +1,298 @@
+use cgmath as cgm;
+use cgmath::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::engine::camera_controller::{CameraController, CameraInput, CameraPose};
+
+pub const CAMERA_PATH_PATH: &str = "camera_path.json";
+
+/// Camera pose at a point in time. Stored as plain arrays to keep the file format independent of math types.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CameraKeyframe {
+    // Seconds since the path start
+    pub time: f64,
+    pub position: [f64; 3],
+    // Quaternion as x, y, z, w
+    pub orientation: [f64; 4],
+}
+
+impl CameraKeyframe {
+    pub fn new(time: f64, pose: &CameraPose) -> Self {
+        let q = pose.orientation;
+        CameraKeyframe {
+            time,
+            position: pose.position.into(),
+            orientation: [q.v.x, q.v.y, q.v.z, q.s],
+        }
+    }
+
+    fn get_position(&self) -> cgm::Vector3<f64> {
+        self.position.into()
+    }
+
+    fn get_orientation(&self) -> cgm::Quaternion<f64> {
+        let [x, y, z, w] = self.orientation;
+        cgm::Quaternion::new(w, x, y, z).normalize()
+    }
+}
+
+/// Recorded camera flight. Positions are interpolated with a Catmull-Rom spline, orientations with slerp.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CameraPath {
+    pub keyframes: Vec<CameraKeyframe>,
+}
+
+impl CameraPath {
+    pub fn load(path: &str) -> Result<CameraPath, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read camera path {}: {}", path, e))?;
+        serde_json::from_str(&text).map_err(|e| format!("Failed to parse camera path {}: {}", path, e))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let text = serde_json::to_string_pretty(self).map_err(|e| format!("Failed to serialize camera path: {}", e))?;
+        std::fs::write(path, text).map_err(|e| format!("Failed to write camera path {}: {}", path, e))
+    }
+
+    /// Append a keyframe. Keyframes must come in increasing time order.
+    pub fn add_keyframe(&mut self, time: f64, pose: &CameraPose) {
+        self.keyframes.push(CameraKeyframe::new(time, pose));
+    }
+
+    pub fn get_duration(&self) -> f64 {
+        match (self.keyframes.first(), self.keyframes.last()) {
+            (Some(first), Some(last)) => last.time - first.time,
+            _ => 0.0,
+        }
+    }
+
+    /// Pose at the given time since the path start, clamped to the path ends
+    pub fn sample(&self, time: f64) -> Option<CameraPose> {
+        let first = self.keyframes.first()?;
+        let time = first.time + time.max(0.0).min(self.get_duration());
+        // Segment between keyframes i and i + 1 containing the time
+        let i = self.keyframes.iter()
+            .rposition(|k| k.time <= time)
+            .unwrap_or(0)
+            .min(self.keyframes.len().saturating_sub(2));
+        let k1 = &self.keyframes[i];
+        let k2 = match self.keyframes.get(i + 1) {
+            Some(k) => k,
+            None => return Some(CameraPose { position: cgm::Point3::from_vec(k1.get_position()), orientation: k1.get_orientation() }),
+        };
+
+        let segment = k2.time - k1.time;
+        let t = if segment > 0.0 { (time - k1.time) / segment } else { 0.0 };
+        let position = hermite(
+            k1.get_position(),
+            k2.get_position(),
+            self.get_tangent(i) * segment,
+            self.get_tangent(i + 1) * segment,
+            t,
+        );
+
+        let q1 = k1.get_orientation();
+        let mut q2 = k2.get_orientation();
+        // Take the shorter way around
+        if q1.dot(q2) < 0.0 {
+            q2 = -q2;
+        }
+
+        Some(CameraPose {
+            position: cgm::Point3::from_vec(position),
+            orientation: q1.slerp(q2, t).normalize(),
+        })
+    }
+
+    // Catmull-Rom velocity at keyframe i for keyframes spaced unevenly in time. Ends use one sided differences.
+    fn get_tangent(&self, i: usize) -> cgm::Vector3<f64> {
+        let previous = &self.keyframes[i.saturating_sub(1)];
+        let next = &self.keyframes[(i + 1).min(self.keyframes.len() - 1)];
+        let span = next.time - previous.time;
+        if span > 0.0 {
+            (next.get_position() - previous.get_position()) / span
+        } else {
+            cgm::Vector3::zero()
+        }
+    }
+}
+
+// Cubic Hermite interpolation for t in [0; 1] with tangents scaled to the segment duration
+fn hermite(p1: cgm::Vector3<f64>, p2: cgm::Vector3<f64>, m1: cgm::Vector3<f64>, m2: cgm::Vector3<f64>, t: f64) -> cgm::Vector3<f64> {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    p1 * (2.0 * t3 - 3.0 * t2 + 1.0) + m1 * (t3 - 2.0 * t2 + t) + p2 * (-2.0 * t3 + 3.0 * t2) + m2 * (t3 - t2)
+}
+
+/// Collects camera poses at a fixed interval of real time
+pub struct CameraPathRecorder {
+    path: CameraPath,
+    // Seconds between keyframes
+    interval: f64,
+    time: f64,
+}
+
+impl CameraPathRecorder {
+    pub fn new(interval: f64) -> Self {
+        CameraPathRecorder {
+            path: CameraPath::default(),
+            interval,
+            time: 0.0,
+        }
+    }
+
+    /// Record the pose dt seconds after the previous call. First pose starts the path.
+    pub fn record(&mut self, pose: &CameraPose, dt: f64) {
+        let last_time = match self.path.keyframes.last() {
+            Some(last) => last.time,
+            None => return self.path.add_keyframe(0.0, pose),
+        };
+        self.time += dt;
+        if self.time - last_time >= self.interval {
+            self.path.add_keyframe(self.time, pose);
+        }
+    }
+
+    pub fn finish(self) -> CameraPath {
+        self.path
+    }
+}
+
+/// How path playback advances
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PlaybackClock {
+    // By real time between frames
+    Real,
+    // By the given seconds every frame regardless of frame duration. Same frames see same poses on every
+    // run, so performance captures are comparable.
+    Fixed(f64),
+}
+
+/// Flies the camera along a recorded path
+pub struct PathController {
+    path: CameraPath,
+    clock: PlaybackClock,
+    time: f64,
+    // Frames played so far
+    frame_count: u64,
+}
+
+impl PathController {
+    pub fn new(path: CameraPath, clock: PlaybackClock) -> Self {
+        PathController {
+            path,
+            clock,
+            time: 0.0,
+            frame_count: 0,
+        }
+    }
+}
+
+impl CameraController for PathController {
+    fn get_name(&self) -> &'static str {
+        "Path"
+    }
+
+    fn update(&mut self, _: &CameraInput, _: &cgm::Matrix4<f64>, dt: f64) -> CameraPose {
+        // Time is derived from the frame count for fixed steps, so no rounding error accumulates
+        self.time = match self.clock {
+            PlaybackClock::Real => self.time + dt,
+            PlaybackClock::Fixed(step) => self.frame_count as f64 * step,
+        };
+        self.frame_count += 1;
+
+        self.path.sample(self.time).unwrap_or(CameraPose {
+            position: cgm::Point3::origin(),
+            orientation: cgm::Quaternion::one(),
+        })
+    }
+
+    fn set_pose(&mut self, _: &CameraPose) {
+        // Path gives all poses
+    }
+
+    fn is_finished(&self) -> bool {
+        self.time >= self.path.get_duration()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath as cgm;
+    use cgmath::prelude::*;
+
+    use crate::engine::camera_controller::{CameraController, CameraInput, CameraPose};
+
+    use super::{CameraPath, CameraPathRecorder, PathController, PlaybackClock};
+
+    fn pose(x: f64, y: f64, yaw: f64) -> CameraPose {
+        CameraPose {
+            position: cgm::Point3::new(x, y, 0.0),
+            orientation: cgm::Quaternion::from_angle_y(cgm::Deg(yaw)),
+        }
+    }
+
+    fn test_path() -> CameraPath {
+        let mut path = CameraPath::default();
+        path.add_keyframe(0.0, &pose(0.0, 0.0, 0.0));
+        path.add_keyframe(1.0, &pose(10.0, 0.0, 90.0));
+        path.add_keyframe(3.0, &pose(10.0, 10.0, 180.0));
+        path.add_keyframe(4.0, &pose(0.0, 10.0, 270.0));
+        path
+    }
+
+    #[test]
+    fn spline_passes_through_keyframes_smoothly() {
+        let path = test_path();
+        assert_eq!(path.get_duration(), 4.0);
+        for keyframe in &path.keyframes {
+            let sampled = path.sample(keyframe.time).unwrap();
+            let expected: cgm::Point3<f64> = keyframe.position.into();
+            assert!((sampled.position - expected).magnitude() < 1e-9);
+        }
+
+        // No jump in position when crossing a keyframe
+        let before = path.sample(1.0 - 1e-6).unwrap().position;
+        let after = path.sample(1.0 + 1e-6).unwrap().position;
+        assert!((after - before).magnitude() < 1e-4);
+
+        // Orientation turns the short way: 45° halfway from 0° to 90° yaw, 225° from 180° to 270°
+        let halfway = path.sample(0.5).unwrap().orientation;
+        assert!(halfway.dot(cgm::Quaternion::from_angle_y(cgm::Deg(45.0))).abs() > 1.0 - 1e-9);
+        let last = path.sample(3.5).unwrap().orientation;
+        let expected = cgm::Quaternion::from_angle_y(cgm::Deg(225.0));
+        assert!(last.dot(expected).abs() > 1.0 - 1e-9);
+
+        // Clamped past the ends
+        assert!((path.sample(10.0).unwrap().position - cgm::Point3::new(0.0, 10.0, 0.0)).magnitude() < 1e-9);
+        assert!(CameraPath::default().sample(1.0).is_none());
+    }
+
+    #[test]
+    fn fixed_step_playback_is_deterministic() {
+        let text = serde_json::to_string(&test_path()).unwrap();
+        let loaded: CameraPath = serde_json::from_str(&text).unwrap();
+        assert_eq!(loaded.keyframes, test_path().keyframes);
+
+        // Frame durations differ between runs, poses do not
+        let run = |frame_time: f64| {
+            let mut controller = PathController::new(loaded.clone(), PlaybackClock::Fixed(0.25));
+            let mut positions = vec![];
+            while !controller.is_finished() {
+                positions.push(controller.update(&CameraInput::default(), &cgm::Matrix4::identity(), frame_time).position);
+            }
+            positions
+        };
+        let fast = run(0.001);
+        assert_eq!(fast, run(0.1));
+        assert_eq!(fast.len(), 17);
+
+        let mut recorder = CameraPathRecorder::new(0.5);
+        for i in 0..40 {
+            recorder.record(&pose(i as f64, 0.0, 0.0), 0.125);
+        }
+        let recorded = recorder.finish();
+        assert_eq!(recorded.keyframes.len(), 10);
+        assert_eq!(recorded.keyframes[1].position, [4.0, 0.0, 0.0]);
+        assert_eq!(recorded.get_duration(), 4.5);
+    }
+}
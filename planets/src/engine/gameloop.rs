@@ -2,6 +2,7 @@ use std::cell::RefCell;
 use std::ops::Add;
 use std::rc::Rc;
 use std::time;
+use crate::engine::simulation::{SimulationClock, SECONDS_PER_DAY};
 use crate::vulkan::device::{Device, MAX_FRAMES_IN_FLIGHT};
 use crate::vulkan::mem::{StructBufferData};
 use crate::vulkan::resources::manager::ResourceManager;
@@ -9,6 +10,9 @@ use crate::vulkan::uniform_buffer::UniformBufferObject;
 
 pub type GameLoopMutRef = Rc<RefCell<GameLoop>>;
 
+// Simulation seconds after which elapsed time passed to shaders starts from 0 again
+const SIMULATION_TIME_PERIOD: f64 = SECONDS_PER_DAY;
+
 pub struct GameLoop {
     // Application start time
     application_start_time: time::Instant,
@@ -16,13 +20,15 @@ pub struct GameLoop {
     frame_start_time: time::Instant,
     // Time passed since previous frame was started
     prev_frame_duration: time::Duration,
-    // Maximal number of frames per second.
+    // Maximal number of frames per second, None for no limit.
     // Game loop will wait for required time to pass before starting a new frame to keep constant FPS if needed
-    max_fps: i32,
+    max_fps: Option<u32>,
     // Defines if frame started or not
     frame_started: bool,
     // Current frame number
     frame_num: u64,
+    // Simulation time with its own epoch, time warp and pause state
+    simulation: SimulationClock,
     // Timer UBO
     timer_ubo: Vec<UniformBufferObject>,
 }
@@ -32,6 +38,8 @@ impl GameLoop {
         let ubo_interface = TimerUBOInterface {
             total_time_elapsed: 0.0,
             frame_time_delta: 0.0,
+            simulation_time_elapsed: 0.0,
+            simulation_time_delta: 0.0,
         };
 
         let ubo_data = StructBufferData::new(&ubo_interface);
@@ -44,18 +52,17 @@ impl GameLoop {
             application_start_time: time::Instant::now(),
             frame_start_time: time::Instant::now(),
             prev_frame_duration: time::Duration::from_millis(0),
-            max_fps: 120,
+            max_fps: Some(120),
             frame_started: false,
             frame_num: 0,
+            simulation: SimulationClock::new(),
             timer_ubo,
         }
     }
 
     // Get if it's time to start frame already
     pub fn should_start_frame(&self) -> bool {
-        let wanted_time_per_frame = time::Duration::from_micros(1000000 / self.max_fps as u64);
-
-        wanted_time_per_frame <= self.frame_start_time.elapsed()
+        self.get_wanted_time_per_frame() <= self.frame_start_time.elapsed()
     }
 
     pub fn get_frame_started(&self) -> bool {
@@ -67,6 +74,7 @@ impl GameLoop {
         self.prev_frame_duration = self.frame_start_time.elapsed();
         self.frame_start_time = time::Instant::now();
         self.frame_started = true;
+        self.simulation.advance(self.prev_frame_duration);
     }
 
     // If frame was started - finish it and increase frame count
@@ -79,7 +87,7 @@ impl GameLoop {
 
     // Get time Instant specifying the time we want to start next frame at
     pub fn get_wait_instant(&self) -> time::Instant {
-        let wanted_time_per_frame = time::Duration::from_micros(1000000 / self.max_fps as u64);
+        let wanted_time_per_frame = self.get_wanted_time_per_frame();
         let mut wait_until = time::Instant::now();
         let time_since_frame_start = self.frame_start_time.elapsed();
         if wanted_time_per_frame > time_since_frame_start {
@@ -98,18 +106,37 @@ impl GameLoop {
         time::Instant::now() - self.application_start_time
     }
 
-    pub fn set_max_fps(&mut self, max_fps: i32) {
+    pub fn set_max_fps(&mut self, max_fps: Option<u32>) {
         self.max_fps = max_fps
     }
 
+    // Shortest frame duration the frame rate limit allows
+    fn get_wanted_time_per_frame(&self) -> time::Duration {
+        match self.max_fps {
+            Some(max_fps) if max_fps > 0 => time::Duration::from_micros(1000000 / max_fps as u64),
+            _ => time::Duration::ZERO,
+        }
+    }
+
     pub fn get_fps(&self) -> f32 {
-        1000.0 / self.prev_frame_duration.as_millis() as f32
+        1.0 / self.prev_frame_duration.as_secs_f32()
+    }
+
+    pub fn get_simulation(&self) -> &SimulationClock {
+        &self.simulation
+    }
+
+    pub fn get_mut_simulation(&mut self) -> &mut SimulationClock {
+        &mut self.simulation
     }
 
     pub fn update_ubo(&mut self, device: &Device) {
         let ubo_interface = TimerUBOInterface {
             total_time_elapsed: self.get_total_elapsed().as_secs_f32(),
             frame_time_delta: self.get_prev_frame_time().as_secs_f32(),
+            // Wrapped before the cast, f32 steps get too coarse for animation at warped elapsed times
+            simulation_time_elapsed: self.simulation.get_elapsed().rem_euclid(SIMULATION_TIME_PERIOD) as f32,
+            simulation_time_delta: self.simulation.get_last_delta() as f32,
         };
 
         let ubo_data = StructBufferData::new(&ubo_interface);
@@ -125,4 +152,7 @@ impl GameLoop {
 struct TimerUBOInterface {
     total_time_elapsed: f32,
     frame_time_delta: f32,
+    // Simulation seconds since epoch modulo SIMULATION_TIME_PERIOD
+    simulation_time_elapsed: f32,
+    simulation_time_delta: f32,
 }
@@ -3,6 +3,7 @@ use ash::vk;
 extern crate cgmath as cgm;
 use cgmath::prelude::*;
 
+use crate::engine::scene::picking::BoundingBox;
 use crate::vulkan::mem::{AllocatedBufferMutRef, VecBufferData};
 use crate::vulkan::resources::manager::ResourceManager;
 
@@ -31,6 +32,8 @@ pub struct Geometry {
     pub vertex_buffer: AllocatedBufferMutRef,
     pub indices: Vec<i32>,
     pub index_buffer: AllocatedBufferMutRef,
+    // Bounds of vertex positions, used for picking
+    pub bounds: BoundingBox,
 }
 
 impl Geometry {
@@ -54,11 +57,14 @@ impl Geometry {
             format!("Geometry::Index({})", label).as_str(),
         );
 
+        let bounds = BoundingBox::from_vertices(&vertices);
+
         Geometry {
             vertices,
             vertex_buffer,
             indices,
             index_buffer,
+            bounds,
         }
     }
 
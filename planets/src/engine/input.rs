@@ -0,0 +1,250 @@
+use std::collections::{HashMap, HashSet};
+
+use cgmath as cgm;
+use cgmath::prelude::*;
+use serde::Deserialize;
+use winit::dpi::PhysicalPosition;
+use winit::event::{ElementState, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode};
+
+pub const INPUT_CONFIG_PATH: &str = "assets/config/input.json";
+// Used when the config file is missing or broken, so the app stays controllable
+const DEFAULT_INPUT_CONFIG: &str = include_str!("../../assets/config/input.json");
+// Logical pixels per mouse wheel line for touchpads reporting pixel deltas
+const PIXELS_PER_LINE: f64 = 20.0;
+
+/// Key or mouse button an action can be bound to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+pub enum Binding {
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+}
+
+/// Axis value is 1 while any positive binding is held, -1 for negative ones and 0 for both or none
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct AxisBindings {
+    #[serde(default)]
+    pub positive: Vec<Binding>,
+    #[serde(default)]
+    pub negative: Vec<Binding>,
+}
+
+/// Named actions and axes with their bindings
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct InputBindings {
+    #[serde(default)]
+    pub actions: HashMap<String, Vec<Binding>>,
+    #[serde(default)]
+    pub axes: HashMap<String, AxisBindings>,
+}
+
+impl InputBindings {
+    pub fn load(path: &str) -> Result<InputBindings, String> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => InputBindings::parse(&text).map_err(|e| format!("Failed to parse input config {}: {}", path, e)),
+            Err(e) => Err(format!("Failed to read input config {}: {}", path, e)),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<InputBindings, String> {
+        serde_json::from_str(text).map_err(|e| e.to_string())
+    }
+
+    /// Bindings from the config file, or the built in defaults if it can't be loaded
+    pub fn load_or_default(path: &str) -> InputBindings {
+        InputBindings::load(path).unwrap_or_else(|e| {
+            log::error!("{}", e);
+            InputBindings::parse(DEFAULT_INPUT_CONFIG).unwrap_or_default()
+        })
+    }
+}
+
+/// Keyboard and mouse state fed by window events. Game code queries named actions and axes instead of
+/// raw keys. Presses, releases and mouse movement are gathered until end_frame().
+pub struct Input {
+    bindings: InputBindings,
+    // Bindings held down at the moment
+    held: HashSet<Binding>,
+    // Bindings pressed or released since the last end_frame()
+    pressed: HashSet<Binding>,
+    released: HashSet<Binding>,
+    // Mouse movement in pixels and wheel lines since the last end_frame()
+    mouse_delta: cgm::Vector2<f64>,
+    scroll: f64,
+    // Cursor position in physical window pixels from the top left corner
+    cursor_position: cgm::Vector2<f64>,
+    // Physical pixels per logical pixel of the window
+    scale_factor: f64,
+}
+
+impl Input {
+    pub fn new(bindings: InputBindings) -> Self {
+        Input {
+            bindings,
+            held: HashSet::new(),
+            pressed: HashSet::new(),
+            released: HashSet::new(),
+            mouse_delta: cgm::Vector2::zero(),
+            scroll: 0.0,
+            cursor_position: cgm::Vector2::zero(),
+            scale_factor: 1.0,
+        }
+    }
+
+    pub fn process_keyboard_input(&mut self, keyboard_input: &KeyboardInput) {
+        if let Some(key) = keyboard_input.virtual_keycode {
+            self.set_state(Binding::Key(key), keyboard_input.state);
+        }
+    }
+
+    pub fn process_mouse_input(&mut self, state: ElementState, button: MouseButton) {
+        self.set_state(Binding::Mouse(button), state);
+    }
+
+    pub fn process_mouse_motion(&mut self, delta: (f64, f64)) {
+        self.mouse_delta += cgm::Vector2::new(delta.0, delta.1);
+    }
+
+    pub fn process_mouse_wheel(&mut self, delta: &MouseScrollDelta) {
+        self.scroll += match delta {
+            MouseScrollDelta::LineDelta(_, lines) => *lines as f64,
+            MouseScrollDelta::PixelDelta(position) => position.y / (self.scale_factor * PIXELS_PER_LINE),
+        };
+    }
+
+    /// Scale factor of the window events come from, used to convert pixel deltas
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.scale_factor = scale_factor;
+    }
+
+    /// Release everything held. Called when the window loses focus, as releases go to other windows then.
+    pub fn release_all(&mut self) {
+        self.released.extend(self.held.drain());
+    }
+
+    pub fn process_cursor_moved(&mut self, position: &PhysicalPosition<f64>) {
+        self.cursor_position = cgm::Vector2::new(position.x, position.y);
+    }
+
+    // Key repeat sends presses of held keys again, they are not new presses
+    fn set_state(&mut self, binding: Binding, state: ElementState) {
+        match state {
+            ElementState::Pressed => {
+                if self.held.insert(binding) {
+                    self.pressed.insert(binding);
+                }
+            },
+            ElementState::Released => {
+                if self.held.remove(&binding) {
+                    self.released.insert(binding);
+                }
+            },
+        }
+    }
+
+    /// Forget presses, releases and mouse movement gathered so far. Call once they were handled.
+    pub fn end_frame(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+        self.mouse_delta = cgm::Vector2::zero();
+        self.scroll = 0.0;
+    }
+
+    pub fn is_action_held(&self, action: &str) -> bool {
+        self.any_binding(action, &self.held)
+    }
+
+    pub fn is_action_pressed(&self, action: &str) -> bool {
+        self.any_binding(action, &self.pressed)
+    }
+
+    #[allow(dead_code)]
+    pub fn is_action_released(&self, action: &str) -> bool {
+        self.any_binding(action, &self.released)
+    }
+
+    /// Axis value in [-1; 1] range. Unknown axes are 0.
+    pub fn get_axis(&self, axis: &str) -> f64 {
+        match self.bindings.axes.get(axis) {
+            Some(bindings) => {
+                let is_held = |bindings: &Vec<Binding>| bindings.iter().any(|b| self.held.contains(b));
+                let mut value = 0.0;
+                if is_held(&bindings.positive) {
+                    value += 1.0;
+                }
+                if is_held(&bindings.negative) {
+                    value -= 1.0;
+                }
+                value
+            },
+            None => 0.0,
+        }
+    }
+
+    pub fn get_mouse_delta(&self) -> cgm::Vector2<f64> {
+        self.mouse_delta
+    }
+
+    pub fn get_scroll(&self) -> f64 {
+        self.scroll
+    }
+
+    pub fn get_cursor_position(&self) -> cgm::Vector2<f64> {
+        self.cursor_position
+    }
+
+    fn any_binding(&self, action: &str, state: &HashSet<Binding>) -> bool {
+        self.bindings.actions.get(action)
+            .is_some_and(|bindings| bindings.iter().any(|b| state.contains(b)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use winit::dpi::PhysicalPosition;
+    use winit::event::{ElementState, MouseButton, MouseScrollDelta, VirtualKeyCode};
+
+    use super::{Binding, Input, InputBindings, DEFAULT_INPUT_CONFIG};
+
+    #[test]
+    fn actions_and_axes_follow_bindings() {
+        let bindings = InputBindings::parse(DEFAULT_INPUT_CONFIG).unwrap();
+        assert_eq!(bindings.actions["pause"], vec![Binding::Key(VirtualKeyCode::P)]);
+        let mut input = Input::new(bindings);
+
+        input.set_state(Binding::Key(VirtualKeyCode::W), ElementState::Pressed);
+        input.set_state(Binding::Mouse(MouseButton::Right), ElementState::Pressed);
+        assert_eq!(input.get_axis("ship_throttle"), 1.0);
+        assert!(input.is_action_pressed("camera_look") && input.is_action_held("camera_look"));
+
+        input.end_frame();
+        // Key repeat is not a new press
+        input.set_state(Binding::Key(VirtualKeyCode::W), ElementState::Pressed);
+        input.set_state(Binding::Key(VirtualKeyCode::S), ElementState::Pressed);
+        input.set_state(Binding::Mouse(MouseButton::Right), ElementState::Released);
+        assert_eq!(input.get_axis("ship_throttle"), 0.0);
+        assert!(!input.is_action_pressed("camera_look") && !input.is_action_held("camera_look"));
+        assert!(input.is_action_released("camera_look"));
+
+        input.end_frame();
+        assert!(!input.is_action_released("camera_look"));
+        assert_eq!(input.get_axis("unknown"), 0.0);
+        assert!(!input.is_action_held("unknown"));
+    }
+
+    #[test]
+    fn focus_loss_and_hidpi_scroll() {
+        let mut input = Input::new(InputBindings::parse(DEFAULT_INPUT_CONFIG).unwrap());
+        input.set_state(Binding::Mouse(MouseButton::Right), ElementState::Pressed);
+        input.end_frame();
+        input.release_all();
+        assert!(!input.is_action_held("camera_look") && input.is_action_released("camera_look"));
+
+        // Same touchpad movement scrolls the same on displays with twice the pixel density
+        input.process_mouse_wheel(&MouseScrollDelta::PixelDelta(PhysicalPosition::new(0.0, 40.0)));
+        let scroll = input.get_scroll();
+        input.end_frame();
+        input.set_scale_factor(2.0);
+        input.process_mouse_wheel(&MouseScrollDelta::PixelDelta(PhysicalPosition::new(0.0, 80.0)));
+        assert_eq!(input.get_scroll(), scroll);
+    }
+}
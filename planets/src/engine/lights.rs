@@ -11,29 +11,64 @@ use crate::vulkan::resources::manager::ResourceManager;
 
 const MAX_LIGHTS: usize = 64;
 
+// Sun luminous intensity in candela
+pub const SUN_LUMINOUS_INTENSITY: f32 = 2.98e27;
+// Sun radius in meters
+pub const SUN_RADIUS: f64 = 6.957e8;
+// Mean Earth-Sun distance in meters
+pub const ASTRONOMICAL_UNIT: f64 = 1.496e11;
+
 pub type LightManagerMutRef = Rc<RefCell<LightManager>>;
 
 #[derive(Clone)]
 #[repr(C)]
 struct LightBlock {
+    // xyz: position, w: light type
     position: cgm::Vector4<f32>,
+    // rgb: color, a: luminous intensity
     color: cgm::Vector4<f32>,
     is_active_radius_padding: cgm::Vector4<f32>,
+    // x: angular radius of star disc, y: star illuminance at render origin
+    star_params: cgm::Vector4<f32>,
 }
 
 impl LightBlock {
     pub fn new(position: cgm::Vector3<f32>) -> LightBlock {
         LightBlock {
-            position: cgm::Vector4::new(position.x, position.y, position.z, 1.0),
+            position: cgm::Vector4::new(position.x, position.y, position.z, LightType::Point.as_shader_value()),
             color: cgm::Vector4::new(1.0, 1.0, 1.0, 1.0),
             is_active_radius_padding: cgm::Vector4::new(0.0, f32::MAX, 0.0, 0.0),
+            star_params: cgm::Vector4::zero(),
         }
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub enum LightType {
     Point,
+    // Distant spherical emitter. Its light arrives almost parallel, so the scene sees it as a
+    // directional light with a visible disc.
+    Star,
+}
+
+impl LightType {
+    // Must match LIGHT_TYPE_* in lights.glsl
+    fn as_shader_value(&self) -> f32 {
+        match self {
+            LightType::Point => 0.0,
+            LightType::Star => 1.0,
+        }
+    }
+}
+
+/// Angular radius in radians of a sphere with the given radius seen from the given distance
+pub fn star_angular_radius(star_radius: f64, distance: f64) -> f64 {
+    (star_radius / distance.max(star_radius)).asin()
+}
+
+/// Illuminance in lux at the given distance in meters from a source of the given luminous intensity in candela
+pub fn star_illuminance(luminous_intensity: f64, distance: f64) -> f64 {
+    luminous_intensity / (distance * distance).max(f64::EPSILON)
 }
 
 #[derive(Clone)]
@@ -42,9 +77,15 @@ pub struct Light {
     light_id: usize,
 
     pub light_type: LightType,
-    pub position: cgm::Vector3<f32>,
+    // World space position
+    pub position: cgm::Vector3<f64>,
     pub color: cgm::Vector3<f32>,
+    // Luminous intensity in candela
+    pub intensity: f32,
+    // Range of point light
     pub radius: f32,
+    // Radius of star sphere in meters, defines angular size of its disc
+    pub star_radius: f64,
     pub is_active: bool,
 }
 
@@ -56,19 +97,43 @@ impl Light {
             light_type: LightType::Point,
             position: cgm::Vector3::zero(),
             color: cgm::Vector3::new(1.0, 1.0, 1.0),
+            intensity: 100.0,
             radius: 100.0,
+            star_radius: 0.0,
             is_active: true,
         }
     }
 
-    pub fn apply(&mut self) {
+    /// Turn light into a star of the given luminous intensity in candela and radius in meters
+    pub fn set_star(&mut self, luminous_intensity: f32, star_radius: f64) {
+        self.light_type = LightType::Star;
+        self.intensity = luminous_intensity;
+        self.star_radius = star_radius;
+    }
+
+    /// Write light state for upload with position relative to the given render origin
+    pub fn apply(&mut self, origin: &cgm::Vector3<f64>) {
         let mut light_mgr = self.light_manager.borrow_mut();
         let mut light_block = &mut light_mgr.light_blocks[self.light_id];
+        let position = self.position - origin;
         light_block.position =
-            cgm::Vector4::new(self.position.x, self.position.y, self.position.z, 1.0);
-        light_block.color = cgm::Vector4::new(self.color.x, self.color.y, self.color.z, 1.0);
+            cgm::Vector4::new(position.x as f32, position.y as f32, position.z as f32, self.light_type.as_shader_value());
+        light_block.color = cgm::Vector4::new(self.color.x, self.color.y, self.color.z, self.intensity);
         light_block.is_active_radius_padding.x = if self.is_active { 1.0 } else { 0.0 };
         light_block.is_active_radius_padding.y = self.radius;
+        light_block.star_params = match self.light_type {
+            // Star is far enough to have the same illuminance and size everywhere in the scene
+            LightType::Star => {
+                let distance = position.magnitude();
+                cgm::Vector4::new(
+                    star_angular_radius(self.star_radius, distance) as f32,
+                    star_illuminance(self.intensity as f64, distance) as f32,
+                    0.0,
+                    0.0,
+                )
+            },
+            LightType::Point => cgm::Vector4::zero(),
+        };
     }
 }
 
@@ -126,7 +191,38 @@ impl LightManager {
         panic!("Maximum number of lights used");
     }
 
+    /// Render origin relative position of the first active star or of the first active light if there
+    /// are no stars. Used as the sun by atmosphere rendering.
+    pub fn get_primary_light_position(&self) -> Option<cgm::Vector3<f32>> {
+        let mut active_blocks = self.light_blocks.iter()
+            .zip(self.used_lights.iter())
+            .filter(|(block, used)| **used && block.is_active_radius_padding.x > 0.0)
+            .map(|(block, _)| block);
+        let first_active = active_blocks.clone().next();
+        active_blocks
+            .find(|block| block.position.w == LightType::Star.as_shader_value())
+            .or(first_active)
+            .map(|block| block.position.truncate())
+    }
+
     pub fn get_ssbo(&self, image_idx: usize) -> &AllocatedBufferMutRef {
         &self.ssbo[image_idx]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{star_angular_radius, star_illuminance, ASTRONOMICAL_UNIT, SUN_LUMINOUS_INTENSITY, SUN_RADIUS};
+
+    #[test]
+    fn sun_seen_from_earth() {
+        let illuminance = star_illuminance(SUN_LUMINOUS_INTENSITY as f64, ASTRONOMICAL_UNIT);
+        assert!((illuminance - 1.33e5).abs() < 2e3);
+
+        let angular_diameter = 2.0 * star_angular_radius(SUN_RADIUS, ASTRONOMICAL_UNIT).to_degrees();
+        assert!((angular_diameter - 0.533).abs() < 0.005);
+
+        // Inside the star the disc covers the whole hemisphere
+        assert!((star_angular_radius(SUN_RADIUS, 1.0) - std::f64::consts::FRAC_PI_2).abs() < 1e-9);
+    }
+}
@@ -1,12 +1,17 @@
 pub mod camera;
+pub mod camera_controller;
+pub mod camera_path;
 pub mod renderpass;
 pub mod gameloop;
+pub mod input;
 pub mod geometry;
 pub mod lights;
 pub mod material;
 pub mod models;
 pub mod renderer;
 pub mod scene;
+pub mod simulation;
+pub mod skybox;
 pub mod textures;
 pub mod viewport;
 pub mod passes;
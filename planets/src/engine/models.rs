@@ -10,8 +10,14 @@ pub struct ModelDataSSBOInterface {
     pub transform: Matrix4<f32>,
 }
 
+const MAX_MODELS: usize = 1024;
+
 pub struct ModelData {
     data: Vec<ModelDataSSBOInterface>,
+    // Number of models written since last reset
+    count: usize,
+    // Number of models not written since last reset because the SSBO was full
+    dropped: usize,
     ssbo: Vec<AllocatedBufferMutRef>,
 }
 
@@ -19,7 +25,7 @@ impl ModelData {
     pub fn new(resource_manager: &ResourceManagerMutRef) -> Self {
         let data = vec![ModelDataSSBOInterface {
             transform: Matrix4::identity(),
-        }; 1024];
+        }; MAX_MODELS];
 
         let ssbo_data = VecBufferData::new(&data);
         let mut resource_manager_ref = resource_manager.borrow_mut();
@@ -31,6 +37,8 @@ impl ModelData {
 
         ModelData {
             data,
+            count: 0,
+            dropped: 0,
             ssbo
         }
     }
@@ -40,8 +48,31 @@ impl ModelData {
         self.ssbo[device.get_image_idx()].borrow().update_data(device, &ssbo_data, 0);
     }
 
-    pub fn set_data_for(&mut self, index: usize, data: &ModelDataSSBOInterface) {
-        self.data[index] = *data;
+    /// Start writing models for a new frame
+    pub fn reset(&mut self) {
+        if self.dropped > 0 {
+            log::error!("Maximum number of models reached: {} models were not drawn", self.dropped);
+        }
+        self.count = 0;
+        self.dropped = 0;
+    }
+
+    /// Append model and return its index in the SSBO. None if the SSBO is full, the model must not be drawn then.
+    pub fn add_model(&mut self, data: &ModelDataSSBOInterface) -> Option<u32> {
+        if self.count >= MAX_MODELS {
+            self.dropped += 1;
+            return None;
+        }
+
+        self.data[self.count] = *data;
+        self.count += 1;
+
+        Some((self.count - 1) as u32)
+    }
+
+    /// Transform of a model added since last reset
+    pub fn get_transform(&self, index: u32) -> Option<&Matrix4<f32>> {
+        self.data[..self.count].get(index as usize).map(|model| &model.transform)
     }
 
     pub fn get_ssbo(&self, image_idx: usize) -> &AllocatedBufferMutRef {
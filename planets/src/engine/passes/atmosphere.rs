@@ -0,0 +1,514 @@
+use std::rc::Rc;
+
+use ash::vk;
+use ash::vk::Handle;
+use cgmath as cgm;
+use cgmath::prelude::*;
+
+use crate::engine::camera::CameraMutRef;
+use crate::engine::renderpass::{RenderPass, SCENE_COLOR_FORMAT};
+use crate::engine::scene::graph::SceneGraphMutRef;
+use crate::engine::viewport::ViewportMutRef;
+use crate::vulkan::debug;
+use crate::vulkan::debug::DebugResource;
+use crate::vulkan::device::{DeviceMutRef, MAX_FRAMES_IN_FLIGHT};
+use crate::vulkan::drawable::FullScreenDrawable;
+use crate::vulkan::img::image::{ImageAccess, ImageMutRef};
+use crate::vulkan::img::sampler::Sampler;
+use crate::vulkan::mem::StructBufferData;
+use crate::vulkan::pipeline::Pipeline;
+use crate::vulkan::resources::manager::{AttachmentSize, ResourceManagerMutRef};
+use crate::vulkan::shader::{Binding, ShaderManager};
+use crate::vulkan::uniform_buffer::UniformBufferObject;
+use crate::world::atmosphere::AtmosphereParams;
+
+// Pass specific bindings, must match atmosphere.frag
+const COLOR_BINDING: u32 = 0;
+const DEPTH_BINDING: u32 = 1;
+const TRANSMITTANCE_BINDING: u32 = 2;
+const SCATTERING_BINDING: u32 = 3;
+const ATMOSPHERE_BINDING: u32 = 4;
+
+#[repr(C)]
+struct AtmosphereUBOInterface {
+    // xyz: planet center relative to camera, w: 1 if there is an atmosphere to render
+    planet_center: cgm::Vector4<f32>,
+    // xyz: direction from planet center to the sun, w: sun intensity
+    sun_direction: cgm::Vector4<f32>,
+    // rgb: Rayleigh scattering coefficients, a: Mie scattering coefficient
+    scattering: cgm::Vector4<f32>,
+    // x: bottom radius, y: top radius, z: Mie phase asymmetry
+    radii: cgm::Vector4<f32>,
+}
+
+impl AtmosphereUBOInterface {
+    fn disabled() -> Self {
+        AtmosphereUBOInterface {
+            planet_center: cgm::Vector4::zero(),
+            sun_direction: cgm::Vector4::zero(),
+            scattering: cgm::Vector4::zero(),
+            radii: cgm::Vector4::zero(),
+        }
+    }
+
+    fn new(params: &AtmosphereParams, planet_center: cgm::Vector3<f32>, sun_direction: cgm::Vector3<f32>) -> Self {
+        let rayleigh = params.rayleigh_scattering.cast().unwrap_or(cgm::Vector3::zero());
+        AtmosphereUBOInterface {
+            planet_center: planet_center.extend(1.0),
+            sun_direction: sun_direction.extend(params.sun_intensity as f32),
+            scattering: rayleigh.extend(params.mie_scattering as f32),
+            radii: cgm::Vector4::new(params.bottom_radius as f32, params.top_radius as f32, params.mie_g as f32, 0.0),
+        }
+    }
+}
+
+/// Sky and aerial perspective of the planet atmosphere closest to the camera.
+/// Composites single scattering over the color of previous passes using G-buffer depth.
+pub struct AtmospherePass {
+    device: DeviceMutRef,
+    resource_manager: ResourceManagerMutRef,
+    viewport: ViewportMutRef,
+    camera: CameraMutRef,
+    scene: SceneGraphMutRef,
+    pipeline: Pipeline,
+    pub render_pass: vk::RenderPass,
+    drawable: FullScreenDrawable,
+    attachment_descrs: Vec<(&'static str, vk::AttachmentDescription)>,
+    output: ImageMutRef,
+    ubo: Vec<UniformBufferObject>,
+    // Depth can't be sampled with linear filter on most devices
+    depth_sampler: Sampler,
+    label: String,
+}
+
+impl AtmospherePass {
+    pub fn new(
+        device: &DeviceMutRef,
+        resource_manager: &ResourceManagerMutRef,
+        shader_manager: &mut ShaderManager,
+        viewport: &ViewportMutRef,
+        camera: &CameraMutRef,
+        scene: &SceneGraphMutRef,
+    ) -> AtmospherePass {
+        let attachments = AtmospherePass::create_attachment_descrs(SCENE_COLOR_FORMAT);
+
+        let attachment_descrs: Vec<vk::AttachmentDescription> =
+            attachments.iter().map(|(_, descr)| *descr).collect();
+        let mut attachment_refs = vec![];
+        for (i, attachment) in attachment_descrs.iter().enumerate() {
+            attachment_refs.push(vk::AttachmentReference {
+                attachment: i as u32,
+                layout: attachment.initial_layout,
+            });
+        }
+
+        let subpass_dependencies = [
+            vk::SubpassDependency {
+                src_subpass: vk::SUBPASS_EXTERNAL,
+                dst_subpass: 0,
+                src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                dst_stage_mask: vk::PipelineStageFlags::FRAGMENT_SHADER,
+                dst_access_mask: vk::AccessFlags::SHADER_READ,
+                ..Default::default()
+            },
+            vk::SubpassDependency {
+                src_subpass: 0,
+                dst_subpass: vk::SUBPASS_EXTERNAL,
+                src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                dst_stage_mask: vk::PipelineStageFlags::TRANSFER,
+                dst_access_mask: vk::AccessFlags::TRANSFER_READ,
+                ..Default::default()
+            }
+        ];
+
+        let subpass_descriptions = [vk::SubpassDescription {
+            pipeline_bind_point: vk::PipelineBindPoint::GRAPHICS,
+            color_attachment_count: attachment_refs.len() as u32,
+            p_color_attachments: attachment_refs.as_ptr(),
+            ..Default::default()
+        }];
+
+        let render_pass_create_info = vk::RenderPassCreateInfo {
+            attachment_count: attachment_descrs.len() as u32,
+            p_attachments: attachment_descrs.as_ptr(),
+            subpass_count: subpass_descriptions.len() as u32,
+            p_subpasses: subpass_descriptions.as_ptr(),
+            dependency_count: subpass_dependencies.len() as u32,
+            p_dependencies: subpass_dependencies.as_ptr(),
+            ..Default::default()
+        };
+
+        let render_pass = unsafe {
+            device
+                .borrow()
+                .logical_device
+                .create_render_pass(&render_pass_create_info, None)
+                .expect("Could not create render pass")
+        };
+
+        let sampler_binding = |binding| vk::DescriptorSetLayoutBinding {
+            binding,
+            descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            descriptor_count: 1,
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            ..Default::default()
+        };
+        let layout_bindings = vec![
+            sampler_binding(COLOR_BINDING),
+            sampler_binding(DEPTH_BINDING),
+            sampler_binding(TRANSMITTANCE_BINDING),
+            sampler_binding(SCATTERING_BINDING),
+            vk::DescriptorSetLayoutBinding {
+                binding: ATMOSPHERE_BINDING,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
+            vk::DescriptorSetLayoutBinding {
+                binding: Binding::Camera as u32,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
+        ];
+
+        let viewport_ref = viewport.borrow();
+        let pipeline = Pipeline::build(
+            device,
+            shader_manager,
+            render_pass,
+            "atmosphere",
+            viewport_ref.width,
+            viewport_ref.height,
+        )
+        .with_layout_bindings(layout_bindings)
+        .build();
+
+        let output = resource_manager.borrow_mut().attachment(
+            AttachmentSize::Relative(1.0),
+            SCENE_COLOR_FORMAT,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::SAMPLED,
+            "AtmosphereAttachment",
+        );
+
+        let ubo_data = AtmosphereUBOInterface::disabled();
+        let mut ubo = vec![];
+        for i in 0..MAX_FRAMES_IN_FLIGHT {
+            ubo.push(UniformBufferObject::new_with_data(
+                &mut resource_manager.borrow_mut(),
+                &StructBufferData::new(&ubo_data),
+                format!("Atmosphere{}", i).as_str(),
+            ));
+        }
+
+        let drawable = FullScreenDrawable::new(&mut resource_manager.borrow_mut());
+        let pass = AtmospherePass {
+            device: Rc::clone(device),
+            resource_manager: Rc::clone(resource_manager),
+            viewport: Rc::clone(viewport),
+            camera: Rc::clone(camera),
+            scene: Rc::clone(scene),
+            pipeline,
+            render_pass,
+            drawable,
+            attachment_descrs: attachments,
+            output,
+            ubo,
+            depth_sampler: Sampler::with_filter(device, vk::Filter::NEAREST),
+            label: String::from("Atmosphere"),
+        };
+        debug::Object::label(&device.borrow(), &pass);
+
+        pass
+    }
+
+    fn create_attachment_descrs(
+        format: vk::Format,
+    ) -> Vec<(&'static str, vk::AttachmentDescription)> {
+        let attachments = vec![(
+            "Atmosphere",
+            vk::AttachmentDescription {
+                format,
+                samples: vk::SampleCountFlags::TYPE_1,
+                load_op: vk::AttachmentLoadOp::DONT_CARE,
+                store_op: vk::AttachmentStoreOp::STORE,
+                initial_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                final_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                ..Default::default()
+            },
+        )];
+
+        attachments
+    }
+
+    /// Fill atmosphere UBO for this frame. Returns lookup tables of the rendered atmosphere if there is one.
+    fn update_atmosphere(&self) -> Option<(ImageMutRef, ImageMutRef)> {
+        let camera_position = self.camera.borrow().get_render_origin();
+        let scene = self.scene.borrow();
+        let sun_position = scene.get_light_manager().borrow().get_primary_light_position();
+
+        let (ubo_data, luts) = match (scene.get_nearest_atmosphere(&camera_position), sun_position) {
+            (Some((atmosphere, planet_center)), Some(sun_position)) => {
+                let planet_center: cgm::Vector3<f32> = (planet_center - camera_position).cast().unwrap_or(cgm::Vector3::zero());
+                let sun_direction = (sun_position - planet_center).normalize();
+
+                let mut atmosphere = atmosphere.borrow_mut();
+                let ubo_data = AtmosphereUBOInterface::new(atmosphere.get_params(), planet_center, sun_direction);
+                let luts = atmosphere.get_luts(&self.device, &mut self.resource_manager.borrow_mut());
+                (ubo_data, Some((Rc::clone(&luts.transmittance), Rc::clone(&luts.scattering))))
+            },
+            _ => (AtmosphereUBOInterface::disabled(), None),
+        };
+
+        let device = self.device.borrow();
+        self.ubo[device.get_image_idx()].buffer.borrow().update_data(&device, &StructBufferData::new(&ubo_data), 0);
+
+        luts
+    }
+
+    /// Transition sampled images and get their descriptors in binding order: color, depth, transmittance, scattering
+    fn access_sampled_images(
+        &self,
+        input_attachments: &[ImageMutRef],
+        transmittance_lut: &ImageMutRef,
+        scattering_lut: &ImageMutRef,
+    ) -> Result<Vec<vk::DescriptorImageInfo>, String> {
+        let device = self.device.borrow();
+        let sampled_access = ImageAccess {
+            new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            src_stage: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::TRANSFER,
+            src_access: vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::TRANSFER_WRITE,
+            dst_stage: vk::PipelineStageFlags::FRAGMENT_SHADER,
+            dst_access: vk::AccessFlags::SHADER_READ,
+        };
+        let sampled_info = |image: &ImageMutRef| -> Result<vk::DescriptorImageInfo, String> {
+            let mut image = image.borrow_mut();
+            let view = image.access_view(&device, &sampled_access, None)?;
+            Ok(vk::DescriptorImageInfo {
+                sampler: image.sampler.sampler,
+                image_view: view,
+                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            })
+        };
+
+        let depth_access = ImageAccess {
+            new_layout: vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+            src_stage: vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            src_access: vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            dst_stage: vk::PipelineStageFlags::FRAGMENT_SHADER,
+            dst_access: vk::AccessFlags::SHADER_READ,
+        };
+        let depth_view = input_attachments[1].borrow_mut().access_depth_view(&device, &depth_access)?;
+        let depth_info = vk::DescriptorImageInfo {
+            sampler: self.depth_sampler.sampler,
+            image_view: depth_view,
+            image_layout: vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+        };
+
+        Ok(vec![
+            sampled_info(&input_attachments[0])?,
+            depth_info,
+            sampled_info(transmittance_lut)?,
+            sampled_info(scattering_lut)?,
+        ])
+    }
+}
+
+impl DebugResource for AtmospherePass {
+    fn get_type(&self) -> vk::ObjectType {
+        vk::ObjectType::RENDER_PASS
+    }
+
+    fn get_handle(&self) -> u64 {
+        self.render_pass.as_raw()
+    }
+
+    fn get_label(&self) -> &String {
+        &self.label
+    }
+}
+
+impl RenderPass for AtmospherePass {
+    fn run(&mut self, cmd_buffer: vk::CommandBuffer, input_attachments: Vec<ImageMutRef>) -> Vec<ImageMutRef> {
+        // Without atmosphere nothing is composited and previous result is passed through
+        let (transmittance_lut, scattering_lut) = match self.update_atmosphere() {
+            Some(luts) => luts,
+            None => return input_attachments,
+        };
+
+        let image_infos = match self.access_sampled_images(&input_attachments, &transmittance_lut, &scattering_lut) {
+            Ok(image_infos) => image_infos,
+            Err(msg) => {
+                log::error!("{}", msg);
+                return input_attachments;
+            }
+        };
+
+        let device = self.device.borrow();
+        let mut _debug_region = debug::Region::new(&device, self.get_label().as_str());
+
+        let mut attachment_views = vec![];
+        {
+            let output_access = ImageAccess {
+                new_layout: self.attachment_descrs[0].1.initial_layout,
+                src_stage: vk::PipelineStageFlags::TRANSFER,
+                src_access: vk::AccessFlags::TRANSFER_READ,
+                dst_stage: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                dst_access: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            };
+            match self.output.borrow_mut().access_view(&device, &output_access, None) {
+                Ok(view) => attachment_views.push(view),
+                Err(msg) => log::error!("{}", msg),
+            }
+        }
+
+        let viewport = self.viewport.borrow();
+        let framebuffer = self.resource_manager.borrow_mut().framebuffer(
+            viewport.width,
+            viewport.height,
+            &attachment_views,
+            self.render_pass,
+            "Atmosphere"
+        );
+
+        match self.get_descriptor_set() {
+            Ok(descriptor_set) => {
+                let image_writes: Vec<vk::WriteDescriptorSet> = [COLOR_BINDING, DEPTH_BINDING, TRANSMITTANCE_BINDING, SCATTERING_BINDING]
+                    .iter()
+                    .zip(image_infos.iter())
+                    .map(|(binding, image_info)| vk::WriteDescriptorSet {
+                        dst_set: descriptor_set,
+                        dst_binding: *binding,
+                        descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                        descriptor_count: 1,
+                        p_image_info: image_info,
+                        ..Default::default()
+                    })
+                    .collect();
+                unsafe {
+                    device.logical_device.update_descriptor_sets(&image_writes, &[]);
+                }
+
+                let render_pass_begin_info = vk::RenderPassBeginInfo {
+                    render_pass: self.render_pass,
+                    framebuffer: framebuffer.borrow().framebuffer,
+                    render_area: vk::Rect2D {
+                        offset: vk::Offset2D { x: 0, y: 0 },
+                        extent: vk::Extent2D {
+                            width: viewport.width,
+                            height: viewport.height,
+                        },
+                    },
+                    ..Default::default()
+                };
+
+                unsafe {
+                    device.logical_device.cmd_begin_render_pass(
+                        cmd_buffer,
+                        &render_pass_begin_info,
+                        vk::SubpassContents::INLINE,
+                    );
+                    device.logical_device.cmd_bind_pipeline(
+                        cmd_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        self.pipeline.pipelines[0],
+                    );
+                    device.logical_device.cmd_bind_descriptor_sets(
+                        cmd_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        self.pipeline.layout,
+                        0,
+                        &[descriptor_set],
+                        &[],
+                    );
+
+                    self.drawable.draw(
+                        &device,
+                        cmd_buffer,
+                    );
+
+                    device.logical_device.cmd_end_render_pass(cmd_buffer);
+                }
+
+                self.output.borrow_mut().set_layout(self.attachment_descrs[0].1.final_layout);
+            },
+            Err(msg) => {
+                log::error!("Failed to execute Atmosphere render pass: {}", msg);
+                return input_attachments;
+            }
+        };
+
+        vec![Rc::clone(&self.output), Rc::clone(&input_attachments[1])]
+    }
+
+    fn get_pipeline(&self) -> &Pipeline {
+        &self.pipeline
+    }
+
+    fn get_descriptor_set(&self) -> Result<vk::DescriptorSet,&'static str> {
+        match self
+            .resource_manager
+            .borrow_mut()
+            .descriptor_set_manager
+            .allocate_descriptor_set(&self.pipeline.descriptor_set_layout) {
+            Ok(descriptor_set) => {
+                let device_ref = self.device.borrow();
+                let atmosphere_buffer_info = vk::DescriptorBufferInfo::builder()
+                    .buffer(self.ubo[device_ref.get_image_idx()].buffer.borrow().get_vk_buffer())
+                    .range(vk::WHOLE_SIZE)
+                    .build();
+
+                let camera_buffer_info = {
+                    let buffer = self.camera.borrow().get_ubo(device_ref.get_image_idx()).buffer.borrow().get_vk_buffer();
+                    vk::DescriptorBufferInfo::builder()
+                        .buffer(buffer)
+                        .range(vk::WHOLE_SIZE)
+                        .build()
+                };
+
+                let descr_set_writes = [
+                    vk::WriteDescriptorSet {
+                        dst_set: descriptor_set,
+                        dst_binding: ATMOSPHERE_BINDING,
+                        descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                        descriptor_count: 1,
+                        p_buffer_info: &atmosphere_buffer_info,
+                        ..Default::default()
+                    },
+                    vk::WriteDescriptorSet {
+                        dst_set: descriptor_set,
+                        dst_binding: Binding::Camera as u32,
+                        descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                        descriptor_count: 1,
+                        p_buffer_info: &camera_buffer_info,
+                        ..Default::default()
+                    },
+                ];
+
+                unsafe {
+                    device_ref
+                        .logical_device
+                        .update_descriptor_sets(&descr_set_writes, &[]);
+                }
+
+                Ok(descriptor_set)
+            },
+            Err(msg) => Err(msg)
+        }
+    }
+}
+
+impl Drop for AtmospherePass {
+    fn drop(&mut self) {
+        unsafe {
+            self.device
+                .borrow()
+                .logical_device
+                .destroy_render_pass(self.render_pass, None);
+        }
+    }
+}
@@ -2,31 +2,89 @@ use std::rc::Rc;
 
 use ash::vk;
 use ash::vk::Handle;
+use cgmath as cgm;
+use cgmath::prelude::*;
 
 use crate::engine::viewport::{ViewportMutRef};
 use crate::vulkan::debug;
-use crate::vulkan::device::{DeviceMutRef};
+use crate::vulkan::device::{DeviceMutRef, MAX_FRAMES_IN_FLIGHT};
 use crate::vulkan::drawable::FullScreenDrawable;
+use crate::vulkan::mem::StructBufferData;
 use crate::vulkan::pipeline::Pipeline;
 use crate::vulkan::resources::manager::{ResourceManagerMutRef};
 use crate::vulkan::shader::{Binding, ShaderManager};
+use crate::vulkan::uniform_buffer::UniformBufferObject;
 
 use crate::engine::camera::CameraMutRef;
-use crate::engine::renderpass::{RenderPass};
+use crate::engine::renderpass::{RenderPass, SCENE_COLOR_FORMAT};
 use crate::engine::gameloop::GameLoopMutRef;
 use crate::engine::passes::gbuffer::GEOMETRY_STENCIL_VAL;
+use crate::engine::scene::graph::SceneGraphMutRef;
+use crate::engine::skybox::Skybox;
 use crate::vulkan::debug::DebugResource;
 use crate::vulkan::img::image::{ImageAccess, ImageMutRef};
 
+// Stars pipeline specific binding, must match stars.vert
+const STARS_BINDING: u32 = 0;
+// Skybox pipeline specific bindings, must match skybox.frag
+const SKYBOX_BINDING: u32 = 0;
+const SKYBOX_UBO_BINDING: u32 = 1;
+// Point sprite size of the brightest stars in pixels
+const MAX_STAR_SIZE: f32 = 6.0;
+
+#[repr(C)]
+struct StarsUBOInterface {
+    // Rotation of the catalog frame to world frame
+    orientation: cgm::Matrix4<f32>,
+    // x: brightness scale, y: maximal point size
+    params: cgm::Vector4<f32>,
+}
+
+impl StarsUBOInterface {
+    fn new(orientation: &cgm::Matrix3<f64>) -> Self {
+        let orientation: cgm::Matrix3<f32> = orientation.cast().unwrap_or(cgm::Matrix3::from_scale(1.0));
+        StarsUBOInterface {
+            orientation: orientation.into(),
+            params: cgm::Vector4::new(1.0, MAX_STAR_SIZE, 0.0, 0.0),
+        }
+    }
+}
+
+#[repr(C)]
+struct SkyboxUBOInterface {
+    // Rotation of world directions to the skybox frame
+    orientation: cgm::Matrix4<f32>,
+    // x: intensity
+    params: cgm::Vector4<f32>,
+}
+
+impl SkyboxUBOInterface {
+    fn new(skybox: &Skybox) -> Self {
+        let orientation: cgm::Matrix3<f32> = skybox.get_orientation().cast().unwrap_or(cgm::Matrix3::from_scale(1.0));
+        SkyboxUBOInterface {
+            orientation: orientation.transpose().into(),
+            params: cgm::Vector4::new(skybox.get_intensity(), 0.0, 0.0, 0.0),
+        }
+    }
+}
+
+/// Space background with optional skybox, catalog stars drawn as point sprites and star light discs where no
+/// geometry was rendered
 pub struct BackgroundPass {
     device: DeviceMutRef,
     resource_manager: ResourceManagerMutRef,
     gameloop: GameLoopMutRef,
     viewport: ViewportMutRef,
     camera: CameraMutRef,
+    scene: SceneGraphMutRef,
     pipeline: Pipeline,
+    stars_pipeline: Pipeline,
+    skybox_pipeline: Pipeline,
+    sun_pipeline: Pipeline,
     pub render_pass: vk::RenderPass,
     drawable: FullScreenDrawable,
+    stars_ubo: Vec<UniformBufferObject>,
+    skybox_ubo: Vec<UniformBufferObject>,
     attachment_descrs: Vec<(&'static str, vk::AttachmentDescription)>,
     depth_attachment_descr: (&'static str, vk::AttachmentDescription),
     label: String,
@@ -40,8 +98,9 @@ impl BackgroundPass {
         shader_manager: &mut ShaderManager,
         viewport: &ViewportMutRef,
         camera: &CameraMutRef,
+        scene: &SceneGraphMutRef,
     ) -> BackgroundPass {
-        let attachments = BackgroundPass::create_attachment_descrs(vk::Format::R8G8B8A8_SRGB);
+        let attachments = BackgroundPass::create_attachment_descrs(SCENE_COLOR_FORMAT);
 
         let mut attachment_descrs: Vec<vk::AttachmentDescription> =
             attachments.iter().map(|(_, descr)| *descr).collect();
@@ -57,7 +116,8 @@ impl BackgroundPass {
             format: vk::Format::D32_SFLOAT_S8_UINT,
             samples: vk::SampleCountFlags::TYPE_1,
             load_op: vk::AttachmentLoadOp::LOAD,
-            store_op: vk::AttachmentStoreOp::DONT_CARE,
+            // Depth is still used by following passes
+            store_op: vk::AttachmentStoreOp::STORE,
             stencil_load_op: vk::AttachmentLoadOp::LOAD,
             stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
             initial_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
@@ -132,7 +192,7 @@ impl BackgroundPass {
                 binding: Binding::Camera as u32,
                 descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
                 descriptor_count: 1,
-                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT | vk::ShaderStageFlags::VERTEX,
                 ..Default::default()
             },
         ];
@@ -146,9 +206,11 @@ impl BackgroundPass {
             .compare_op(vk::CompareOp::NOT_EQUAL)
             .reference(GEOMETRY_STENCIL_VAL);
 
+        // Background quad is placed at far plane depth, so it only passes where no geometry was drawn
         let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::builder()
-            .depth_test_enable(false)
+            .depth_test_enable(true)
             .depth_write_enable(false)
+            .depth_compare_op(camera.borrow().get_depth_mode().get_compare_op())
             .stencil_test_enable(true)
             .front(*front_stencil_op_state);
 
@@ -165,6 +227,134 @@ impl BackgroundPass {
         .with_depth_stencil_info(*depth_stencil_info)
         .build();
 
+        let stars_layout_bindings = vec![
+            vk::DescriptorSetLayoutBinding {
+                binding: STARS_BINDING,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::VERTEX,
+                ..Default::default()
+            },
+            vk::DescriptorSetLayoutBinding {
+                binding: Binding::Camera as u32,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::VERTEX,
+                ..Default::default()
+            },
+        ];
+
+        // Overlapping star sprites add up
+        let stars_blend_attachment = vk::PipelineColorBlendAttachmentState {
+            blend_enable: vk::TRUE,
+            src_color_blend_factor: vk::BlendFactor::ONE,
+            dst_color_blend_factor: vk::BlendFactor::ONE,
+            color_blend_op: vk::BlendOp::ADD,
+            src_alpha_blend_factor: vk::BlendFactor::ZERO,
+            dst_alpha_blend_factor: vk::BlendFactor::ONE,
+            alpha_blend_op: vk::BlendOp::ADD,
+            color_write_mask: vk::ColorComponentFlags::RGBA,
+        };
+
+        let stars_pipeline = Pipeline::build(
+            device,
+            shader_manager,
+            render_pass,
+            "stars",
+            viewport_ref.width,
+            viewport_ref.height,
+        )
+        .with_layout_bindings(stars_layout_bindings)
+        .with_depth_stencil_info(*depth_stencil_info)
+        .with_topology(vk::PrimitiveTopology::POINT_LIST)
+        .with_cull_mode(vk::CullModeFlags::NONE)
+        .with_blend_attachment(stars_blend_attachment)
+        .build();
+
+        let skybox_layout_bindings = vec![
+            vk::DescriptorSetLayoutBinding {
+                binding: SKYBOX_BINDING,
+                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
+            vk::DescriptorSetLayoutBinding {
+                binding: SKYBOX_UBO_BINDING,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
+            vk::DescriptorSetLayoutBinding {
+                binding: Binding::Camera as u32,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT | vk::ShaderStageFlags::VERTEX,
+                ..Default::default()
+            },
+        ];
+
+        let skybox_pipeline = Pipeline::build(
+            device,
+            shader_manager,
+            render_pass,
+            "skybox",
+            viewport_ref.width,
+            viewport_ref.height,
+        )
+        .with_layout_bindings(skybox_layout_bindings)
+        .with_depth_stencil_info(*depth_stencil_info)
+        .build();
+
+        let sun_layout_bindings = vec![
+            vk::DescriptorSetLayoutBinding {
+                binding: Binding::Lights as u32,
+                descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
+            vk::DescriptorSetLayoutBinding {
+                binding: Binding::Camera as u32,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT | vk::ShaderStageFlags::VERTEX,
+                ..Default::default()
+            },
+        ];
+
+        // Star discs and glow are added on top of the sky
+        let sun_pipeline = Pipeline::build(
+            device,
+            shader_manager,
+            render_pass,
+            "sun",
+            viewport_ref.width,
+            viewport_ref.height,
+        )
+        .with_layout_bindings(sun_layout_bindings)
+        .with_depth_stencil_info(*depth_stencil_info)
+        .with_blend_attachment(stars_blend_attachment)
+        .build();
+
+        let mut stars_ubo = vec![];
+        let mut skybox_ubo = vec![];
+        {
+            let stars_data = StarsUBOInterface::new(&cgm::Matrix3::from_scale(1.0));
+            let stars_data = StructBufferData::new(&stars_data);
+            let skybox_data = SkyboxUBOInterface {
+                orientation: cgm::Matrix4::from_scale(1.0),
+                params: cgm::Vector4::new(1.0, 0.0, 0.0, 0.0),
+            };
+            let skybox_data = StructBufferData::new(&skybox_data);
+            let mut resource_manager = resource_manager.borrow_mut();
+            for i in 0..MAX_FRAMES_IN_FLIGHT {
+                stars_ubo.push(UniformBufferObject::new_with_data(&mut resource_manager, &stars_data, format!("Stars{}", i).as_str()));
+                skybox_ubo.push(UniformBufferObject::new_with_data(&mut resource_manager, &skybox_data, format!("Skybox{}", i).as_str()));
+            }
+        }
+
         let drawable = FullScreenDrawable::new(&mut resource_manager.borrow_mut());
         let pass = BackgroundPass {
             device: Rc::clone(device),
@@ -172,9 +362,15 @@ impl BackgroundPass {
             gameloop: Rc::clone(gameloop),
             viewport: Rc::clone(viewport),
             camera: Rc::clone(camera),
+            scene: Rc::clone(scene),
             pipeline,
+            stars_pipeline,
+            skybox_pipeline,
+            sun_pipeline,
             render_pass,
             drawable,
+            stars_ubo,
+            skybox_ubo,
             attachment_descrs: attachments,
             depth_attachment_descr: ("DepthStencilAttachment", depth_attachment),
             label: String::from("Background"),
@@ -202,6 +398,238 @@ impl BackgroundPass {
 
         attachments
     }
+
+    /// Transition skybox image for sampling. Must be called before the render pass starts.
+    fn access_skybox(&self) -> Option<(vk::DescriptorImageInfo, SkyboxUBOInterface)> {
+        let skybox = Rc::clone(self.scene.borrow().get_skybox()?);
+        let skybox = skybox.borrow();
+
+        let sampled_access = ImageAccess {
+            new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            src_stage: vk::PipelineStageFlags::TRANSFER | vk::PipelineStageFlags::FRAGMENT_SHADER,
+            src_access: vk::AccessFlags::TRANSFER_WRITE,
+            dst_stage: vk::PipelineStageFlags::FRAGMENT_SHADER,
+            dst_access: vk::AccessFlags::SHADER_READ,
+        };
+        let mut image = skybox.get_image().borrow_mut();
+        match image.access_view(&self.device.borrow(), &sampled_access, None) {
+            Ok(view) => Some((
+                vk::DescriptorImageInfo {
+                    sampler: image.sampler.sampler,
+                    image_view: view,
+                    image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                },
+                SkyboxUBOInterface::new(&skybox),
+            )),
+            Err(msg) => {
+                log::error!("{}", msg);
+                None
+            },
+        }
+    }
+
+    /// Bind skybox pipeline instead of the plain background one. Must be called inside the render pass.
+    fn bind_skybox(&self, cmd_buffer: vk::CommandBuffer, image_info: &vk::DescriptorImageInfo, ubo_data: &SkyboxUBOInterface) -> Result<(), &'static str> {
+        let device = self.device.borrow();
+        let image_idx = device.get_image_idx();
+        self.skybox_ubo[image_idx].buffer.borrow().update_data(&device, &StructBufferData::new(ubo_data), 0);
+
+        let descriptor_set = self
+            .resource_manager
+            .borrow_mut()
+            .descriptor_set_manager
+            .allocate_descriptor_set(&self.skybox_pipeline.descriptor_set_layout)?;
+        let skybox_buffer_info = vk::DescriptorBufferInfo::builder()
+            .buffer(self.skybox_ubo[image_idx].buffer.borrow().get_vk_buffer())
+            .range(vk::WHOLE_SIZE)
+            .build();
+        let camera_buffer_info = vk::DescriptorBufferInfo::builder()
+            .buffer(self.camera.borrow().get_ubo(image_idx).buffer.borrow().get_vk_buffer())
+            .range(vk::WHOLE_SIZE)
+            .build();
+        let descr_set_writes = [
+            vk::WriteDescriptorSet {
+                dst_set: descriptor_set,
+                dst_binding: SKYBOX_BINDING,
+                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: 1,
+                p_image_info: image_info,
+                ..Default::default()
+            },
+            vk::WriteDescriptorSet {
+                dst_set: descriptor_set,
+                dst_binding: SKYBOX_UBO_BINDING,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+                p_buffer_info: &skybox_buffer_info,
+                ..Default::default()
+            },
+            vk::WriteDescriptorSet {
+                dst_set: descriptor_set,
+                dst_binding: Binding::Camera as u32,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+                p_buffer_info: &camera_buffer_info,
+                ..Default::default()
+            },
+        ];
+
+        unsafe {
+            device.logical_device.update_descriptor_sets(&descr_set_writes, &[]);
+            device.logical_device.cmd_bind_pipeline(
+                cmd_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.skybox_pipeline.pipelines[0],
+            );
+            device.logical_device.cmd_bind_descriptor_sets(
+                cmd_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.skybox_pipeline.layout,
+                0,
+                &[descriptor_set],
+                &[],
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Draw stars with the stars pipeline. Must be called inside the render pass.
+    fn draw_stars(&self, cmd_buffer: vk::CommandBuffer) -> Result<(), &'static str> {
+        let starfield = match self.scene.borrow().get_starfield() {
+            Some(starfield) => Rc::clone(starfield),
+            None => return Ok(()),
+        };
+        let starfield = starfield.borrow();
+
+        let device = self.device.borrow();
+        let image_idx = device.get_image_idx();
+        let ubo_data = StarsUBOInterface::new(starfield.get_orientation());
+        self.stars_ubo[image_idx].buffer.borrow().update_data(&device, &StructBufferData::new(&ubo_data), 0);
+
+        let descriptor_set = self
+            .resource_manager
+            .borrow_mut()
+            .descriptor_set_manager
+            .allocate_descriptor_set(&self.stars_pipeline.descriptor_set_layout)?;
+        let stars_buffer_info = vk::DescriptorBufferInfo::builder()
+            .buffer(self.stars_ubo[image_idx].buffer.borrow().get_vk_buffer())
+            .range(vk::WHOLE_SIZE)
+            .build();
+        let camera_buffer_info = vk::DescriptorBufferInfo::builder()
+            .buffer(self.camera.borrow().get_ubo(image_idx).buffer.borrow().get_vk_buffer())
+            .range(vk::WHOLE_SIZE)
+            .build();
+        let descr_set_writes = [
+            vk::WriteDescriptorSet {
+                dst_set: descriptor_set,
+                dst_binding: STARS_BINDING,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+                p_buffer_info: &stars_buffer_info,
+                ..Default::default()
+            },
+            vk::WriteDescriptorSet {
+                dst_set: descriptor_set,
+                dst_binding: Binding::Camera as u32,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+                p_buffer_info: &camera_buffer_info,
+                ..Default::default()
+            },
+        ];
+
+        let geometry = starfield.get_geometry();
+        unsafe {
+            device.logical_device.update_descriptor_sets(&descr_set_writes, &[]);
+            device.logical_device.cmd_bind_pipeline(
+                cmd_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.stars_pipeline.pipelines[0],
+            );
+            device.logical_device.cmd_bind_descriptor_sets(
+                cmd_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.stars_pipeline.layout,
+                0,
+                &[descriptor_set],
+                &[],
+            );
+            device.logical_device.cmd_bind_vertex_buffers(
+                cmd_buffer,
+                0,
+                &[geometry.vertex_buffer.borrow().get_vk_buffer()],
+                &[0],
+            );
+            device.logical_device.cmd_bind_index_buffer(
+                cmd_buffer,
+                geometry.index_buffer.borrow().get_vk_buffer(),
+                0,
+                vk::IndexType::UINT32,
+            );
+            device.logical_device.cmd_draw_indexed(cmd_buffer, geometry.indices.len() as u32, 1, 0, 0, 0);
+        }
+
+        Ok(())
+    }
+
+    /// Draw discs and glow of star lights with the sun pipeline. Must be called inside the render pass.
+    fn draw_sun(&self, cmd_buffer: vk::CommandBuffer) -> Result<(), &'static str> {
+        let device = self.device.borrow();
+        let image_idx = device.get_image_idx();
+
+        let descriptor_set = self
+            .resource_manager
+            .borrow_mut()
+            .descriptor_set_manager
+            .allocate_descriptor_set(&self.sun_pipeline.descriptor_set_layout)?;
+        let lights_buffer_info = vk::DescriptorBufferInfo::builder()
+            .buffer(self.scene.borrow().get_light_manager().borrow().get_ssbo(image_idx).borrow().get_vk_buffer())
+            .range(vk::WHOLE_SIZE)
+            .build();
+        let camera_buffer_info = vk::DescriptorBufferInfo::builder()
+            .buffer(self.camera.borrow().get_ubo(image_idx).buffer.borrow().get_vk_buffer())
+            .range(vk::WHOLE_SIZE)
+            .build();
+        let descr_set_writes = [
+            vk::WriteDescriptorSet {
+                dst_set: descriptor_set,
+                dst_binding: Binding::Lights as u32,
+                descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+                descriptor_count: 1,
+                p_buffer_info: &lights_buffer_info,
+                ..Default::default()
+            },
+            vk::WriteDescriptorSet {
+                dst_set: descriptor_set,
+                dst_binding: Binding::Camera as u32,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+                p_buffer_info: &camera_buffer_info,
+                ..Default::default()
+            },
+        ];
+
+        unsafe {
+            device.logical_device.update_descriptor_sets(&descr_set_writes, &[]);
+            device.logical_device.cmd_bind_pipeline(
+                cmd_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.sun_pipeline.pipelines[0],
+            );
+            device.logical_device.cmd_bind_descriptor_sets(
+                cmd_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.sun_pipeline.layout,
+                0,
+                &[descriptor_set],
+                &[],
+            );
+        }
+        self.drawable.draw(&device, cmd_buffer);
+
+        Ok(())
+    }
 }
 
 impl DebugResource for BackgroundPass {
@@ -253,6 +681,8 @@ impl RenderPass for BackgroundPass {
             }
         }
 
+        let skybox = self.access_skybox();
+
         let viewport = self.viewport.borrow();
         let framebuffer = self.resource_manager.borrow_mut().framebuffer(
             viewport.width,
@@ -283,25 +713,51 @@ impl RenderPass for BackgroundPass {
                         &render_pass_begin_info,
                         vk::SubpassContents::INLINE,
                     );
-                    device.logical_device.cmd_bind_pipeline(
-                        cmd_buffer,
-                        vk::PipelineBindPoint::GRAPHICS,
-                        self.pipeline.pipelines[0],
-                    );
-                    device.logical_device.cmd_bind_descriptor_sets(
-                        cmd_buffer,
-                        vk::PipelineBindPoint::GRAPHICS,
-                        self.pipeline.layout,
-                        0,
-                        &[descriptor_set],
-                        &[],
-                    );
+                }
+
+                let skybox_bound = match &skybox {
+                    Some((image_info, ubo_data)) => match self.bind_skybox(cmd_buffer, image_info, ubo_data) {
+                        Ok(()) => true,
+                        Err(msg) => {
+                            log::error!("Failed to draw skybox: {}", msg);
+                            false
+                        },
+                    },
+                    None => false,
+                };
+
+                unsafe {
+                    if !skybox_bound {
+                        device.logical_device.cmd_bind_pipeline(
+                            cmd_buffer,
+                            vk::PipelineBindPoint::GRAPHICS,
+                            self.pipeline.pipelines[0],
+                        );
+                        device.logical_device.cmd_bind_descriptor_sets(
+                            cmd_buffer,
+                            vk::PipelineBindPoint::GRAPHICS,
+                            self.pipeline.layout,
+                            0,
+                            &[descriptor_set],
+                            &[],
+                        );
+                    }
 
                     self.drawable.draw(
                         &device,
                         cmd_buffer,
                     );
+                }
 
+                if let Err(msg) = self.draw_stars(cmd_buffer) {
+                    log::error!("Failed to draw stars: {}", msg);
+                }
+
+                if let Err(msg) = self.draw_sun(cmd_buffer) {
+                    log::error!("Failed to draw sun: {}", msg);
+                }
+
+                unsafe {
                     device.logical_device.cmd_end_render_pass(cmd_buffer);
                 }
 
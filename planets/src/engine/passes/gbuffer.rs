@@ -1,6 +1,6 @@
 use std::rc::Rc;
-use crate::engine::camera::CameraMutRef;
-use crate::engine::renderpass::RenderPass;
+use crate::engine::camera::{CameraMutRef, DepthMode};
+use crate::engine::renderpass::{RenderPass, SCENE_COLOR_FORMAT};
 use crate::engine::viewport::{Viewport, ViewportMutRef};
 use crate::vulkan::debug;
 use crate::vulkan::device::{DeviceMutRef};
@@ -14,9 +14,14 @@ use crate::engine::gameloop::GameLoopMutRef;
 use crate::engine::scene::graph::SceneGraphMutRef;
 use crate::vulkan::debug::DebugResource;
 use crate::vulkan::img::image::{ImageAccess, ImageMutRef};
+use crate::world::terrain::surface::PlanetSurface;
 
 pub const GEOMETRY_STENCIL_VAL: u32 = 1;
 
+// Bindings of the terrain pipeline for planet surface maps
+const ALBEDO_BINDING: u32 = 0;
+const SURFACE_BINDING: u32 = 1;
+
 pub struct GBufferPass {
     device: DeviceMutRef,
     resource_manager: ResourceManagerMutRef,
@@ -24,12 +29,15 @@ pub struct GBufferPass {
     viewport: ViewportMutRef,
     camera: CameraMutRef,
     pipeline: Pipeline,
+    // Draws chunks of planets with surface maps
+    terrain_pipeline: Pipeline,
     pub render_pass: vk::RenderPass,
     scene: SceneGraphMutRef,
     color_attachment_imgs: Vec<ImageMutRef>,
     depth_attachment_img: ImageMutRef,
     attachment_descrs: Vec<(&'static str, vk::AttachmentDescription)>,
     depth_attachment_descr: (&'static str, vk::AttachmentDescription),
+    depth_mode: DepthMode,
     label: String,
 }
 
@@ -77,8 +85,8 @@ impl GBufferPass {
         let color_attachment_imgs = vec![
             resource_manager.borrow_mut().attachment(
                 AttachmentSize::Relative(1.0),
-                vk::Format::R8G8B8A8_SRGB,
-                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+                SCENE_COLOR_FORMAT,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::SAMPLED,
                 "ColorAttachment",
             )
         ];
@@ -136,7 +144,25 @@ impl GBufferPass {
                 .expect("Could not create render pass")
         };
 
-        let pipeline = GBufferPass::create_pipeline(device, shader_manager, &viewport.borrow(), render_pass);
+        let depth_mode = camera.borrow().get_depth_mode();
+        let pipeline = GBufferPass::create_pipeline(device, shader_manager, &viewport.borrow(), render_pass, depth_mode, "gbuffer", vec![]);
+        let surface_bindings = vec![
+            vk::DescriptorSetLayoutBinding {
+                binding: ALBEDO_BINDING,
+                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
+            vk::DescriptorSetLayoutBinding {
+                binding: SURFACE_BINDING,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
+        ];
+        let terrain_pipeline = GBufferPass::create_pipeline(device, shader_manager, &viewport.borrow(), render_pass, depth_mode, "terrain", surface_bindings);
 
         let pass = GBufferPass {
             device: Rc::clone(device),
@@ -145,11 +171,13 @@ impl GBufferPass {
             viewport: Rc::clone(viewport),
             camera: Rc::clone(camera),
             pipeline,
+            terrain_pipeline,
             render_pass,
             color_attachment_imgs,
             depth_attachment_img,
             attachment_descrs: attachments,
             depth_attachment_descr: ("DepthStencilAttachment", depth_attachment),
+            depth_mode,
             scene: Rc::clone(scene),
             label: String::from("GBuffer"),
         };
@@ -164,7 +192,7 @@ impl GBufferPass {
             (
                 "GBuffer::Color",
                 vk::AttachmentDescription {
-                    format: vk::Format::R8G8B8A8_SRGB,
+                    format: SCENE_COLOR_FORMAT,
                     samples: vk::SampleCountFlags::TYPE_1,
                     load_op: vk::AttachmentLoadOp::CLEAR,
                     store_op: vk::AttachmentStoreOp::STORE,
@@ -175,8 +203,16 @@ impl GBufferPass {
         ]
     }
 
-    fn create_pipeline(device: &DeviceMutRef, shader_manager: &mut ShaderManager, viewport: &Viewport, render_pass: vk::RenderPass) -> Pipeline {
-        let layout_bindings = vec![
+    fn create_pipeline(
+        device: &DeviceMutRef,
+        shader_manager: &mut ShaderManager,
+        viewport: &Viewport,
+        render_pass: vk::RenderPass,
+        depth_mode: DepthMode,
+        name: &str,
+        extra_bindings: Vec<vk::DescriptorSetLayoutBinding>,
+    ) -> Pipeline {
+        let mut layout_bindings = vec![
             vk::DescriptorSetLayoutBinding {
                 binding: Binding::Models as u32,
                 descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
@@ -206,6 +242,7 @@ impl GBufferPass {
                 ..Default::default()
             },
         ];
+        layout_bindings.extend(extra_bindings);
 
         let front_stencil_op_state = vk::StencilOpState::builder()
             .fail_op(vk::StencilOp::KEEP)
@@ -231,13 +268,13 @@ impl GBufferPass {
             .stencil_test_enable(true)
             .front(*front_stencil_op_state)
             .back(*back_stencil_op_state)
-            .depth_compare_op(vk::CompareOp::GREATER_OR_EQUAL);
+            .depth_compare_op(depth_mode.get_compare_op());
 
         Pipeline::build(
             device,
             shader_manager,
             render_pass,
-            "gbuffer",
+            name,
             viewport.width,
             viewport.height,
         )
@@ -252,14 +289,47 @@ impl RenderPass for GBufferPass {
         let device = self.device.borrow();
         let mut _debug_region = debug::Region::new(&device, self.get_label().as_str());
 
+        // Surface maps have to be transitioned before the render pass starts
+        let albedo_access = ImageAccess {
+            new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            src_stage: vk::PipelineStageFlags::TRANSFER,
+            src_access: vk::AccessFlags::TRANSFER_WRITE,
+            dst_stage: vk::PipelineStageFlags::FRAGMENT_SHADER,
+            dst_access: vk::AccessFlags::SHADER_READ,
+        };
+        let mut surface_infos = vec![];
+        for terrain in self.scene.borrow().get_terrains() {
+            let surface = match terrain.borrow().get_surface() {
+                Some(surface) => Rc::clone(surface),
+                None => continue,
+            };
+            let image_info = {
+                let s = surface.borrow();
+                let mut albedo = s.get_albedo().borrow_mut();
+                match albedo.access_view(&device, &albedo_access, None) {
+                    Ok(view) => vk::DescriptorImageInfo {
+                        sampler: albedo.sampler.sampler,
+                        image_view: view,
+                        image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    },
+                    Err(msg) => {
+                        log::error!("{}", msg);
+                        continue;
+                    },
+                }
+            };
+            surface_infos.push((Rc::clone(terrain), surface, image_info));
+        }
+
         let mut attachment_views = vec![];
 
         {
             let mut color_attachment = self.color_attachment_imgs[0].borrow_mut();
             let color_access = ImageAccess {
                 new_layout: self.attachment_descrs[0].1.initial_layout,
-                src_stage: vk::PipelineStageFlags::TRANSFER,
-                src_access: vk::AccessFlags::TRANSFER_READ,
+                // Previous frame blitted or sampled the color
+                src_stage: vk::PipelineStageFlags::TRANSFER | vk::PipelineStageFlags::FRAGMENT_SHADER,
+                src_access: vk::AccessFlags::TRANSFER_READ | vk::AccessFlags::SHADER_READ,
                 dst_stage: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
                 dst_access: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
             };
@@ -271,9 +341,9 @@ impl RenderPass for GBufferPass {
             let mut depth_attachment = self.depth_attachment_img.borrow_mut();
             let depth_access = ImageAccess {
                 new_layout: self.depth_attachment_descr.1.initial_layout,
-                src_stage: vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+                src_stage: vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::FRAGMENT_SHADER,
                 dst_stage: vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
-                src_access: vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ,
+                src_access: vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags::SHADER_READ,
                 dst_access: vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
             };
             match depth_attachment.access_view(&device, &depth_access, None) {
@@ -300,7 +370,7 @@ impl RenderPass for GBufferPass {
                 },
                 vk::ClearValue {
                     depth_stencil: vk::ClearDepthStencilValue {
-                        depth: 0.0,
+                        depth: self.depth_mode.get_far_depth(),
                         stencil: 0,
                     }
                 }];
@@ -340,6 +410,34 @@ impl RenderPass for GBufferPass {
 
             self.scene.borrow().get_draw_list().borrow().write_draw_commands(DrawType::Opaque, &cmd_buffer);
 
+            if !surface_infos.is_empty() {
+                unsafe {
+                    device.logical_device.cmd_bind_pipeline(
+                        cmd_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        self.terrain_pipeline.pipelines[0],
+                    );
+                }
+                for (terrain, surface, albedo_info) in &surface_infos {
+                    match self.get_surface_descriptor_set(&surface.borrow(), albedo_info) {
+                        Ok(surface_set) => {
+                            unsafe {
+                                device.logical_device.cmd_bind_descriptor_sets(
+                                    cmd_buffer,
+                                    vk::PipelineBindPoint::GRAPHICS,
+                                    self.terrain_pipeline.layout,
+                                    0,
+                                    &[surface_set],
+                                    &[],
+                                );
+                            }
+                            terrain.borrow().write_draw_commands(&device, &cmd_buffer);
+                        },
+                        Err(msg) => log::error!("Failed to draw planet surface: {}", msg),
+                    }
+                }
+            }
+
             unsafe {
                 device.logical_device.cmd_end_render_pass(cmd_buffer);
             }
@@ -356,89 +454,131 @@ impl RenderPass for GBufferPass {
     }
 
     fn get_descriptor_set(&self) -> Result<vk::DescriptorSet,&'static str> {
-        match self
+        self.allocate_descriptor_set(&self.pipeline.descriptor_set_layout)
+    }
+}
+
+impl GBufferPass {
+    /// Descriptor set of the given layout with the resources shared by both pipelines written
+    fn allocate_descriptor_set(&self, layout: &vk::DescriptorSetLayout) -> Result<vk::DescriptorSet,&'static str> {
+        let descriptor_set = self
             .resource_manager
             .borrow_mut()
             .descriptor_set_manager
-            .allocate_descriptor_set(&self.pipeline.descriptor_set_layout) {
-            Ok(descriptor_set) => {
-                let device_ref = self.device.borrow();
-                let timer_buffer_info = {
-                    let buffer = self.gameloop.borrow().get_timer_ubo(device_ref.get_image_idx()).buffer.borrow().get_vk_buffer();
-                    vk::DescriptorBufferInfo::builder()
-                        .buffer(buffer)
-                        .range(vk::WHOLE_SIZE)
-                        .build()
-                };
-
-                let camera_buffer_info = {
-                    let buffer = self.camera.borrow().get_ubo(device_ref.get_image_idx()).buffer.borrow().get_vk_buffer();
-                    vk::DescriptorBufferInfo::builder()
-                        .buffer(buffer)
-                        .range(vk::WHOLE_SIZE)
-                        .build()
-                };
-                let scene = self.scene.borrow();
-                let models_buffer_info = {
-                    let buffer = scene.get_model_data_ssbo(device_ref.get_image_idx()).borrow().get_vk_buffer();
-                    vk::DescriptorBufferInfo::builder()
-                        .buffer(buffer)
-                        .range(vk::WHOLE_SIZE)
-                        .build()
-                };
-                let lights_buffer_info = {
-                    let buffer = scene.get_light_manager().borrow().get_ssbo(device_ref.get_image_idx()).borrow().get_vk_buffer();
-                    vk::DescriptorBufferInfo::builder()
-                        .buffer(buffer)
-                        .range(vk::WHOLE_SIZE)
-                        .build()
-                };
-
-                let descr_set_writes = [
-                    vk::WriteDescriptorSet {
-                        dst_set: descriptor_set,
-                        dst_binding: Binding::Timer as u32,
-                        descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
-                        descriptor_count: 1,
-                        p_buffer_info: &timer_buffer_info,
-                        ..Default::default()
-                    },
-                    vk::WriteDescriptorSet {
-                        dst_set: descriptor_set,
-                        dst_binding: Binding::Camera as u32,
-                        descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
-                        descriptor_count: 1,
-                        p_buffer_info: &camera_buffer_info,
-                        ..Default::default()
-                    },
-                    vk::WriteDescriptorSet {
-                        dst_set: descriptor_set,
-                        dst_binding: Binding::Models as u32,
-                        descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
-                        descriptor_count: 1,
-                        p_buffer_info: &models_buffer_info,
-                        ..Default::default()
-                    },
-                    vk::WriteDescriptorSet {
-                        dst_set: descriptor_set,
-                        dst_binding: Binding::Lights as u32,
-                        descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
-                        descriptor_count: 1,
-                        p_buffer_info: &lights_buffer_info,
-                        ..Default::default()
-                    },
-                ];
+            .allocate_descriptor_set(layout)?;
+
+        let device_ref = self.device.borrow();
+        let timer_buffer_info = {
+            let buffer = self.gameloop.borrow().get_timer_ubo(device_ref.get_image_idx()).buffer.borrow().get_vk_buffer();
+            vk::DescriptorBufferInfo::builder()
+                .buffer(buffer)
+                .range(vk::WHOLE_SIZE)
+                .build()
+        };
 
-                unsafe {
-                    device_ref
-                        .logical_device
-                        .update_descriptor_sets(&descr_set_writes, &[]);
-                }
+        let camera_buffer_info = {
+            let buffer = self.camera.borrow().get_ubo(device_ref.get_image_idx()).buffer.borrow().get_vk_buffer();
+            vk::DescriptorBufferInfo::builder()
+                .buffer(buffer)
+                .range(vk::WHOLE_SIZE)
+                .build()
+        };
+        let scene = self.scene.borrow();
+        let models_buffer_info = {
+            let buffer = scene.get_model_data_ssbo(device_ref.get_image_idx()).borrow().get_vk_buffer();
+            vk::DescriptorBufferInfo::builder()
+                .buffer(buffer)
+                .range(vk::WHOLE_SIZE)
+                .build()
+        };
+        let lights_buffer_info = {
+            let buffer = scene.get_light_manager().borrow().get_ssbo(device_ref.get_image_idx()).borrow().get_vk_buffer();
+            vk::DescriptorBufferInfo::builder()
+                .buffer(buffer)
+                .range(vk::WHOLE_SIZE)
+                .build()
+        };
+
+        let descr_set_writes = [
+            vk::WriteDescriptorSet {
+                dst_set: descriptor_set,
+                dst_binding: Binding::Timer as u32,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+                p_buffer_info: &timer_buffer_info,
+                ..Default::default()
+            },
+            vk::WriteDescriptorSet {
+                dst_set: descriptor_set,
+                dst_binding: Binding::Camera as u32,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+                p_buffer_info: &camera_buffer_info,
+                ..Default::default()
+            },
+            vk::WriteDescriptorSet {
+                dst_set: descriptor_set,
+                dst_binding: Binding::Models as u32,
+                descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+                descriptor_count: 1,
+                p_buffer_info: &models_buffer_info,
+                ..Default::default()
+            },
+            vk::WriteDescriptorSet {
+                dst_set: descriptor_set,
+                dst_binding: Binding::Lights as u32,
+                descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+                descriptor_count: 1,
+                p_buffer_info: &lights_buffer_info,
+                ..Default::default()
+            },
+        ];
 
-                Ok(descriptor_set)
+        unsafe {
+            device_ref
+                .logical_device
+                .update_descriptor_sets(&descr_set_writes, &[]);
+        }
+
+        Ok(descriptor_set)
+    }
+
+    /// Terrain pipeline descriptor set with surface maps of the given planet
+    fn get_surface_descriptor_set(&self, surface: &PlanetSurface, albedo_info: &vk::DescriptorImageInfo) -> Result<vk::DescriptorSet,&'static str> {
+        let descriptor_set = self.allocate_descriptor_set(&self.terrain_pipeline.descriptor_set_layout)?;
+
+        let device = self.device.borrow();
+        let surface_buffer_info = vk::DescriptorBufferInfo::builder()
+            .buffer(surface.get_ubo(device.get_image_idx()).buffer.borrow().get_vk_buffer())
+            .range(vk::WHOLE_SIZE)
+            .build();
+
+        let descr_set_writes = [
+            vk::WriteDescriptorSet {
+                dst_set: descriptor_set,
+                dst_binding: ALBEDO_BINDING,
+                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: 1,
+                p_image_info: albedo_info,
+                ..Default::default()
             },
-            Err(msg) => Err(msg)
+            vk::WriteDescriptorSet {
+                dst_set: descriptor_set,
+                dst_binding: SURFACE_BINDING,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+                p_buffer_info: &surface_buffer_info,
+                ..Default::default()
+            },
+        ];
+
+        unsafe {
+            device
+                .logical_device
+                .update_descriptor_sets(&descr_set_writes, &[]);
         }
+
+        Ok(descriptor_set)
     }
 }
 
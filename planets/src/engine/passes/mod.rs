@@ -1,3 +1,8 @@
+pub mod atmosphere;
 pub mod background;
 pub mod gbuffer;
+pub mod impostors;
+pub mod orbits;
+pub mod output;
+pub mod rings;
 pub mod rtao;
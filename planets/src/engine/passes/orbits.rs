@@ -0,0 +1,427 @@
+use std::rc::Rc;
+
+use ash::vk;
+use ash::vk::Handle;
+
+use crate::engine::camera::CameraMutRef;
+use crate::engine::renderpass::{RenderPass, SCENE_COLOR_FORMAT};
+use crate::engine::scene::graph::SceneGraphMutRef;
+use crate::engine::viewport::ViewportMutRef;
+use crate::vulkan::debug;
+use crate::vulkan::debug::DebugResource;
+use crate::vulkan::device::DeviceMutRef;
+use crate::vulkan::img::image::{ImageAccess, ImageMutRef};
+use crate::vulkan::pipeline::Pipeline;
+use crate::vulkan::resources::manager::ResourceManagerMutRef;
+use crate::vulkan::shader::{Binding, ShaderManager};
+use crate::world::orbit::OrbitLines;
+
+// Pass specific binding, must match orbit.vert and orbit.frag
+const ORBIT_BINDING: u32 = 0;
+
+/// Orbit lines over the final scene color. Lines are tested against scene depth, so planets hide them.
+pub struct OrbitsPass {
+    device: DeviceMutRef,
+    resource_manager: ResourceManagerMutRef,
+    viewport: ViewportMutRef,
+    camera: CameraMutRef,
+    scene: SceneGraphMutRef,
+    pipeline: Pipeline,
+    pub render_pass: vk::RenderPass,
+    attachment_descrs: Vec<(&'static str, vk::AttachmentDescription)>,
+    depth_attachment_descr: (&'static str, vk::AttachmentDescription),
+    label: String,
+}
+
+impl OrbitsPass {
+    pub fn new(
+        device: &DeviceMutRef,
+        resource_manager: &ResourceManagerMutRef,
+        shader_manager: &mut ShaderManager,
+        viewport: &ViewportMutRef,
+        camera: &CameraMutRef,
+        scene: &SceneGraphMutRef,
+    ) -> OrbitsPass {
+        let attachments = OrbitsPass::create_attachment_descrs(SCENE_COLOR_FORMAT);
+
+        let mut attachment_descrs: Vec<vk::AttachmentDescription> =
+            attachments.iter().map(|(_, descr)| *descr).collect();
+        let mut attachment_refs = vec![];
+        for (i, attachment) in attachment_descrs.iter().enumerate() {
+            attachment_refs.push(vk::AttachmentReference {
+                attachment: i as u32,
+                layout: attachment.initial_layout,
+            });
+        }
+
+        let depth_attachment = vk::AttachmentDescription {
+            format: vk::Format::D32_SFLOAT_S8_UINT,
+            samples: vk::SampleCountFlags::TYPE_1,
+            load_op: vk::AttachmentLoadOp::LOAD,
+            store_op: vk::AttachmentStoreOp::STORE,
+            stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+            stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+            initial_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            final_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            ..Default::default()
+        };
+        let depth_attachment_ref = [
+            vk::AttachmentReference {
+                attachment: attachment_refs.len() as u32,
+                layout: depth_attachment.initial_layout
+            }
+        ];
+
+        attachment_descrs.push(depth_attachment);
+
+        let subpass_dependencies = [
+            vk::SubpassDependency {
+                src_subpass: vk::SUBPASS_EXTERNAL,
+                dst_subpass: 0,
+                src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+                dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ,
+                ..Default::default()
+            },
+            vk::SubpassDependency {
+                src_subpass: 0,
+                dst_subpass: vk::SUBPASS_EXTERNAL,
+                src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                dst_stage_mask: vk::PipelineStageFlags::TRANSFER | vk::PipelineStageFlags::FRAGMENT_SHADER,
+                dst_access_mask: vk::AccessFlags::TRANSFER_READ | vk::AccessFlags::SHADER_READ,
+                ..Default::default()
+            }
+        ];
+
+        let subpass_descriptions = [vk::SubpassDescription {
+            pipeline_bind_point: vk::PipelineBindPoint::GRAPHICS,
+            color_attachment_count: attachment_refs.len() as u32,
+            p_color_attachments: attachment_refs.as_ptr(),
+            p_depth_stencil_attachment: depth_attachment_ref.as_ptr(),
+            ..Default::default()
+        }];
+
+        let render_pass_create_info = vk::RenderPassCreateInfo {
+            attachment_count: attachment_descrs.len() as u32,
+            p_attachments: attachment_descrs.as_ptr(),
+            subpass_count: subpass_descriptions.len() as u32,
+            p_subpasses: subpass_descriptions.as_ptr(),
+            dependency_count: subpass_dependencies.len() as u32,
+            p_dependencies: subpass_dependencies.as_ptr(),
+            ..Default::default()
+        };
+
+        let render_pass = unsafe {
+            device
+                .borrow()
+                .logical_device
+                .create_render_pass(&render_pass_create_info, None)
+                .expect("Could not create render pass")
+        };
+
+        let layout_bindings = vec![
+            vk::DescriptorSetLayoutBinding {
+                binding: ORBIT_BINDING,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT | vk::ShaderStageFlags::VERTEX,
+                ..Default::default()
+            },
+            vk::DescriptorSetLayoutBinding {
+                binding: Binding::Camera as u32,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::VERTEX,
+                ..Default::default()
+            },
+        ];
+
+        let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(true)
+            .depth_write_enable(false)
+            .depth_compare_op(camera.borrow().get_depth_mode().get_compare_op())
+            .stencil_test_enable(false);
+
+        let blend_attachment = vk::PipelineColorBlendAttachmentState {
+            blend_enable: vk::TRUE,
+            src_color_blend_factor: vk::BlendFactor::SRC_ALPHA,
+            dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            color_blend_op: vk::BlendOp::ADD,
+            src_alpha_blend_factor: vk::BlendFactor::ZERO,
+            dst_alpha_blend_factor: vk::BlendFactor::ONE,
+            alpha_blend_op: vk::BlendOp::ADD,
+            color_write_mask: vk::ColorComponentFlags::RGBA,
+        };
+
+        let viewport_ref = viewport.borrow();
+        let pipeline = Pipeline::build(
+            device,
+            shader_manager,
+            render_pass,
+            "orbit",
+            viewport_ref.width,
+            viewport_ref.height,
+        )
+        .with_layout_bindings(layout_bindings)
+        .with_depth_stencil_info(*depth_stencil_info)
+        .with_topology(vk::PrimitiveTopology::LINE_STRIP)
+        .with_cull_mode(vk::CullModeFlags::NONE)
+        .with_blend_attachment(blend_attachment)
+        .build();
+
+        let pass = OrbitsPass {
+            device: Rc::clone(device),
+            resource_manager: Rc::clone(resource_manager),
+            viewport: Rc::clone(viewport),
+            camera: Rc::clone(camera),
+            scene: Rc::clone(scene),
+            pipeline,
+            render_pass,
+            attachment_descrs: attachments,
+            depth_attachment_descr: ("DepthStencilAttachment", depth_attachment),
+            label: String::from("Orbits"),
+        };
+        debug::Object::label(&device.borrow(), &pass);
+
+        pass
+    }
+
+    fn create_attachment_descrs(
+        format: vk::Format,
+    ) -> Vec<(&'static str, vk::AttachmentDescription)> {
+        let attachments = vec![(
+            "Orbits",
+            vk::AttachmentDescription {
+                format,
+                samples: vk::SampleCountFlags::TYPE_1,
+                load_op: vk::AttachmentLoadOp::LOAD,
+                store_op: vk::AttachmentStoreOp::STORE,
+                initial_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                final_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                ..Default::default()
+            },
+        )];
+
+        attachments
+    }
+
+    /// Descriptor set of the shared pass resources with parameters of the given orbit
+    fn get_orbit_descriptor_set(&self, orbit_lines: &OrbitLines) -> Result<vk::DescriptorSet,&'static str> {
+        let descriptor_set = self.get_descriptor_set()?;
+
+        let image_idx = self.device.borrow().get_image_idx();
+        let orbit_buffer_info = vk::DescriptorBufferInfo::builder()
+            .buffer(orbit_lines.get_ubo(image_idx).buffer.borrow().get_vk_buffer())
+            .range(vk::WHOLE_SIZE)
+            .build();
+
+        let descr_set_writes = [
+            vk::WriteDescriptorSet {
+                dst_set: descriptor_set,
+                dst_binding: ORBIT_BINDING,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+                p_buffer_info: &orbit_buffer_info,
+                ..Default::default()
+            },
+        ];
+
+        unsafe {
+            self.device
+                .borrow()
+                .logical_device
+                .update_descriptor_sets(&descr_set_writes, &[]);
+        }
+
+        Ok(descriptor_set)
+    }
+}
+
+impl DebugResource for OrbitsPass {
+    fn get_type(&self) -> vk::ObjectType {
+        vk::ObjectType::RENDER_PASS
+    }
+
+    fn get_handle(&self) -> u64 {
+        self.render_pass.as_raw()
+    }
+
+    fn get_label(&self) -> &String {
+        &self.label
+    }
+}
+
+impl RenderPass for OrbitsPass {
+    fn run(&mut self, cmd_buffer: vk::CommandBuffer, input_attachments: Vec<ImageMutRef>) -> Vec<ImageMutRef> {
+        let orbits = self.scene.borrow().get_orbit_lines().clone();
+        if orbits.is_empty() {
+            return input_attachments;
+        }
+
+        let device = self.device.borrow();
+        let mut _debug_region = debug::Region::new(&device, self.get_label().as_str());
+
+        let mut attachment_views = vec![];
+        {
+            let mut color_attachment = input_attachments[0].borrow_mut();
+            let color_access = ImageAccess {
+                new_layout: self.attachment_descrs[0].1.initial_layout,
+                src_stage: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                src_access: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                dst_stage: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                dst_access: vk::AccessFlags::COLOR_ATTACHMENT_READ,
+            };
+            match color_attachment.access_view(&device, &color_access, None) {
+                Ok(view) => attachment_views.push(view),
+                Err(msg) => log::error!("{}", msg),
+            }
+
+            let mut depth_attachment = input_attachments[1].borrow_mut();
+            // Depth was sampled by the atmosphere pass last
+            let depth_access = ImageAccess {
+                new_layout: self.depth_attachment_descr.1.initial_layout,
+                src_stage: vk::PipelineStageFlags::LATE_FRAGMENT_TESTS | vk::PipelineStageFlags::FRAGMENT_SHADER,
+                dst_stage: vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+                src_access: vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                dst_access: vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ,
+            };
+            match depth_attachment.access_view(&device, &depth_access, None) {
+                Ok(view) => attachment_views.push(view),
+                Err(msg) => log::error!("{}", msg),
+            }
+        }
+
+        let viewport = self.viewport.borrow();
+        let framebuffer = self.resource_manager.borrow_mut().framebuffer(
+            viewport.width,
+            viewport.height,
+            &attachment_views,
+            self.render_pass,
+            "Orbits"
+        );
+
+        let render_pass_begin_info = vk::RenderPassBeginInfo {
+            render_pass: self.render_pass,
+            framebuffer: framebuffer.borrow().framebuffer,
+            render_area: vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent: vk::Extent2D {
+                    width: viewport.width,
+                    height: viewport.height,
+                },
+            },
+            ..Default::default()
+        };
+
+        unsafe {
+            device.logical_device.cmd_begin_render_pass(
+                cmd_buffer,
+                &render_pass_begin_info,
+                vk::SubpassContents::INLINE,
+            );
+            device.logical_device.cmd_bind_pipeline(
+                cmd_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline.pipelines[0],
+            );
+        }
+
+        for orbit_lines in &orbits {
+            let orbit_lines = orbit_lines.borrow();
+            match self.get_orbit_descriptor_set(&orbit_lines) {
+                Ok(descriptor_set) => {
+                    let geometry = orbit_lines.get_geometry();
+                    unsafe {
+                        device.logical_device.cmd_bind_descriptor_sets(
+                            cmd_buffer,
+                            vk::PipelineBindPoint::GRAPHICS,
+                            self.pipeline.layout,
+                            0,
+                            &[descriptor_set],
+                            &[],
+                        );
+                        device.logical_device.cmd_bind_vertex_buffers(
+                            cmd_buffer,
+                            0,
+                            &[geometry.vertex_buffer.borrow().get_vk_buffer()],
+                            &[0],
+                        );
+                        device.logical_device.cmd_bind_index_buffer(
+                            cmd_buffer,
+                            geometry.index_buffer.borrow().get_vk_buffer(),
+                            0,
+                            vk::IndexType::UINT32,
+                        );
+                        device.logical_device.cmd_draw_indexed(cmd_buffer, geometry.indices.len() as u32, 1, 0, 0, 0);
+                    }
+                },
+                Err(msg) => log::error!("Failed to draw orbit: {}", msg),
+            }
+        }
+
+        unsafe {
+            device.logical_device.cmd_end_render_pass(cmd_buffer);
+        }
+
+        input_attachments[0].borrow_mut().set_layout(self.attachment_descrs[0].1.final_layout);
+        input_attachments[1].borrow_mut().set_layout(self.depth_attachment_descr.1.final_layout);
+
+        input_attachments
+    }
+
+    fn get_pipeline(&self) -> &Pipeline {
+        &self.pipeline
+    }
+
+    fn get_descriptor_set(&self) -> Result<vk::DescriptorSet,&'static str> {
+        match self
+            .resource_manager
+            .borrow_mut()
+            .descriptor_set_manager
+            .allocate_descriptor_set(&self.pipeline.descriptor_set_layout) {
+            Ok(descriptor_set) => {
+                let device_ref = self.device.borrow();
+                let camera_buffer_info = {
+                    let buffer = self.camera.borrow().get_ubo(device_ref.get_image_idx()).buffer.borrow().get_vk_buffer();
+                    vk::DescriptorBufferInfo::builder()
+                        .buffer(buffer)
+                        .range(vk::WHOLE_SIZE)
+                        .build()
+                };
+
+                let descr_set_writes = [
+                    vk::WriteDescriptorSet {
+                        dst_set: descriptor_set,
+                        dst_binding: Binding::Camera as u32,
+                        descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                        descriptor_count: 1,
+                        p_buffer_info: &camera_buffer_info,
+                        ..Default::default()
+                    },
+                ];
+
+                unsafe {
+                    device_ref
+                        .logical_device
+                        .update_descriptor_sets(&descr_set_writes, &[]);
+                }
+
+                Ok(descriptor_set)
+            },
+            Err(msg) => Err(msg)
+        }
+    }
+}
+
+impl Drop for OrbitsPass {
+    fn drop(&mut self) {
+        unsafe {
+            self.device
+                .borrow()
+                .logical_device
+                .destroy_render_pass(self.render_pass, None);
+        }
+    }
+}
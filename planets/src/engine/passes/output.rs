@@ -0,0 +1,315 @@
+use std::rc::Rc;
+
+use ash::vk;
+use ash::vk::Handle;
+use cgmath as cgm;
+
+use crate::vulkan::debug;
+use crate::vulkan::debug::DebugResource;
+use crate::vulkan::device::{DeviceMutRef, MAX_FRAMES_IN_FLIGHT};
+use crate::vulkan::drawable::FullScreenDrawable;
+use crate::vulkan::img::image::{Image, ImageAccess, ImageMutRef};
+use crate::vulkan::mem::StructBufferData;
+use crate::vulkan::pipeline::Pipeline;
+use crate::vulkan::resources::manager::ResourceManagerMutRef;
+use crate::vulkan::shader::ShaderManager;
+use crate::vulkan::swapchain::OutputEncoding;
+use crate::vulkan::uniform_buffer::UniformBufferObject;
+
+// Pass specific bindings, must match output.frag
+const COLOR_BINDING: u32 = 0;
+const OUTPUT_BINDING: u32 = 1;
+
+#[repr(C)]
+struct OutputUBOInterface {
+    // x: encoding id, y: SDR white in nits
+    params: cgm::Vector4<f32>,
+}
+
+/// Writes the final image to a swapchain image with the encoding its format and color space need.
+/// Used instead of a blit when the encoding can't be done by the format on store.
+pub struct OutputPass {
+    device: DeviceMutRef,
+    resource_manager: ResourceManagerMutRef,
+    pipeline: Pipeline,
+    pub render_pass: vk::RenderPass,
+    drawable: FullScreenDrawable,
+    // Swapchain properties the pass was created for
+    format: vk::Format,
+    extent: vk::Extent2D,
+    encoding: OutputEncoding,
+    ubo: Vec<UniformBufferObject>,
+    label: String,
+}
+
+impl OutputPass {
+    pub fn new(
+        device: &DeviceMutRef,
+        resource_manager: &ResourceManagerMutRef,
+        shader_manager: &mut ShaderManager,
+        format: vk::Format,
+        extent: vk::Extent2D,
+        encoding: OutputEncoding,
+        paper_white: f32,
+    ) -> OutputPass {
+        let attachment_descrs = [vk::AttachmentDescription {
+            format,
+            samples: vk::SampleCountFlags::TYPE_1,
+            load_op: vk::AttachmentLoadOp::DONT_CARE,
+            store_op: vk::AttachmentStoreOp::STORE,
+            initial_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            final_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+            ..Default::default()
+        }];
+        let attachment_refs = [vk::AttachmentReference {
+            attachment: 0,
+            layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        }];
+
+        let subpass_dependencies = [
+            vk::SubpassDependency {
+                src_subpass: vk::SUBPASS_EXTERNAL,
+                dst_subpass: 0,
+                src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                src_access_mask: vk::AccessFlags::empty(),
+                dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                ..Default::default()
+            },
+            vk::SubpassDependency {
+                src_subpass: 0,
+                dst_subpass: vk::SUBPASS_EXTERNAL,
+                src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                dst_stage_mask: vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                dst_access_mask: vk::AccessFlags::empty(),
+                ..Default::default()
+            }
+        ];
+
+        let subpass_descriptions = [vk::SubpassDescription {
+            pipeline_bind_point: vk::PipelineBindPoint::GRAPHICS,
+            color_attachment_count: attachment_refs.len() as u32,
+            p_color_attachments: attachment_refs.as_ptr(),
+            ..Default::default()
+        }];
+
+        let render_pass_create_info = vk::RenderPassCreateInfo {
+            attachment_count: attachment_descrs.len() as u32,
+            p_attachments: attachment_descrs.as_ptr(),
+            subpass_count: subpass_descriptions.len() as u32,
+            p_subpasses: subpass_descriptions.as_ptr(),
+            dependency_count: subpass_dependencies.len() as u32,
+            p_dependencies: subpass_dependencies.as_ptr(),
+            ..Default::default()
+        };
+
+        let render_pass = unsafe {
+            device
+                .borrow()
+                .logical_device
+                .create_render_pass(&render_pass_create_info, None)
+                .expect("Could not create render pass")
+        };
+
+        let layout_bindings = vec![
+            vk::DescriptorSetLayoutBinding {
+                binding: COLOR_BINDING,
+                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
+            vk::DescriptorSetLayoutBinding {
+                binding: OUTPUT_BINDING,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
+        ];
+
+        let pipeline = Pipeline::build(
+            device,
+            shader_manager,
+            render_pass,
+            "output",
+            extent.width,
+            extent.height,
+        )
+        .with_layout_bindings(layout_bindings)
+        .build();
+
+        // Encoding does not change for the lifetime of the swapchain the pass is created for
+        let ubo_data = OutputUBOInterface {
+            params: cgm::Vector4::new(encoding.get_shader_id() as f32, paper_white, 0.0, 0.0),
+        };
+        let mut ubo = vec![];
+        for i in 0..MAX_FRAMES_IN_FLIGHT {
+            ubo.push(UniformBufferObject::new_with_data(
+                &mut resource_manager.borrow_mut(),
+                &StructBufferData::new(&ubo_data),
+                format!("Output{}", i).as_str(),
+            ));
+        }
+
+        let drawable = FullScreenDrawable::new(&mut resource_manager.borrow_mut());
+        let pass = OutputPass {
+            device: Rc::clone(device),
+            resource_manager: Rc::clone(resource_manager),
+            pipeline,
+            render_pass,
+            drawable,
+            format,
+            extent,
+            encoding,
+            ubo,
+            label: String::from("Output"),
+        };
+        debug::Object::label(&device.borrow(), &pass);
+
+        pass
+    }
+
+    /// Whether the pass can write to swapchain images of the given properties
+    pub fn is_compatible(&self, format: vk::Format, extent: vk::Extent2D, encoding: OutputEncoding) -> bool {
+        self.format == format && self.extent == extent && self.encoding == encoding
+    }
+
+    /// Encode the final image into the swapchain image and leave it ready for present
+    pub fn run(&self, cmd_buffer: vk::CommandBuffer, input: &ImageMutRef, target: &mut Image) {
+        let device = self.device.borrow();
+        let mut _debug_region = debug::Region::new(&device, self.get_label().as_str());
+
+        let input_info = {
+            let sampled_access = ImageAccess {
+                new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                src_stage: vk::PipelineStageFlags::ALL_COMMANDS,
+                src_access: vk::AccessFlags::MEMORY_WRITE,
+                dst_stage: vk::PipelineStageFlags::FRAGMENT_SHADER,
+                dst_access: vk::AccessFlags::SHADER_READ,
+            };
+            let mut input = input.borrow_mut();
+            match input.access_view(&device, &sampled_access, None) {
+                Ok(view) => vk::DescriptorImageInfo {
+                    sampler: input.sampler.sampler,
+                    image_view: view,
+                    image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                },
+                Err(msg) => return log::error!("{}", msg),
+            }
+        };
+
+        let target_access = ImageAccess {
+            new_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            src_stage: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            src_access: vk::AccessFlags::empty(),
+            dst_stage: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            dst_access: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+        };
+        let target_view = match target.access_view(&device, &target_access, None) {
+            Ok(view) => view,
+            Err(msg) => return log::error!("{}", msg),
+        };
+        let framebuffer = self.resource_manager.borrow_mut().framebuffer(
+            self.extent.width,
+            self.extent.height,
+            &vec![target_view],
+            self.render_pass,
+            "Output"
+        );
+
+        let descriptor_set = match self.resource_manager.borrow_mut().descriptor_set_manager.allocate_descriptor_set(&self.pipeline.descriptor_set_layout) {
+            Ok(descriptor_set) => descriptor_set,
+            Err(msg) => return log::error!("Failed to execute Output render pass: {}", msg),
+        };
+        let output_buffer_info = vk::DescriptorBufferInfo::builder()
+            .buffer(self.ubo[device.get_image_idx()].buffer.borrow().get_vk_buffer())
+            .range(vk::WHOLE_SIZE)
+            .build();
+        let descr_set_writes = [
+            vk::WriteDescriptorSet {
+                dst_set: descriptor_set,
+                dst_binding: COLOR_BINDING,
+                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: 1,
+                p_image_info: &input_info,
+                ..Default::default()
+            },
+            vk::WriteDescriptorSet {
+                dst_set: descriptor_set,
+                dst_binding: OUTPUT_BINDING,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+                p_buffer_info: &output_buffer_info,
+                ..Default::default()
+            },
+        ];
+
+        let render_pass_begin_info = vk::RenderPassBeginInfo {
+            render_pass: self.render_pass,
+            framebuffer: framebuffer.borrow().framebuffer,
+            render_area: vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent: self.extent,
+            },
+            ..Default::default()
+        };
+
+        unsafe {
+            device.logical_device.update_descriptor_sets(&descr_set_writes, &[]);
+            device.logical_device.cmd_begin_render_pass(
+                cmd_buffer,
+                &render_pass_begin_info,
+                vk::SubpassContents::INLINE,
+            );
+            device.logical_device.cmd_bind_pipeline(
+                cmd_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline.pipelines[0],
+            );
+            device.logical_device.cmd_bind_descriptor_sets(
+                cmd_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline.layout,
+                0,
+                &[descriptor_set],
+                &[],
+            );
+
+            self.drawable.draw(
+                &device,
+                cmd_buffer,
+            );
+
+            device.logical_device.cmd_end_render_pass(cmd_buffer);
+        }
+
+        target.set_layout(vk::ImageLayout::PRESENT_SRC_KHR);
+    }
+}
+
+impl DebugResource for OutputPass {
+    fn get_type(&self) -> vk::ObjectType {
+        vk::ObjectType::RENDER_PASS
+    }
+
+    fn get_handle(&self) -> u64 {
+        self.render_pass.as_raw()
+    }
+
+    fn get_label(&self) -> &String {
+        &self.label
+    }
+}
+
+impl Drop for OutputPass {
+    fn drop(&mut self) {
+        unsafe {
+            self.device
+                .borrow()
+                .logical_device
+                .destroy_render_pass(self.render_pass, None);
+        }
+    }
+}
@@ -0,0 +1,500 @@
+use std::rc::Rc;
+
+use ash::vk;
+use ash::vk::Handle;
+
+use crate::engine::camera::CameraMutRef;
+use crate::engine::renderpass::{RenderPass, SCENE_COLOR_FORMAT};
+use crate::engine::scene::graph::SceneGraphMutRef;
+use crate::engine::viewport::ViewportMutRef;
+use crate::vulkan::debug;
+use crate::vulkan::debug::DebugResource;
+use crate::vulkan::device::DeviceMutRef;
+use crate::vulkan::img::image::{ImageAccess, ImageMutRef};
+use crate::vulkan::pipeline::Pipeline;
+use crate::vulkan::resources::manager::ResourceManagerMutRef;
+use crate::vulkan::shader::{Binding, ShaderManager};
+use crate::world::rings::PlanetRings;
+
+// Pass specific bindings, must match rings.frag
+const PROFILE_BINDING: u32 = 0;
+const RING_BINDING: u32 = 1;
+
+/// Alpha blended planet rings over the opaque scene. Rings are tested against scene depth but don't write it.
+pub struct RingsPass {
+    device: DeviceMutRef,
+    resource_manager: ResourceManagerMutRef,
+    viewport: ViewportMutRef,
+    camera: CameraMutRef,
+    scene: SceneGraphMutRef,
+    pipeline: Pipeline,
+    pub render_pass: vk::RenderPass,
+    attachment_descrs: Vec<(&'static str, vk::AttachmentDescription)>,
+    depth_attachment_descr: (&'static str, vk::AttachmentDescription),
+    label: String,
+}
+
+impl RingsPass {
+    pub fn new(
+        device: &DeviceMutRef,
+        resource_manager: &ResourceManagerMutRef,
+        shader_manager: &mut ShaderManager,
+        viewport: &ViewportMutRef,
+        camera: &CameraMutRef,
+        scene: &SceneGraphMutRef,
+    ) -> RingsPass {
+        let attachments = RingsPass::create_attachment_descrs(SCENE_COLOR_FORMAT);
+
+        let mut attachment_descrs: Vec<vk::AttachmentDescription> =
+            attachments.iter().map(|(_, descr)| *descr).collect();
+        let mut attachment_refs = vec![];
+        for (i, attachment) in attachment_descrs.iter().enumerate() {
+            attachment_refs.push(vk::AttachmentReference {
+                attachment: i as u32,
+                layout: attachment.initial_layout,
+            });
+        }
+
+        let depth_attachment = vk::AttachmentDescription {
+            format: vk::Format::D32_SFLOAT_S8_UINT,
+            samples: vk::SampleCountFlags::TYPE_1,
+            load_op: vk::AttachmentLoadOp::LOAD,
+            store_op: vk::AttachmentStoreOp::STORE,
+            stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+            stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+            initial_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            final_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            ..Default::default()
+        };
+        let depth_attachment_ref = [
+            vk::AttachmentReference {
+                attachment: attachment_refs.len() as u32,
+                layout: depth_attachment.initial_layout
+            }
+        ];
+
+        attachment_descrs.push(depth_attachment);
+
+        let subpass_dependencies = [
+            vk::SubpassDependency {
+                src_subpass: vk::SUBPASS_EXTERNAL,
+                dst_subpass: 0,
+                src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+                dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ,
+                ..Default::default()
+            },
+            vk::SubpassDependency {
+                src_subpass: 0,
+                dst_subpass: vk::SUBPASS_EXTERNAL,
+                src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                dst_stage_mask: vk::PipelineStageFlags::TRANSFER | vk::PipelineStageFlags::FRAGMENT_SHADER,
+                dst_access_mask: vk::AccessFlags::TRANSFER_READ | vk::AccessFlags::SHADER_READ,
+                ..Default::default()
+            }
+        ];
+
+        let subpass_descriptions = [vk::SubpassDescription {
+            pipeline_bind_point: vk::PipelineBindPoint::GRAPHICS,
+            color_attachment_count: attachment_refs.len() as u32,
+            p_color_attachments: attachment_refs.as_ptr(),
+            p_depth_stencil_attachment: depth_attachment_ref.as_ptr(),
+            ..Default::default()
+        }];
+
+        let render_pass_create_info = vk::RenderPassCreateInfo {
+            attachment_count: attachment_descrs.len() as u32,
+            p_attachments: attachment_descrs.as_ptr(),
+            subpass_count: subpass_descriptions.len() as u32,
+            p_subpasses: subpass_descriptions.as_ptr(),
+            dependency_count: subpass_dependencies.len() as u32,
+            p_dependencies: subpass_dependencies.as_ptr(),
+            ..Default::default()
+        };
+
+        let render_pass = unsafe {
+            device
+                .borrow()
+                .logical_device
+                .create_render_pass(&render_pass_create_info, None)
+                .expect("Could not create render pass")
+        };
+
+        let layout_bindings = vec![
+            vk::DescriptorSetLayoutBinding {
+                binding: PROFILE_BINDING,
+                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
+            vk::DescriptorSetLayoutBinding {
+                binding: RING_BINDING,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
+            vk::DescriptorSetLayoutBinding {
+                binding: Binding::Models as u32,
+                descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::VERTEX,
+                ..Default::default()
+            },
+            vk::DescriptorSetLayoutBinding {
+                binding: Binding::Lights as u32,
+                descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                ..Default::default()
+            },
+            vk::DescriptorSetLayoutBinding {
+                binding: Binding::Camera as u32,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT | vk::ShaderStageFlags::VERTEX,
+                ..Default::default()
+            },
+        ];
+
+        let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::builder()
+            .depth_test_enable(true)
+            .depth_write_enable(false)
+            .depth_compare_op(camera.borrow().get_depth_mode().get_compare_op())
+            .stencil_test_enable(false);
+
+        let blend_attachment = vk::PipelineColorBlendAttachmentState {
+            blend_enable: vk::TRUE,
+            src_color_blend_factor: vk::BlendFactor::SRC_ALPHA,
+            dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            color_blend_op: vk::BlendOp::ADD,
+            src_alpha_blend_factor: vk::BlendFactor::ZERO,
+            dst_alpha_blend_factor: vk::BlendFactor::ONE,
+            alpha_blend_op: vk::BlendOp::ADD,
+            color_write_mask: vk::ColorComponentFlags::RGBA,
+        };
+
+        let viewport_ref = viewport.borrow();
+        // Rings are seen from both sides
+        let pipeline = Pipeline::build(
+            device,
+            shader_manager,
+            render_pass,
+            "rings",
+            viewport_ref.width,
+            viewport_ref.height,
+        )
+        .with_layout_bindings(layout_bindings)
+        .with_depth_stencil_info(*depth_stencil_info)
+        .with_cull_mode(vk::CullModeFlags::NONE)
+        .with_blend_attachment(blend_attachment)
+        .build();
+
+        let pass = RingsPass {
+            device: Rc::clone(device),
+            resource_manager: Rc::clone(resource_manager),
+            viewport: Rc::clone(viewport),
+            camera: Rc::clone(camera),
+            scene: Rc::clone(scene),
+            pipeline,
+            render_pass,
+            attachment_descrs: attachments,
+            depth_attachment_descr: ("DepthStencilAttachment", depth_attachment),
+            label: String::from("Rings"),
+        };
+        debug::Object::label(&device.borrow(), &pass);
+
+        pass
+    }
+
+    fn create_attachment_descrs(
+        format: vk::Format,
+    ) -> Vec<(&'static str, vk::AttachmentDescription)> {
+        let attachments = vec![(
+            "Rings",
+            vk::AttachmentDescription {
+                format,
+                samples: vk::SampleCountFlags::TYPE_1,
+                load_op: vk::AttachmentLoadOp::LOAD,
+                store_op: vk::AttachmentStoreOp::STORE,
+                initial_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                final_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                ..Default::default()
+            },
+        )];
+
+        attachments
+    }
+
+    /// Descriptor set of the shared pass resources with the profile texture and parameters of the given rings
+    fn get_rings_descriptor_set(&self, rings: &PlanetRings, profile_info: &vk::DescriptorImageInfo) -> Result<vk::DescriptorSet,&'static str> {
+        let descriptor_set = self.get_descriptor_set()?;
+
+        let ring_buffer_info = vk::DescriptorBufferInfo::builder()
+            .buffer(rings.get_ubo().buffer.borrow().get_vk_buffer())
+            .range(vk::WHOLE_SIZE)
+            .build();
+
+        let descr_set_writes = [
+            vk::WriteDescriptorSet {
+                dst_set: descriptor_set,
+                dst_binding: PROFILE_BINDING,
+                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: 1,
+                p_image_info: profile_info,
+                ..Default::default()
+            },
+            vk::WriteDescriptorSet {
+                dst_set: descriptor_set,
+                dst_binding: RING_BINDING,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+                p_buffer_info: &ring_buffer_info,
+                ..Default::default()
+            },
+        ];
+
+        unsafe {
+            self.device
+                .borrow()
+                .logical_device
+                .update_descriptor_sets(&descr_set_writes, &[]);
+        }
+
+        Ok(descriptor_set)
+    }
+}
+
+impl DebugResource for RingsPass {
+    fn get_type(&self) -> vk::ObjectType {
+        vk::ObjectType::RENDER_PASS
+    }
+
+    fn get_handle(&self) -> u64 {
+        self.render_pass.as_raw()
+    }
+
+    fn get_label(&self) -> &String {
+        &self.label
+    }
+}
+
+impl RenderPass for RingsPass {
+    fn run(&mut self, cmd_buffer: vk::CommandBuffer, input_attachments: Vec<ImageMutRef>) -> Vec<ImageMutRef> {
+        let rings = self.scene.borrow().get_rings();
+        if rings.is_empty() {
+            return input_attachments;
+        }
+
+        let device = self.device.borrow();
+        let mut _debug_region = debug::Region::new(&device, self.get_label().as_str());
+
+        // Profile textures have to be transitioned before the render pass starts
+        let profile_access = ImageAccess {
+            new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            src_stage: vk::PipelineStageFlags::TRANSFER,
+            src_access: vk::AccessFlags::TRANSFER_WRITE,
+            dst_stage: vk::PipelineStageFlags::FRAGMENT_SHADER,
+            dst_access: vk::AccessFlags::SHADER_READ,
+        };
+        let mut profile_infos = vec![];
+        for rings_ref in &rings {
+            let r = rings_ref.borrow();
+            let drawable = r.get_drawable().borrow();
+            let profile = match &drawable.material.albedo_map {
+                Some(profile) => profile,
+                None => continue,
+            };
+            let mut profile = profile.borrow_mut();
+            match profile.access_view(&device, &profile_access, None) {
+                Ok(view) => profile_infos.push((Rc::clone(rings_ref), vk::DescriptorImageInfo {
+                    sampler: profile.sampler.sampler,
+                    image_view: view,
+                    image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                })),
+                Err(msg) => log::error!("{}", msg),
+            }
+        }
+
+        let mut attachment_views = vec![];
+        {
+            let mut color_attachment = input_attachments[0].borrow_mut();
+            let color_access = ImageAccess {
+                new_layout: self.attachment_descrs[0].1.initial_layout,
+                src_stage: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                src_access: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                dst_stage: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                dst_access: vk::AccessFlags::COLOR_ATTACHMENT_READ,
+            };
+            match color_attachment.access_view(&device, &color_access, None) {
+                Ok(view) => attachment_views.push(view),
+                Err(msg) => log::error!("{}", msg),
+            }
+
+            let mut depth_attachment = input_attachments[1].borrow_mut();
+            let depth_access = ImageAccess {
+                new_layout: self.depth_attachment_descr.1.initial_layout,
+                src_stage: vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                dst_stage: vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+                src_access: vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                dst_access: vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ,
+            };
+            match depth_attachment.access_view(&device, &depth_access, None) {
+                Ok(view) => attachment_views.push(view),
+                Err(msg) => log::error!("{}", msg),
+            }
+        }
+
+        let viewport = self.viewport.borrow();
+        let framebuffer = self.resource_manager.borrow_mut().framebuffer(
+            viewport.width,
+            viewport.height,
+            &attachment_views,
+            self.render_pass,
+            "Rings"
+        );
+
+        let render_pass_begin_info = vk::RenderPassBeginInfo {
+            render_pass: self.render_pass,
+            framebuffer: framebuffer.borrow().framebuffer,
+            render_area: vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent: vk::Extent2D {
+                    width: viewport.width,
+                    height: viewport.height,
+                },
+            },
+            ..Default::default()
+        };
+
+        unsafe {
+            device.logical_device.cmd_begin_render_pass(
+                cmd_buffer,
+                &render_pass_begin_info,
+                vk::SubpassContents::INLINE,
+            );
+            device.logical_device.cmd_bind_pipeline(
+                cmd_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline.pipelines[0],
+            );
+        }
+
+        for (r, profile_info) in &profile_infos {
+            let r = r.borrow();
+            match self.get_rings_descriptor_set(&r, profile_info) {
+                Ok(descriptor_set) => {
+                    unsafe {
+                        device.logical_device.cmd_bind_descriptor_sets(
+                            cmd_buffer,
+                            vk::PipelineBindPoint::GRAPHICS,
+                            self.pipeline.layout,
+                            0,
+                            &[descriptor_set],
+                            &[],
+                        );
+                    }
+                    r.get_drawable().borrow().write_draw_commands(&device, &cmd_buffer);
+                },
+                Err(msg) => log::error!("Failed to draw planet rings: {}", msg),
+            }
+        }
+
+        unsafe {
+            device.logical_device.cmd_end_render_pass(cmd_buffer);
+        }
+
+        input_attachments[0].borrow_mut().set_layout(self.attachment_descrs[0].1.final_layout);
+        input_attachments[1].borrow_mut().set_layout(self.depth_attachment_descr.1.final_layout);
+
+        input_attachments
+    }
+
+    fn get_pipeline(&self) -> &Pipeline {
+        &self.pipeline
+    }
+
+    fn get_descriptor_set(&self) -> Result<vk::DescriptorSet,&'static str> {
+        match self
+            .resource_manager
+            .borrow_mut()
+            .descriptor_set_manager
+            .allocate_descriptor_set(&self.pipeline.descriptor_set_layout) {
+            Ok(descriptor_set) => {
+                let device_ref = self.device.borrow();
+                let camera_buffer_info = {
+                    let buffer = self.camera.borrow().get_ubo(device_ref.get_image_idx()).buffer.borrow().get_vk_buffer();
+                    vk::DescriptorBufferInfo::builder()
+                        .buffer(buffer)
+                        .range(vk::WHOLE_SIZE)
+                        .build()
+                };
+                let scene = self.scene.borrow();
+                let models_buffer_info = {
+                    let buffer = scene.get_model_data_ssbo(device_ref.get_image_idx()).borrow().get_vk_buffer();
+                    vk::DescriptorBufferInfo::builder()
+                        .buffer(buffer)
+                        .range(vk::WHOLE_SIZE)
+                        .build()
+                };
+                let lights_buffer_info = {
+                    let buffer = scene.get_light_manager().borrow().get_ssbo(device_ref.get_image_idx()).borrow().get_vk_buffer();
+                    vk::DescriptorBufferInfo::builder()
+                        .buffer(buffer)
+                        .range(vk::WHOLE_SIZE)
+                        .build()
+                };
+
+                let descr_set_writes = [
+                    vk::WriteDescriptorSet {
+                        dst_set: descriptor_set,
+                        dst_binding: Binding::Camera as u32,
+                        descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                        descriptor_count: 1,
+                        p_buffer_info: &camera_buffer_info,
+                        ..Default::default()
+                    },
+                    vk::WriteDescriptorSet {
+                        dst_set: descriptor_set,
+                        dst_binding: Binding::Models as u32,
+                        descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+                        descriptor_count: 1,
+                        p_buffer_info: &models_buffer_info,
+                        ..Default::default()
+                    },
+                    vk::WriteDescriptorSet {
+                        dst_set: descriptor_set,
+                        dst_binding: Binding::Lights as u32,
+                        descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+                        descriptor_count: 1,
+                        p_buffer_info: &lights_buffer_info,
+                        ..Default::default()
+                    },
+                ];
+
+                unsafe {
+                    device_ref
+                        .logical_device
+                        .update_descriptor_sets(&descr_set_writes, &[]);
+                }
+
+                Ok(descriptor_set)
+            },
+            Err(msg) => Err(msg)
+        }
+    }
+}
+
+impl Drop for RingsPass {
+    fn drop(&mut self) {
+        unsafe {
+            self.device
+                .borrow()
+                .logical_device
+                .destroy_render_pass(self.render_pass, None);
+        }
+    }
+}
@@ -1,16 +1,18 @@
 use alloc::rc::Rc;
+use std::collections::HashSet;
 use ash::vk;
 use crate::engine::camera::CameraMutRef;
-use crate::engine::renderpass::RenderPass;
+use crate::engine::renderpass::{RenderPass, SCENE_COLOR_FORMAT};
 use crate::engine::scene::graph::SceneGraphMutRef;
 use crate::vulkan::device::DeviceMutRef;
+use crate::vulkan::drawable::DrawType;
 use rand::Rng;
 use crate::vulkan::img::image::{ImageAccess, ImageMutRef};
 use crate::vulkan::mem::{AllocatedBufferMutRef, BufferAccess, StructBufferData, VecBufferData};
 use crate::vulkan::pipeline::Pipeline;
 use crate::vulkan::resources::manager::{AttachmentSize, ResourceManager, ResourceManagerMutRef};
 use crate::vulkan::resources::objects::ObjectDescriptionsMutRef;
-use crate::vulkan::rt::r#as::AccelerationStructure;
+use crate::vulkan::rt::r#as::{AccelerationStructure, AsInstance};
 use crate::vulkan::shader::{Binding, ShaderManager};
 
 struct ObjDesc
@@ -57,7 +59,7 @@ impl RaytracedAo {
                camera: &CameraMutRef) -> Option<Self> {
 
         let camera = Rc::clone(camera);
-        let image = resource_manager.borrow_mut().attachment(AttachmentSize::Fixed(camera.borrow().get_viewport_size().x, camera.borrow().get_viewport_size().y), vk::Format::R8G8B8A8_SNORM, vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::TRANSFER_SRC, "RtImage");
+        let image = resource_manager.borrow_mut().attachment(AttachmentSize::Fixed(camera.borrow().get_viewport_size().x, camera.borrow().get_viewport_size().y), SCENE_COLOR_FORMAT, vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::SAMPLED, "RtImage");
         let debug_image = resource_manager.borrow_mut().attachment(AttachmentSize::Fixed(camera.borrow().get_viewport_size().x, camera.borrow().get_viewport_size().y), vk::Format::R16G16B16A16_SFLOAT, vk::ImageUsageFlags::STORAGE, "RtDebugImage");
         let ray_param = RayParams {
             ray_origin: cgmath::Vector4::new(0.0, 0.0, 2.0, 1.0),
@@ -106,6 +108,7 @@ impl RaytracedAo {
                 vk::DescriptorBindingFlagsEXT::empty(),
                 vk::DescriptorBindingFlagsEXT::empty(),
                 vk::DescriptorBindingFlagsEXT::empty(),
+                vk::DescriptorBindingFlagsEXT::empty(),
                 //vk::DescriptorBindingFlagsEXT::empty(),
             ];
 
@@ -153,6 +156,12 @@ impl RaytracedAo {
                                 .stage_flags(vk::ShaderStageFlags::CLOSEST_HIT_KHR)
                                 .binding(Binding::ObjDescrs as u32)
                                 .build(),
+                            vk::DescriptorSetLayoutBinding::builder()
+                                .descriptor_count(1)
+                                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                                .stage_flags(vk::ShaderStageFlags::CLOSEST_HIT_KHR)
+                                .binding(Binding::Lights as u32)
+                                .build(),
                         ])
                         .push_next(&mut binding_flags)
                         .build(),
@@ -288,6 +297,40 @@ impl RaytracedAo {
     fn aligned_size(value: u32, alignment: u32) -> u32 {
         (value + alignment - 1) & !(alignment - 1)
     }
+
+    /// Rebuild the TLAS from the drawn instances with the same rebased transforms as rasterized models.
+    /// BLASes follow the draw set, e.g. terrain chunks refined after the first frame.
+    fn update_acceleration_structure(&mut self, cmd_buffer: vk::CommandBuffer) {
+        let device = self.device.borrow();
+        let mut resource_manager = self.resource_manager.borrow_mut();
+        let scene = self.scene.borrow();
+        let accel = self.accel.get_or_insert_with(|| AccelerationStructure::new(&device));
+
+        let mut instances = vec![];
+        let mut object_ids = HashSet::new();
+        for d in &scene.cull() {
+            let drawable = d.drawable.borrow();
+            // Impostor billboards stand in for bodies traced with their own geometry
+            if drawable.draw_type == DrawType::Impostor {
+                continue;
+            }
+
+            let object_id = drawable.get_object_id();
+            object_ids.insert(object_id);
+            if !accel.has_blas(object_id, drawable.get_geometry()) {
+                accel.add_blas(&device, &mut resource_manager, object_id, drawable.get_geometry());
+            }
+            for instance in drawable.get_instances() {
+                let transform = instance.borrow().get_model_index().and_then(|i| scene.get_model_transform(i));
+                if let Some(transform) = transform {
+                    instances.push(AsInstance { object_id, transform: *transform });
+                }
+            }
+        }
+
+        accel.remove_unused_blases(&object_ids);
+        accel.build_tlas(&device, &mut resource_manager, cmd_buffer, &instances);
+    }
 }
 
 impl RenderPass for RaytracedAo {
@@ -304,15 +347,9 @@ impl RenderPass for RaytracedAo {
         let ray_param_data = StructBufferData::new(&ray_param);
         self.ray_params_buffer.borrow_mut().update_data(&self.device.borrow(), &ray_param_data, 0);
 
-        if self.accel.is_none() {
-            let drawables = self.scene.borrow().cull();
-            let mut geometries = vec![];
-            for d in &drawables {
-                let drawable = d.drawable.borrow();
-                geometries.push(drawable.get_geometry().clone());
-            }
-            self.accel = Some(AccelerationStructure::new(&self.device.borrow(), &mut self.resource_manager.borrow_mut(), &geometries));
-        }
+        // Previous frames may still trace against the acceleration structures updated below
+        self.device.borrow().wait_idle();
+        self.update_acceleration_structure(cmd_buffer);
 
         let rt_pipeline_properties = &self.device.borrow().rt_pipeline.properties;
         let handle_size_aligned = Self::aligned_size(
@@ -344,7 +381,6 @@ impl RenderPass for RaytracedAo {
 
         let descriptor_sets = [self.get_descriptor_set().unwrap()];
         let device_ref = self.device.borrow();
-        device_ref.wait_idle();
         let viewport = *self.camera.borrow().get_viewport_size();
         unsafe {
             device_ref.logical_device.cmd_bind_pipeline(
@@ -387,7 +423,7 @@ impl RenderPass for RaytracedAo {
             .allocate_descriptor_set(&self.descriptor_set_layout) {
             Ok(descriptor_set) => {
                 let device_ref = self.device.borrow();
-                let accel_structs = [self.accel.as_ref().unwrap().tlas];
+                let accel_structs = [self.accel.as_ref().unwrap().get_tlas()];
                 let mut accel_info = vk::WriteDescriptorSetAccelerationStructureKHR::builder()
                     .acceleration_structures(&accel_structs)
                     .build();
@@ -514,7 +550,23 @@ impl RenderPass for RaytracedAo {
                     .buffer_info(&camera_buffer_info)
                     .build();
 
-                let descr_set_writes = [accel_write, image_write, /*debug_image_write,*/ buffers_write, object_descs_write, camera_write];
+                let lights_buffer_info = [{
+                    let buffer = self.scene.borrow().get_light_manager().borrow().get_ssbo(device_ref.get_image_idx()).borrow().get_vk_buffer();
+                    vk::DescriptorBufferInfo::builder()
+                        .buffer(buffer)
+                        .range(vk::WHOLE_SIZE)
+                        .build()
+                }];
+
+                let lights_write = vk::WriteDescriptorSet::builder()
+                    .dst_set(descriptor_set)
+                    .dst_binding(Binding::Lights as u32)
+                    .dst_array_element(0)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .buffer_info(&lights_buffer_info)
+                    .build();
+
+                let descr_set_writes = [accel_write, image_write, /*debug_image_write,*/ buffers_write, object_descs_write, camera_write, lights_write];
                 unsafe {
                     device_ref
                         .logical_device
@@ -2,6 +2,9 @@ use ash::vk;
 use crate::vulkan::img::image::{ImageMutRef};
 use crate::vulkan::pipeline::Pipeline;
 
+/// Format of the color target the scene is rendered to. Float keeps values above SDR white for HDR output.
+pub const SCENE_COLOR_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
 pub trait RenderPass {
     fn run(&mut self, cmd_buffer: vk::CommandBuffer, input_attachments: Vec<ImageMutRef>) -> Vec<ImageMutRef>;
     fn get_pipeline(&self) -> &Pipeline;
@@ -1,10 +1,29 @@
 use std::cell::RefCell;
+use std::path::Path;
 use std::rc::Rc;
+use crate::engine::camera::{Projection, ProjectionKind};
 use crate::engine::scene::graph::SceneGraph;
 use crate::engine::scene::node::{Node, NodeContent, UpdateCallResult};
 use cgmath as cgm;
-use cgmath::SquareMatrix;
+use cgmath::prelude::*;
 use crate::world::loader::ModelLoader;
+use crate::world::atmosphere::AtmosphereParams;
+use crate::world::orbit::OrbitalElements;
+use crate::world::rings::RingParams;
+use crate::world::ship::{Ship, ShipParams, ShipState};
+use crate::world::terrain::{PlanetTerrain, PlanetTerrainMutRef};
+use crate::world::terrain::surface::HeightMap;
+
+// Equirectangular HDR Milky Way, replaced by authored cube faces in +X, -X, +Y, -Y, +Z, -Z order when present
+const SKYBOX_PATH: &str = "assets/textures/skybox/milky_way.hdr";
+const SKYBOX_FACE_PATHS: [&str; 6] = [
+    "assets/textures/skybox/px.hdr",
+    "assets/textures/skybox/nx.hdr",
+    "assets/textures/skybox/py.hdr",
+    "assets/textures/skybox/ny.hdr",
+    "assets/textures/skybox/pz.hdr",
+    "assets/textures/skybox/nz.hdr",
+];
 
 pub fn build_scene(scene: &mut SceneGraph, model_loader: &mut ModelLoader) {
     match model_loader.load_gltf("assets/gltf/ao/ao.gltf") {
@@ -31,4 +50,135 @@ pub fn build_scene(scene: &mut SceneGraph, model_loader: &mut ModelLoader) {
         },
         Err(str) => log::error!("{}", str),
     };
+
+    match model_loader.load_starfield("assets/stars/catalog.csv") {
+        Ok(starfield) => scene.set_starfield(starfield),
+        Err(str) => log::error!("{}", str),
+    };
+
+    build_skybox(scene, model_loader);
+
+    let planet_gravitational_parameter = 1.0e5;
+    let planet = PlanetTerrain::new_mut_ref(50.0, &cgm::Vector3::new(0.0, -55.0, 0.0));
+    planet.borrow_mut().set_gravitational_parameter(planet_gravitational_parameter);
+    planet.borrow_mut().set_atmosphere(AtmosphereParams::earth_like(50.0));
+    planet.borrow_mut().set_axial_tilt(cgm::Deg(20.0));
+    let rings = model_loader.create_rings(banded_ring_params(), 50.0);
+    planet.borrow_mut().set_rings(rings);
+    let planet_center = planet.borrow().get_center();
+    scene.add_terrain(planet);
+
+    // Small moon on an inclined eccentric orbit outside the rings
+    let moon_orbit = OrbitalElements {
+        semi_major_axis: 220.0,
+        eccentricity: 0.2,
+        inclination: cgm::Deg(12.0).into(),
+        longitude_of_ascending_node: cgm::Deg(30.0).into(),
+        argument_of_periapsis: cgm::Deg(70.0).into(),
+        mean_anomaly_at_epoch: cgm::Deg(200.0).into(),
+        gravitational_parameter: planet_gravitational_parameter,
+    };
+    let moon = PlanetTerrain::new_mut_ref(8.0, &planet_center);
+    moon.borrow_mut().set_orbit(moon_orbit.clone(), planet_center);
+    moon.borrow_mut().set_gravitational_parameter(400.0);
+    build_moon_surface(&moon, model_loader);
+    scene.add_terrain(moon);
+    let moon_orbit_lines = model_loader.create_orbit_lines(moon_orbit, planet_center, cgm::Vector3::new(0.3, 0.7, 1.0));
+    moon_orbit_lines.borrow_mut().set_trajectory_duration(20.0);
+    scene.add_orbit_lines(moon_orbit_lines);
+
+    build_ship(scene, model_loader, planet_center, planet_gravitational_parameter);
+}
+
+// Cratered albedo and 16 bit elevation maps, craters are up to a few percent of the radius deep
+fn build_moon_surface(moon: &PlanetTerrainMutRef, model_loader: &mut ModelLoader) {
+    match HeightMap::load("assets/textures/moon/height.png", -0.3, 0.11) {
+        Ok(height_map) => moon.borrow_mut().set_height_map(height_map),
+        Err(str) => log::error!("{}", str),
+    };
+
+    match model_loader.load_planet_surface("assets/textures/moon/albedo.png") {
+        Ok(surface) => moon.borrow_mut().set_surface(surface),
+        Err(str) => log::error!("{}", str),
+    };
+}
+
+fn build_skybox(scene: &mut SceneGraph, model_loader: &mut ModelLoader) {
+    let skybox = if SKYBOX_FACE_PATHS.iter().all(|path| Path::new(path).exists()) {
+        model_loader.load_skybox_cubemap(&SKYBOX_FACE_PATHS)
+    } else {
+        model_loader.load_skybox(SKYBOX_PATH)
+    };
+
+    match skybox {
+        Ok(skybox) => {
+            // Galactic plane is inclined by about 60 degrees to the orbital plane of the scene
+            skybox.borrow_mut().set_orientation(cgm::Matrix3::from_angle_x(cgm::Deg(60.0)));
+            scene.set_skybox(skybox);
+        },
+        Err(str) => log::error!("{}", str),
+    };
+}
+
+// Starship on a circular orbit around the planet
+fn build_ship(scene: &mut SceneGraph, model_loader: &mut ModelLoader, planet_center: cgm::Vector3<f64>, gravitational_parameter: f64) {
+    let orbit_radius = 150.0;
+    let params = ShipParams {
+        mass: 1000.0,
+        max_thrust: 30000.0,
+        rcs_torque: 1500.0,
+        moment_of_inertia: 1000.0,
+    };
+    let state = ShipState {
+        position: planet_center + cgm::Vector3::new(orbit_radius, 0.0, 0.0),
+        velocity: cgm::Vector3::new(0.0, 0.0, -(gravitational_parameter / orbit_radius).sqrt()),
+        orientation: cgm::Quaternion::one(),
+        angular_velocity: cgm::Vector3::zero(),
+    };
+    let ship = Ship::new_mut_ref(params, state);
+
+    match model_loader.load_gltf("assets/gltf/starship/starship.gltf") {
+        Ok(model) => {
+            // Model nose points along +Y, turn it to ship forward
+            let mut model_node = Node::with_content(NodeContent::Transform(
+                cgm::Matrix4::from_angle_x(cgm::Deg(-90.0)) * cgm::Matrix4::from_scale(2.0),
+            ));
+            model_node.add_child(model.borrow().spawn_instance());
+            ship.borrow().set_model(Rc::new(RefCell::new(model_node)));
+        },
+        Err(str) => log::error!("{}", str),
+    };
+
+    // Cockpit view slightly above the ship nose
+    let mut cockpit = Node::with_content(NodeContent::Transform(cgm::Matrix4::from_translation(cgm::Vector3::new(0.0, 0.6, -1.5))));
+    cockpit.add_child(Rc::new(RefCell::new(Node::with_content(NodeContent::Camera(Projection {
+        kind: ProjectionKind::Perspective(cgm::Deg(75.0)),
+        near: 0.05,
+        ..Default::default()
+    })))));
+    ship.borrow().get_node().borrow_mut().add_child(Rc::new(RefCell::new(cockpit)));
+
+    scene.set_ship(ship);
+}
+
+// Dense bright bands separated by a few gaps
+fn banded_ring_params() -> RingParams {
+    let texels = 128;
+    let profile = (0..texels).map(|i| {
+        let x = i as f32 / (texels - 1) as f32;
+        let bands = 0.5 + 0.5 * (x * 37.0).sin() * (x * 11.0).cos();
+        let gap = if (0.55..0.6).contains(&x) || (0.85..0.87).contains(&x) { 0.05 } else { 1.0 };
+        let edge_fade = (x * 10.0).min((1.0 - x) * 20.0).min(1.0);
+        let density = (0.3 + 0.6 * bands) * gap * edge_fade;
+        let tint = 0.75 + 0.25 * bands;
+        cgm::Vector4::new(0.85 * tint, 0.78 * tint, 0.65 * tint, density)
+    }).collect();
+
+    RingParams {
+        inner_radius: 65.0,
+        outer_radius: 120.0,
+        segments: 256,
+        forward_scattering: 0.6,
+        profile,
+    }
 }
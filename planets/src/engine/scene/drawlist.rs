@@ -40,6 +40,11 @@ impl DrawList {
         }
     }
 
+    /// Check if any drawable of the given type is to be drawn this frame
+    pub fn contains(&self, draw_type: DrawType) -> bool {
+        self.drawables.iter().any(|d| d.drawable.borrow().draw_type == draw_type)
+    }
+
     pub fn end_frame(&mut self) {
         self.drawables.clear();
     }
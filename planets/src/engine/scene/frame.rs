@@ -0,0 +1,119 @@
+use std::rc::Rc;
+
+use cgmath as cgm;
+use cgmath::prelude::*;
+
+use crate::engine::scene::graph::SceneGraph;
+use crate::engine::scene::node::NodeMutRef;
+use crate::engine::simulation::SimulationClock;
+use crate::util::math;
+
+/// Coordinate frame positions can be expressed in and the camera can be attached to
+#[derive(Clone)]
+pub enum ReferenceFrame {
+    // World origin and axes. Heliocentric as long as the star sits at the world origin.
+    Inertial,
+    // Origin moves with the body, axes stay aligned to the world
+    BodyInertial(NodeMutRef),
+    // Origin moves and axes rotate with the body
+    BodyFixed(NodeMutRef),
+}
+
+impl ReferenceFrame {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            ReferenceFrame::Inertial => "Inertial",
+            ReferenceFrame::BodyInertial(_) => "Body inertial",
+            ReferenceFrame::BodyFixed(_) => "Body fixed",
+        }
+    }
+
+    pub fn get_body(&self) -> Option<&NodeMutRef> {
+        match self {
+            ReferenceFrame::Inertial => None,
+            ReferenceFrame::BodyInertial(body) | ReferenceFrame::BodyFixed(body) => Some(body),
+        }
+    }
+
+    /// Transform from this frame to world space for the current frame interpolation. Bodies missing from
+    /// the scene resolve to the world frame.
+    pub fn resolve(&self, scene: &SceneGraph, simulation: &SimulationClock) -> cgm::Matrix4<f64> {
+        let body_transform = self.get_body()
+            .and_then(|body| scene.root.find_world_transform(body, &cgm::Matrix4::identity(), simulation.get_interpolation_alpha()));
+        match body_transform {
+            Some(body_transform) => self.get_frame_to_world(&body_transform),
+            None => cgm::Matrix4::identity(),
+        }
+    }
+
+    // Frame to world transform for the given body world transform. Body scale is dropped.
+    fn get_frame_to_world(&self, body_transform: &cgm::Matrix4<f64>) -> cgm::Matrix4<f64> {
+        let (translation, rotation, _) = math::decompose_transform(body_transform);
+        match self {
+            ReferenceFrame::Inertial => cgm::Matrix4::identity(),
+            ReferenceFrame::BodyInertial(_) => cgm::Matrix4::from_translation(translation),
+            ReferenceFrame::BodyFixed(_) => cgm::Matrix4::from_translation(translation) * cgm::Matrix4::from(rotation),
+        }
+    }
+}
+
+impl PartialEq for ReferenceFrame {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (ReferenceFrame::Inertial, ReferenceFrame::Inertial) => true,
+            (ReferenceFrame::BodyInertial(a), ReferenceFrame::BodyInertial(b)) => Rc::ptr_eq(a, b),
+            (ReferenceFrame::BodyFixed(a), ReferenceFrame::BodyFixed(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+/// Convert position between frames given their frame to world transforms
+pub fn convert_position(
+    position: &cgm::Point3<f64>,
+    from_frame_to_world: &cgm::Matrix4<f64>,
+    to_frame_to_world: &cgm::Matrix4<f64>,
+) -> cgm::Point3<f64> {
+    let world = from_frame_to_world.transform_point(*position);
+    match to_frame_to_world.inverse_transform() {
+        Some(world_to_frame) => world_to_frame.transform_point(world),
+        None => world,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use cgmath as cgm;
+    use cgmath::prelude::*;
+
+    use crate::engine::scene::node::Node;
+
+    use super::{convert_position, ReferenceFrame};
+
+    #[test]
+    fn body_frames_follow_body() {
+        let body = Rc::new(RefCell::new(Node::new()));
+        let body_transform = cgm::Matrix4::from_translation(cgm::Vector3::new(100.0, 0.0, 0.0))
+            * cgm::Matrix4::from_angle_y(cgm::Deg(90.0))
+            * cgm::Matrix4::from_scale(5.0);
+
+        let inertial = ReferenceFrame::BodyInertial(Rc::clone(&body)).get_frame_to_world(&body_transform);
+        let fixed = ReferenceFrame::BodyFixed(Rc::clone(&body)).get_frame_to_world(&body_transform);
+        let surface_point = cgm::Point3::new(1.0, 0.0, 0.0);
+
+        // Inertial axes keep world orientation, fixed axes turn with the body, neither is scaled
+        assert!((inertial.transform_point(surface_point) - cgm::Point3::new(101.0, 0.0, 0.0)).magnitude() < 1e-9);
+        assert!((fixed.transform_point(surface_point) - cgm::Point3::new(100.0, 0.0, -1.0)).magnitude() < 1e-9);
+
+        let converted = convert_position(&surface_point, &fixed, &inertial);
+        assert!((converted - cgm::Point3::new(0.0, 0.0, -1.0)).magnitude() < 1e-9);
+        let back = convert_position(&converted, &inertial, &fixed);
+        assert!((back - surface_point).magnitude() < 1e-9);
+
+        assert!(ReferenceFrame::BodyFixed(Rc::clone(&body)) == ReferenceFrame::BodyFixed(body));
+        assert!(ReferenceFrame::Inertial != ReferenceFrame::BodyInertial(Rc::new(RefCell::new(Node::new()))));
+    }
+}
@@ -4,16 +4,29 @@ use std::rc::Rc;
 use cgmath as cgm;
 use cgmath::prelude::*;
 
-use crate::engine::lights::{LightManager, LightManagerMutRef};
-use crate::engine::scene::node::{Node, NodeContent};
+use crate::engine::camera::Camera;
+use crate::engine::lights::{LightManager, LightManagerMutRef, ASTRONOMICAL_UNIT, SUN_LUMINOUS_INTENSITY, SUN_RADIUS};
+use crate::engine::scene::frame::{self, ReferenceFrame};
+use crate::engine::scene::node::{Node, NodeContent, NodeMutRef};
+use crate::engine::scene::picking::{PickListener, PickResult, Ray};
 use crate::vulkan::device::{Device, DeviceMutRef};
 use crate::vulkan::drawable::DrawableHash;
 use crate::vulkan::resources::manager::{ResourceManager, ResourceManagerMutRef};
 use std::collections::HashSet;
 use crate::engine::gameloop::{GameLoop};
 use crate::engine::models::ModelData;
+use crate::engine::simulation::SimulationClock;
+use crate::engine::skybox::SkyboxMutRef;
 use crate::engine::scene::drawlist::{DrawList, DrawListMutRef};
 use crate::vulkan::mem::{AllocatedBufferMutRef};
+use crate::vulkan::resources::objects::ObjectDescriptions;
+use crate::world::atmosphere::AtmosphereMutRef;
+use crate::world::impostor::ImpostorSettings;
+use crate::world::orbit::OrbitLinesMutRef;
+use crate::world::rings::PlanetRingsMutRef;
+use crate::world::ship::ShipMutRef;
+use crate::world::stars::StarfieldMutRef;
+use crate::world::terrain::PlanetTerrainMutRef;
 
 #[allow(dead_code)]
 pub const UP: cgm::Vector3<f32> = cgm::Vector3 {
@@ -29,6 +42,14 @@ pub struct SceneGraph {
     gpu_model_data: ModelData,
     pub root: Node,
     draw_list: DrawListMutRef, // TODO: should not be part of SceneGraph - cull() should return new to draw list
+    terrains: Vec<PlanetTerrainMutRef>,
+    starfield: Option<StarfieldMutRef>,
+    skybox: Option<SkyboxMutRef>,
+    orbit_lines: Vec<OrbitLinesMutRef>,
+    ship: Option<ShipMutRef>,
+    impostor_settings: ImpostorSettings,
+    // Notified of every object picked by pick()
+    pick_listeners: Vec<PickListener>,
 }
 
 impl SceneGraph {
@@ -45,14 +66,23 @@ impl SceneGraph {
             root,
             light_manager,
             draw_list: DrawList::new_mut_ref(device),
+            terrains: vec![],
+            starfield: None,
+            skybox: None,
+            orbit_lines: vec![],
+            ship: None,
+            impostor_settings: ImpostorSettings::default(),
+            pick_listeners: vec![],
         };
 
+        // Sun one astronomical unit away from the scene
+        let sun_direction = cgm::Vector3::new(0.3, 1.0, -0.4).normalize();
         let mut light_transform_node = Node::with_content(NodeContent::Transform(
-            cgm::Matrix4::from_translation(cgm::Vector3::new(0.0, 0.0, -10.0)),
+            cgm::Matrix4::from_translation(sun_direction * ASTRONOMICAL_UNIT),
         ));
-        let light_node = Rc::new(RefCell::new(Node::with_content(NodeContent::Light(
-            LightManager::create_light(&scene.light_manager),
-        ))));
+        let mut sun = LightManager::create_light(&scene.light_manager);
+        sun.set_star(SUN_LUMINOUS_INTENSITY, SUN_RADIUS);
+        let light_node = Rc::new(RefCell::new(Node::with_content(NodeContent::Light(sun))));
         light_transform_node.add_child(light_node);
         let light_transform_node = Rc::new(RefCell::new(light_transform_node));
         scene.root.add_child(light_transform_node);
@@ -60,11 +90,154 @@ impl SceneGraph {
         scene
     }
 
-    pub fn update(&mut self, device: &Device, resource_manager: &mut ResourceManager, gameloop: &GameLoop) {
+    /// Update scene and upload it for rendering. World positions are rebased to be relative to
+    /// the given origin, usually the camera position, before conversion to single precision.
+    pub fn update(&mut self, device: &Device, resource_manager: &mut ResourceManager, gameloop: &GameLoop, origin: &cgm::Vector3<f64>) {
         let identity = cgm::Matrix4::identity();
-        self.root.update(gameloop, &identity, &mut self.gpu_model_data);
+        self.gpu_model_data.reset();
+        self.root.update(gameloop, &identity, origin, &mut self.gpu_model_data);
         //self.light_manager.borrow_mut().update(device);
         self.gpu_model_data.update(device);
+        for orbit_lines in &self.orbit_lines {
+            orbit_lines.borrow().update(device, gameloop.get_simulation(), origin);
+        }
+        for terrain in &self.terrains {
+            terrain.borrow().update_surface(device, origin);
+        }
+    }
+
+    /// Attach terrain node to the scene root and refine it on every update_terrains()
+    pub fn add_terrain(&mut self, terrain: PlanetTerrainMutRef) {
+        self.root.add_child(Rc::clone(terrain.borrow().get_node()));
+        self.terrains.push(terrain);
+    }
+
+    /// Refine terrain chunks for the camera. Must be called before update() to have chunks in this frame.
+    pub fn update_terrains(&mut self, camera: &Camera, resource_manager: &mut ResourceManager, object_descriptions: &mut ObjectDescriptions) {
+        let camera_position = camera.get_render_origin();
+        for terrain in &self.terrains {
+            terrain.borrow_mut().update(&camera_position, camera.get_projection_scale(), &self.impostor_settings, resource_manager, object_descriptions);
+        }
+    }
+
+    /// Atmosphere of the planet with center closest to the given world position together with the planet center
+    pub fn get_nearest_atmosphere(&self, position: &cgm::Vector3<f64>) -> Option<(AtmosphereMutRef, cgm::Vector3<f64>)> {
+        self.terrains.iter()
+            .filter_map(|terrain| {
+                let terrain = terrain.borrow();
+                terrain.get_atmosphere().map(|atmosphere| (Rc::clone(atmosphere), terrain.get_center()))
+            })
+            .min_by(|(_, a), (_, b)| {
+                (a - position).magnitude2().partial_cmp(&(b - position).magnitude2()).unwrap_or(std::cmp::Ordering::Equal)
+            })
+    }
+
+    pub fn get_terrains(&self) -> &Vec<PlanetTerrainMutRef> {
+        &self.terrains
+    }
+
+    /// Angular size at which distant planets switch to impostors
+    #[allow(dead_code)]
+    pub fn set_impostor_settings(&mut self, impostor_settings: ImpostorSettings) {
+        self.impostor_settings = impostor_settings;
+    }
+
+    pub fn get_impostor_settings(&self) -> &ImpostorSettings {
+        &self.impostor_settings
+    }
+
+    /// Rings of all planets
+    pub fn get_rings(&self) -> Vec<PlanetRingsMutRef> {
+        self.terrains.iter()
+            .filter_map(|terrain| terrain.borrow().get_rings().map(Rc::clone))
+            .collect()
+    }
+
+    /// Stars drawn behind the scene by the background pass
+    pub fn set_starfield(&mut self, starfield: StarfieldMutRef) {
+        self.starfield = Some(starfield);
+    }
+
+    pub fn get_starfield(&self) -> Option<&StarfieldMutRef> {
+        self.starfield.as_ref()
+    }
+
+    /// Environment drawn behind the scene instead of empty space
+    pub fn set_skybox(&mut self, skybox: SkyboxMutRef) {
+        self.skybox = Some(skybox);
+    }
+
+    pub fn get_skybox(&self) -> Option<&SkyboxMutRef> {
+        self.skybox.as_ref()
+    }
+
+    /// Orbit drawn by the orbits pass
+    pub fn add_orbit_lines(&mut self, orbit_lines: OrbitLinesMutRef) {
+        self.orbit_lines.push(orbit_lines);
+    }
+
+    pub fn get_orbit_lines(&self) -> &Vec<OrbitLinesMutRef> {
+        &self.orbit_lines
+    }
+
+    /// Convert position between reference frames at the current frame interpolation
+    #[allow(dead_code)]
+    pub fn convert_position(
+        &self,
+        position: &cgm::Point3<f64>,
+        from: &ReferenceFrame,
+        to: &ReferenceFrame,
+        simulation: &SimulationClock,
+    ) -> cgm::Point3<f64> {
+        frame::convert_position(position, &from.resolve(self, simulation), &to.resolve(self, simulation))
+    }
+
+    /// Attach player ship to the scene root. It is attracted by all planets with gravity.
+    pub fn set_ship(&mut self, ship: ShipMutRef) {
+        let bodies = self.terrains.iter()
+            .map(|terrain| terrain.borrow())
+            .filter(|terrain| terrain.get_gravitational_parameter() > 0.0)
+            .map(|terrain| (Rc::clone(terrain.get_node()), terrain.get_gravitational_parameter()))
+            .collect();
+        ship.borrow().set_gravity_bodies(bodies);
+        self.root.add_child(Rc::clone(ship.borrow().get_node()));
+        self.ship = Some(ship);
+    }
+
+    /// Camera nodes attached to the scene, including ones imported from glTF models
+    pub fn get_cameras(&self) -> Vec<NodeMutRef> {
+        let mut cameras = vec![];
+        self.root.collect_cameras(&mut cameras);
+        cameras
+    }
+
+    /// Closest object hit by the world space ray at the given frame interpolation. Pick listeners are
+    /// notified of the hit.
+    pub fn pick(&self, ray: &Ray, simulation: &SimulationClock) -> Option<PickResult> {
+        let mut closest = None;
+        let alpha = simulation.get_interpolation_alpha();
+        self.root.pick(ray, &cgm::Matrix4::identity(), alpha, &mut vec![], &mut closest);
+        if let Some(hit) = &closest {
+            for listener in &self.pick_listeners {
+                listener(hit);
+            }
+        }
+
+        closest
+    }
+
+    /// Call the listener with every object picked from now on. Lets gameplay and UI react to picks.
+    pub fn add_pick_listener(&mut self, listener: PickListener) {
+        self.pick_listeners.push(listener);
+    }
+
+    pub fn get_ship(&self) -> Option<&ShipMutRef> {
+        self.ship.as_ref()
+    }
+
+    /// Advance scene by one fixed simulation step
+    pub fn simulate(&mut self, simulation: &SimulationClock) {
+        self.root.simulate(simulation);
     }
 
     pub fn cull(&self) -> HashSet<DrawableHash> {
@@ -78,6 +251,11 @@ impl SceneGraph {
         &self.gpu_model_data.get_ssbo(image_idx)
     }
 
+    /// Rebased transform of a model written in the last update()
+    pub fn get_model_transform(&self, model_index: u32) -> Option<&cgm::Matrix4<f32>> {
+        self.gpu_model_data.get_transform(model_index)
+    }
+
     pub fn get_light_manager(&self) -> &LightManagerMutRef {
         &self.light_manager
     }
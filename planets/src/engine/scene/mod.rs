@@ -2,3 +2,5 @@ pub mod drawlist;
 pub mod graph;
 pub mod node;
 pub mod builder;
+pub mod frame;
+pub mod picking;
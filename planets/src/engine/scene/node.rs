@@ -1,3 +1,4 @@
+use crate::engine::camera::Projection;
 use crate::engine::lights::{Light};
 use crate::util::math;
 use crate::vulkan::drawable::{Drawable, DrawableHash, DrawableInstanceMutRef, DrawableMutRef};
@@ -8,6 +9,8 @@ use std::rc::Rc;
 use cgmath as cgm;
 use crate::engine::gameloop::{GameLoop};
 use crate::engine::models::{ModelData, ModelDataSSBOInterface};
+use crate::engine::scene::picking::{self, PickResult, Ray};
+use crate::engine::simulation::SimulationClock;
 
 pub type NodeMutRef = Rc<RefCell<Node>>;
 
@@ -17,9 +20,11 @@ pub enum PreUpdateAction {
 }
 
 pub type NodeUpdateCall = Box<dyn Fn(&Node, &GameLoop) -> UpdateCallResult>;
+/// Called once per fixed simulation step. Transform returned is interpolated for rendering.
+pub type NodeSimulationCall = Box<dyn Fn(&Node, &SimulationClock) -> UpdateCallResult>;
 
 pub struct UpdateCallResult {
-    pub transform: Option<cgm::Matrix4<f32>>,
+    pub transform: Option<cgm::Matrix4<f64>>,
     pub pre_update_action: Option<PreUpdateAction>,
 }
 
@@ -27,10 +32,18 @@ pub struct UpdateCallResult {
 pub enum NodeContent {
     None,
     Group,
-    Transform(cgm::Matrix4<f32>),
+    Transform(cgm::Matrix4<f64>),
     Drawable(DrawableMutRef),
     DrawableInstance(DrawableInstanceMutRef),
     Light(Light),
+    // Viewpoint looking along node -Z with +Y up
+    Camera(Projection),
+}
+
+// Transforms of the last two simulation steps
+struct SimulationState {
+    previous: cgm::Matrix4<f64>,
+    current: cgm::Matrix4<f64>,
 }
 
 pub struct Node {
@@ -38,6 +51,8 @@ pub struct Node {
     pub content: NodeContent,
     children: Vec<NodeMutRef>,
     pub update_call: Option<NodeUpdateCall>,
+    pub simulation_call: Option<NodeSimulationCall>,
+    simulation_state: Option<SimulationState>,
 }
 
 impl Node {
@@ -51,6 +66,8 @@ impl Node {
             content,
             children: vec![],
             update_call: None,
+            simulation_call: None,
+            simulation_state: None,
         }
     }
 
@@ -72,6 +89,18 @@ impl Node {
         self.children.push(child);
     }
 
+    /// Remove child from this node and all its descendants
+    pub fn remove_child(&mut self, child: &NodeMutRef) {
+        self.children.retain(|c| !Rc::ptr_eq(c, child));
+        for c in &self.children {
+            c.borrow_mut().remove_child(child);
+        }
+    }
+
+    pub fn clear_children(&mut self) {
+        self.children.clear();
+    }
+
     pub fn spawn_instance(&self) -> NodeMutRef {
         let mut instance_node = Node::new();
 
@@ -88,12 +117,127 @@ impl Node {
         Rc::new(RefCell::new(instance_node))
     }
 
+    /// Advance node and its children by one fixed simulation step
+    pub fn simulate(&mut self, simulation: &SimulationClock) {
+        if let Some(simulation_call) = self.simulation_call.as_ref() {
+            let simulation_call_result = simulation_call(self, simulation);
+            if let Some(transform) = simulation_call_result.transform {
+                let previous = match (&self.simulation_state, &self.content) {
+                    (Some(state), _) => state.current,
+                    (None, NodeContent::Transform(t)) => *t,
+                    (None, _) => transform,
+                };
+                self.simulation_state = Some(SimulationState { previous, current: transform });
+            } else if let Some(state) = &mut self.simulation_state {
+                state.previous = state.current;
+            }
+            if let Some(pre_update_action) = simulation_call_result.pre_update_action {
+                self.pre_update_action = pre_update_action;
+            }
+        }
+
+        for child in &mut self.children {
+            child.borrow_mut().simulate(simulation);
+        }
+    }
+
+    /// Transform produced by the last simulation step. Node content holds interpolated transform instead.
+    #[allow(dead_code)]
+    pub fn get_simulated_transform(&self) -> Option<cgm::Matrix4<f64>> {
+        self.simulation_state.as_ref().map(|state| state.current)
+    }
+
+    /// Local transform this node gets on the next update() for the given interpolation alpha
+    pub fn get_local_transform(&self, alpha: f64) -> Option<cgm::Matrix4<f64>> {
+        match (&self.simulation_state, &self.content) {
+            (Some(state), _) => Some(math::interpolate_transform(&state.previous, &state.current, alpha)),
+            (None, NodeContent::Transform(t)) => Some(*t),
+            (None, _) => None,
+        }
+    }
+
+    /// World transform of the first occurrence of the target among descendants of this node placed
+    /// with the given transform. Transforms set by update calls are taken from the last update.
+    pub fn find_world_transform(&self, target: &NodeMutRef, transform: &cgm::Matrix4<f64>, alpha: f64) -> Option<cgm::Matrix4<f64>> {
+        for child in &self.children {
+            let c = child.borrow();
+            let child_transform = match c.get_local_transform(alpha) {
+                Some(t) => transform * t,
+                None => *transform,
+            };
+            if Rc::ptr_eq(child, target) {
+                return Some(child_transform);
+            }
+            if let Some(found) = c.find_world_transform(target, &child_transform, alpha) {
+                return Some(found);
+            }
+        }
+
+        None
+    }
+
+    /// Camera nodes among descendants in depth first order. Nodes of shared subtrees are listed once.
+    pub fn collect_cameras(&self, cameras: &mut Vec<NodeMutRef>) {
+        for child in &self.children {
+            let c = child.borrow();
+            if let NodeContent::Camera(_) = c.content {
+                if !cameras.iter().any(|camera| Rc::ptr_eq(camera, child)) {
+                    cameras.push(Rc::clone(child));
+                }
+            }
+            c.collect_cameras(cameras);
+        }
+    }
+
+    /// Closest drawable instance among descendants of this node placed with the given transform hit by the
+    /// world space ray. Path holds ancestors of this node, closest holds the closest hit found so far.
+    pub fn pick(
+        &self,
+        ray: &Ray,
+        transform: &cgm::Matrix4<f64>,
+        alpha: f64,
+        path: &mut Vec<NodeMutRef>,
+        closest: &mut Option<PickResult>,
+    ) {
+        for child in &self.children {
+            let c = child.borrow();
+            let child_transform = match c.get_local_transform(alpha) {
+                Some(t) => transform * t,
+                None => *transform,
+            };
+            path.push(Rc::clone(child));
+            if let NodeContent::DrawableInstance(instance) = &c.content {
+                if let Some(drawable) = instance.borrow().drawable.upgrade() {
+                    let max_distance = closest.as_ref().map_or(f64::INFINITY, |hit| hit.distance);
+                    if let Some(distance) = picking::intersect_drawable(&drawable.borrow(), ray, &child_transform, max_distance) {
+                        *closest = Some(PickResult {
+                            node: Rc::clone(child),
+                            path: path.clone(),
+                            position: ray.get_point(distance),
+                            distance,
+                        });
+                    }
+                }
+            }
+            c.pick(ray, &child_transform, alpha, path, closest);
+            path.pop();
+        }
+    }
+
     pub fn update(
         &mut self,
         gameloop: &GameLoop,
-        transform: &cgm::Matrix4<f32>,
+        transform: &cgm::Matrix4<f64>,
+        origin: &cgm::Vector3<f64>,
         model_data: &mut ModelData,
     ) {
+        if self.simulation_state.is_some() {
+            let alpha = gameloop.get_simulation().get_interpolation_alpha();
+            if let Some(transform) = self.get_local_transform(alpha) {
+                self.content = NodeContent::Transform(transform);
+            }
+        }
+
         if let Some(update_call) = self.update_call.as_ref() {
             let update_call_result = update_call(self, gameloop);
             if let Some(transform) = update_call_result.transform {
@@ -112,11 +256,12 @@ impl Node {
         match &mut self.content {
             NodeContent::Light(l) => {
                 l.position = math::position_from_transform(&next_transform);
-                l.apply();
+                l.apply(origin);
             }
             NodeContent::DrawableInstance(d) => {
-                let new_model_data = ModelDataSSBOInterface{ transform: next_transform };
-                model_data.set_data_for(d.borrow().get_instance_id() as usize, &new_model_data);
+                let new_model_data = ModelDataSSBOInterface{ transform: math::rebase_transform(&next_transform, origin) };
+                let model_index = model_data.add_model(&new_model_data);
+                d.borrow_mut().set_model_index(model_index);
             }
             _ => {}
         }
@@ -130,7 +275,7 @@ impl Node {
 
         for child in &mut self.children {
             child.borrow_mut()
-                .update(gameloop, &next_transform, model_data);
+                .update(gameloop, &next_transform, origin, model_data);
         }
     }
 }
@@ -148,7 +293,11 @@ mod tests {
     use std::cell::RefCell;
     use std::rc::Rc;
 
-    use super::Node;
+    use cgmath as cgm;
+
+    use crate::engine::camera::Projection;
+
+    use super::{Node, NodeContent};
 
     #[test]
     fn node_add_child() {
@@ -177,17 +326,54 @@ mod tests {
         node.add_child(Rc::clone(&child2));
         node.add_child(Rc::clone(&child3));
 
-        // node.remove_child(&child3);
-        // assert_eq!(node.children.len(), 2);
-        // assert_eq!(child1.borrow().children.len(), 0);
-        // assert_eq!(Rc::ptr_eq(&node.children[0], &child1), true);
-        // assert_eq!(Rc::ptr_eq(&node.children[1], &child2), true);
-        //
-        // node.remove_child(&child1);
-        // assert_eq!(node.children.len(), 1);
-        // assert_eq!(Rc::ptr_eq(&node.children[0], &child2), true);
-        //
-        // node.remove_child(&child2);
-        // assert_eq!(node.children.len(), 0);
+        node.remove_child(&child3);
+        assert_eq!(node.children.len(), 2);
+        assert_eq!(child1.borrow().children.len(), 0);
+        assert_eq!(Rc::ptr_eq(&node.children[0], &child1), true);
+        assert_eq!(Rc::ptr_eq(&node.children[1], &child2), true);
+
+        node.remove_child(&child1);
+        assert_eq!(node.children.len(), 1);
+        assert_eq!(Rc::ptr_eq(&node.children[0], &child2), true);
+
+        node.remove_child(&child2);
+        assert_eq!(node.children.len(), 0);
+    }
+
+    #[test]
+    fn node_find_world_transform() {
+        let translation = |x| NodeContent::Transform(cgm::Matrix4::from_translation(cgm::Vector3::new(x, 0.0, 0.0)));
+        let mut root = Node::new();
+        let planet = Rc::new(RefCell::new(Node::with_content(translation(10.0))));
+        let group = Rc::new(RefCell::new(Node::with_content(NodeContent::Group)));
+        let moon = Rc::new(RefCell::new(Node::with_content(translation(2.0))));
+        group.borrow_mut().add_child(Rc::clone(&moon));
+        planet.borrow_mut().add_child(Rc::clone(&group));
+        root.add_child(Rc::clone(&planet));
+
+        let found = root.find_world_transform(&moon, &cgm::Matrix4::from_scale(1.0), 0.0).unwrap();
+        assert_eq!(found.w, cgm::Vector4::new(12.0, 0.0, 0.0, 1.0));
+
+        let detached = Rc::new(RefCell::new(Node::new()));
+        assert!(root.find_world_transform(&detached, &cgm::Matrix4::from_scale(1.0), 0.0).is_none());
+    }
+
+    #[test]
+    fn node_collect_cameras() {
+        let camera = || Rc::new(RefCell::new(Node::with_content(NodeContent::Camera(Projection::default()))));
+        let mut root = Node::new();
+        let ship = Rc::new(RefCell::new(Node::new()));
+        let cockpit = camera();
+        ship.borrow_mut().add_child(Rc::clone(&cockpit));
+        // Shared model reachable twice
+        root.add_child(Rc::clone(&ship));
+        root.add_child(Rc::clone(&ship));
+        let overview = camera();
+        root.add_child(Rc::clone(&overview));
+
+        let mut cameras = vec![];
+        root.collect_cameras(&mut cameras);
+        assert_eq!(cameras.len(), 2);
+        assert!(Rc::ptr_eq(&cameras[0], &cockpit) && Rc::ptr_eq(&cameras[1], &overview));
     }
 }
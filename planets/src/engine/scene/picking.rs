@@ -0,0 +1,233 @@
+use std::rc::Rc;
+
+use cgmath as cgm;
+use cgmath::prelude::*;
+
+use crate::engine::geometry::Vertex;
+use crate::engine::scene::node::NodeMutRef;
+use crate::vulkan::drawable::{DrawType, Drawable};
+
+// Hits closer than this to the ray origin are ignored to avoid self intersections
+const MIN_HIT_DISTANCE: f64 = 1.0e-9;
+
+/// Half line starting at origin
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: cgm::Point3<f64>,
+    pub direction: cgm::Vector3<f64>,
+}
+
+impl Ray {
+    pub fn new(origin: cgm::Point3<f64>, direction: cgm::Vector3<f64>) -> Self {
+        Ray { origin, direction }
+    }
+
+    pub fn get_point(&self, distance: f64) -> cgm::Point3<f64> {
+        self.origin + self.direction * distance
+    }
+
+    /// Ray in other space. Direction is not normalized, so distances along the ray stay the same.
+    pub fn transform(&self, transform: &cgm::Matrix4<f64>) -> Ray {
+        Ray {
+            origin: transform.transform_point(self.origin),
+            direction: transform.transform_vector(self.direction),
+        }
+    }
+}
+
+/// Axis aligned bounding box
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BoundingBox {
+    pub min: cgm::Point3<f64>,
+    pub max: cgm::Point3<f64>,
+}
+
+impl BoundingBox {
+    /// Box containing all vertices. Box of no vertices is empty and never hit.
+    pub fn from_vertices(vertices: &[Vertex]) -> Self {
+        let empty = BoundingBox {
+            min: cgm::Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            max: cgm::Point3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        };
+        vertices.iter().fold(empty, |bounds, vertex| {
+            let position = get_position(vertex);
+            BoundingBox {
+                min: cgm::Point3::new(bounds.min.x.min(position.x), bounds.min.y.min(position.y), bounds.min.z.min(position.z)),
+                max: cgm::Point3::new(bounds.max.x.max(position.x), bounds.max.y.max(position.y), bounds.max.z.max(position.z)),
+            }
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    /// Distance along the ray to where it enters the box, 0 if it starts inside
+    pub fn intersect(&self, ray: &Ray) -> Option<f64> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let mut near = 0.0_f64;
+        let mut far = f64::INFINITY;
+        for axis in 0..3 {
+            let inverse = 1.0 / ray.direction[axis];
+            let t1 = (self.min[axis] - ray.origin[axis]) * inverse;
+            let t2 = (self.max[axis] - ray.origin[axis]) * inverse;
+            // NaN from rays parallel to a box side is skipped by min() and max()
+            near = near.max(t1.min(t2));
+            far = far.min(t1.max(t2));
+        }
+
+        if near <= far {
+            Some(near)
+        } else {
+            None
+        }
+    }
+}
+
+/// Distance along the ray to the triangle, both sides of it are hit. Möller–Trumbore algorithm.
+pub fn intersect_triangle(ray: &Ray, a: cgm::Point3<f64>, b: cgm::Point3<f64>, c: cgm::Point3<f64>) -> Option<f64> {
+    let edge1 = b - a;
+    let edge2 = c - a;
+    let p = ray.direction.cross(edge2);
+    let determinant = edge1.dot(p);
+    if determinant.abs() < f64::EPSILON * edge1.magnitude() * edge2.magnitude() * ray.direction.magnitude() {
+        // Ray is parallel to the triangle
+        return None;
+    }
+
+    let inverse = 1.0 / determinant;
+    let offset = ray.origin - a;
+    let u = offset.dot(p) * inverse;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = offset.cross(edge1);
+    let v = ray.direction.dot(q) * inverse;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let distance = edge2.dot(q) * inverse;
+    if distance > MIN_HIT_DISTANCE {
+        Some(distance)
+    } else {
+        None
+    }
+}
+
+/// Distance to the closest triangle of the indexed mesh hit by the ray
+pub fn intersect_triangles(ray: &Ray, vertices: &[Vertex], indices: &[i32]) -> Option<f64> {
+    let position = |index: &i32| vertices.get(*index as usize).map(get_position);
+    indices.chunks_exact(3)
+        .filter_map(|triangle| {
+            let (a, b, c) = (position(&triangle[0])?, position(&triangle[1])?, position(&triangle[2])?);
+            intersect_triangle(ray, a, b, c)
+        })
+        .fold(None, |closest: Option<f64>, distance| Some(closest.map_or(distance, |c| c.min(distance))))
+}
+
+/// Distance along the world space ray to the drawable placed with the given world transform. Bounds are
+/// tested first, so triangles are only tested for drawables the ray passes closer than max_distance.
+pub fn intersect_drawable(drawable: &Drawable, ray: &Ray, transform: &cgm::Matrix4<f64>, max_distance: f64) -> Option<f64> {
+    // Impostor quads are expanded to the body silhouette on GPU, the body geometry is picked instead
+    if drawable.draw_type == DrawType::Impostor {
+        return None;
+    }
+
+    let local_ray = ray.transform(&transform.inverse_transform()?);
+    let geometry = drawable.get_geometry();
+    if geometry.bounds.intersect(&local_ray)? >= max_distance {
+        return None;
+    }
+
+    intersect_triangles(&local_ray, &geometry.vertices, &geometry.indices).filter(|distance| *distance < max_distance)
+}
+
+/// Object under the cursor
+#[derive(Clone)]
+pub struct PickResult {
+    // Node with the drawable instance hit
+    #[allow(dead_code)]
+    pub node: NodeMutRef,
+    // Nodes from a child of the scene root down to the hit node
+    pub path: Vec<NodeMutRef>,
+    // World space hit position
+    pub position: cgm::Point3<f64>,
+    // Distance from the ray origin
+    pub distance: f64,
+}
+
+impl PickResult {
+    /// Whether the hit node is the given node or one of its descendants. Tells which game object was picked.
+    pub fn is_part_of(&self, node: &NodeMutRef) -> bool {
+        self.path.iter().any(|n| Rc::ptr_eq(n, node))
+    }
+}
+
+/// Called with every successful pick
+pub type PickListener = Box<dyn Fn(&PickResult)>;
+
+fn get_position(vertex: &Vertex) -> cgm::Point3<f64> {
+    cgm::Point3::from_vec(vertex.position.cast().unwrap_or(cgm::Vector3::zero()))
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath as cgm;
+    use cgmath::prelude::*;
+
+    use crate::engine::geometry::Vertex;
+
+    use super::{intersect_triangles, BoundingBox, Ray};
+
+    // Unit quad in XY plane at the given depth
+    fn quad(z: f32) -> Vec<Vertex> {
+        vec![
+            Vertex::from_position(-1.0, -1.0, z),
+            Vertex::from_position(1.0, -1.0, z),
+            Vertex::from_position(1.0, 1.0, z),
+            Vertex::from_position(-1.0, 1.0, z),
+        ]
+    }
+
+    #[test]
+    fn ray_hits_bounds_then_closest_triangle() {
+        let mut vertices = quad(-5.0);
+        vertices.extend(quad(-3.0));
+        let indices = vec![0, 1, 2, 2, 3, 0, 4, 5, 6, 6, 7, 4];
+        let bounds = BoundingBox::from_vertices(&vertices);
+        assert_eq!(bounds.min, cgm::Point3::new(-1.0, -1.0, -5.0));
+        assert_eq!(bounds.max, cgm::Point3::new(1.0, 1.0, -3.0));
+
+        let ray = Ray::new(cgm::Point3::new(0.5, 0.25, 0.0), -cgm::Vector3::unit_z());
+        assert_eq!(bounds.intersect(&ray), Some(3.0));
+        let distance = intersect_triangles(&ray, &vertices, &indices).unwrap();
+        assert!((distance - 3.0).abs() < 1e-9);
+        assert!((ray.get_point(distance) - cgm::Point3::new(0.5, 0.25, -3.0)).magnitude() < 1e-9);
+
+        // Distances survive moving the ray into scaled model space
+        let transform = cgm::Matrix4::from_nonuniform_scale(2.0, 0.5, 4.0);
+        let scaled = ray.transform(&transform);
+        let scaled_vertices: Vec<Vertex> = vertices.iter()
+            .map(|v| Vertex::from_position(v.position.x * 2.0, v.position.y * 0.5, v.position.z * 4.0))
+            .collect();
+        assert!((intersect_triangles(&scaled, &scaled_vertices, &indices).unwrap() - 3.0).abs() < 1e-9);
+
+        // Misses beside, behind and parallel
+        let beside = Ray::new(cgm::Point3::new(1.5, 0.0, 0.0), -cgm::Vector3::unit_z());
+        assert!(bounds.intersect(&beside).is_none() && intersect_triangles(&beside, &vertices, &indices).is_none());
+        let away = Ray::new(cgm::Point3::new(0.0, 0.0, 0.0), cgm::Vector3::unit_z());
+        assert!(bounds.intersect(&away).is_none() && intersect_triangles(&away, &vertices, &indices).is_none());
+        let parallel = Ray::new(cgm::Point3::new(-2.0, 0.0, -3.0), cgm::Vector3::unit_x());
+        assert!(intersect_triangles(&parallel, &vertices, &indices).is_none());
+
+        // Ray starting inside the bounds
+        let inside = Ray::new(cgm::Point3::new(0.0, 0.0, -4.0), -cgm::Vector3::unit_z());
+        assert_eq!(bounds.intersect(&inside), Some(0.0));
+        assert!((intersect_triangles(&inside, &vertices, &indices).unwrap() - 1.0).abs() < 1e-9);
+        assert!(BoundingBox::from_vertices(&[]).intersect(&ray).is_none());
+    }
+}
@@ -0,0 +1,415 @@
+use std::time;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+
+/// Julian date of the Unix epoch (1970-01-01T00:00:00Z)
+const UNIX_EPOCH_JULIAN_DATE: f64 = 2440587.5;
+/// Julian date of the J2000.0 epoch (2000-01-01T12:00:00 TT) used as default simulation epoch
+pub const J2000_JULIAN_DATE: f64 = 2451545.0;
+pub const SECONDS_PER_DAY: f64 = 86400.0;
+
+/// Default length of one fixed simulation step in simulation seconds
+pub const DEFAULT_FIXED_STEP: f64 = 1.0 / 60.0;
+/// Default number of fixed steps allowed per frame before simulation starts lagging behind
+pub const DEFAULT_MAX_STEPS_PER_FRAME: u32 = 240;
+/// Time warp factors the warp keys step through
+pub const TIME_WARP_LEVELS: [f64; 13] = [
+    0.25, 0.5, 1.0, 2.0, 5.0, 10.0, 100.0, 1000.0, 10_000.0, 100_000.0, 1_000_000.0, 10_000_000.0, 100_000_000.0,
+];
+/// Largest time warp, about three years per real second
+pub const MAX_TIME_WARP: f64 = TIME_WARP_LEVELS[TIME_WARP_LEVELS.len() - 1];
+// Real frame duration steps have to keep up with at any time warp
+const REFERENCE_FRAME_TIME: f64 = 1.0 / 60.0;
+// Minimal real time between warnings about dropped steps
+const DROPPED_STEPS_WARNING_INTERVAL: time::Duration = time::Duration::from_secs(5);
+
+/// Simulation time decoupled from the wall clock.
+/// Simulation time is measured in seconds since a settable epoch. Real frame time multiplied by
+/// the time warp factor is accumulated and consumed in steps of fixed length, so simulation results
+/// do not depend on frame rate. Left over time is used to interpolate between the last two states.
+/// High time warps lengthen the steps by powers of two, so frames never need more steps than allowed.
+pub struct SimulationClock {
+    // Julian date the simulation time is counted from
+    epoch: f64,
+    // Simulation seconds passed since epoch. Counted as steps taken since the step length was set, so
+    // long runs of steps do not accumulate rounding errors.
+    elapsed: f64,
+    step_start: f64,
+    step_count: u64,
+    // Simulation seconds the clock advanced by during the last frame
+    last_delta: f64,
+    // Multiplier applied to real time when advancing simulation time
+    time_warp: f64,
+    // Defines if simulation time is advanced by frame time or not
+    paused: bool,
+    // Length of one simulation step at low time warp in simulation seconds
+    fixed_step: f64,
+    // Length of steps taken, fixed step scaled up for the current time warp
+    step: f64,
+    // Simulation time not consumed by steps yet
+    accumulator: f64,
+    // Explicitly requested simulation time not consumed by steps yet. Never dropped.
+    explicit_time: f64,
+    // Maximal number of steps taken per frame for real time. Time above that limit is dropped.
+    max_steps_per_frame: u32,
+    // Steps left to be taken in the current frame
+    pending_steps: u64,
+    // Steps dropped since the last warning about them and when that warning was logged
+    dropped_steps: u64,
+    dropped_steps_warning: Option<time::Instant>,
+}
+
+impl SimulationClock {
+    pub fn new() -> Self {
+        SimulationClock {
+            epoch: J2000_JULIAN_DATE,
+            elapsed: 0.0,
+            step_start: 0.0,
+            step_count: 0,
+            last_delta: 0.0,
+            time_warp: 1.0,
+            paused: false,
+            fixed_step: DEFAULT_FIXED_STEP,
+            step: DEFAULT_FIXED_STEP,
+            accumulator: 0.0,
+            explicit_time: 0.0,
+            max_steps_per_frame: DEFAULT_MAX_STEPS_PER_FRAME,
+            pending_steps: 0,
+            dropped_steps: 0,
+            dropped_steps_warning: None,
+        }
+    }
+
+    /// Accumulate the given real time duration scaled by time warp and schedule steps for it
+    pub fn advance(&mut self, real_duration: time::Duration) {
+        if !self.paused {
+            self.accumulator += real_duration.as_secs_f64() * self.time_warp;
+        }
+        self.last_delta = 0.0;
+        self.schedule_steps();
+    }
+
+    /// Schedule exact amount of simulation seconds to be simulated. Works while paused too.
+    /// Not limited by max steps per frame, all of it is simulated in the current frame.
+    #[allow(dead_code)]
+    pub fn step(&mut self, seconds: f64) {
+        self.accumulator += seconds;
+        self.explicit_time += seconds;
+        self.last_delta = 0.0;
+        self.schedule_steps();
+    }
+
+    /// Take next scheduled step. Returns false if all steps for this frame were taken.
+    pub fn next_step(&mut self) -> bool {
+        if self.pending_steps == 0 {
+            return false;
+        }
+
+        self.pending_steps -= 1;
+        self.accumulator -= self.step;
+        self.explicit_time = (self.explicit_time - self.step).max(0.0);
+        self.step_count += 1;
+        let elapsed = self.step_start + self.step_count as f64 * self.step;
+        self.last_delta += elapsed - self.elapsed;
+        self.elapsed = elapsed;
+
+        true
+    }
+
+    /// Fraction of step between the previous and the current simulation state to render at
+    pub fn get_interpolation_alpha(&self) -> f64 {
+        let left_over = self.accumulator - self.pending_steps as f64 * self.step;
+        (left_over / self.step).clamp(0.0, 1.0)
+    }
+
+    /// Simulation time matching interpolated transforms rendered this frame
+    pub fn get_interpolated_elapsed(&self) -> f64 {
+        self.elapsed - (1.0 - self.get_interpolation_alpha()) * self.step
+    }
+
+    fn schedule_steps(&mut self) {
+        // Small epsilon keeps exact multiples of step from being lost to rounding errors
+        let to_steps = |seconds: f64| ((seconds + self.step * 1e-9) / self.step).floor() as u64;
+        let steps = to_steps(self.accumulator);
+        let max_steps = self.max_steps_per_frame as u64 + to_steps(self.explicit_time);
+        if steps > max_steps {
+            // Steps are long enough for max steps to keep up with time warp, so this only happens on slow frames
+            self.accumulator -= (steps - max_steps) as f64 * self.step;
+            self.warn_dropped_steps(steps - max_steps);
+        }
+        self.pending_steps = steps.min(max_steps);
+    }
+
+    fn warn_dropped_steps(&mut self, dropped_steps: u64) {
+        self.dropped_steps += dropped_steps;
+        let now = time::Instant::now();
+        let is_due = match self.dropped_steps_warning {
+            Some(last) => now.duration_since(last) >= DROPPED_STEPS_WARNING_INTERVAL,
+            None => true,
+        };
+        if is_due {
+            log::warn!("Simulation can't catch up with slow frames: dropped {} steps", self.dropped_steps);
+            self.dropped_steps = 0;
+            self.dropped_steps_warning = Some(now);
+        }
+    }
+
+    // Lengthen steps by the smallest power of two that lets max steps per frame keep up with time warp
+    fn update_step(&mut self) {
+        let max_time = self.max_steps_per_frame.max(1) as f64 * self.fixed_step;
+        let scale = (self.time_warp * REFERENCE_FRAME_TIME / max_time).max(1.0).log2().ceil().exp2();
+        let step = self.fixed_step * scale;
+        if step != self.step {
+            self.step_start = self.elapsed;
+            self.step_count = 0;
+            self.step = step;
+            self.schedule_steps();
+        }
+    }
+
+    /// Set length of one simulation step at low time warp. Values that are not finite and positive are ignored.
+    #[allow(dead_code)]
+    pub fn set_fixed_step(&mut self, seconds: f64) {
+        if !(seconds.is_finite() && seconds > 0.0) {
+            return log::error!("Invalid simulation fixed step: {}", seconds);
+        }
+        self.fixed_step = seconds;
+        self.update_step();
+    }
+
+    #[allow(dead_code)]
+    pub fn get_fixed_step(&self) -> f64 {
+        self.fixed_step
+    }
+
+    /// Length of the steps taken at the current time warp
+    pub fn get_step(&self) -> f64 {
+        self.step
+    }
+
+    #[allow(dead_code)]
+    pub fn set_max_steps_per_frame(&mut self, max_steps: u32) {
+        self.max_steps_per_frame = max_steps;
+        self.update_step();
+    }
+
+    /// Set calendar date of the epoch and restart simulation time from it
+    #[allow(dead_code)]
+    pub fn set_epoch(&mut self, date_time: &DateTime<Utc>) {
+        self.set_epoch_julian(Self::julian_date_from_date_time(date_time));
+    }
+
+    /// Set Julian date of the epoch and restart simulation time from it
+    #[allow(dead_code)]
+    pub fn set_epoch_julian(&mut self, julian_date: f64) {
+        self.epoch = julian_date;
+        self.elapsed = 0.0;
+        self.step_start = 0.0;
+        self.step_count = 0;
+        self.last_delta = 0.0;
+        self.accumulator = 0.0;
+        self.explicit_time = 0.0;
+        self.pending_steps = 0;
+    }
+
+    #[allow(dead_code)]
+    pub fn get_epoch_julian(&self) -> f64 {
+        self.epoch
+    }
+
+    /// Current simulation time as Julian date
+    pub fn get_julian_date(&self) -> f64 {
+        self.epoch + self.elapsed / SECONDS_PER_DAY
+    }
+
+    /// Current simulation time as calendar date
+    pub fn get_date_time(&self) -> DateTime<Utc> {
+        Self::date_time_from_julian_date(self.get_julian_date())
+    }
+
+    /// Simulation seconds passed since epoch
+    pub fn get_elapsed(&self) -> f64 {
+        self.elapsed
+    }
+
+    /// Simulation seconds passed during the last frame
+    pub fn get_last_delta(&self) -> f64 {
+        self.last_delta
+    }
+
+    pub fn set_time_warp(&mut self, time_warp: f64) {
+        self.time_warp = time_warp.clamp(0.0, MAX_TIME_WARP);
+        self.update_step();
+    }
+
+    pub fn get_time_warp(&self) -> f64 {
+        self.time_warp
+    }
+
+    /// Switch to the next larger time warp level, stays at the largest one
+    pub fn increase_time_warp(&mut self) {
+        if let Some(level) = TIME_WARP_LEVELS.iter().find(|l| **l > self.time_warp) {
+            self.set_time_warp(*level);
+        }
+    }
+
+    /// Switch to the next smaller time warp level, stays at the smallest one
+    pub fn decrease_time_warp(&mut self) {
+        if let Some(level) = TIME_WARP_LEVELS.iter().rev().find(|l| **l < self.time_warp) {
+            self.set_time_warp(*level);
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    #[allow(dead_code)]
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn julian_date_from_date_time(date_time: &DateTime<Utc>) -> f64 {
+        let seconds = date_time.timestamp() as f64 + date_time.timestamp_subsec_nanos() as f64 * 1e-9;
+        UNIX_EPOCH_JULIAN_DATE + seconds / SECONDS_PER_DAY
+    }
+
+    pub fn date_time_from_julian_date(julian_date: f64) -> DateTime<Utc> {
+        // Julian date in f64 is only precise to a few tens of microseconds, so round to milliseconds
+        let millis = ((julian_date - UNIX_EPOCH_JULIAN_DATE) * SECONDS_PER_DAY * 1000.0).round() as i64;
+        let nanos = (millis.rem_euclid(1000) * 1_000_000) as u32;
+        let naive = NaiveDateTime::from_timestamp_opt(millis.div_euclid(1000), nanos)
+            .unwrap_or(NaiveDateTime::MIN);
+
+        DateTime::<Utc>::from_utc(naive, Utc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time;
+
+    use chrono::{TimeZone, Utc};
+
+    use super::{SimulationClock, J2000_JULIAN_DATE, MAX_TIME_WARP, TIME_WARP_LEVELS};
+
+    #[test]
+    fn julian_date_conversion() {
+        let j2000 = Utc.with_ymd_and_hms(2000, 1, 1, 12, 0, 0).unwrap();
+        assert_eq!(SimulationClock::julian_date_from_date_time(&j2000), J2000_JULIAN_DATE);
+        assert_eq!(SimulationClock::date_time_from_julian_date(J2000_JULIAN_DATE), j2000);
+    }
+
+    fn run_steps(clock: &mut SimulationClock) -> u32 {
+        let mut steps = 0;
+        while clock.next_step() {
+            steps += 1;
+        }
+        steps
+    }
+
+    #[test]
+    fn time_warp_and_pause() {
+        let mut clock = SimulationClock::new();
+        clock.set_fixed_step(0.25);
+        clock.set_time_warp(10.0);
+        clock.advance(time::Duration::from_millis(500));
+        assert_eq!(run_steps(&mut clock), 20);
+        assert_eq!(clock.get_elapsed(), 5.0);
+        assert_eq!(clock.get_last_delta(), 5.0);
+
+        clock.pause();
+        clock.advance(time::Duration::from_secs(1));
+        assert_eq!(run_steps(&mut clock), 0);
+        assert_eq!(clock.get_elapsed(), 5.0);
+        assert_eq!(clock.get_last_delta(), 0.0);
+
+        clock.step(1.5);
+        run_steps(&mut clock);
+        assert_eq!(clock.get_elapsed(), 6.5);
+    }
+
+    #[test]
+    fn fixed_steps_do_not_depend_on_frame_rate() {
+        let mut slow = SimulationClock::new();
+        slow.set_fixed_step(0.25);
+        slow.advance(time::Duration::from_millis(600));
+        assert_eq!(run_steps(&mut slow), 2);
+        assert!((slow.get_interpolation_alpha() - 0.4).abs() < 1e-9);
+
+        let mut fast = SimulationClock::new();
+        fast.set_fixed_step(0.25);
+        for _ in 0..6 {
+            fast.advance(time::Duration::from_millis(100));
+            run_steps(&mut fast);
+        }
+        assert_eq!(fast.get_elapsed(), slow.get_elapsed());
+        assert!((fast.get_interpolation_alpha() - 0.4).abs() < 1e-9);
+    }
+
+    #[test]
+    fn catch_up_is_limited() {
+        let mut clock = SimulationClock::new();
+        clock.set_fixed_step(0.25);
+        clock.set_max_steps_per_frame(4);
+        clock.advance(time::Duration::from_millis(2100));
+        assert_eq!(run_steps(&mut clock), 4);
+        assert_eq!(clock.get_elapsed(), 1.0);
+        assert!((clock.get_interpolation_alpha() - 0.4).abs() < 1e-9);
+    }
+
+    #[test]
+    fn high_time_warp_lengthens_steps() {
+        let mut clock = SimulationClock::new();
+        for _ in 0..TIME_WARP_LEVELS.len() {
+            clock.increase_time_warp();
+        }
+        assert_eq!(clock.get_time_warp(), MAX_TIME_WARP);
+        clock.set_time_warp(1e12);
+        assert_eq!(clock.get_time_warp(), MAX_TIME_WARP);
+
+        // Frames at the reference rate simulate all warped time without dropping steps
+        clock.set_time_warp(100_000.0);
+        assert_eq!(clock.get_step(), clock.get_fixed_step() * 512.0);
+        for _ in 0..10 {
+            clock.advance(time::Duration::from_secs_f64(1.0 / 60.0));
+            assert!(run_steps(&mut clock) <= 240);
+        }
+        let expected = 10.0 * 100_000.0 / 60.0;
+        assert!(clock.get_elapsed() <= expected && expected - clock.get_elapsed() < clock.get_step());
+
+        for _ in 0..TIME_WARP_LEVELS.len() {
+            clock.decrease_time_warp();
+        }
+        assert_eq!(clock.get_time_warp(), 0.25);
+        assert_eq!(clock.get_step(), clock.get_fixed_step());
+
+        clock.set_fixed_step(0.0);
+        clock.set_fixed_step(f64::NAN);
+        assert_eq!(clock.get_fixed_step(), 1.0 / 60.0);
+        clock.advance(time::Duration::from_millis(100));
+        assert!(clock.get_interpolation_alpha().is_finite());
+    }
+
+    #[test]
+    fn epoch_restarts_simulation_time() {
+        let mut clock = SimulationClock::new();
+        clock.step(86400.0);
+        run_steps(&mut clock);
+        assert_eq!(clock.get_julian_date(), J2000_JULIAN_DATE + 1.0);
+
+        let epoch = Utc.with_ymd_and_hms(2024, 3, 20, 3, 6, 0).unwrap();
+        clock.set_epoch(&epoch);
+        assert_eq!(clock.get_elapsed(), 0.0);
+        assert_eq!(clock.get_date_time(), epoch);
+    }
+}
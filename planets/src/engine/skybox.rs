@@ -0,0 +1,168 @@
+use std::cell::RefCell;
+use std::f32::consts::PI;
+use std::rc::Rc;
+
+use cgmath as cgm;
+use cgmath::prelude::*;
+use image::{DynamicImage, Rgba32FImage};
+
+use crate::vulkan::img::image::{ImageMutRef, CUBE_FACES};
+
+pub type SkyboxMutRef = Rc<RefCell<Skybox>>;
+
+/// Direction through texel coordinates of a cube face. Faces are ordered +X, -X, +Y, -Y, +Z, -Z and
+/// oriented as Vulkan samples cube maps.
+pub fn cube_face_direction(face: u32, s: f32, t: f32) -> cgm::Vector3<f32> {
+    let a = 2.0 * s - 1.0;
+    let b = 2.0 * t - 1.0;
+    let direction = match face {
+        0 => cgm::Vector3::new(1.0, -b, -a),
+        1 => cgm::Vector3::new(-1.0, -b, a),
+        2 => cgm::Vector3::new(a, 1.0, b),
+        3 => cgm::Vector3::new(a, -1.0, -b),
+        4 => cgm::Vector3::new(a, -b, 1.0),
+        _ => cgm::Vector3::new(-a, -b, -1.0),
+    };
+
+    direction.normalize()
+}
+
+/// Equirectangular texture coordinates of a direction. U follows longitude around +Y starting at -X,
+/// V goes from +Y at the top to -Y at the bottom.
+pub fn equirect_uv(direction: &cgm::Vector3<f32>) -> cgm::Vector2<f32> {
+    let u = 0.5 + direction.z.atan2(direction.x) / (2.0 * PI);
+    let v = direction.y.clamp(-1.0, 1.0).acos() / PI;
+
+    cgm::Vector2::new(u, v)
+}
+
+// Bilinear sample wrapping horizontally and clamping vertically
+fn sample_equirect(image: &Rgba32FImage, uv: cgm::Vector2<f32>) -> [f32; 4] {
+    let (width, height) = image.dimensions();
+    let x = uv.x * width as f32 - 0.5;
+    let y = (uv.y * height as f32 - 0.5).max(0.0).min(height as f32 - 1.0);
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+
+    let texel = |x: f32, y: f32| {
+        let x = (x as i64).rem_euclid(width as i64) as u32;
+        let y = (y as u32).min(height - 1);
+        image.get_pixel(x, y).0
+    };
+    let (a, b, c, d) = (texel(x0, y0), texel(x0 + 1.0, y0), texel(x0, y0 + 1.0), texel(x0 + 1.0, y0 + 1.0));
+
+    let mut result = [0.0; 4];
+    for i in 0..4 {
+        let top = a[i] + (b[i] - a[i]) * fx;
+        let bottom = c[i] + (d[i] - c[i]) * fx;
+        result[i] = top + (bottom - top) * fy;
+    }
+
+    result
+}
+
+/// Resample equirectangular image into cube faces stacked vertically. Float images stay in float,
+/// others are converted back to 8 bit.
+pub fn equirect_to_cube(equirect: &DynamicImage, face_size: u32) -> DynamicImage {
+    let source = equirect.to_rgba32f();
+    let mut faces = Rgba32FImage::new(face_size, face_size * CUBE_FACES);
+    for face in 0..CUBE_FACES {
+        for y in 0..face_size {
+            for x in 0..face_size {
+                let s = (x as f32 + 0.5) / face_size as f32;
+                let t = (y as f32 + 0.5) / face_size as f32;
+                let uv = equirect_uv(&cube_face_direction(face, s, t));
+                faces.put_pixel(x, face * face_size + y, image::Rgba(sample_equirect(&source, uv)));
+            }
+        }
+    }
+
+    match equirect {
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => DynamicImage::ImageRgba32F(faces),
+        _ => DynamicImage::ImageRgba8(DynamicImage::ImageRgba32F(faces).into_rgba8()),
+    }
+}
+
+/// Environment cube map drawn behind the scene
+pub struct Skybox {
+    image: ImageMutRef,
+    // Rotation of skybox frame to world frame
+    orientation: cgm::Matrix3<f64>,
+    // Multiplier of HDR values
+    intensity: f32,
+}
+
+impl Skybox {
+    pub fn new_mut_ref(image: ImageMutRef) -> SkyboxMutRef {
+        Rc::new(RefCell::new(Skybox::new(image)))
+    }
+
+    pub fn new(image: ImageMutRef) -> Self {
+        Skybox {
+            image,
+            orientation: cgm::Matrix3::identity(),
+            intensity: 1.0,
+        }
+    }
+
+    pub fn set_orientation(&mut self, orientation: cgm::Matrix3<f64>) {
+        self.orientation = orientation;
+    }
+
+    pub fn get_orientation(&self) -> &cgm::Matrix3<f64> {
+        &self.orientation
+    }
+
+    #[allow(dead_code)]
+    pub fn set_intensity(&mut self, intensity: f32) {
+        self.intensity = intensity;
+    }
+
+    pub fn get_intensity(&self) -> f32 {
+        self.intensity
+    }
+
+    pub fn get_image(&self) -> &ImageMutRef {
+        &self.image
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath as cgm;
+    use cgmath::prelude::*;
+    use image::{DynamicImage, Rgba32FImage};
+
+    use super::{cube_face_direction, equirect_to_cube, equirect_uv};
+
+    #[test]
+    fn cube_faces_point_along_axes() {
+        let axes = [
+            cgm::Vector3::unit_x(), -cgm::Vector3::unit_x(),
+            cgm::Vector3::unit_y(), -cgm::Vector3::unit_y(),
+            cgm::Vector3::unit_z(), -cgm::Vector3::unit_z(),
+        ];
+        for (face, axis) in axes.iter().enumerate() {
+            assert!(cube_face_direction(face as u32, 0.5, 0.5).dot(*axis) > 0.9999);
+        }
+        // Top row of side faces looks up
+        assert!(cube_face_direction(4, 0.5, 0.0).y > 0.0);
+
+        assert!((equirect_uv(&cgm::Vector3::unit_y()).y).abs() < 1e-6);
+        assert!(equirect_uv(&-cgm::Vector3::unit_x()).x.fract().abs() < 1e-6);
+    }
+
+    #[test]
+    fn equirect_sky_and_ground_land_on_vertical_faces() {
+        let mut equirect = Rgba32FImage::new(64, 32);
+        for (_, y, pixel) in equirect.enumerate_pixels_mut() {
+            *pixel = if y < 16 { image::Rgba([4.0, 0.0, 0.0, 1.0]) } else { image::Rgba([0.0, 0.0, 2.0, 1.0]) };
+        }
+
+        let faces = equirect_to_cube(&DynamicImage::ImageRgba32F(equirect), 8).into_rgba32f();
+        assert_eq!(faces.dimensions(), (8, 48));
+        // Centers of +Y and -Y faces
+        assert_eq!(faces.get_pixel(4, 2 * 8 + 4).0, [4.0, 0.0, 0.0, 1.0]);
+        assert_eq!(faces.get_pixel(4, 3 * 8 + 4).0, [0.0, 0.0, 2.0, 1.0]);
+    }
+}
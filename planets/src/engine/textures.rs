@@ -1,10 +1,20 @@
 use std::cell::RefCell;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
 use std::rc::Rc;
 
 use std::collections::HashMap;
 
+use ash::vk;
+use image::{DynamicImage, Rgb32FImage};
+use image::codecs::hdr::HdrDecoder;
+use image::io::Reader as ImageReader;
+
+use crate::engine::skybox::equirect_to_cube;
 use crate::vulkan::device::{DeviceMutRef};
-use crate::vulkan::img::image::{Image, ImageMutRef};
+use crate::vulkan::img::image::{Image, ImageMutRef, CUBE_FACES};
+use crate::vulkan::img::sampler::Sampler;
 use crate::vulkan::resources::manager::{ResourceManagerMutRef};
 
 const INVALID_IMAGE_PATH: &str = "assets/textures/invalid.png";
@@ -54,6 +64,106 @@ impl TextureManager {
         }
     }
 
+    /// Create texture from pixel data generated at runtime. It is uploaded with other pending textures.
+    pub fn add_texture(&mut self, name: &str, data: DynamicImage, format: vk::Format) -> ImageMutRef {
+        let image = Rc::new(RefCell::new(Image::from_data(&self.device, data, format, name)));
+        self.pending_uploads.insert(name.to_string(), Rc::clone(&image));
+
+        image
+    }
+
+    /// Cube map from six face images in +X, -X, +Y, -Y, +Z, -Z order
+    pub fn get_cubemap(&mut self, face_paths: &[&str; 6]) -> Result<ImageMutRef, String> {
+        let key = face_paths.join(";");
+        if let Some(existing) = self.loaded.get(&key).or_else(|| self.pending_uploads.get(&key)) {
+            return Ok(Rc::clone(existing));
+        }
+
+        let faces = face_paths.iter()
+            .map(|path| TextureManager::decode(path))
+            .collect::<Result<Vec<DynamicImage>, String>>()?;
+        let size = faces[0].width();
+        if faces.iter().any(|face| face.width() != size || face.height() != size) {
+            return Err(format!("Cube map faces {} must be square and of the same size", key));
+        }
+
+        // Faces are stacked vertically, so their pixels end up one after another
+        let format = Image::format_for_data(&faces[0]);
+        let stacked = if format == vk::Format::R8G8B8A8_SRGB {
+            let pixels = faces.into_iter().flat_map(|face| face.into_rgba8().into_raw()).collect();
+            DynamicImage::ImageRgba8(image::RgbaImage::from_raw(size, size * CUBE_FACES, pixels).unwrap())
+        } else {
+            let pixels = faces.into_iter().flat_map(|face| face.into_rgba32f().into_raw()).collect();
+            DynamicImage::ImageRgba32F(image::Rgba32FImage::from_raw(size, size * CUBE_FACES, pixels).unwrap())
+        };
+
+        Ok(self.add_cubemap(&key, stacked, format))
+    }
+
+    /// Cube map resampled from an equirectangular image. Face size defaults to a quarter of image width.
+    pub fn get_equirect_cubemap(&mut self, path: &str, face_size: Option<u32>) -> Result<ImageMutRef, String> {
+        if let Some(existing) = self.loaded.get(path).or_else(|| self.pending_uploads.get(path)) {
+            return Ok(Rc::clone(existing));
+        }
+
+        let equirect = TextureManager::decode(path)?;
+        let face_size = face_size.unwrap_or((equirect.width() / 4).max(1));
+        let format = Image::format_for_data(&equirect);
+        let faces = equirect_to_cube(&equirect, face_size);
+
+        Ok(self.add_cubemap(path, faces, format))
+    }
+
+    /// Equirectangular map of a planet surface. It is sampled with longitude wrapping around.
+    pub fn get_equirect_texture(&mut self, path: &str) -> Result<ImageMutRef, String> {
+        if let Some(existing) = self.loaded.get(path).or_else(|| self.pending_uploads.get(path)) {
+            return Ok(Rc::clone(existing));
+        }
+
+        let data = TextureManager::decode(path)?;
+        let format = Image::format_for_data(&data);
+        let mut image = Image::from_data(&self.device, data, format, path);
+        image.sampler = Sampler::equirect(&self.device);
+        let image = Rc::new(RefCell::new(image));
+        self.pending_uploads.insert(path.to_string(), Rc::clone(&image));
+
+        Ok(image)
+    }
+
+    fn add_cubemap(&mut self, key: &str, faces: DynamicImage, format: vk::Format) -> ImageMutRef {
+        let image = Rc::new(RefCell::new(Image::cube_from_data(&self.device, faces, format, key)));
+        self.pending_uploads.insert(key.to_string(), Rc::clone(&image));
+
+        image
+    }
+
+    fn decode(path: &str) -> Result<DynamicImage, String> {
+        // Generic decoding converts Radiance images to 8 bit
+        if Path::new(path).extension().is_some_and(|ext| ext.eq_ignore_ascii_case("hdr")) {
+            return TextureManager::decode_hdr(path);
+        }
+
+        match ImageReader::open(path) {
+            Ok(reader) => reader.decode().map_err(|e| format!("Could not decode image file {}: {}", path, e)),
+            Err(_) => Err(format!("Could not open image file {}", path)),
+        }
+    }
+
+    fn decode_hdr(path: &str) -> Result<DynamicImage, String> {
+        let file = File::open(path).map_err(|_| format!("Could not open image file {}", path))?;
+        let decode_error = |e: image::ImageError| format!("Could not decode image file {}: {}", path, e);
+        let decoder = HdrDecoder::new(BufReader::new(file)).map_err(decode_error)?;
+        let metadata = decoder.metadata();
+        let pixels = decoder.read_image_hdr().map_err(decode_error)?
+            .into_iter()
+            .flat_map(|pixel| pixel.0)
+            .collect();
+
+        Rgb32FImage::from_raw(metadata.width, metadata.height, pixels)
+            .map(DynamicImage::ImageRgb32F)
+            .ok_or_else(|| format!("Could not decode image file {}: size mismatch", path))
+    }
+
     pub fn upload_pending(&mut self) {
         for (key, image) in &self.pending_uploads {
             if let Ok(()) = image.borrow_mut().upload(&self.device.borrow(), &mut self.resource_manager.borrow_mut()) {
@@ -1,38 +1,207 @@
-use winit::dpi::PhysicalSize;
+use serde::Deserialize;
+use winit::dpi::{LogicalPosition, LogicalSize, PhysicalSize};
 use winit::event_loop::EventLoop;
-use winit::window::WindowBuilder;
+use winit::monitor::MonitorHandle;
+use winit::window::{CursorGrabMode, Fullscreen, WindowBuilder};
 use crate::util::constants::{WINDOW_HEIGHT, WINDOW_TITLE, WINDOW_WIDTH};
 use crate::vulkan::device::DeviceMutRef;
-use crate::vulkan::swapchain::{SurfaceDefinition, Swapchain};
+use crate::vulkan::swapchain::{PresentSettings, SurfaceDefinition, Swapchain};
+
+pub const WINDOW_CONFIG_PATH: &str = "assets/config/window.json";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum WindowMode {
+    Windowed,
+    // Undecorated window covering the whole monitor at its current video mode
+    Borderless,
+    // Exclusive fullscreen with the video mode chosen by window settings
+    Exclusive,
+}
+
+/// How the window is created. Sizes and positions are in logical pixels unless stated otherwise.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct WindowSettings {
+    pub mode: WindowMode,
+    pub resizable: bool,
+    // Inner size in windowed mode
+    pub size: [u32; 2],
+    // Outer position in windowed mode, None lets the OS place the window
+    pub position: Option<[i32; 2]>,
+    // Exclusive fullscreen size in physical pixels and refresh rate in Hz. None takes the largest and fastest.
+    pub fullscreen_size: Option<[u32; 2]>,
+    pub refresh_rate: Option<u32>,
+    pub present: PresentSettings,
+    // Frame rate limit, None renders as fast as presentation allows
+    pub max_fps: Option<u32>,
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        WindowSettings {
+            mode: WindowMode::Windowed,
+            resizable: true,
+            size: [WINDOW_WIDTH, WINDOW_HEIGHT],
+            position: None,
+            fullscreen_size: None,
+            refresh_rate: None,
+            present: PresentSettings::default(),
+            max_fps: Some(60),
+        }
+    }
+}
+
+impl WindowSettings {
+    pub fn load(path: &str) -> Result<WindowSettings, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read window config {}: {}", path, e))?;
+        serde_json::from_str(&text).map_err(|e| format!("Failed to parse window config {}: {}", path, e))
+    }
+
+    /// Settings from the config file, or the defaults if it can't be loaded
+    pub fn load_or_default(path: &str) -> WindowSettings {
+        WindowSettings::load(path).unwrap_or_else(|e| {
+            log::error!("{}", e);
+            WindowSettings::default()
+        })
+    }
+
+    /// Fullscreen state for the mode on the given monitor. Exclusive mode falls back to borderless when
+    /// the monitor has no video modes.
+    fn get_fullscreen(&self, mode: WindowMode, monitor: Option<MonitorHandle>) -> Option<Fullscreen> {
+        match mode {
+            WindowMode::Windowed => None,
+            WindowMode::Borderless => Some(Fullscreen::Borderless(monitor)),
+            WindowMode::Exclusive => {
+                let video_modes: Vec<_> = monitor.iter().flat_map(|m| m.video_modes()).collect();
+                let infos: Vec<VideoModeInfo> = video_modes.iter()
+                    .map(|m| VideoModeInfo {
+                        size: [m.size().width, m.size().height],
+                        refresh_rate_millihertz: m.refresh_rate_millihertz(),
+                        bit_depth: m.bit_depth(),
+                    })
+                    .collect();
+                match choose_video_mode(&infos, self.fullscreen_size, self.refresh_rate) {
+                    Some(i) => {
+                        log::info!("Exclusive fullscreen video mode: {}", video_modes[i]);
+                        Some(Fullscreen::Exclusive(video_modes[i].clone()))
+                    },
+                    None => {
+                        log::warn!("No video modes for exclusive fullscreen, using borderless fullscreen.");
+                        Some(Fullscreen::Borderless(monitor))
+                    },
+                }
+            },
+        }
+    }
+}
+
+// Video mode properties the choice is based on
+#[derive(Clone, Debug, PartialEq)]
+struct VideoModeInfo {
+    size: [u32; 2],
+    refresh_rate_millihertz: u32,
+    bit_depth: u16,
+}
+
+// Index of the mode closest to the wanted size, then to the wanted refresh rate, then with the most color bits.
+// Without wishes the largest size and the highest refresh rate are closest.
+fn choose_video_mode(modes: &[VideoModeInfo], size: Option<[u32; 2]>, refresh_rate: Option<u32>) -> Option<usize> {
+    let size_difference = |mode: &VideoModeInfo| match size {
+        Some([width, height]) => (mode.size[0] as i64 - width as i64).abs() + (mode.size[1] as i64 - height as i64).abs(),
+        None => -(mode.size[0] as i64 * mode.size[1] as i64),
+    };
+    let refresh_difference = |mode: &VideoModeInfo| match refresh_rate {
+        Some(hz) => (mode.refresh_rate_millihertz as i64 - hz as i64 * 1000).abs(),
+        None => -(mode.refresh_rate_millihertz as i64),
+    };
+    (0..modes.len()).min_by_key(|i| {
+        let mode = &modes[*i];
+        (size_difference(mode), refresh_difference(mode), -(mode.bit_depth as i64))
+    })
+}
 
 pub struct Window {
     os_window: winit::window::Window,
     swapchain: Option<Swapchain>,
+    settings: WindowSettings,
+    mode: WindowMode,
+    // Framebuffer size in physical pixels as reported by the last resize or scale factor change
+    size: cgmath::Vector2<u32>,
+    // Physical pixels per logical pixel
+    scale_factor: f64,
+    cursor_grabbed: bool,
 }
 
 impl Window {
-    pub fn new(instance: &ash::Instance, device: &DeviceMutRef, surface: &SurfaceDefinition, os_window: winit::window::Window) -> Self {
+    pub fn new(
+        instance: &ash::Instance,
+        device: &DeviceMutRef,
+        surface: &SurfaceDefinition,
+        os_window: winit::window::Window,
+        settings: WindowSettings,
+    ) -> Self {
+        let inner_size = os_window.inner_size();
         let swapchain = Swapchain::new(
             instance,
             device,
             surface,
-            os_window.inner_size().width,
-            os_window.inner_size().height,
+            inner_size.width,
+            inner_size.height,
+            &settings.present,
             &None,
         );
 
         Window {
+            size: cgmath::Vector2::new(inner_size.width, inner_size.height),
+            scale_factor: os_window.scale_factor(),
             os_window,
             swapchain: Some(swapchain),
+            mode: settings.mode,
+            settings,
+            cursor_grabbed: false,
         }
     }
 
-    pub fn create_os_window(event_loop: &EventLoop<()>) -> winit::window::Window {
-        WindowBuilder::new()
+    /// Window size is given in logical pixels, so it looks the same on HiDPI displays with a larger framebuffer
+    pub fn create_os_window(event_loop: &EventLoop<()>, settings: &WindowSettings) -> winit::window::Window {
+        let [width, height] = settings.size;
+        let mut builder = WindowBuilder::new()
             .with_title(WINDOW_TITLE)
-            .with_inner_size(PhysicalSize::new(WINDOW_WIDTH, WINDOW_HEIGHT))
-            .build(event_loop)
-            .unwrap()
+            .with_inner_size(LogicalSize::new(width, height))
+            .with_resizable(settings.resizable)
+            .with_fullscreen(settings.get_fullscreen(settings.mode, event_loop.primary_monitor()));
+        if let Some([x, y]) = settings.position {
+            builder = builder.with_position(LogicalPosition::new(x, y));
+        }
+
+        builder.build(event_loop).unwrap()
+    }
+
+    /// Switch window mode. Resize events that follow recreate the swapchain.
+    pub fn set_mode(&mut self, mode: WindowMode) {
+        log::info!("Window mode: {:?}", mode);
+        let monitor = self.os_window.current_monitor();
+        self.os_window.set_fullscreen(self.settings.get_fullscreen(mode, monitor));
+        self.mode = mode;
+    }
+
+    #[allow(dead_code)]
+    pub fn get_mode(&self) -> WindowMode {
+        self.mode
+    }
+
+    pub fn get_settings(&self) -> &WindowSettings {
+        &self.settings
+    }
+
+    /// Switch between windowed mode and the fullscreen mode from settings, borderless if settings are windowed
+    pub fn toggle_fullscreen(&mut self) {
+        let mode = match (self.mode, self.settings.mode) {
+            (WindowMode::Windowed, WindowMode::Windowed) => WindowMode::Borderless,
+            (WindowMode::Windowed, fullscreen) => fullscreen,
+            _ => WindowMode::Windowed,
+        };
+        self.set_mode(mode);
     }
 
     pub fn get_mut_swapchain(&mut self) -> &mut Option<Swapchain> {
@@ -57,14 +226,15 @@ impl Window {
         device: &DeviceMutRef,
         surface: &SurfaceDefinition,
     ) {
-        let window_size = self.os_window.inner_size();
-
+        // Old swapchain is destroyed below, its images and sync objects must not be in use anymore
+        device.borrow().wait_idle();
         self.swapchain = Some(Swapchain::new(
             ash_instance,
             device,
             surface,
-            window_size.width,
-            window_size.height,
+            self.size.x,
+            self.size.y,
+            &self.settings.present,
             &self.swapchain,
         ));
     }
@@ -77,7 +247,80 @@ impl Window {
         self.os_window.request_redraw();
     }
 
+    /// Framebuffer size in physical pixels. Cursor positions from window events are in the same pixels.
     pub fn get_size(&self) -> cgmath::Vector2::<u32> {
-        cgmath::Vector2::new(self.os_window.inner_size().width, self.os_window.inner_size().height)
+        self.size
     }
-}
\ No newline at end of file
+
+    /// Take the new size from resize events. Inner size of the OS window may lag behind it.
+    pub fn set_size(&mut self, size: &PhysicalSize<u32>) {
+        self.size = cgmath::Vector2::new(size.width, size.height);
+    }
+
+    /// Take the new scale factor and framebuffer size when the window moves to a display with other DPI
+    pub fn set_scale_factor(&mut self, scale_factor: f64, size: &PhysicalSize<u32>) {
+        log::info!("Window scale factor changed to {}", scale_factor);
+        self.scale_factor = scale_factor;
+        self.set_size(size);
+    }
+
+    pub fn get_scale_factor(&self) -> f64 {
+        self.scale_factor
+    }
+
+    /// Capture and hide the cursor for relative mouse movement, or release and show it. Cursor is locked in
+    /// place where supported and confined to the window otherwise.
+    pub fn set_cursor_grab(&mut self, grab: bool) {
+        let result = if grab {
+            self.os_window.set_cursor_grab(CursorGrabMode::Locked)
+                .or_else(|_| self.os_window.set_cursor_grab(CursorGrabMode::Confined))
+        } else {
+            self.os_window.set_cursor_grab(CursorGrabMode::None)
+        };
+        if let Err(e) = result {
+            log::warn!("Failed to set cursor grab: {}", e);
+        }
+        self.os_window.set_cursor_visible(!grab);
+        self.cursor_grabbed = grab;
+    }
+
+    pub fn is_cursor_grabbed(&self) -> bool {
+        self.cursor_grabbed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vulkan::swapchain::PresentMode;
+
+    use super::{choose_video_mode, VideoModeInfo, WindowMode, WindowSettings};
+
+    fn mode(width: u32, height: u32, refresh_rate: u32, bit_depth: u16) -> VideoModeInfo {
+        VideoModeInfo { size: [width, height], refresh_rate_millihertz: refresh_rate * 1000, bit_depth }
+    }
+
+    #[test]
+    fn video_mode_closest_to_settings() {
+        let modes = [
+            mode(1280, 720, 60, 32),
+            mode(1920, 1080, 60, 24),
+            mode(1920, 1080, 60, 32),
+            mode(1920, 1080, 144, 32),
+            mode(2560, 1440, 60, 32),
+        ];
+        assert_eq!(choose_video_mode(&modes, None, None), Some(4));
+        assert_eq!(choose_video_mode(&modes, Some([1920, 1080]), None), Some(3));
+        assert_eq!(choose_video_mode(&modes, Some([1920, 1080]), Some(60)), Some(2));
+        // Unavailable size takes the nearest one
+        assert_eq!(choose_video_mode(&modes, Some([1366, 768]), Some(75)), Some(0));
+        assert_eq!(choose_video_mode(&[], None, None), None);
+
+        let text = r#"{ "mode": "Exclusive", "refresh_rate": 60, "present": { "mode": "Immediate" }, "max_fps": null }"#;
+        let settings: WindowSettings = serde_json::from_str(text).unwrap();
+        assert_eq!(settings.mode, WindowMode::Exclusive);
+        assert_eq!(settings.refresh_rate, Some(60));
+        assert!(settings.resizable && settings.position.is_none());
+        assert_eq!(settings.present.mode, PresentMode::Immediate);
+        assert!(settings.present.image_count.is_none() && settings.max_fps.is_none());
+    }
+}
@@ -1,4 +1,5 @@
 use cgmath as cgm;
+use cgmath::prelude::*;
 
 #[allow(dead_code)]
 pub fn direction_to_rotation(
@@ -15,11 +16,120 @@ pub fn direction_to_rotation(
     cgm::Matrix4::from(rotation)
 }
 
-pub fn position_from_transform(transform: &cgm::Matrix4<f32>) -> cgm::Vector3<f32> {
+pub fn position_from_transform<S: cgm::BaseFloat>(transform: &cgm::Matrix4<S>) -> cgm::Vector3<S> {
     cgm::Vector3::new(transform[3].x, transform[3].y, transform[3].z)
 }
 
+/// Convert world transform to single precision transform relative to the given origin.
+/// Translation is subtracted in double precision, so objects near the origin keep full f32 precision.
+pub fn rebase_transform(transform: &cgm::Matrix4<f64>, origin: &cgm::Vector3<f64>) -> cgm::Matrix4<f32> {
+    let mut relative = *transform;
+    relative[3] = (relative[3].truncate() - origin).extend(relative[3].w);
+
+    relative.cast().unwrap_or(cgm::Matrix4::identity())
+}
+
 #[allow(dead_code)]
 pub fn set_translation(transform: &mut cgm::Matrix4<f32>, translation: &cgm::Vector3<f32>) {
     transform[3] = cgm::Vector4::new(translation.x, translation.y, translation.z, transform[3].w);
 }
+
+/// Split transform into translation, rotation and scale. Expects transform without shear.
+pub fn decompose_transform<S: cgm::BaseFloat>(
+    transform: &cgm::Matrix4<S>,
+) -> (cgm::Vector3<S>, cgm::Quaternion<S>, cgm::Vector3<S>) {
+    let translation = position_from_transform(transform);
+    let scale = cgm::Vector3::new(
+        transform[0].truncate().magnitude(),
+        transform[1].truncate().magnitude(),
+        transform[2].truncate().magnitude(),
+    );
+    let rotation = cgm::Matrix3::from_cols(
+        transform[0].truncate() / scale.x,
+        transform[1].truncate() / scale.y,
+        transform[2].truncate() / scale.z,
+    );
+
+    (translation, cgm::Quaternion::from(rotation), scale)
+}
+
+/// Interpolate between two transforms with linear translation and scale and spherical rotation
+pub fn interpolate_transform<S: cgm::BaseFloat>(
+    from: &cgm::Matrix4<S>,
+    to: &cgm::Matrix4<S>,
+    alpha: S,
+) -> cgm::Matrix4<S> {
+    let (from_translation, from_rotation, from_scale) = decompose_transform(from);
+    let (to_translation, to_rotation, to_scale) = decompose_transform(to);
+
+    let translation = from_translation.lerp(to_translation, alpha);
+    let rotation = from_rotation.slerp(to_rotation, alpha);
+    let scale = from_scale.lerp(to_scale, alpha);
+
+    cgm::Matrix4::from_translation(translation)
+        * cgm::Matrix4::from(rotation)
+        * cgm::Matrix4::from_nonuniform_scale(scale.x, scale.y, scale.z)
+}
+
+/// Convert to IEEE 754 half precision bits rounding to nearest even. Out of range values become infinity.
+pub fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    // Infinity and NaN
+    if exponent == 0xff {
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+
+    let half_exponent = exponent - 127 + 15;
+    if half_exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+
+    // Rounding up may carry into exponent, which still gives correct result
+    let round = |value: u32, dropped: u32, shift: u32| {
+        let round_bit = 1 << (shift - 1);
+        if dropped & round_bit != 0 && dropped & (3 * round_bit - 1) != 0 {
+            value + 1
+        } else {
+            value
+        }
+    };
+
+    if half_exponent <= 0 {
+        if half_exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - half_exponent) as u32;
+        return sign | round(mantissa >> shift, mantissa, shift) as u16;
+    }
+
+    let half = ((half_exponent as u32) << 10) | (mantissa >> 13);
+    sign | round(half, mantissa, 13) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::f32_to_f16;
+
+    #[test]
+    fn f16_conversion_matches_reference_values() {
+        assert_eq!(f32_to_f16(0.0), 0x0000);
+        assert_eq!(f32_to_f16(-0.0), 0x8000);
+        assert_eq!(f32_to_f16(1.0), 0x3c00);
+        assert_eq!(f32_to_f16(-2.0), 0xc000);
+        assert_eq!(f32_to_f16(0.1), 0x2e66);
+        assert_eq!(f32_to_f16(65504.0), 0x7bff);
+        assert_eq!(f32_to_f16(1.0e6), 0x7c00);
+        assert_eq!(f32_to_f16(f32::INFINITY), 0x7c00);
+        assert!(f32_to_f16(f32::NAN) & 0x3ff != 0);
+        // Smallest subnormal
+        assert_eq!(f32_to_f16(2.0_f32.powi(-24)), 0x0001);
+        // Ties round to even
+        assert_eq!(f32_to_f16(1.0 + 2.0_f32.powi(-11)), 0x3c00);
+        assert_eq!(f32_to_f16(1.0 + 3.0 * 2.0_f32.powi(-11)), 0x3c02);
+    }
+}
@@ -46,6 +46,12 @@ pub fn get_default_attribute_descriptions() -> Vec<vk::VertexInputAttributeDescr
 #[derive(PartialEq, Eq, Clone, Copy)]
 pub enum DrawType {
     Opaque,
+    // Semi transparent planet rings drawn by their own pass
+    Ring,
+    // Terrain chunks of planets with surface maps, drawn per planet with its maps bound
+    Terrain,
+    // Billboards of distant bodies drawn by the impostor pass
+    Impostor,
 }
 
 pub type DrawableMutRef = Rc<RefCell<Drawable>>;
@@ -56,6 +62,8 @@ pub struct Drawable {
     instances: Vec<DrawableInstanceMutRef>,
     geometry: Geometry,
     pub material: Material,
+    // Index in ObjectDescriptions
+    object_id: usize,
 }
 
 impl Drawable {
@@ -65,7 +73,7 @@ impl Drawable {
         geometry: Geometry,
         material: Material,
     ) -> Drawable {
-        object_descriptions.add_object(DrawableMemDescr {
+        let object_id = object_descriptions.add_object(DrawableMemDescr {
             vertex_buf_addr: geometry.vertex_buffer.borrow().get_buffer_device_address(),
             index_buf_addr: geometry.index_buffer.borrow().get_buffer_device_address(),
         });
@@ -75,6 +83,7 @@ impl Drawable {
             instances: vec![],
             geometry,
             material,
+            object_id,
         }
     }
 
@@ -101,14 +110,18 @@ impl Drawable {
                 vk::IndexType::UINT32,
             );
 
-            device.logical_device.cmd_draw_indexed(
-                *cmd_buffer,
-                self.geometry.indices.len() as u32,
-                self.instances.len() as u32,
-                0,
-                0,
-                0,
-            );
+            // Model data of instances is not laid out contiguously, so draw them one by one
+            // Instances without model data did not fit into ModelData this frame and are skipped
+            for model_index in self.instances.iter().filter_map(|instance| instance.borrow().get_model_index()) {
+                device.logical_device.cmd_draw_indexed(
+                    *cmd_buffer,
+                    self.geometry.indices.len() as u32,
+                    1,
+                    0,
+                    0,
+                    model_index,
+                );
+            }
         }
     }
 
@@ -116,6 +129,14 @@ impl Drawable {
         &self.geometry
     }
 
+    pub fn get_instances(&self) -> &[DrawableInstanceMutRef] {
+        &self.instances
+    }
+
+    pub fn get_object_id(&self) -> usize {
+        self.object_id
+    }
+
     pub fn create_instance(drawable: &DrawableMutRef) -> DrawableInstanceMutRef {
         let instance = DrawableInstance::new(
             Rc::downgrade(drawable),
@@ -166,6 +187,8 @@ pub type DrawableInstanceMutRef = Rc<RefCell<DrawableInstance>>;
 pub struct DrawableInstance {
     pub drawable: DrawableWeakMutRef,
     instance_id: u64,
+    // Index of instance transform in ModelData written during last scene update, None if it did not fit
+    model_index: Option<u32>,
 }
 
 impl DrawableInstance {
@@ -182,12 +205,22 @@ impl DrawableInstance {
         DrawableInstance {
             drawable,
             instance_id,
+            model_index: None,
         }
     }
 
+    #[allow(dead_code)]
     pub fn get_instance_id(&self) -> u64 {
         self.instance_id
     }
+
+    pub fn set_model_index(&mut self, model_index: Option<u32>) {
+        self.model_index = model_index;
+    }
+
+    pub fn get_model_index(&self) -> Option<u32> {
+        self.model_index
+    }
 }
 
 pub struct FullScreenDrawable {
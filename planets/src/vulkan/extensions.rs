@@ -1,3 +1,5 @@
+use std::ffi::CStr;
+
 use ash::extensions::ext::DebugUtils;
 use ash::vk;
 
@@ -45,6 +47,14 @@ pub fn required_instance_extension_names() -> Vec<*const i8> {
 }
 // ------------------------------------------------------------------------
 
+/// Instance extensions enabled when available
+pub fn optional_instance_extension_names() -> Vec<&'static CStr> {
+    vec![
+        // HDR and extended color spaces of swapchains
+        vk::ExtSwapchainColorspaceFn::name(),
+    ]
+}
+
 #[cfg(target_os = "macos")]
 pub fn required_device_extension_names() -> Vec<*const i8> {
     vec![
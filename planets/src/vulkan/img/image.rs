@@ -15,9 +15,12 @@ use crate::vulkan::device::{Device, DeviceMutRef};
 use crate::vulkan::img::sampler::Sampler;
 use crate::vulkan::mem::{AllocatedBuffer, Memory, VecBufferData};
 use crate::vulkan::resources::manager::ResourceManager;
+use crate::util::math::f32_to_f16;
 
 pub type ImageMutRef = Rc<RefCell<Image>>;
 
+pub const CUBE_FACES: u32 = 6;
+
 pub struct ImageAccess {
     pub new_layout: vk::ImageLayout,
     pub src_stage: vk::PipelineStageFlags,
@@ -34,7 +37,8 @@ pub struct MemoryBarrier {
     src_stage: vk::PipelineStageFlags,
     src_access: vk::AccessFlags,
     dst_stage: vk::PipelineStageFlags,
-    dst_access: vk::AccessFlags
+    dst_access: vk::AccessFlags,
+    layer_count: u32,
 }
 
 impl MemoryBarrier {
@@ -52,7 +56,7 @@ impl MemoryBarrier {
                 base_mip_level: 0,
                 level_count: 1,
                 base_array_layer: 0,
-                layer_count: 1,
+                layer_count: self.layer_count,
             })
             .build()];
 
@@ -80,7 +84,11 @@ pub struct Image {
     format: vk::Format,
     width: u32,
     height: u32,
+    // 1 for 2D images, CUBE_FACES for cube maps
+    layers: u32,
     pub views: HashMap<vk::Format, vk::ImageView>,
+    // View of the depth aspect only, used to sample depth of combined depth stencil images
+    depth_view: Option<vk::ImageView>,
     pub sampler: Sampler,
     label: String,
 }
@@ -94,7 +102,7 @@ impl Image {
         usage: vk::ImageUsageFlags,
         label: &'static str,
     ) -> Image {
-        Image::create_image_intern(device, width, height, format, usage, label)
+        Image::create_image_intern(device, width, height, 1, format, usage, label)
     }
 
     pub fn from_vk_image(
@@ -116,7 +124,9 @@ impl Image {
             format,
             width,
             height,
+            layers: 1,
             views: HashMap::new(),
+            depth_view: None,
             sampler,
             label: String::from(label),
         }
@@ -141,7 +151,8 @@ impl Image {
             device,
             image_data.width(),
             image_data.height(),
-            vk::Format::R8G8B8A8_SRGB,
+            1,
+            Image::format_for_data(&image_data),
             usage,
             path,
         );
@@ -151,6 +162,61 @@ impl Image {
         Ok(image)
     }
 
+    /// Image with the given pixel data to be uploaded by upload(). Only 8 bit RGBA and 32 bit float RGBA
+    /// formats are supported.
+    pub fn from_data(
+        device: &DeviceMutRef,
+        data: DynamicImage,
+        format: vk::Format,
+        label: &str,
+    ) -> Image {
+        let usage = vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED;
+        let mut image = Image::create_image_intern(
+            device,
+            data.width(),
+            data.height(),
+            1,
+            format,
+            usage,
+            label,
+        );
+
+        image.data = Some(data);
+
+        image
+    }
+
+    /// Cube map from faces stacked vertically in +X, -X, +Y, -Y, +Z, -Z order
+    pub fn cube_from_data(
+        device: &DeviceMutRef,
+        faces: DynamicImage,
+        format: vk::Format,
+        label: &str,
+    ) -> Image {
+        let usage = vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED;
+        let mut image = Image::create_image_intern(
+            device,
+            faces.width(),
+            faces.height() / CUBE_FACES,
+            CUBE_FACES,
+            format,
+            usage,
+            label,
+        );
+
+        image.data = Some(faces);
+
+        image
+    }
+
+    /// High dynamic range images (.hdr, .exr) are kept in half float, others are treated as sRGB colors
+    pub fn format_for_data(data: &DynamicImage) -> vk::Format {
+        match data {
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => vk::Format::R16G16B16A16_SFLOAT,
+            _ => vk::Format::R8G8B8A8_SRGB,
+        }
+    }
+
     pub fn access_view(&mut self, device: &Device, barrier_params: &ImageAccess, format: Option<vk::Format>) -> Result<vk::ImageView,String> {
         let format = format.unwrap_or(self.format);
         let image = self.access_image(device, barrier_params);
@@ -159,24 +225,42 @@ impl Image {
                 Ok(*view)
             },
             None => {
-                let view = Self::create_view(device, image, format, &self.label)?;
+                let view = Self::create_view(device, image, format, Image::aspect_mask_from_format(format), self.layers, &self.label)?;
                 self.views.insert(format, view);
                 Ok(view)
             }
         }
     }
 
-    fn create_view(device: &Device, image: vk::Image, format: vk::Format, label: &String) -> Result<vk::ImageView, String> {
+    /// View to sample depth from depth stencil image
+    pub fn access_depth_view(&mut self, device: &Device, barrier_params: &ImageAccess) -> Result<vk::ImageView,String> {
+        let image = self.access_image(device, barrier_params);
+        match self.depth_view {
+            Some(view) => Ok(view),
+            None => {
+                let view = Self::create_view(device, image, self.format, vk::ImageAspectFlags::DEPTH, self.layers, &self.label)?;
+                self.depth_view = Some(view);
+                Ok(view)
+            }
+        }
+    }
+
+    fn create_view(device: &Device, image: vk::Image, format: vk::Format, aspect_mask: vk::ImageAspectFlags, layers: u32, label: &String) -> Result<vk::ImageView, String> {
+        let view_type = if layers == CUBE_FACES {
+            vk::ImageViewType::CUBE
+        } else {
+            vk::ImageViewType::TYPE_2D
+        };
         let view_create_info = vk::ImageViewCreateInfo {
             image,
-            view_type: vk::ImageViewType::TYPE_2D,
+            view_type,
             format,
             subresource_range: vk::ImageSubresourceRange {
-                aspect_mask: Image::aspect_mask_from_format(format),
+                aspect_mask,
                 base_mip_level: 0,
                 level_count: 1,
                 base_array_layer: 0,
-                layer_count: 1,
+                layer_count: layers,
             },
             ..Default::default()
         };
@@ -205,6 +289,7 @@ impl Image {
             src_access: barrier_params.src_access,
             dst_stage: barrier_params.dst_stage,
             dst_access: barrier_params.dst_access,
+            layer_count: self.layers,
         };
 
         barrier.record(device);
@@ -233,13 +318,20 @@ impl Image {
         device: &DeviceMutRef,
         width: u32,
         height: u32,
+        layers: u32,
         format: vk::Format,
         usage: vk::ImageUsageFlags,
         label: &str,
     ) -> Image {
         let initial_layout = vk::ImageLayout::UNDEFINED;
 
+        let flags = if layers == CUBE_FACES {
+            vk::ImageCreateFlags::CUBE_COMPATIBLE
+        } else {
+            vk::ImageCreateFlags::empty()
+        };
         let create_info = vk::ImageCreateInfo {
+            flags,
             image_type: vk::ImageType::TYPE_2D,
             extent: vk::Extent3D {
                 width,
@@ -247,7 +339,7 @@ impl Image {
                 depth: 1,
             },
             mip_levels: 1,
-            array_layers: 1,
+            array_layers: layers,
             format,
             tiling: vk::ImageTiling::OPTIMAL,
             initial_layout,
@@ -294,7 +386,9 @@ impl Image {
             format,
             width,
             height,
+            layers,
             views: HashMap::new(),
+            depth_view: None,
             sampler: Sampler::new(device),
             label: String::from(label),
         }
@@ -349,7 +443,9 @@ impl Image {
         image: vk::Image,
         width: u32,
         height: u32,
+        layers: u32,
     ) {
+        // Layers are tightly packed one after another in the buffer
         let regions = [vk::BufferImageCopy {
             buffer_offset: 0,
             buffer_row_length: 0,
@@ -358,7 +454,7 @@ impl Image {
                 aspect_mask: vk::ImageAspectFlags::COLOR,
                 mip_level: 0,
                 base_array_layer: 0,
-                layer_count: 1,
+                layer_count: layers,
             },
             image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
             image_extent: vk::Extent3D {
@@ -381,39 +477,57 @@ impl Image {
 
     pub fn upload(&mut self, device: &Device, resource_manager: &mut ResourceManager) -> Result<(), ()> {
         if let Some(data) = self.data.take() {
-            let image_data = data.into_rgba8();
-            let vec_data_buffer = VecBufferData::new(image_data.as_raw());
-
-            let staging_buffer = ResourceManager::buffer_host_visible_coherent(
-                resource_manager,
-                &vec_data_buffer,
-                vk::BufferUsageFlags::TRANSFER_SRC,
-                "Staging",
-            );
-            let staging_borrow_ref = staging_buffer.borrow();
-            staging_borrow_ref.update_data(device, &vec_data_buffer, 0);
-
-            let barrier_params = ImageAccess {
-                new_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-                src_stage: vk::PipelineStageFlags::TOP_OF_PIPE,
-                src_access: vk::AccessFlags::default(),
-                dst_stage: vk::PipelineStageFlags::TRANSFER,
-                dst_access: vk::AccessFlags::TRANSFER_WRITE,
-            };
-            let image = self.access_image(device, &barrier_params);
-            Image::copy_buffer_to_image(
-                device,
-                &staging_borrow_ref,
-                image,
-                image_data.width(),
-                image_data.height(),
-            );
+            let (width, height) = (self.width, self.height);
+            match self.format {
+                vk::Format::R32G32B32A32_SFLOAT => {
+                    let image_data = data.into_rgba32f().into_raw();
+                    self.upload_data(device, resource_manager, &image_data, width, height);
+                },
+                vk::Format::R16G16B16A16_SFLOAT => {
+                    let image_data: Vec<u16> = data.into_rgba32f().into_raw().into_iter().map(f32_to_f16).collect();
+                    self.upload_data(device, resource_manager, &image_data, width, height);
+                },
+                _ => {
+                    let image_data = data.into_rgba8().into_raw();
+                    self.upload_data(device, resource_manager, &image_data, width, height);
+                },
+            }
 
             Ok(())
         } else {
             Err(())
         }
     }
+
+    fn upload_data<T>(&mut self, device: &Device, resource_manager: &mut ResourceManager, data: &Vec<T>, width: u32, height: u32) {
+        let vec_data_buffer = VecBufferData::new(data);
+
+        let staging_buffer = ResourceManager::buffer_host_visible_coherent(
+            resource_manager,
+            &vec_data_buffer,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            "Staging",
+        );
+        let staging_borrow_ref = staging_buffer.borrow();
+        staging_borrow_ref.update_data(device, &vec_data_buffer, 0);
+
+        let barrier_params = ImageAccess {
+            new_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            src_stage: vk::PipelineStageFlags::TOP_OF_PIPE,
+            src_access: vk::AccessFlags::default(),
+            dst_stage: vk::PipelineStageFlags::TRANSFER,
+            dst_access: vk::AccessFlags::TRANSFER_WRITE,
+        };
+        let image = self.access_image(device, &barrier_params);
+        Image::copy_buffer_to_image(
+            device,
+            &staging_borrow_ref,
+            image,
+            width,
+            height,
+            self.layers,
+        );
+    }
 }
 
 impl DebugResource for Image {
@@ -434,7 +548,7 @@ impl Drop for Image {
     fn drop(&mut self) {
         if let Some(device) = self.device.upgrade() {
             unsafe {
-                for view in self.views.values() {
+                for view in self.views.values().chain(self.depth_view.iter()) {
                     device
                         .borrow()
                         .logical_device
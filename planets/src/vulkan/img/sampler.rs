@@ -10,11 +10,30 @@ pub struct Sampler {
 
 impl Sampler {
     pub fn new(device: &DeviceMutRef) -> Sampler {
+        Sampler::with_filter(device, vk::Filter::LINEAR)
+    }
+
+    /// Sampler with the given filter. Depth and integer formats usually have to be sampled with NEAREST.
+    pub fn with_filter(device: &DeviceMutRef, filter: vk::Filter) -> Sampler {
+        Sampler::with_address_modes(device, filter, vk::SamplerAddressMode::CLAMP_TO_EDGE, vk::SamplerAddressMode::CLAMP_TO_EDGE)
+    }
+
+    /// Sampler for equirectangular maps. Longitude wraps around, latitude stops at the poles.
+    pub fn equirect(device: &DeviceMutRef) -> Sampler {
+        Sampler::with_address_modes(device, vk::Filter::LINEAR, vk::SamplerAddressMode::REPEAT, vk::SamplerAddressMode::CLAMP_TO_EDGE)
+    }
+
+    pub fn with_address_modes(
+        device: &DeviceMutRef,
+        filter: vk::Filter,
+        address_mode_u: vk::SamplerAddressMode,
+        address_mode_v: vk::SamplerAddressMode,
+    ) -> Sampler {
         let create_info = vk::SamplerCreateInfo {
-            mag_filter: vk::Filter::LINEAR,
-            min_filter: vk::Filter::LINEAR,
-            address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
-            address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            mag_filter: filter,
+            min_filter: filter,
+            address_mode_u,
+            address_mode_v,
             address_mode_w: vk::SamplerAddressMode::CLAMP_TO_EDGE,
             anisotropy_enable: vk::TRUE,
             max_anisotropy: device.borrow().physical_props.limits.max_sampler_anisotropy,
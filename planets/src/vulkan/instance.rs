@@ -1,5 +1,6 @@
 use alloc::ffi::CString;
 use ash::vk;
+use std::ffi::CStr;
 use std::os::raw::c_void;
 use std::ptr;
 
@@ -53,6 +54,16 @@ impl VulkanInstance {
         let mut extension_names = extensions::required_instance_extension_names();
         let mut debug_extensions = extensions::debug_instance_extension_names();
         extension_names.append(&mut debug_extensions);
+        let available_extensions = entry.enumerate_instance_extension_properties(None).unwrap_or_default();
+        for name in extensions::optional_instance_extension_names() {
+            let is_available = available_extensions.iter()
+                .any(|extension| unsafe { CStr::from_ptr(extension.extension_name.as_ptr()) } == name);
+            if is_available {
+                extension_names.push(name.as_ptr());
+            } else {
+                log::info!("Optional instance extension {:?} is not available", name);
+            }
+        }
         let validation_layer_names = helpers::required_validation_layer_names();
         let validation_layer_names: Vec<CString> = validation_layer_names
             .iter()
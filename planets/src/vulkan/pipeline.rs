@@ -72,9 +72,9 @@ impl<'a> Pipeline {
         }
     }
 
-    fn create_input_assembly_state_info() -> vk::PipelineInputAssemblyStateCreateInfo {
+    fn create_input_assembly_state_info(topology: vk::PrimitiveTopology) -> vk::PipelineInputAssemblyStateCreateInfo {
         vk::PipelineInputAssemblyStateCreateInfo {
-            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            topology,
             primitive_restart_enable: vk::FALSE,
             ..Default::default()
         }
@@ -118,12 +118,12 @@ impl<'a> Pipeline {
         }
     }
 
-    fn create_rasterization_state_info() -> vk::PipelineRasterizationStateCreateInfo {
+    fn create_rasterization_state_info(cull_mode: vk::CullModeFlags) -> vk::PipelineRasterizationStateCreateInfo {
         vk::PipelineRasterizationStateCreateInfo {
             flags: vk::PipelineRasterizationStateCreateFlags::empty(),
             polygon_mode: vk::PolygonMode::FILL,
             line_width: 1.0,
-            cull_mode: vk::CullModeFlags::BACK,
+            cull_mode,
             front_face: vk::FrontFace::COUNTER_CLOCKWISE,
             ..Default::default()
         }
@@ -138,12 +138,12 @@ impl<'a> Pipeline {
         }
     }
 
-    fn create_color_blend_attachment_state_def() -> ColorAttachmentDef {
-        let attachments = vec![vk::PipelineColorBlendAttachmentState {
+    fn create_color_blend_attachment_state_def(blend_attachment: Option<vk::PipelineColorBlendAttachmentState>) -> ColorAttachmentDef {
+        let attachments = vec![blend_attachment.unwrap_or(vk::PipelineColorBlendAttachmentState {
             color_write_mask: vk::ColorComponentFlags::RGBA,
             blend_enable: vk::FALSE,
             ..Default::default()
-        }];
+        })];
 
         let create_info = vk::PipelineColorBlendStateCreateInfo {
             s_type: vk::StructureType::PIPELINE_COLOR_BLEND_STATE_CREATE_INFO,
@@ -234,6 +234,9 @@ pub struct PipelineBuilder<'a> {
     vertex_input_binding_description: Option<vk::VertexInputBindingDescription>,
     vertex_input_attribute_descriptions: Option<Vec<vk::VertexInputAttributeDescription>>,
     dynamic_state: Option<vk::PipelineDynamicStateCreateInfo>,
+    topology: vk::PrimitiveTopology,
+    cull_mode: vk::CullModeFlags,
+    blend_attachment: Option<vk::PipelineColorBlendAttachmentState>,
 }
 
 impl<'a> PipelineBuilder<'a> {
@@ -252,6 +255,9 @@ impl<'a> PipelineBuilder<'a> {
             vertex_input_binding_description: None,
             vertex_input_attribute_descriptions: None,
             dynamic_state: None,
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            cull_mode: vk::CullModeFlags::BACK,
+            blend_attachment: None,
         }
     }
 
@@ -279,6 +285,22 @@ impl<'a> PipelineBuilder<'a> {
         self
     }
 
+    pub fn with_topology(&mut self, topology: vk::PrimitiveTopology) -> &mut Self {
+        self.topology = topology;
+        self
+    }
+
+    pub fn with_cull_mode(&mut self, cull_mode: vk::CullModeFlags) -> &mut Self {
+        self.cull_mode = cull_mode;
+        self
+    }
+
+    /// Blend state of the color attachment. Blending is disabled by default.
+    pub fn with_blend_attachment(&mut self, blend_attachment: vk::PipelineColorBlendAttachmentState) -> &mut Self {
+        self.blend_attachment = Some(blend_attachment);
+        self
+    }
+
     pub fn with_layout_bindings(
         &mut self,
         layout_bindings: Vec<vk::DescriptorSetLayoutBinding>,
@@ -340,9 +362,9 @@ impl<'a> PipelineBuilder<'a> {
             p_vertex_attribute_descriptions: attribute_descs.as_ptr(),
         };
 
-        let input_assembly_state = Pipeline::create_input_assembly_state_info();
+        let input_assembly_state = Pipeline::create_input_assembly_state_info(self.topology);
         let viewport_state = Pipeline::create_viewport_state_def(self.viewport_width, self.viewport_height, 0, 0);
-        let rasterization_state = Pipeline::create_rasterization_state_info();
+        let rasterization_state = Pipeline::create_rasterization_state_info(self.cull_mode);
         let depth_stencil_state = match self.depth_stencil_info {
             None => vk::PipelineDepthStencilStateCreateInfo {
                 ..Default::default()
@@ -350,7 +372,7 @@ impl<'a> PipelineBuilder<'a> {
             Some(info) => info,
         };
         let multisample_state = Pipeline::create_multisample_state_info();
-        let color_blend_state = Pipeline::create_color_blend_attachment_state_def();
+        let color_blend_state = Pipeline::create_color_blend_attachment_state_def(self.blend_attachment);
         let descriptor_set_layout =
             Pipeline::create_descriptor_set_layout(&self.device, &self.layout_bindings);
         let layout =
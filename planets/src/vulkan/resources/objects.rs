@@ -13,6 +13,8 @@ pub struct DrawableMemDescr {
 pub struct ObjectDescriptions {
     is_dirty: bool,
     descriptions: Vec<DrawableMemDescr>,
+    // Indices of removed descriptions to be reused by new objects
+    free_ids: Vec<usize>,
     ssbo: Option<AllocatedBufferMutRef>,
 }
 
@@ -23,12 +25,29 @@ impl ObjectDescriptions {
         Self {
             is_dirty: false,
             descriptions: vec![],
+            free_ids: vec![],
             ssbo: None,
         }
     }
 
-    pub fn add_object(&mut self, descr: DrawableMemDescr) {
-        self.descriptions.push(descr);
+    /// Add object description and return its index in the descriptions buffer
+    pub fn add_object(&mut self, descr: DrawableMemDescr) -> usize {
+        self.is_dirty = true;
+        if let Some(id) = self.free_ids.pop() {
+            self.descriptions[id] = descr;
+            id
+        } else {
+            self.descriptions.push(descr);
+            self.descriptions.len() - 1
+        }
+    }
+
+    pub fn remove_object(&mut self, id: usize) {
+        self.descriptions[id] = DrawableMemDescr {
+            vertex_buf_addr: 0,
+            index_buf_addr: 0,
+        };
+        self.free_ids.push(id);
         self.is_dirty = true;
     }
 
@@ -1,282 +1,335 @@
+use std::collections::{HashMap, HashSet};
 use std::mem;
 use ash::extensions::khr;
-use ash::util::Align;
 use ash::vk;
+use cgmath as cgm;
 use crate::engine::geometry::{Geometry, Vertex};
 use crate::vulkan::cmd_buffers::SingleTimeCmdBuffer;
 use crate::vulkan::device::Device;
-use crate::vulkan::mem::{AllocatedBufferMutRef, BufferAccess, VecBufferData};
+use crate::vulkan::mem::{AllocatedBufferMutRef, VecBufferData};
 use crate::vulkan::resources::manager::ResourceManager;
 
-pub struct AccelerationStructure {
-    accel: khr::AccelerationStructure,
-    pub blas: vk::AccelerationStructureKHR,
-    blas_buffer: AllocatedBufferMutRef,
-    pub tlas: vk::AccelerationStructureKHR,
-    tlas_buffer: AllocatedBufferMutRef,
+/// Drawable instance placed into the top level acceleration structure
+pub struct AsInstance {
+    // Index in ObjectDescriptions, read by hit shaders as the custom instance index
+    pub object_id: usize,
+    // Model transform rebased to the render origin, same as in ModelData
+    pub transform: cgm::Matrix4<f32>,
 }
 
-impl AccelerationStructure {
-    pub fn new(device: &Device, resource_manager: &mut ResourceManager, geometries: &[Geometry]) -> Self {
-        let acceleration_structure =
-            khr::AccelerationStructure::new(&device.instance.instance, &device.logical_device);
-
-        let mut as_geometries = vec![];
-        let mut as_geometry_counts = vec![];
-        let mut as_build_range_infos = vec![];
-        for geometry in geometries {
-            let as_geometry = vk::AccelerationStructureGeometryKHR::builder()
-                .geometry_type(vk::GeometryTypeKHR::TRIANGLES)
-                .geometry(vk::AccelerationStructureGeometryDataKHR {
-                    triangles: vk::AccelerationStructureGeometryTrianglesDataKHR::builder()
-                        .vertex_data(vk::DeviceOrHostAddressConstKHR {
-                            device_address: geometry.vertex_buffer.borrow().get_buffer_device_address(),
-                        })
-                        .max_vertex(geometry.vertices.len() as u32 - 1)
-                        .vertex_stride(mem::size_of::<Vertex>() as u64)
-                        .vertex_format(vk::Format::R32G32B32_SFLOAT)
-                        .index_data(vk::DeviceOrHostAddressConstKHR {
-                            device_address: geometry.index_buffer.borrow().get_buffer_device_address(),
-                        })
-                        .index_type(vk::IndexType::UINT32)
-                        .build(),
-                })
-                .flags(vk::GeometryFlagsKHR::OPAQUE)
-                .build();
-            as_geometries.push(as_geometry);
+// Bottom level acceleration structure of a single drawable geometry
+struct Blas {
+    accel: vk::AccelerationStructureKHR,
+    _buffer: AllocatedBufferMutRef,
+    address: u64,
+    // Geometry the BLAS was built from, object ids are reused by new drawables
+    vertex_address: u64,
+}
 
-            as_geometry_counts.push(geometry.get_primitives_count());
+// Top level acceleration structure with room for a fixed number of instances
+struct Tlas {
+    accel: vk::AccelerationStructureKHR,
+    _buffer: AllocatedBufferMutRef,
+    scratch_buffer: AllocatedBufferMutRef,
+    instance_buffer: AllocatedBufferMutRef,
+    capacity: usize,
+}
 
-            let as_build_range_info = vk::AccelerationStructureBuildRangeInfoKHR::builder()
-                .first_vertex(0)
-                .primitive_count(geometry.get_primitives_count())
-                .primitive_offset(0)
-                .transform_offset(0)
-                .build();
-            as_build_range_infos.push(as_build_range_info);
-        }
+pub struct AccelerationStructure {
+    loader: khr::AccelerationStructure,
+    // BLAS of every traced drawable by object id
+    blases: HashMap<usize, Blas>,
+    tlas: Option<Tlas>,
+}
 
-        let (bottom_as, bottom_as_buffer) = {
-            let mut build_info = vk::AccelerationStructureBuildGeometryInfoKHR::builder()
-                .flags(vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE)
-                .geometries(&as_geometries)
-                .mode(vk::BuildAccelerationStructureModeKHR::BUILD)
-                .ty(vk::AccelerationStructureTypeKHR::BOTTOM_LEVEL)
-                .build();
+impl AccelerationStructure {
+    pub fn new(device: &Device) -> Self {
+        AccelerationStructure {
+            loader: khr::AccelerationStructure::new(&device.instance.instance, &device.logical_device),
+            blases: HashMap::new(),
+            tlas: None,
+        }
+    }
 
-            let size_info = unsafe {
-                acceleration_structure.get_acceleration_structure_build_sizes(
-                    vk::AccelerationStructureBuildTypeKHR::DEVICE,
-                    &build_info,
-                    &as_geometry_counts,
-                )
-            };
-
-            let bottom_as_buffer = resource_manager.buffer_with_size(
-                size_info.acceleration_structure_size,
-                vk::BufferUsageFlags::ACCELERATION_STRUCTURE_STORAGE_KHR
-                    | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
-                    | vk::BufferUsageFlags::STORAGE_BUFFER,
-                vk::MemoryPropertyFlags::DEVICE_LOCAL,
-                "BLAS Buffer"
-            );
+    /// Whether the BLAS of the object id is built from the given geometry
+    pub fn has_blas(&self, object_id: usize, geometry: &Geometry) -> bool {
+        let vertex_address = geometry.vertex_buffer.borrow().get_buffer_device_address();
+        self.blases.get(&object_id).is_some_and(|blas| blas.vertex_address == vertex_address)
+    }
 
-            let bottom_as_vk_buffer = {
-                let barrier_params = BufferAccess {
-                    src_access: vk::AccessFlags::TRANSFER_WRITE,
-                    src_stage: vk::PipelineStageFlags::TRANSFER,
-                    dst_access: vk::AccessFlags::ACCELERATION_STRUCTURE_WRITE_KHR,
-                    dst_stage: vk::PipelineStageFlags::ACCELERATION_STRUCTURE_BUILD_KHR,
-                };
-
-                bottom_as_buffer.borrow().access_buffer(device, &barrier_params)
-            };
-
-            let as_create_info = vk::AccelerationStructureCreateInfoKHR::builder()
-                .ty(build_info.ty)
-                .size(size_info.acceleration_structure_size)
-                .buffer(bottom_as_vk_buffer)
-                .offset(0)
-                .build();
+    /// Destroy BLASes of drawables no longer drawn. They must not be in use by the GPU.
+    pub fn remove_unused_blases(&mut self, object_ids: &HashSet<usize>) {
+        let loader = &self.loader;
+        self.blases.retain(|object_id, blas| {
+            let keep = object_ids.contains(object_id);
+            if !keep {
+                unsafe { loader.destroy_acceleration_structure(blas.accel, None) };
+            }
+            keep
+        });
+    }
 
-            let bottom_as =
-                unsafe { acceleration_structure.create_acceleration_structure(&as_create_info, None) }
-                    .unwrap();
+    /// Build the BLAS of the geometry drawn with the given object id
+    pub fn add_blas(&mut self, device: &Device, resource_manager: &mut ResourceManager, object_id: usize, geometry: &Geometry) {
+        if geometry.vertices.is_empty() || geometry.get_primitives_count() == 0 {
+            return;
+        }
+        let vertex_address = geometry.vertex_buffer.borrow().get_buffer_device_address();
+
+        let as_geometries = [vk::AccelerationStructureGeometryKHR::builder()
+            .geometry_type(vk::GeometryTypeKHR::TRIANGLES)
+            .geometry(vk::AccelerationStructureGeometryDataKHR {
+                triangles: vk::AccelerationStructureGeometryTrianglesDataKHR::builder()
+                    .vertex_data(vk::DeviceOrHostAddressConstKHR {
+                        device_address: vertex_address,
+                    })
+                    .max_vertex(geometry.vertices.len() as u32 - 1)
+                    .vertex_stride(mem::size_of::<Vertex>() as u64)
+                    .vertex_format(vk::Format::R32G32B32_SFLOAT)
+                    .index_data(vk::DeviceOrHostAddressConstKHR {
+                        device_address: geometry.index_buffer.borrow().get_buffer_device_address(),
+                    })
+                    .index_type(vk::IndexType::UINT32)
+                    .build(),
+            })
+            .flags(vk::GeometryFlagsKHR::OPAQUE)
+            .build()];
+
+        let build_range_info = vk::AccelerationStructureBuildRangeInfoKHR::builder()
+            .first_vertex(0)
+            .primitive_count(geometry.get_primitives_count())
+            .primitive_offset(0)
+            .transform_offset(0)
+            .build();
+
+        let mut build_info = vk::AccelerationStructureBuildGeometryInfoKHR::builder()
+            .flags(vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE)
+            .geometries(&as_geometries)
+            .mode(vk::BuildAccelerationStructureModeKHR::BUILD)
+            .ty(vk::AccelerationStructureTypeKHR::BOTTOM_LEVEL)
+            .build();
+
+        let size_info = unsafe {
+            self.loader.get_acceleration_structure_build_sizes(
+                vk::AccelerationStructureBuildTypeKHR::DEVICE,
+                &build_info,
+                &[build_range_info.primitive_count],
+            )
+        };
 
-            build_info.dst_acceleration_structure = bottom_as;
+        let (accel, buffer) = self.create(resource_manager, build_info.ty, size_info.acceleration_structure_size, "BLAS Buffer");
+        build_info.dst_acceleration_structure = accel;
+
+        let scratch_buffer = resource_manager.buffer_with_size(
+            size_info.build_scratch_size,
+            vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS | vk::BufferUsageFlags::STORAGE_BUFFER,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            "BLAS Scratch Buffer",
+        );
+        build_info.scratch_data = vk::DeviceOrHostAddressKHR {
+            device_address: scratch_buffer.borrow().get_buffer_device_address(),
+        };
 
-            let scratch_buffer = resource_manager.buffer_with_size(
-                size_info.build_scratch_size,
-                vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS | vk::BufferUsageFlags::STORAGE_BUFFER,
-                vk::MemoryPropertyFlags::DEVICE_LOCAL,
-                "BLAS Scratch Buffer",
+        let command_buffer = SingleTimeCmdBuffer::begin(device);
+        unsafe {
+            self.loader.cmd_build_acceleration_structures(
+                command_buffer.get_command_buffer(),
+                &[build_info],
+                &[&[build_range_info]],
             );
+        }
 
-            build_info.scratch_data = vk::DeviceOrHostAddressKHR {
-                device_address: scratch_buffer.borrow().get_buffer_device_address(),
-            };
-
-            let command_buffer = SingleTimeCmdBuffer::begin(device);
-            unsafe {
-                acceleration_structure.cmd_build_acceleration_structures(
-                    command_buffer.get_command_buffer(),
-                    &[build_info],
-                    &[&as_build_range_infos[0..]]
-                );
-            }
-
-            (bottom_as, bottom_as_buffer)
-        };
-
-        let accel_handle = {
+        let address = {
             let as_addr_info = vk::AccelerationStructureDeviceAddressInfoKHR::builder()
-                .acceleration_structure(bottom_as)
+                .acceleration_structure(accel)
                 .build();
-            unsafe { acceleration_structure.get_acceleration_structure_device_address(&as_addr_info) }
+            unsafe { self.loader.get_acceleration_structure_device_address(&as_addr_info) }
         };
 
-        let (instance_count, instance_buffer) = {
-            let transform_0: [f32; 12] = [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 1.0];
+        let replaced = self.blases.insert(object_id, Blas { accel, _buffer: buffer, address, vertex_address });
+        if let Some(blas) = replaced {
+            unsafe { self.loader.destroy_acceleration_structure(blas.accel, None) };
+        }
+    }
 
-            let instances = vec![
-                vk::AccelerationStructureInstanceKHR {
-                    transform: vk::TransformMatrixKHR {
-                        matrix: transform_0,
-                    },
-                    instance_custom_index_and_mask: vk::Packed24_8::new(0xff << 24, 0xff),
-                    instance_shader_binding_table_record_offset_and_flags: vk::Packed24_8::new(vk::GeometryInstanceFlagsKHR::TRIANGLE_FACING_CULL_DISABLE.as_raw() << 24, 0),
+    /// Rebuild the TLAS from the given instances. The build is recorded into the command buffer
+    /// and finished before ray tracing shaders run. Instances must not be in use by the GPU.
+    pub fn build_tlas(&mut self, device: &Device, resource_manager: &mut ResourceManager, cmd_buffer: vk::CommandBuffer, instances: &[AsInstance]) {
+        let as_instances: Vec<vk::AccelerationStructureInstanceKHR> = instances
+            .iter()
+            .filter_map(|instance| {
+                let blas = self.blases.get(&instance.object_id)?;
+                Some(vk::AccelerationStructureInstanceKHR {
+                    transform: Self::to_transform_matrix(&instance.transform),
+                    instance_custom_index_and_mask: vk::Packed24_8::new(instance.object_id as u32, 0xff),
+                    instance_shader_binding_table_record_offset_and_flags: vk::Packed24_8::new(
+                        0,
+                        vk::GeometryInstanceFlagsKHR::TRIANGLE_FACING_CULL_DISABLE.as_raw() as u8,
+                    ),
                     acceleration_structure_reference: vk::AccelerationStructureReferenceKHR {
-                        device_handle: accel_handle,
+                        device_handle: blas.address,
                     },
-                },
-            ];
-
-            let instance_data = VecBufferData::new(&instances);
-            let instance_buffer = resource_manager.buffer_host_visible_coherent(
-                &instance_data,
-                vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR
-                    | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
-                "TLAS Instance Buffer"
-            );
-
-            (instances.len() as u32, instance_buffer)
-        };
-
-        let (top_as, top_as_buffer) = {
-            let build_range_info = vk::AccelerationStructureBuildRangeInfoKHR::builder()
-                .first_vertex(0)
-                .primitive_count(instance_count)
-                .primitive_offset(0)
-                .transform_offset(0)
-                .build();
-
-            unsafe {
-                let memory_barrier = vk::MemoryBarrier::builder()
-                    .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
-                    .dst_access_mask(vk::AccessFlags::ACCELERATION_STRUCTURE_WRITE_KHR)
-                    .build();
-                device.logical_device.cmd_pipeline_barrier(
-                    device.get_command_buffer(),
-                    vk::PipelineStageFlags::TRANSFER,
-                    vk::PipelineStageFlags::ACCELERATION_STRUCTURE_BUILD_KHR,
-                    vk::DependencyFlags::empty(),
-                    &[memory_barrier],
-                    &[],
-                    &[],
-                );
-            }
-
-            let instances = vk::AccelerationStructureGeometryInstancesDataKHR::builder()
-                .array_of_pointers(false)
-                .data(vk::DeviceOrHostAddressConstKHR {
-                    device_address: instance_buffer.borrow().get_buffer_device_address(),
                 })
-                .build();
-
-            let geometry = vk::AccelerationStructureGeometryKHR::builder()
-                .geometry_type(vk::GeometryTypeKHR::INSTANCES)
-                .geometry(vk::AccelerationStructureGeometryDataKHR { instances })
-                .build();
+            })
+            .collect();
 
-            let geometries = [geometry];
+        let capacity = self.tlas.as_ref().map_or(0, |tlas| tlas.capacity);
+        if as_instances.len() > capacity || self.tlas.is_none() {
+            self.create_tlas(resource_manager, as_instances.len().max(1).next_power_of_two());
+        }
+        let tlas = self.tlas.as_ref().unwrap();
 
-            let mut build_info = vk::AccelerationStructureBuildGeometryInfoKHR::builder()
-                .flags(vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE)
-                .geometries(&geometries)
-                .mode(vk::BuildAccelerationStructureModeKHR::BUILD)
-                .ty(vk::AccelerationStructureTypeKHR::TOP_LEVEL)
-                .build();
+        if !as_instances.is_empty() {
+            tlas.instance_buffer.borrow().update_data(device, &VecBufferData::new(&as_instances), 0);
+        }
 
-            let size_info = unsafe {
-                acceleration_structure.get_acceleration_structure_build_sizes(
-                    vk::AccelerationStructureBuildTypeKHR::DEVICE,
-                    &build_info,
-                    &[build_range_info.primitive_count],
-                )
-            };
-
-            let top_as_buffer = resource_manager.buffer_with_size(
-                size_info.acceleration_structure_size,
-                vk::BufferUsageFlags::ACCELERATION_STRUCTURE_STORAGE_KHR
-                    | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
-                    | vk::BufferUsageFlags::STORAGE_BUFFER,
-                vk::MemoryPropertyFlags::DEVICE_LOCAL,
-                "TLAS Buffer"
+        let geometries = [Self::tlas_geometry(&tlas.instance_buffer)];
+        let build_info = vk::AccelerationStructureBuildGeometryInfoKHR::builder()
+            .flags(vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE)
+            .geometries(&geometries)
+            .mode(vk::BuildAccelerationStructureModeKHR::BUILD)
+            .ty(vk::AccelerationStructureTypeKHR::TOP_LEVEL)
+            .dst_acceleration_structure(tlas.accel)
+            .scratch_data(vk::DeviceOrHostAddressKHR {
+                device_address: tlas.scratch_buffer.borrow().get_buffer_device_address(),
+            })
+            .build();
+        let build_range_info = vk::AccelerationStructureBuildRangeInfoKHR::builder()
+            .primitive_count(as_instances.len() as u32)
+            .build();
+
+        unsafe {
+            self.loader.cmd_build_acceleration_structures(
+                cmd_buffer,
+                &[build_info],
+                &[&[build_range_info]],
             );
 
-            let top_as_vk_buffer = {
-                let barrier_params = BufferAccess {
-                    src_access: vk::AccessFlags::TRANSFER_WRITE,
-                    src_stage: vk::PipelineStageFlags::TRANSFER,
-                    dst_access: vk::AccessFlags::ACCELERATION_STRUCTURE_WRITE_KHR,
-                    dst_stage: vk::PipelineStageFlags::ACCELERATION_STRUCTURE_BUILD_KHR,
-                };
-
-                top_as_buffer.borrow().access_buffer(device, &barrier_params)
-            };
-
-            let as_create_info = vk::AccelerationStructureCreateInfoKHR::builder()
-                .ty(build_info.ty)
-                .size(size_info.acceleration_structure_size)
-                .buffer(top_as_vk_buffer)
-                .offset(0)
+            let memory_barrier = vk::MemoryBarrier::builder()
+                .src_access_mask(vk::AccessFlags::ACCELERATION_STRUCTURE_WRITE_KHR)
+                .dst_access_mask(vk::AccessFlags::ACCELERATION_STRUCTURE_READ_KHR)
                 .build();
+            device.logical_device.cmd_pipeline_barrier(
+                cmd_buffer,
+                vk::PipelineStageFlags::ACCELERATION_STRUCTURE_BUILD_KHR,
+                vk::PipelineStageFlags::RAY_TRACING_SHADER_KHR,
+                vk::DependencyFlags::empty(),
+                &[memory_barrier],
+                &[],
+                &[],
+            );
+        }
+    }
+
+    /// TLAS of the last build_tlas(), null before the first build
+    pub fn get_tlas(&self) -> vk::AccelerationStructureKHR {
+        self.tlas.as_ref().map_or(vk::AccelerationStructureKHR::null(), |tlas| tlas.accel)
+    }
+
+    fn create_tlas(&mut self, resource_manager: &mut ResourceManager, capacity: usize) {
+        let instance_buffer = resource_manager.buffer_with_size(
+            (capacity * mem::size_of::<vk::AccelerationStructureInstanceKHR>()) as u64,
+            vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR
+                | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            "TLAS Instance Buffer"
+        );
+
+        let geometries = [Self::tlas_geometry(&instance_buffer)];
+        let build_info = vk::AccelerationStructureBuildGeometryInfoKHR::builder()
+            .flags(vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE)
+            .geometries(&geometries)
+            .mode(vk::BuildAccelerationStructureModeKHR::BUILD)
+            .ty(vk::AccelerationStructureTypeKHR::TOP_LEVEL)
+            .build();
+
+        let size_info = unsafe {
+            self.loader.get_acceleration_structure_build_sizes(
+                vk::AccelerationStructureBuildTypeKHR::DEVICE,
+                &build_info,
+                &[capacity as u32],
+            )
+        };
 
-            let top_as =
-                unsafe { acceleration_structure.create_acceleration_structure(&as_create_info, None) }
-                    .unwrap();
+        let (accel, buffer) = self.create(resource_manager, build_info.ty, size_info.acceleration_structure_size, "TLAS Buffer");
+        let scratch_buffer = resource_manager.buffer_with_size(
+            size_info.build_scratch_size,
+            vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS | vk::BufferUsageFlags::STORAGE_BUFFER,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            "TLAS Scratch Buffer",
+        );
+
+        let replaced = self.tlas.replace(Tlas {
+            accel,
+            _buffer: buffer,
+            scratch_buffer,
+            instance_buffer,
+            capacity,
+        });
+        if let Some(tlas) = replaced {
+            unsafe { self.loader.destroy_acceleration_structure(tlas.accel, None) };
+        }
+    }
 
-            build_info.dst_acceleration_structure = top_as;
+    fn create(&self, resource_manager: &mut ResourceManager, ty: vk::AccelerationStructureTypeKHR, size: u64, label: &str) -> (vk::AccelerationStructureKHR, AllocatedBufferMutRef) {
+        let buffer = resource_manager.buffer_with_size(
+            size,
+            vk::BufferUsageFlags::ACCELERATION_STRUCTURE_STORAGE_KHR
+                | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
+                | vk::BufferUsageFlags::STORAGE_BUFFER,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            label
+        );
+
+        let as_create_info = vk::AccelerationStructureCreateInfoKHR::builder()
+            .ty(ty)
+            .size(size)
+            .buffer(buffer.borrow().get_vk_buffer())
+            .offset(0)
+            .build();
+
+        let accel = unsafe { self.loader.create_acceleration_structure(&as_create_info, None) }
+            .expect("Failed to create acceleration structure");
+
+        (accel, buffer)
+    }
 
-            let scratch_buffer = resource_manager.buffer_with_size(
-                size_info.build_scratch_size,
-                vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS | vk::BufferUsageFlags::STORAGE_BUFFER,
-                vk::MemoryPropertyFlags::DEVICE_LOCAL,
-                "TLAS Scratch Buffer",
-            );
+    fn tlas_geometry(instance_buffer: &AllocatedBufferMutRef) -> vk::AccelerationStructureGeometryKHR {
+        let instances = vk::AccelerationStructureGeometryInstancesDataKHR::builder()
+            .array_of_pointers(false)
+            .data(vk::DeviceOrHostAddressConstKHR {
+                device_address: instance_buffer.borrow().get_buffer_device_address(),
+            })
+            .build();
+
+        vk::AccelerationStructureGeometryKHR::builder()
+            .geometry_type(vk::GeometryTypeKHR::INSTANCES)
+            .geometry(vk::AccelerationStructureGeometryDataKHR { instances })
+            .build()
+    }
 
-            build_info.scratch_data = vk::DeviceOrHostAddressKHR {
-                device_address: scratch_buffer.borrow().get_buffer_device_address(),
-            };
-
-            let command_buffer = SingleTimeCmdBuffer::begin(device);
-            unsafe {
-                acceleration_structure.cmd_build_acceleration_structures(
-                    command_buffer.get_command_buffer(),
-                    &[build_info],
-                    &[&[build_range_info]],
-                );
+    // Row major 3x4 matrix from a column major affine transform
+    fn to_transform_matrix(transform: &cgm::Matrix4<f32>) -> vk::TransformMatrixKHR {
+        let mut matrix = [0.0; 12];
+        for row in 0..3 {
+            for column in 0..4 {
+                matrix[row * 4 + column] = transform[column][row];
             }
+        }
 
-            (top_as, top_as_buffer)
-        };
+        vk::TransformMatrixKHR { matrix }
+    }
+}
 
-        AccelerationStructure {
-            accel: acceleration_structure,
-            blas: bottom_as,
-            blas_buffer: bottom_as_buffer,
-            tlas: top_as,
-            tlas_buffer: top_as_buffer,
+impl Drop for AccelerationStructure {
+    fn drop(&mut self) {
+        unsafe {
+            if let Some(tlas) = &self.tlas {
+                self.loader.destroy_acceleration_structure(tlas.accel, None);
+            }
+            for blas in self.blases.values() {
+                self.loader.destroy_acceleration_structure(blas.accel, None);
+            }
         }
     }
-}
\ No newline at end of file
+}
@@ -1,6 +1,7 @@
 use std::rc::Rc;
 
 use ash::vk;
+use serde::Deserialize;
 use crate::vulkan::fence::Fence;
 use crate::vulkan::img::image::{Image};
 use crate::vulkan::semaphore::Semaphore;
@@ -20,6 +21,110 @@ impl Drop for SurfaceDefinition {
     }
 }
 
+/// How finished frames are handed to the display
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum PresentMode {
+    // Vsync, rendering blocks when all images wait for display
+    Fifo,
+    // Vsync, but late frames are shown right away and may tear
+    FifoRelaxed,
+    // Vsync without blocking, the newest frame replaces waiting ones
+    Mailbox,
+    // No vsync, frames are shown right away and tear. Used for uncapped benchmarks.
+    Immediate,
+}
+
+impl PresentMode {
+    /// Vulkan present modes to try in order. FIFO is always supported, so it ends every list.
+    pub fn get_preferences(&self) -> &'static [vk::PresentModeKHR] {
+        match self {
+            PresentMode::Fifo => &[vk::PresentModeKHR::FIFO],
+            PresentMode::FifoRelaxed => &[vk::PresentModeKHR::FIFO_RELAXED, vk::PresentModeKHR::FIFO],
+            PresentMode::Mailbox => &[vk::PresentModeKHR::MAILBOX, vk::PresentModeKHR::FIFO],
+            // Mailbox does not block either, it only drops frames instead of tearing
+            PresentMode::Immediate => &[vk::PresentModeKHR::IMMEDIATE, vk::PresentModeKHR::MAILBOX, vk::PresentModeKHR::FIFO],
+        }
+    }
+}
+
+/// Wide gamut output wanted from the surface. Falls back to SDR when the surface offers none.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum HdrMode {
+    Off,
+    // BT.2020 primaries with SMPTE ST 2084 (PQ) transfer function
+    Hdr10,
+    // Extended linear sRGB in half floats where 1.0 is 80 nits
+    ScRgb,
+    // HDR10, then scRGB
+    Auto,
+}
+
+/// How the final image is encoded for the swapchain format and color space. Exactly one is applied.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputEncoding {
+    // sRGB format encodes linear color on store, so copies need no shader
+    SrgbFormat,
+    // sRGB transfer function applied by the output shader for UNORM formats
+    Srgb,
+    ScRgb,
+    Hdr10,
+}
+
+impl OutputEncoding {
+    /// Encoding id used by output.frag
+    pub fn get_shader_id(&self) -> u32 {
+        match self {
+            OutputEncoding::SrgbFormat => 0,
+            OutputEncoding::Srgb => 1,
+            OutputEncoding::ScRgb => 2,
+            OutputEncoding::Hdr10 => 3,
+        }
+    }
+
+    /// Whether the final image has to be encoded by the output pass instead of a plain copy
+    pub fn needs_shader(&self) -> bool {
+        *self != OutputEncoding::SrgbFormat
+    }
+}
+
+/// Swapchain presentation options
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct PresentSettings {
+    pub mode: PresentMode,
+    // Swapchain images to request, clamped to what the surface supports. None requests one per frame in flight.
+    pub image_count: Option<u32>,
+    pub hdr: HdrMode,
+    // Brightness of SDR white in nits for HDR output
+    pub paper_white: f32,
+}
+
+impl Default for PresentSettings {
+    fn default() -> Self {
+        PresentSettings {
+            mode: PresentMode::Mailbox,
+            image_count: None,
+            hdr: HdrMode::Off,
+            paper_white: 200.0,
+        }
+    }
+}
+
+// Surface formats for HDR output with their encodings in order of preference
+const HDR10_FORMATS: [(vk::Format, vk::ColorSpaceKHR, OutputEncoding); 2] = [
+    (vk::Format::A2B10G10R10_UNORM_PACK32, vk::ColorSpaceKHR::HDR10_ST2084_EXT, OutputEncoding::Hdr10),
+    (vk::Format::A2R10G10B10_UNORM_PACK32, vk::ColorSpaceKHR::HDR10_ST2084_EXT, OutputEncoding::Hdr10),
+];
+const SCRGB_FORMATS: [(vk::Format, vk::ColorSpaceKHR, OutputEncoding); 1] = [
+    (vk::Format::R16G16B16A16_SFLOAT, vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT, OutputEncoding::ScRgb),
+];
+const SDR_FORMATS: [(vk::Format, vk::ColorSpaceKHR, OutputEncoding); 4] = [
+    (vk::Format::B8G8R8A8_SRGB, vk::ColorSpaceKHR::SRGB_NONLINEAR, OutputEncoding::SrgbFormat),
+    (vk::Format::R8G8B8A8_SRGB, vk::ColorSpaceKHR::SRGB_NONLINEAR, OutputEncoding::SrgbFormat),
+    (vk::Format::B8G8R8A8_UNORM, vk::ColorSpaceKHR::SRGB_NONLINEAR, OutputEncoding::Srgb),
+    (vk::Format::R8G8B8A8_UNORM, vk::ColorSpaceKHR::SRGB_NONLINEAR, OutputEncoding::Srgb),
+];
+
 pub struct SwapchainSupportDetails {
     capabilities: vk::SurfaceCapabilitiesKHR,
     formats: Vec<vk::SurfaceFormatKHR>,
@@ -33,11 +138,14 @@ pub struct Swapchain {
     pub swapchain: vk::SwapchainKHR,
     pub images: Vec<Image>,
     pub format: vk::Format,
+    pub encoding: OutputEncoding,
     pub extent: vk::Extent2D,
     pub image_available_sems: Vec<Semaphore>,
     pub render_finished_sems: Vec<Semaphore>,
     pub in_flight_fences: Vec<Fence>,
     in_flight_images: Vec<Option<vk::Fence>>,
+    // Image acquired for the current frame. Differs from current_frame when the image count differs from frames in flight.
+    image_idx: usize,
 }
 
 impl SwapchainSupportDetails {
@@ -71,26 +179,64 @@ impl SwapchainSupportDetails {
         !self.formats.is_empty() && !self.present_modes.is_empty()
     }
 
-    pub fn choose_format(&self) -> vk::SurfaceFormatKHR {
-        for fmt in &self.formats {
-            if fmt.format == vk::Format::B8G8R8A8_SRGB
-                && fmt.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
-            {
-                return *fmt;
-            }
+    /// Surface format for the wanted HDR mode with the encoding it needs. SDR formats are the fallback.
+    pub fn choose_format(&self, hdr: HdrMode) -> (vk::SurfaceFormatKHR, OutputEncoding) {
+        let hdr_formats: Vec<_> = match hdr {
+            HdrMode::Off => vec![],
+            HdrMode::Hdr10 => HDR10_FORMATS.to_vec(),
+            HdrMode::ScRgb => SCRGB_FORMATS.to_vec(),
+            HdrMode::Auto => HDR10_FORMATS.iter().chain(SCRGB_FORMATS.iter()).copied().collect(),
+        };
+        let chosen = hdr_formats.iter().chain(SDR_FORMATS.iter())
+            .find_map(|(format, color_space, encoding)| {
+                self.formats.iter()
+                    .find(|f| f.format == *format && f.color_space == *color_space)
+                    .map(|f| (*f, *encoding))
+            })
+            .unwrap_or_else(|| {
+                // Unknown format, assume it is meant for sRGB content
+                let format = *self.formats.first().unwrap();
+                let encoding = match format.format {
+                    vk::Format::R8G8B8A8_SRGB | vk::Format::B8G8R8A8_SRGB | vk::Format::A8B8G8R8_SRGB_PACK32
+                    | vk::Format::R8G8B8_SRGB | vk::Format::B8G8R8_SRGB => OutputEncoding::SrgbFormat,
+                    _ => OutputEncoding::Srgb,
+                };
+                (format, encoding)
+            });
+
+        if hdr != HdrMode::Off && !hdr_formats.iter().any(|(format, _, _)| *format == chosen.0.format) {
+            log::warn!("Surface does not support {:?} output, using SDR.", hdr);
         }
+        log::info!("Swapchain format: {:?} {:?} with {:?} encoding", chosen.0.format, chosen.0.color_space, chosen.1);
 
-        *self.formats.first().unwrap()
+        chosen
     }
 
-    pub fn choose_present_mode(&self) -> vk::PresentModeKHR {
-        for mode in &self.present_modes {
-            if *mode == vk::PresentModeKHR::MAILBOX {
-                return *mode;
-            }
+    /// First present mode of the wanted ones the surface supports
+    pub fn choose_present_mode(&self, mode: PresentMode) -> vk::PresentModeKHR {
+        let preferences = mode.get_preferences();
+        let chosen = preferences.iter()
+            .find(|m| self.present_modes.contains(m))
+            .copied()
+            .unwrap_or(vk::PresentModeKHR::FIFO);
+        if chosen == preferences[0] {
+            log::info!("Present mode: {:?}", chosen);
+        } else {
+            log::warn!("Present mode {:?} is not supported by the surface, using {:?}.", preferences[0], chosen);
         }
 
-        vk::PresentModeKHR::FIFO
+        chosen
+    }
+
+    /// Requested image count clamped to the surface limits. Max image count of 0 means no limit.
+    pub fn choose_image_count(&self, requested: Option<u32>) -> u32 {
+        let requested = requested.unwrap_or(MAX_FRAMES_IN_FLIGHT as u32);
+        let count = requested.max(self.capabilities.min_image_count);
+        if self.capabilities.max_image_count > 0 {
+            count.min(self.capabilities.max_image_count)
+        } else {
+            count
+        }
     }
 
     pub fn choose_extent(&self, width: u32, height: u32) -> vk::Extent2D {
@@ -121,21 +267,16 @@ impl Swapchain {
         surface: &SurfaceDefinition,
         width: u32,
         height: u32,
+        settings: &PresentSettings,
         old_swapchain: &Option<Swapchain>,
     ) -> Swapchain {
         let device_ref = device.borrow();
         let swapchain_support =
             SwapchainSupportDetails::get_for(device_ref.physical_device, surface);
         let extent = swapchain_support.choose_extent(width, height);
-        let format = swapchain_support.choose_format();
-        let present_mode = swapchain_support.choose_present_mode();
-
-        let image_count =
-            if swapchain_support.capabilities.max_image_count >= MAX_FRAMES_IN_FLIGHT as u32 {
-                MAX_FRAMES_IN_FLIGHT as u32
-            } else {
-                swapchain_support.capabilities.min_image_count + 1
-            };
+        let (format, encoding) = swapchain_support.choose_format(settings.hdr);
+        let present_mode = swapchain_support.choose_present_mode(settings.mode);
+        let image_count = swapchain_support.choose_image_count(settings.image_count);
 
         let (image_sharing_mode, queue_family_index_count, queue_family_indices) =
             if device_ref.queue_family_indices.graphics_family
@@ -200,9 +341,9 @@ impl Swapchain {
                 *image,
                 width,
                 height,
-                vk::Format::R8G8B8A8_SRGB,
+                format.format,
                 format!("Swapchain-{}", i).as_str()
-            ); // TODO: format is a guess
+            );
             wrapped_images.push(wrapped);
         }
 
@@ -215,6 +356,7 @@ impl Swapchain {
             in_flight_fences.push(Fence::new(device, vk::FenceCreateFlags::SIGNALED, format!("InFlight{}", i).as_str()));
         }
 
+        log::info!("Swapchain has {} images of {} requested", wrapped_images.len(), image_count);
         let in_flight_images = vec![None; wrapped_images.len()];
 
         Swapchain {
@@ -223,12 +365,14 @@ impl Swapchain {
             loader: swapchain_loader,
             swapchain,
             format: format.format,
+            encoding,
             extent,
             images: wrapped_images,
             image_available_sems,
             render_finished_sems,
             in_flight_fences,
             in_flight_images,
+            image_idx: 0,
         }
     }
 
@@ -267,12 +411,13 @@ impl Swapchain {
         }
 
         self.in_flight_images[image_idx as usize] = Some(self.in_flight_fences[self.current_frame].get_fence());
+        self.image_idx = image_idx as usize;
 
-        Ok(image_idx as usize)
+        Ok(self.image_idx)
     }
 
     pub fn reset_inflight_fence(&self) {
-        let fences = [self.in_flight_fences[self.device.borrow().get_image_idx()].get_fence()];
+        let fences = [self.in_flight_fences[self.current_frame].get_fence()];
         unsafe {
             self.device
                 .borrow()
@@ -322,7 +467,7 @@ impl Swapchain {
             p_wait_semaphores: wait_semaphores.as_ptr(),
             swapchain_count: swapchains.len() as u32,
             p_swapchains: swapchains.as_ptr(),
-            p_image_indices: &(self.current_frame as u32),
+            p_image_indices: &(self.image_idx as u32),
             ..Default::default()
         };
 
@@ -340,6 +485,74 @@ impl Swapchain {
 
 impl Drop for Swapchain {
     fn drop(&mut self) {
+        // Views of swapchain images go before the swapchain owning the images
+        self.images.clear();
         self.destroy();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ash::vk;
+
+    use super::{HdrMode, OutputEncoding, PresentMode, SwapchainSupportDetails};
+
+    fn support(present_modes: Vec<vk::PresentModeKHR>, min_image_count: u32, max_image_count: u32) -> SwapchainSupportDetails {
+        SwapchainSupportDetails {
+            capabilities: vk::SurfaceCapabilitiesKHR { min_image_count, max_image_count, ..Default::default() },
+            formats: vec![],
+            present_modes,
+        }
+    }
+
+    #[test]
+    fn present_mode_and_image_count_fall_back_to_supported() {
+        let full = support(
+            vec![vk::PresentModeKHR::FIFO, vk::PresentModeKHR::FIFO_RELAXED, vk::PresentModeKHR::MAILBOX, vk::PresentModeKHR::IMMEDIATE],
+            2,
+            0,
+        );
+        assert_eq!(full.choose_present_mode(PresentMode::Immediate), vk::PresentModeKHR::IMMEDIATE);
+        assert_eq!(full.choose_present_mode(PresentMode::FifoRelaxed), vk::PresentModeKHR::FIFO_RELAXED);
+        assert_eq!(full.choose_present_mode(PresentMode::Fifo), vk::PresentModeKHR::FIFO);
+
+        let fifo_only = support(vec![vk::PresentModeKHR::FIFO], 3, 4);
+        for mode in &[PresentMode::Fifo, PresentMode::FifoRelaxed, PresentMode::Mailbox, PresentMode::Immediate] {
+            assert_eq!(fifo_only.choose_present_mode(*mode), vk::PresentModeKHR::FIFO);
+        }
+        let mailbox = support(vec![vk::PresentModeKHR::FIFO, vk::PresentModeKHR::MAILBOX], 2, 8);
+        assert_eq!(mailbox.choose_present_mode(PresentMode::Immediate), vk::PresentModeKHR::MAILBOX);
+
+        // No maximum for 0
+        assert_eq!(full.choose_image_count(Some(6)), 6);
+        assert_eq!(full.choose_image_count(Some(1)), 2);
+        assert_eq!(fifo_only.choose_image_count(Some(6)), 4);
+        assert_eq!(fifo_only.choose_image_count(None), 3);
+    }
+
+    #[test]
+    fn hdr_format_with_single_encoding_or_sdr_fallback() {
+        let format = |format, color_space| vk::SurfaceFormatKHR { format, color_space };
+        let mut surface = support(vec![vk::PresentModeKHR::FIFO], 2, 0);
+        surface.formats = vec![
+            format(vk::Format::B8G8R8A8_UNORM, vk::ColorSpaceKHR::SRGB_NONLINEAR),
+            format(vk::Format::B8G8R8A8_SRGB, vk::ColorSpaceKHR::SRGB_NONLINEAR),
+            format(vk::Format::R16G16B16A16_SFLOAT, vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT),
+            format(vk::Format::A2B10G10R10_UNORM_PACK32, vk::ColorSpaceKHR::HDR10_ST2084_EXT),
+        ];
+        let chosen = |surface: &SwapchainSupportDetails, hdr| {
+            let (format, encoding) = surface.choose_format(hdr);
+            (format.format, encoding)
+        };
+        assert_eq!(chosen(&surface, HdrMode::Off), (vk::Format::B8G8R8A8_SRGB, OutputEncoding::SrgbFormat));
+        assert_eq!(chosen(&surface, HdrMode::Auto), (vk::Format::A2B10G10R10_UNORM_PACK32, OutputEncoding::Hdr10));
+        assert_eq!(chosen(&surface, HdrMode::ScRgb), (vk::Format::R16G16B16A16_SFLOAT, OutputEncoding::ScRgb));
+
+        // Without HDR formats sRGB is encoded by the format if possible, by the output shader otherwise
+        surface.formats.truncate(2);
+        assert_eq!(chosen(&surface, HdrMode::Hdr10), (vk::Format::B8G8R8A8_SRGB, OutputEncoding::SrgbFormat));
+        surface.formats.remove(1);
+        assert_eq!(chosen(&surface, HdrMode::Auto), (vk::Format::B8G8R8A8_UNORM, OutputEncoding::Srgb));
+        assert!(!OutputEncoding::SrgbFormat.needs_shader() && OutputEncoding::Srgb.needs_shader());
+    }
+}
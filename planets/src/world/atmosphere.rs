@@ -0,0 +1,396 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use ash::vk;
+use cgmath as cgm;
+use cgmath::prelude::*;
+use image::{DynamicImage, ImageBuffer};
+
+use crate::vulkan::device::DeviceMutRef;
+use crate::vulkan::img::image::{Image, ImageMutRef};
+use crate::vulkan::resources::manager::ResourceManager;
+
+// LUT sizes must match the constants in atmosphere.frag
+pub const TRANSMITTANCE_LUT_WIDTH: usize = 256;
+pub const TRANSMITTANCE_LUT_HEIGHT: usize = 64;
+pub const SCATTERING_R_SIZE: usize = 16;
+pub const SCATTERING_MU_SIZE: usize = 64;
+pub const SCATTERING_MU_S_SIZE: usize = 32;
+pub const SCATTERING_NU_SIZE: usize = 8;
+
+const TRANSMITTANCE_STEPS: usize = 40;
+const SCATTERING_STEPS: usize = 24;
+
+// Earth atmosphere in meters used as a reference for earth_like()
+const EARTH_RADIUS: f64 = 6_360_000.0;
+const EARTH_ATMOSPHERE_HEIGHT: f64 = 60_000.0;
+
+pub type AtmosphereMutRef = Rc<RefCell<Atmosphere>>;
+
+/// Physical description of planet atmosphere. Lengths are in scene units, coefficients per scene unit.
+#[derive(Copy, Clone)]
+pub struct AtmosphereParams {
+    // Radius of the planet surface
+    pub bottom_radius: f64,
+    // Radius of the atmosphere boundary
+    pub top_radius: f64,
+    pub rayleigh_scattering: cgm::Vector3<f64>,
+    // Altitude at which Rayleigh particle density drops by e
+    pub rayleigh_scale_height: f64,
+    pub mie_scattering: f64,
+    pub mie_extinction: f64,
+    // Altitude at which Mie particle density drops by e
+    pub mie_scale_height: f64,
+    // Asymmetry of Mie phase function
+    pub mie_g: f64,
+    // Irradiance of the sun at the top of the atmosphere
+    pub sun_intensity: f64,
+}
+
+impl AtmosphereParams {
+    /// Earth atmosphere scaled to the planet of the given radius
+    pub fn earth_like(radius: f64) -> Self {
+        let scale = radius / EARTH_RADIUS;
+
+        AtmosphereParams {
+            bottom_radius: radius,
+            top_radius: radius + EARTH_ATMOSPHERE_HEIGHT * scale,
+            rayleigh_scattering: cgm::Vector3::new(5.802e-6, 13.558e-6, 33.1e-6) / scale,
+            rayleigh_scale_height: 8000.0 * scale,
+            mie_scattering: 3.996e-6 / scale,
+            mie_extinction: 4.44e-6 / scale,
+            mie_scale_height: 1200.0 * scale,
+            mie_g: 0.8,
+            sun_intensity: 10.0,
+        }
+    }
+
+    fn rayleigh_density(&self, altitude: f64) -> f64 {
+        (-altitude.max(0.0) / self.rayleigh_scale_height).exp()
+    }
+
+    fn mie_density(&self, altitude: f64) -> f64 {
+        (-altitude.max(0.0) / self.mie_scale_height).exp()
+    }
+
+    fn extinction(&self, altitude: f64) -> cgm::Vector3<f64> {
+        self.rayleigh_scattering * self.rayleigh_density(altitude)
+            + cgm::Vector3::from_value(self.mie_extinction * self.mie_density(altitude))
+    }
+
+    // Distance from the ground to the top of the atmosphere along the horizon
+    fn horizon_length(&self) -> f64 {
+        (self.top_radius * self.top_radius - self.bottom_radius * self.bottom_radius).sqrt()
+    }
+
+    fn distance_to_top(&self, r: f64, mu: f64) -> f64 {
+        let discriminant = r * r * (mu * mu - 1.0) + self.top_radius * self.top_radius;
+        (-r * mu + discriminant.max(0.0).sqrt()).max(0.0)
+    }
+
+    fn distance_to_bottom(&self, r: f64, mu: f64) -> f64 {
+        let discriminant = r * r * (mu * mu - 1.0) + self.bottom_radius * self.bottom_radius;
+        (-r * mu - discriminant.max(0.0).sqrt()).max(0.0)
+    }
+
+    /// Check if ray from distance r to planet center with zenith angle cosine mu hits the ground
+    pub fn intersects_ground(&self, r: f64, mu: f64) -> bool {
+        mu < 0.0 && r * r * (mu * mu - 1.0) + self.bottom_radius * self.bottom_radius >= 0.0
+    }
+
+    /// Transmittance from distance r to the top of the atmosphere. Ground is ignored.
+    pub fn compute_transmittance(&self, r: f64, mu: f64) -> cgm::Vector3<f64> {
+        let length = self.distance_to_top(r, mu);
+        let dt = length / TRANSMITTANCE_STEPS as f64;
+        let mut optical_depth = cgm::Vector3::zero();
+        for i in 0..TRANSMITTANCE_STEPS {
+            let t = (i as f64 + 0.5) * dt;
+            let r_t = (t * t + 2.0 * r * mu * t + r * r).sqrt();
+            optical_depth += self.extinction(r_t - self.bottom_radius) * dt;
+        }
+
+        optical_depth.map(|d| (-d).exp())
+    }
+
+    /// Zenith angle cosine for the mu coordinate of scattering LUT. Lower half of the range holds rays
+    /// hitting the ground, upper half the rays leaving the atmosphere, so the horizon is not blurred.
+    /// Both halves go from the nadir or zenith in the middle towards the horizon at the ends.
+    fn scattering_mu(&self, r: f64, u: f64) -> (f64, bool) {
+        let h = self.horizon_length();
+        let rho = (r * r - self.bottom_radius * self.bottom_radius).max(0.0).sqrt();
+        if u < 0.5 {
+            let (d_min, d_max) = (r - self.bottom_radius, rho);
+            let d = d_min + (d_max - d_min) * (1.0 - 2.0 * u);
+            let mu = if d == 0.0 { -1.0 } else { (-(rho * rho + d * d) / (2.0 * r * d)).clamp(-1.0, 1.0) };
+            (mu, true)
+        } else {
+            let (d_min, d_max) = (self.top_radius - r, rho + h);
+            let d = d_min + (d_max - d_min) * (2.0 * u - 1.0);
+            let mu = if d == 0.0 { 1.0 } else { ((h * h - rho * rho - d * d) / (2.0 * r * d)).clamp(-1.0, 1.0) };
+            (mu, false)
+        }
+    }
+
+    /// Distance to planet center for the r coordinate of LUTs
+    fn lut_radius(&self, u: f64) -> f64 {
+        let rho = self.horizon_length() * u;
+        (rho * rho + self.bottom_radius * self.bottom_radius).sqrt()
+    }
+}
+
+/// Transmittance to the top of the atmosphere for a grid of distances to planet center and zenith angles
+pub struct TransmittanceLut {
+    values: Vec<cgm::Vector3<f64>>,
+}
+
+impl TransmittanceLut {
+    pub fn compute(params: &AtmosphereParams) -> Self {
+        let mut values = Vec::with_capacity(TRANSMITTANCE_LUT_WIDTH * TRANSMITTANCE_LUT_HEIGHT);
+        for j in 0..TRANSMITTANCE_LUT_HEIGHT {
+            let r = params.lut_radius(unit_from_index(j, TRANSMITTANCE_LUT_HEIGHT));
+            for i in 0..TRANSMITTANCE_LUT_WIDTH {
+                let mu = unit_from_index(i, TRANSMITTANCE_LUT_WIDTH) * 2.0 - 1.0;
+                values.push(params.compute_transmittance(r, mu));
+            }
+        }
+
+        TransmittanceLut { values }
+    }
+
+    /// Bilinearly interpolated transmittance to the top of the atmosphere
+    pub fn sample(&self, params: &AtmosphereParams, r: f64, mu: f64) -> cgm::Vector3<f64> {
+        let rho = (r * r - params.bottom_radius * params.bottom_radius).max(0.0).sqrt();
+        let x = ((mu + 1.0) * 0.5).clamp(0.0, 1.0) * (TRANSMITTANCE_LUT_WIDTH - 1) as f64;
+        let y = (rho / params.horizon_length()).clamp(0.0, 1.0) * (TRANSMITTANCE_LUT_HEIGHT - 1) as f64;
+        let (x0, y0) = ((x as usize).min(TRANSMITTANCE_LUT_WIDTH - 2), (y as usize).min(TRANSMITTANCE_LUT_HEIGHT - 2));
+        let (fx, fy) = (x - x0 as f64, y - y0 as f64);
+        let value = |i: usize, j: usize| self.values[j * TRANSMITTANCE_LUT_WIDTH + i];
+
+        let bottom = value(x0, y0).lerp(value(x0 + 1, y0), fx);
+        let top = value(x0, y0 + 1).lerp(value(x0 + 1, y0 + 1), fx);
+        bottom.lerp(top, fy)
+    }
+
+    pub fn to_rgba(&self) -> Vec<f32> {
+        self.values.iter()
+            .flat_map(|v| vec![v.x as f32, v.y as f32, v.z as f32, 1.0])
+            .collect()
+    }
+}
+
+/// Rayleigh and Mie single scattering along the ray before phase functions are applied.
+/// Light is scattered towards the ray origin at distance r from planet center, mu and mu_s are cosines of
+/// the view and sun zenith angles and nu is the cosine of the angle between view and sun directions.
+pub fn single_scattering(
+    params: &AtmosphereParams,
+    transmittance: &TransmittanceLut,
+    r: f64,
+    mu: f64,
+    mu_s: f64,
+    nu: f64,
+    ray_hits_ground: bool,
+) -> (cgm::Vector3<f64>, cgm::Vector3<f64>) {
+    let position = cgm::Vector3::new(0.0, 0.0, r);
+    let sin_view = (1.0 - mu * mu).max(0.0).sqrt();
+    let view = cgm::Vector3::new(sin_view, 0.0, mu);
+    let sun_x = if sin_view > 1e-6 { ((nu - mu * mu_s) / sin_view).clamp(-1.0, 1.0) } else { 0.0 };
+    let sun_y = (1.0 - sun_x * sun_x - mu_s * mu_s).max(0.0).sqrt();
+    let sun = cgm::Vector3::new(sun_x, sun_y, mu_s).normalize();
+
+    let length = if ray_hits_ground { params.distance_to_bottom(r, mu) } else { params.distance_to_top(r, mu) };
+    let dt = length / SCATTERING_STEPS as f64;
+    let mut optical_depth = cgm::Vector3::zero();
+    let mut rayleigh = cgm::Vector3::zero();
+    let mut mie = cgm::Vector3::zero();
+    for i in 0..SCATTERING_STEPS {
+        let point = position + view * ((i as f64 + 0.5) * dt);
+        let r_point = point.magnitude();
+        let altitude = r_point - params.bottom_radius;
+        let extinction = params.extinction(altitude);
+
+        optical_depth += extinction * (dt * 0.5);
+        let mu_s_point = point.dot(sun) / r_point;
+        if !params.intersects_ground(r_point, mu_s_point) {
+            let view_transmittance = optical_depth.map(|d| (-d).exp());
+            let light = view_transmittance.mul_element_wise(transmittance.sample(params, r_point, mu_s_point));
+            rayleigh += light * (params.rayleigh_density(altitude) * dt);
+            mie += light * (params.mie_density(altitude) * dt);
+        }
+        optical_depth += extinction * (dt * 0.5);
+    }
+
+    (rayleigh.mul_element_wise(params.rayleigh_scattering), mie * params.mie_scattering)
+}
+
+/// 4D single scattering table packed into 2D texture: nu and mu_s along width, r and mu along height.
+/// RGB store Rayleigh scattering, A stores red channel of Mie scattering.
+pub fn compute_scattering_lut(params: &AtmosphereParams, transmittance: &TransmittanceLut) -> Vec<f32> {
+    let width = SCATTERING_NU_SIZE * SCATTERING_MU_S_SIZE;
+    let height = SCATTERING_R_SIZE * SCATTERING_MU_SIZE;
+    let mut data = vec![0.0; width * height * 4];
+
+    for r_idx in 0..SCATTERING_R_SIZE {
+        let r = params.lut_radius(unit_from_index(r_idx, SCATTERING_R_SIZE));
+        for mu_idx in 0..SCATTERING_MU_SIZE {
+            let (mu, ray_hits_ground) = params.scattering_mu(r, unit_from_index(mu_idx, SCATTERING_MU_SIZE));
+            for nu_idx in 0..SCATTERING_NU_SIZE {
+                let nu = unit_from_index(nu_idx, SCATTERING_NU_SIZE) * 2.0 - 1.0;
+                for mu_s_idx in 0..SCATTERING_MU_S_SIZE {
+                    let mu_s = unit_from_index(mu_s_idx, SCATTERING_MU_S_SIZE) * 2.0 - 1.0;
+                    let (rayleigh, mie) = single_scattering(params, transmittance, r, mu, mu_s, nu, ray_hits_ground);
+
+                    let x = nu_idx * SCATTERING_MU_S_SIZE + mu_s_idx;
+                    let y = r_idx * SCATTERING_MU_SIZE + mu_idx;
+                    let offset = (y * width + x) * 4;
+                    data[offset..offset + 4].copy_from_slice(&[rayleigh.x as f32, rayleigh.y as f32, rayleigh.z as f32, mie.x as f32]);
+                }
+            }
+        }
+    }
+
+    data
+}
+
+// LUT texels store values at both ends of the parameter range
+fn unit_from_index(index: usize, size: usize) -> f64 {
+    index as f64 / (size - 1) as f64
+}
+
+/// Lookup tables of the atmosphere uploaded to GPU
+pub struct AtmosphereLuts {
+    pub transmittance: ImageMutRef,
+    pub scattering: ImageMutRef,
+}
+
+/// Atmosphere of a planet. Lookup tables are precomputed when the atmosphere is rendered for the first time.
+pub struct Atmosphere {
+    params: AtmosphereParams,
+    luts: Option<AtmosphereLuts>,
+}
+
+impl Atmosphere {
+    pub fn new_mut_ref(params: AtmosphereParams) -> AtmosphereMutRef {
+        Rc::new(RefCell::new(Atmosphere { params, luts: None }))
+    }
+
+    pub fn get_params(&self) -> &AtmosphereParams {
+        &self.params
+    }
+
+    /// Lookup tables of the atmosphere. Computes them and records upload on the first call.
+    pub fn get_luts(&mut self, device: &DeviceMutRef, resource_manager: &mut ResourceManager) -> &AtmosphereLuts {
+        if self.luts.is_none() {
+            let transmittance = TransmittanceLut::compute(&self.params);
+            let scattering = compute_scattering_lut(&self.params, &transmittance);
+
+            let transmittance_image = Self::upload_lut(
+                device,
+                resource_manager,
+                TRANSMITTANCE_LUT_WIDTH,
+                TRANSMITTANCE_LUT_HEIGHT,
+                transmittance.to_rgba(),
+                "AtmosphereTransmittance",
+            );
+            let scattering_image = Self::upload_lut(
+                device,
+                resource_manager,
+                SCATTERING_NU_SIZE * SCATTERING_MU_S_SIZE,
+                SCATTERING_R_SIZE * SCATTERING_MU_SIZE,
+                scattering,
+                "AtmosphereScattering",
+            );
+
+            self.luts = Some(AtmosphereLuts {
+                transmittance: transmittance_image,
+                scattering: scattering_image,
+            });
+        }
+
+        self.luts.as_ref().unwrap()
+    }
+
+    fn upload_lut(
+        device: &DeviceMutRef,
+        resource_manager: &mut ResourceManager,
+        width: usize,
+        height: usize,
+        data: Vec<f32>,
+        label: &str,
+    ) -> ImageMutRef {
+        let buffer = ImageBuffer::from_raw(width as u32, height as u32, data).expect("LUT data does not match its size");
+        let mut image = Image::from_data(device, DynamicImage::ImageRgba32F(buffer), vk::Format::R32G32B32A32_SFLOAT, label);
+        if image.upload(&device.borrow(), resource_manager).is_err() {
+            log::error!("Failed to upload {}", label);
+        }
+
+        Rc::new(RefCell::new(image))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params() -> AtmosphereParams {
+        AtmosphereParams::earth_like(EARTH_RADIUS)
+    }
+
+    #[test]
+    fn vertical_transmittance_matches_analytic() {
+        let params = params();
+        let height = params.top_radius - params.bottom_radius;
+        let column = |scale_height: f64| scale_height * (1.0 - (-height / scale_height).exp());
+        let optical_depth = params.rayleigh_scattering * column(params.rayleigh_scale_height)
+            + cgm::Vector3::from_value(params.mie_extinction * column(params.mie_scale_height));
+
+        let transmittance = params.compute_transmittance(params.bottom_radius, 1.0);
+        for i in 0..3 {
+            assert!((transmittance[i] - (-optical_depth[i]).exp()).abs() < 1e-3);
+        }
+
+        let lut = TransmittanceLut::compute(&params);
+        let sampled = lut.sample(&params, params.bottom_radius, 1.0);
+        assert!((sampled - transmittance).magnitude() < 1e-6);
+        // Red light passes better than blue, more so at the horizon
+        let horizon = lut.sample(&params, params.bottom_radius, 0.0);
+        assert!(horizon.z < horizon.x && horizon.x < transmittance.x);
+    }
+
+    #[test]
+    fn scattering_mu_splits_ground_and_sky_rays() {
+        let params = params();
+        let r = params.bottom_radius + 1000.0;
+        // Both halves start at the zenith or nadir in the middle and end at the horizon
+        let (mu, hits_ground) = params.scattering_mu(r, 0.5 - 1e-12);
+        assert!(hits_ground && (mu + 1.0).abs() < 1e-6);
+        let (mu, hits_ground) = params.scattering_mu(r, 0.5);
+        assert!(!hits_ground && (mu - 1.0).abs() < 1e-9);
+        let horizon_mu = -(r * r - params.bottom_radius * params.bottom_radius).sqrt() / r;
+        let (mu, _) = params.scattering_mu(r, 0.0);
+        assert!((mu - horizon_mu).abs() < 1e-9);
+
+        for &u in &[0.1, 0.3, 0.45] {
+            let (mu, hits_ground) = params.scattering_mu(r, u);
+            assert!(hits_ground && params.intersects_ground(r, mu));
+        }
+        for &u in &[0.55, 0.7, 0.9] {
+            let (mu, hits_ground) = params.scattering_mu(r, u);
+            assert!(!hits_ground && !params.intersects_ground(r, mu));
+        }
+    }
+
+    #[test]
+    fn daylight_sky_is_blue_and_night_sky_is_dark() {
+        let params = params();
+        let lut = TransmittanceLut::compute(&params);
+        let r = params.bottom_radius;
+
+        let (rayleigh, mie) = single_scattering(&params, &lut, r, 1.0, 0.7, 0.7, false);
+        assert!(rayleigh.z > rayleigh.y && rayleigh.y > rayleigh.x);
+        assert!(mie.x > 0.0);
+
+        let (rayleigh, mie) = single_scattering(&params, &lut, r, 1.0, -0.5, -0.5, false);
+        assert_eq!(rayleigh, cgm::Vector3::zero());
+        assert_eq!(mie, cgm::Vector3::zero());
+    }
+}
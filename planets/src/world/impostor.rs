@@ -0,0 +1,126 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use cgmath as cgm;
+
+use crate::engine::geometry::{Geometry, Vertex};
+use crate::engine::material::Material;
+use crate::engine::scene::node::{Node, NodeContent, NodeMutRef};
+use crate::vulkan::drawable::{DrawType, Drawable, DrawableMutRef};
+use crate::vulkan::resources::manager::ResourceManager;
+use crate::vulkan::resources::objects::ObjectDescriptions;
+
+/// Angular diameter in radians of a sphere seen from the given distance to its center
+pub fn angular_size(radius: f64, distance: f64) -> f64 {
+    if distance <= radius {
+        std::f64::consts::PI
+    } else {
+        2.0 * (radius / distance).asin()
+    }
+}
+
+/// When bodies switch between full geometry and impostors
+#[derive(Clone, Debug)]
+pub struct ImpostorSettings {
+    // Angular diameter in radians below which only the impostor is drawn
+    pub angular_size: f64,
+    // Part of the angular size above it over which the impostor fades out on top of the geometry
+    pub fade_range: f64,
+}
+
+impl Default for ImpostorSettings {
+    fn default() -> Self {
+        ImpostorSettings {
+            angular_size: 0.01,
+            fade_range: 0.5,
+        }
+    }
+}
+
+impl ImpostorSettings {
+    /// Angular size at which the impostor is fully faded out
+    pub fn get_fade_end(&self) -> f64 {
+        self.angular_size * (1.0 + self.fade_range)
+    }
+
+    pub fn is_geometry_visible(&self, angular_size: f64) -> bool {
+        angular_size >= self.angular_size
+    }
+
+    pub fn is_impostor_visible(&self, angular_size: f64) -> bool {
+        angular_size < self.get_fade_end()
+    }
+
+    /// Impostor opacity for the given angular size. Must match impostor.frag.
+    #[allow(dead_code)]
+    pub fn get_opacity(&self, angular_size: f64) -> f64 {
+        let fade = (angular_size - self.angular_size) / (self.get_fade_end() - self.angular_size);
+        1.0 - fade.clamp(0.0, 1.0)
+    }
+}
+
+/// Camera facing billboard drawn instead of a distant body. Its node is scaled by body radius, the
+/// impostor pass takes sphere center and radius from its model transform.
+pub struct Impostor {
+    // Owned here as the instance only holds a weak reference
+    #[allow(dead_code)]
+    drawable: DrawableMutRef,
+    node: NodeMutRef,
+}
+
+impl Impostor {
+    pub fn new(resource_manager: &mut ResourceManager, object_descriptions: &mut ObjectDescriptions, radius: f64) -> Self {
+        // Quad corners in position xy, expanded to cover the body silhouette in impostor.vert
+        let vertices = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].iter()
+            .map(|(x, y)| Vertex {
+                position: cgm::Vector3::new(*x, *y, 0.0),
+                normal: cgm::Vector3::new(0.0, 0.0, 1.0),
+                uv: cgm::Vector2::new(x * 0.5 + 0.5, y * 0.5 + 0.5),
+            })
+            .collect();
+        let geometry = Geometry::new(resource_manager, vertices, vec![0, 1, 2, 2, 3, 0], &String::from("Impostor"));
+        let drawable = Rc::new(RefCell::new(Drawable::new(object_descriptions, DrawType::Impostor, geometry, Material::new())));
+
+        let instance_node = Rc::new(RefCell::new(Node::with_content(NodeContent::DrawableInstance(
+            Drawable::create_instance(&drawable),
+        ))));
+        let mut node = Node::with_content(NodeContent::Transform(cgm::Matrix4::from_scale(radius)));
+        node.add_child(instance_node);
+
+        Impostor {
+            drawable,
+            node: Rc::new(RefCell::new(node)),
+        }
+    }
+
+    pub fn get_node(&self) -> &NodeMutRef {
+        &self.node
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{angular_size, ImpostorSettings};
+
+    #[test]
+    fn impostor_cross_fades_with_geometry() {
+        let settings = ImpostorSettings { angular_size: 0.01, fade_range: 0.5 };
+        let radius = 50.0;
+        let far = angular_size(radius, 20000.0);
+        let fading = angular_size(radius, 8000.0);
+        let near = angular_size(radius, 1000.0);
+        assert!(far < 0.01 && fading > 0.01 && fading < 0.015 && near > 0.015);
+
+        assert!(settings.is_impostor_visible(far) && !settings.is_geometry_visible(far));
+        assert_eq!(settings.get_opacity(far), 1.0);
+
+        // Both are drawn while the impostor fades out
+        assert!(settings.is_impostor_visible(fading) && settings.is_geometry_visible(fading));
+        assert!(settings.get_opacity(fading) > 0.0 && settings.get_opacity(fading) < 1.0);
+
+        assert!(!settings.is_impostor_visible(near) && settings.is_geometry_visible(near));
+        assert_eq!(settings.get_opacity(near), 0.0);
+
+        assert_eq!(angular_size(radius, radius * 0.5), std::f64::consts::PI);
+    }
+}
@@ -5,15 +5,25 @@ use std::cell::RefCell;
 use std::collections::HashMap;
 
 use cgmath as cgm;
+use cgmath::SquareMatrix;
 
+use crate::engine::camera::{Projection, ProjectionKind};
 use crate::engine::geometry::{Geometry, Vertex};
 use crate::engine::material::Material;
 use crate::engine::scene::node::{Node, NodeContent, NodeMutRef};
+use crate::engine::skybox::{Skybox, SkyboxMutRef};
 use crate::engine::textures::{TextureManager, TextureManagerMutRef};
 
 use crate::vulkan::drawable::{Drawable, DrawType};
 use crate::vulkan::resources::manager::{ResourceManager, ResourceManagerMutRef};
 use crate::vulkan::resources::objects::{ObjectDescriptions, ObjectDescriptionsMutRef};
+use crate::world::orbit::{OrbitalElements, OrbitLines, OrbitLinesMutRef};
+use crate::world::rings::{PlanetRings, PlanetRingsMutRef, RingParams};
+use crate::world::terrain::surface::{PlanetSurface, PlanetSurfaceMutRef};
+use crate::world::stars::{StarCatalog, Starfield, StarfieldMutRef};
+
+// Number of line segments of orbit ellipses
+const ORBIT_SEGMENTS: u32 = 256;
 
 pub struct ModelLoader {
     resource_manager: ResourceManagerMutRef,
@@ -58,6 +68,45 @@ impl ModelLoader {
         }
     }
 
+    /// Create ring geometry and profile texture. Planet radius is needed to cast planet shadow on the rings.
+    pub fn create_rings(&mut self, params: RingParams, planet_radius: f64) -> PlanetRingsMutRef {
+        PlanetRings::new_mut_ref(
+            &mut self.resource_manager.borrow_mut(),
+            &mut self.object_descriptions.borrow_mut(),
+            &mut self.texture_manager.borrow_mut(),
+            params,
+            planet_radius,
+        )
+    }
+
+    /// Create orbit line geometry around the given world position
+    pub fn create_orbit_lines(&mut self, elements: OrbitalElements, focus: cgm::Vector3<f64>, color: cgm::Vector3<f32>) -> OrbitLinesMutRef {
+        OrbitLines::new_mut_ref(&mut self.resource_manager.borrow_mut(), elements, focus, color, ORBIT_SEGMENTS)
+    }
+
+    /// Planet surface maps from an equirectangular albedo image
+    pub fn load_planet_surface(&mut self, albedo_path: &str) -> Result<PlanetSurfaceMutRef, String> {
+        let albedo = self.texture_manager.borrow_mut().get_equirect_texture(albedo_path)?;
+        Ok(PlanetSurface::new_mut_ref(&mut self.resource_manager.borrow_mut(), albedo))
+    }
+
+    pub fn load_starfield(&mut self, path: &str) -> Result<StarfieldMutRef, String> {
+        let catalog = StarCatalog::load_csv(path)?;
+        Ok(Starfield::new_mut_ref(&mut self.resource_manager.borrow_mut(), &catalog))
+    }
+
+    /// Skybox from an equirectangular image, usually .hdr or .exr
+    pub fn load_skybox(&mut self, path: &str) -> Result<SkyboxMutRef, String> {
+        let image = self.texture_manager.borrow_mut().get_equirect_cubemap(path, None)?;
+        Ok(Skybox::new_mut_ref(image))
+    }
+
+    /// Skybox from six cube face images in +X, -X, +Y, -Y, +Z, -Z order
+    pub fn load_skybox_cubemap(&mut self, face_paths: &[&str; 6]) -> Result<SkyboxMutRef, String> {
+        let image = self.texture_manager.borrow_mut().get_cubemap(face_paths)?;
+        Ok(Skybox::new_mut_ref(image))
+    }
+
     pub fn load_gltf_impl(&mut self, path: &str) -> Result<Vec<NodeMutRef>,String> {
         let (document, _, _) = match gltf::import(path) {
             Ok(x) => x,
@@ -93,7 +142,7 @@ impl ModelLoader {
     }
 
     fn node_from_gltf(&mut self, gltf_node: &gltf::Node, gltf_dir_path: &Path) -> Result<Vec<NodeMutRef>,String> {
-        if let Some(mesh) = gltf_node.mesh() {
+        if gltf_node.mesh().is_some() || gltf_node.camera().is_some() {
             // TODO: don't create transform node if node transform is identity
             let gltf_transform = gltf_node.transform().matrix();
             let transform = cgm::Matrix4 {
@@ -102,19 +151,27 @@ impl ModelLoader {
                 z: cgm::Vector4 { x: gltf_transform[2][0], y: gltf_transform[2][1], z: gltf_transform[2][2], w: gltf_transform[2][3] },
                 w: cgm::Vector4 { x: gltf_transform[3][0], y: gltf_transform[3][1], z: gltf_transform[3][2], w: gltf_transform[3][3] }
             };
-            let transform_node = Rc::new(RefCell::new(Node::with_content(NodeContent::Transform(transform))));
-            match ModelLoader::mesh_from_node(
-                    &mut self.resource_manager.borrow_mut(),
-                    &mut self.object_descriptions.borrow_mut(),
-                    &mut self.texture_manager.borrow_mut(),
-                    &mesh,
-                    gltf_dir_path) {
-                Ok(mesh_node) => transform_node.borrow_mut().add_child(mesh_node),
-                Err(str) => {
-                    log::warn!("Could not load mesh node from {}", gltf_dir_path.to_str().unwrap_or("Unknown"));
-                    log::error!("{}", str);
-                },
-            };
+            let transform_node = Rc::new(RefCell::new(Node::with_content(NodeContent::Transform(
+                transform.cast().unwrap_or(cgm::Matrix4::identity())
+            ))));
+            if let Some(mesh) = gltf_node.mesh() {
+                match ModelLoader::mesh_from_node(
+                        &mut self.resource_manager.borrow_mut(),
+                        &mut self.object_descriptions.borrow_mut(),
+                        &mut self.texture_manager.borrow_mut(),
+                        &mesh,
+                        gltf_dir_path) {
+                    Ok(mesh_node) => transform_node.borrow_mut().add_child(mesh_node),
+                    Err(str) => {
+                        log::warn!("Could not load mesh node from {}", gltf_dir_path.to_str().unwrap_or("Unknown"));
+                        log::error!("{}", str);
+                    },
+                };
+            }
+            if let Some(camera) = gltf_node.camera() {
+                let camera_node = Node::with_content(NodeContent::Camera(ModelLoader::projection_from_gltf(&camera)));
+                transform_node.borrow_mut().add_child(Rc::new(RefCell::new(camera_node)));
+            }
 
             for child in gltf_node.children() {
                 if let Ok(children_nodes) = self.node_from_gltf(&child, gltf_dir_path) {
@@ -143,6 +200,22 @@ impl ModelLoader {
         }
     }
 
+    // glTF cameras look along -Z with +Y up like scene camera nodes do
+    fn projection_from_gltf(camera: &gltf::Camera) -> Projection {
+        match camera.projection() {
+            gltf::camera::Projection::Perspective(perspective) => Projection {
+                kind: ProjectionKind::Perspective(cgm::Rad(perspective.yfov()).into()),
+                near: perspective.znear(),
+                far: perspective.zfar().unwrap_or(Projection::default().far),
+            },
+            gltf::camera::Projection::Orthographic(orthographic) => Projection {
+                kind: ProjectionKind::Orthographic(2.0 * orthographic.ymag()),
+                near: orthographic.znear(),
+                far: orthographic.zfar(),
+            },
+        }
+    }
+
     fn mesh_from_node(resource_manager: &mut ResourceManager, object_descriptions: &mut ObjectDescriptions, texture_manager: &mut TextureManager, mesh: &gltf::Mesh, gltf_dir_path: &Path) -> Result<NodeMutRef,String> {
         let mut vertices = vec![];
         let mut indices = vec![];
@@ -1 +1,8 @@
+pub mod atmosphere;
+pub mod impostor;
 pub mod loader;
+pub mod orbit;
+pub mod rings;
+pub mod ship;
+pub mod stars;
+pub mod terrain;
@@ -0,0 +1,308 @@
+use std::cell::RefCell;
+use std::f64::consts::PI;
+use std::rc::Rc;
+
+use cgmath as cgm;
+use cgmath::prelude::*;
+
+use crate::engine::geometry::{Geometry, Vertex};
+use crate::engine::simulation::SimulationClock;
+use crate::util::math;
+use crate::vulkan::device::{Device, MAX_FRAMES_IN_FLIGHT};
+use crate::vulkan::mem::StructBufferData;
+use crate::vulkan::resources::manager::ResourceManager;
+use crate::vulkan::uniform_buffer::UniformBufferObject;
+
+pub type OrbitLinesMutRef = Rc<RefCell<OrbitLines>>;
+
+// Lines start fading at this many semi-major axes from the camera
+const FADE_START: f64 = 4.0;
+// Lines disappear at this many semi-major axes from the camera
+const FADE_END: f64 = 40.0;
+const KEPLER_MAX_ITERATIONS: u32 = 32;
+
+/// Keplerian elements of an elliptic orbit. Reference plane is world XZ plane with north along +Y and
+/// reference direction along +X.
+#[derive(Clone, Debug)]
+pub struct OrbitalElements {
+    pub semi_major_axis: f64,
+    // 0 for circular orbits, must stay below 1
+    pub eccentricity: f64,
+    pub inclination: cgm::Rad<f64>,
+    pub longitude_of_ascending_node: cgm::Rad<f64>,
+    pub argument_of_periapsis: cgm::Rad<f64>,
+    // Mean anomaly at simulation time 0
+    pub mean_anomaly_at_epoch: cgm::Rad<f64>,
+    // Standard gravitational parameter of the orbited body
+    pub gravitational_parameter: f64,
+}
+
+impl OrbitalElements {
+    #[allow(dead_code)]
+    pub fn circular(radius: f64, gravitational_parameter: f64) -> Self {
+        OrbitalElements {
+            semi_major_axis: radius,
+            eccentricity: 0.0,
+            inclination: cgm::Rad(0.0),
+            longitude_of_ascending_node: cgm::Rad(0.0),
+            argument_of_periapsis: cgm::Rad(0.0),
+            mean_anomaly_at_epoch: cgm::Rad(0.0),
+            gravitational_parameter,
+        }
+    }
+
+    /// Orbital period in simulation seconds
+    pub fn get_period(&self) -> f64 {
+        2.0 * PI / self.get_mean_motion()
+    }
+
+    /// Mean angular velocity in radians per second
+    pub fn get_mean_motion(&self) -> f64 {
+        (self.gravitational_parameter / self.semi_major_axis.powi(3)).sqrt()
+    }
+
+    /// Mean anomaly in [0, 2π) at the given simulation time
+    pub fn mean_anomaly_at(&self, time: f64) -> f64 {
+        (self.mean_anomaly_at_epoch.0 + self.get_mean_motion() * time).rem_euclid(2.0 * PI)
+    }
+
+    /// Solve Kepler's equation M = E - e sin E for eccentric anomaly E with Newton's method
+    pub fn eccentric_anomaly(&self, mean_anomaly: f64) -> f64 {
+        let e = self.eccentricity;
+        // Starting at π converges for any eccentricity
+        let mut anomaly = if e > 0.8 { PI } else { mean_anomaly };
+        for _ in 0..KEPLER_MAX_ITERATIONS {
+            let delta = (anomaly - e * anomaly.sin() - mean_anomaly) / (1.0 - e * anomaly.cos());
+            anomaly -= delta;
+            if delta.abs() < 1e-12 {
+                break;
+            }
+        }
+
+        anomaly
+    }
+
+    /// Position relative to the orbited body at the given eccentric anomaly
+    pub fn position_from_eccentric_anomaly(&self, eccentric_anomaly: f64) -> cgm::Vector3<f64> {
+        let a = self.semi_major_axis;
+        let e = self.eccentricity;
+        let b = a * (1.0 - e * e).sqrt();
+        let (sin, cos) = eccentric_anomaly.sin_cos();
+        let perifocal = cgm::Vector3::new(a * (cos - e), b * sin, 0.0);
+
+        self.perifocal_to_world() * perifocal
+    }
+
+    /// Position relative to the orbited body at the given simulation time
+    pub fn position_at(&self, time: f64) -> cgm::Vector3<f64> {
+        self.position_from_eccentric_anomaly(self.eccentric_anomaly(self.mean_anomaly_at(time)))
+    }
+
+    /// Closed polyline around the orbit, evenly spaced in eccentric anomaly so it stays smooth near
+    /// periapsis. Each point comes with its mean anomaly as fraction of the orbit, the last point repeats the first one.
+    pub fn sample_ellipse(&self, segments: u32) -> Vec<(cgm::Vector3<f64>, f64)> {
+        let segments = segments.max(3);
+        (0..=segments).map(|i| {
+            let eccentric_anomaly = 2.0 * PI * i as f64 / segments as f64;
+            let mean_anomaly = eccentric_anomaly - self.eccentricity * eccentric_anomaly.sin();
+            (self.position_from_eccentric_anomaly(eccentric_anomaly), mean_anomaly / (2.0 * PI))
+        }).collect()
+    }
+
+    /// Positions relative to the orbited body over the given simulation time span
+    #[allow(dead_code)]
+    pub fn sample_trajectory(&self, start_time: f64, duration: f64, samples: u32) -> Vec<cgm::Vector3<f64>> {
+        let samples = samples.max(2);
+        (0..samples)
+            .map(|i| self.position_at(start_time + duration * i as f64 / (samples - 1) as f64))
+            .collect()
+    }
+
+    // Rotation from perifocal frame (X to periapsis, Z along orbit normal) to the world
+    fn perifocal_to_world(&self) -> cgm::Matrix3<f64> {
+        // Elements are defined in a Z-up reference frame, world is Y-up
+        let reference_to_world = cgm::Matrix3::new(
+            1.0, 0.0, 0.0,
+            0.0, 0.0, -1.0,
+            0.0, 1.0, 0.0,
+        );
+
+        reference_to_world
+            * cgm::Matrix3::from_angle_z(self.longitude_of_ascending_node)
+            * cgm::Matrix3::from_angle_x(self.inclination)
+            * cgm::Matrix3::from_angle_z(self.argument_of_periapsis)
+    }
+}
+
+#[repr(C)]
+struct OrbitUBOInterface {
+    // Orbit frame relative to render origin
+    model: cgm::Matrix4<f32>,
+    // rgb: line color, a: fraction of the orbit highlighted as predicted trajectory
+    color: cgm::Vector4<f32>,
+    // x: body mean anomaly as fraction of the orbit, y: camera distance where lines start fading,
+    // z: camera distance where lines disappear
+    params: cgm::Vector4<f32>,
+}
+
+/// Orbit ellipse of a body drawn as a line strip around the orbited body. Part of the ellipse the body
+/// reaches within trajectory duration is highlighted as its predicted trajectory.
+pub struct OrbitLines {
+    elements: OrbitalElements,
+    // World position of the orbited body
+    focus: cgm::Vector3<f64>,
+    color: cgm::Vector3<f32>,
+    // Simulation seconds ahead of the body shown as predicted trajectory
+    trajectory_duration: f64,
+    // Ellipse relative to focus. uv.x holds mean anomaly as fraction of the orbit.
+    geometry: Geometry,
+    // Per frame in flight, written by update()
+    ubo: Vec<UniformBufferObject>,
+}
+
+impl OrbitLines {
+    pub fn new_mut_ref(
+        resource_manager: &mut ResourceManager,
+        elements: OrbitalElements,
+        focus: cgm::Vector3<f64>,
+        color: cgm::Vector3<f32>,
+        segments: u32,
+    ) -> OrbitLinesMutRef {
+        Rc::new(RefCell::new(OrbitLines::new(resource_manager, elements, focus, color, segments)))
+    }
+
+    pub fn new(
+        resource_manager: &mut ResourceManager,
+        elements: OrbitalElements,
+        focus: cgm::Vector3<f64>,
+        color: cgm::Vector3<f32>,
+        segments: u32,
+    ) -> Self {
+        let label = format!("OrbitLines({})", elements.semi_major_axis);
+        let vertices: Vec<Vertex> = elements.sample_ellipse(segments).iter().map(|(position, anomaly)| Vertex {
+            position: position.cast().unwrap_or(cgm::Vector3::zero()),
+            normal: cgm::Vector3::zero(),
+            uv: cgm::Vector2::new(*anomaly as f32, 0.0),
+        }).collect();
+        let indices = (0..vertices.len() as i32).collect();
+        let geometry = Geometry::new(resource_manager, vertices, indices, &label);
+
+        let ubo_data = OrbitUBOInterface {
+            model: cgm::Matrix4::identity(),
+            color: color.extend(0.0),
+            params: cgm::Vector4::zero(),
+        };
+        let ubo_data = StructBufferData::new(&ubo_data);
+        let ubo = (0..MAX_FRAMES_IN_FLIGHT)
+            .map(|i| UniformBufferObject::new_with_data(resource_manager, &ubo_data, format!("{}{}", label, i).as_str()))
+            .collect();
+
+        OrbitLines {
+            elements,
+            focus,
+            color,
+            trajectory_duration: 0.0,
+            geometry,
+            ubo,
+        }
+    }
+
+    /// Write body position and line placement relative to the render origin for the current frame
+    pub fn update(&self, device: &Device, simulation: &SimulationClock, origin: &cgm::Vector3<f64>) {
+        let period = self.elements.get_period();
+        let mean_anomaly = self.elements.mean_anomaly_at(simulation.get_interpolated_elapsed());
+        let a = self.elements.semi_major_axis;
+        let ubo_data = OrbitUBOInterface {
+            model: math::rebase_transform(&cgm::Matrix4::from_translation(self.focus), origin),
+            color: self.color.extend((self.trajectory_duration / period).min(1.0) as f32),
+            params: cgm::Vector4::new((mean_anomaly / (2.0 * PI)) as f32, (FADE_START * a) as f32, (FADE_END * a) as f32, 0.0),
+        };
+        self.ubo[device.get_image_idx()].buffer.borrow().update_data(device, &StructBufferData::new(&ubo_data), 0);
+    }
+
+    pub fn set_trajectory_duration(&mut self, seconds: f64) {
+        self.trajectory_duration = seconds.max(0.0);
+    }
+
+    #[allow(dead_code)]
+    pub fn get_elements(&self) -> &OrbitalElements {
+        &self.elements
+    }
+
+    pub fn get_geometry(&self) -> &Geometry {
+        &self.geometry
+    }
+
+    pub fn get_ubo(&self, image_idx: usize) -> &UniformBufferObject {
+        &self.ubo[image_idx]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use cgmath as cgm;
+    use cgmath::prelude::*;
+
+    use super::OrbitalElements;
+
+    fn eccentric_orbit() -> OrbitalElements {
+        OrbitalElements {
+            semi_major_axis: 100.0,
+            eccentricity: 0.5,
+            inclination: cgm::Deg(30.0).into(),
+            longitude_of_ascending_node: cgm::Deg(40.0).into(),
+            argument_of_periapsis: cgm::Deg(60.0).into(),
+            mean_anomaly_at_epoch: cgm::Rad(0.0),
+            gravitational_parameter: 1000.0,
+        }
+    }
+
+    #[test]
+    fn circular_orbit_keeps_radius_and_period() {
+        let orbit = OrbitalElements::circular(10.0, 4.0 * PI * PI * 1000.0);
+        assert!((orbit.get_period() - (1000.0_f64 / 1000.0).sqrt()).abs() < 1e-9);
+
+        let start = orbit.position_at(0.0);
+        assert!((start - cgm::Vector3::new(10.0, 0.0, 0.0)).magnitude() < 1e-9);
+        // Prograde orbits turn counterclockwise around +Y
+        assert!(orbit.position_at(0.25 * orbit.get_period()).z < -9.99);
+        for i in 0..10 {
+            assert!((orbit.position_at(i as f64 * 0.037).magnitude() - 10.0).abs() < 1e-9);
+        }
+        assert!((orbit.position_at(orbit.get_period()) - start).magnitude() < 1e-9);
+    }
+
+    #[test]
+    fn kepler_solution_and_apsides() {
+        let orbit = eccentric_orbit();
+        for i in 0..16 {
+            let mean_anomaly = 2.0 * PI * i as f64 / 16.0;
+            let eccentric_anomaly = orbit.eccentric_anomaly(mean_anomaly);
+            assert!((eccentric_anomaly - 0.5 * eccentric_anomaly.sin() - mean_anomaly).abs() < 1e-10);
+        }
+
+        let periapsis = orbit.position_at(0.0).magnitude();
+        let apoapsis = orbit.position_at(0.5 * orbit.get_period()).magnitude();
+        assert!((periapsis - 50.0).abs() < 1e-9);
+        assert!((apoapsis - 150.0).abs() < 1e-9);
+
+        // Orbit normal is tilted from +Y by inclination
+        let normal = orbit.position_at(0.0).cross(orbit.position_at(0.1 * orbit.get_period())).normalize();
+        assert!((normal.dot(cgm::Vector3::unit_y()) - cgm::Deg(30.0_f64).cos()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn ellipse_samples_follow_orbit() {
+        let orbit = eccentric_orbit();
+        let samples = orbit.sample_ellipse(64);
+        assert_eq!(samples.len(), 65);
+        assert!((samples[0].0 - samples[64].0).magnitude() < 1e-9);
+        assert!((samples[64].1 - 1.0).abs() < 1e-12);
+        for (position, anomaly) in &samples {
+            let expected = orbit.position_at(anomaly * orbit.get_period());
+            assert!((position - expected).magnitude() < 1e-6);
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use std::cell::RefCell;
+use std::f32::consts::PI;
+use std::rc::Rc;
+
+use ash::vk;
+use cgmath as cgm;
+use image::{DynamicImage, Rgba, RgbaImage};
+
+use crate::engine::geometry::{Geometry, Vertex};
+use crate::engine::material::Material;
+use crate::engine::scene::node::{Node, NodeContent, NodeMutRef};
+use crate::engine::textures::TextureManager;
+use crate::vulkan::drawable::{Drawable, DrawType, DrawableMutRef};
+use crate::vulkan::mem::StructBufferData;
+use crate::vulkan::resources::manager::ResourceManager;
+use crate::vulkan::resources::objects::ObjectDescriptions;
+use crate::vulkan::uniform_buffer::UniformBufferObject;
+
+pub type PlanetRingsMutRef = Rc<RefCell<PlanetRings>>;
+
+#[derive(Clone)]
+pub struct RingParams {
+    pub inner_radius: f32,
+    pub outer_radius: f32,
+    // Number of quads around the ring
+    pub segments: u32,
+    // Henyey-Greenstein asymmetry of ring particles, close to 1 for fine dust
+    pub forward_scattering: f32,
+    // Radial profile from inner to outer edge. rgb: particle color, a: density
+    pub profile: Vec<cgm::Vector4<f32>>,
+}
+
+/// Annulus in XZ plane of the parent node facing +Y. U coordinate goes from 0 at the inner edge to 1
+/// at the outer one, V goes around the ring.
+pub fn generate_ring_mesh(params: &RingParams) -> (Vec<Vertex>, Vec<i32>) {
+    let segments = params.segments.max(3);
+    let mut vertices = Vec::with_capacity(2 * (segments as usize + 1));
+    let mut indices = Vec::with_capacity(6 * segments as usize);
+
+    // Seam vertices are duplicated so V does not wrap inside a quad
+    for i in 0..=segments {
+        let v = i as f32 / segments as f32;
+        let angle = 2.0 * PI * v;
+        let (sin, cos) = angle.sin_cos();
+        for (u, radius) in [(0.0, params.inner_radius), (1.0, params.outer_radius)].iter().cloned() {
+            vertices.push(Vertex {
+                position: cgm::Vector3::new(radius * cos, 0.0, radius * sin),
+                normal: cgm::Vector3::new(0.0, 1.0, 0.0),
+                uv: cgm::Vector2::new(u, v),
+            });
+        }
+    }
+
+    for i in 0..segments as i32 {
+        let inner = 2 * i;
+        let outer = inner + 1;
+        let next_inner = inner + 2;
+        let next_outer = inner + 3;
+        indices.extend_from_slice(&[inner, next_inner, outer, outer, next_inner, next_outer]);
+    }
+
+    (vertices, indices)
+}
+
+#[repr(C)]
+struct RingUBOInterface {
+    // x: planet radius, y: forward scattering asymmetry
+    params: cgm::Vector4<f32>,
+}
+
+/// Ring system of a planet. Its node is attached to the planet node, so rings follow planet position
+/// and axial tilt.
+pub struct PlanetRings {
+    params: RingParams,
+    drawable: DrawableMutRef,
+    // Node with the ring drawable instance
+    node: NodeMutRef,
+    // Ring shading parameters. They never change, so one buffer is shared by frames in flight.
+    ubo: UniformBufferObject,
+}
+
+impl PlanetRings {
+    pub fn new_mut_ref(
+        resource_manager: &mut ResourceManager,
+        object_descriptions: &mut ObjectDescriptions,
+        texture_manager: &mut TextureManager,
+        params: RingParams,
+        planet_radius: f64,
+    ) -> PlanetRingsMutRef {
+        Rc::new(RefCell::new(PlanetRings::new(resource_manager, object_descriptions, texture_manager, params, planet_radius)))
+    }
+
+    pub fn new(
+        resource_manager: &mut ResourceManager,
+        object_descriptions: &mut ObjectDescriptions,
+        texture_manager: &mut TextureManager,
+        params: RingParams,
+        planet_radius: f64,
+    ) -> Self {
+        let label = format!("PlanetRings({}-{})", params.inner_radius, params.outer_radius);
+        let (vertices, indices) = generate_ring_mesh(&params);
+        let geometry = Geometry::new(resource_manager, vertices, indices, &label);
+
+        let mut material = Material::new();
+        material.albedo_map = Some(texture_manager.add_texture(
+            &format!("{}::Profile", label),
+            PlanetRings::profile_image(&params.profile),
+            vk::Format::R8G8B8A8_SRGB,
+        ));
+        let drawable = Rc::new(RefCell::new(Drawable::new(object_descriptions, DrawType::Ring, geometry, material)));
+        let node = Rc::new(RefCell::new(Node::with_content(NodeContent::DrawableInstance(
+            Drawable::create_instance(&drawable),
+        ))));
+
+        let ubo_data = RingUBOInterface {
+            params: cgm::Vector4::new(planet_radius as f32, params.forward_scattering, 0.0, 0.0),
+        };
+        let ubo = UniformBufferObject::new_with_data(resource_manager, &StructBufferData::new(&ubo_data), &label);
+
+        PlanetRings {
+            params,
+            drawable,
+            node,
+            ubo,
+        }
+    }
+
+    // Color is stored in sRGB, density stays linear in alpha
+    fn profile_image(profile: &[cgm::Vector4<f32>]) -> DynamicImage {
+        let to_byte = |x: f32| (x.clamp(0.0, 1.0) * 255.0).round() as u8;
+        let to_srgb = |x: f32| if x <= 0.003_130_8 { 12.92 * x } else { 1.055 * x.powf(1.0 / 2.4) - 0.055 };
+
+        let mut image = RgbaImage::new(profile.len().max(1) as u32, 1);
+        for (i, texel) in profile.iter().enumerate() {
+            image.put_pixel(i as u32, 0, Rgba([
+                to_byte(to_srgb(texel.x)),
+                to_byte(to_srgb(texel.y)),
+                to_byte(to_srgb(texel.z)),
+                to_byte(texel.w),
+            ]));
+        }
+
+        DynamicImage::ImageRgba8(image)
+    }
+
+    #[allow(dead_code)]
+    pub fn get_params(&self) -> &RingParams {
+        &self.params
+    }
+
+    pub fn get_drawable(&self) -> &DrawableMutRef {
+        &self.drawable
+    }
+
+    pub fn get_node(&self) -> &NodeMutRef {
+        &self.node
+    }
+
+    pub fn get_ubo(&self) -> &UniformBufferObject {
+        &self.ubo
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath as cgm;
+    use cgmath::prelude::*;
+
+    use super::{generate_ring_mesh, RingParams};
+
+    #[test]
+    fn ring_mesh_maps_radius_to_u() {
+        let params = RingParams {
+            inner_radius: 70.0,
+            outer_radius: 120.0,
+            segments: 32,
+            forward_scattering: 0.7,
+            profile: vec![cgm::Vector4::new(1.0, 1.0, 1.0, 1.0)],
+        };
+        let (vertices, indices) = generate_ring_mesh(&params);
+
+        assert_eq!(indices.len(), 6 * 32);
+        assert!(indices.iter().all(|i| (*i as usize) < vertices.len()));
+        for v in &vertices {
+            let radius = v.position.magnitude();
+            let u = (radius - params.inner_radius) / (params.outer_radius - params.inner_radius);
+            assert!(v.position.y.abs() < 1e-6);
+            assert!((u - v.uv.x).abs() < 1e-4);
+        }
+
+        // Triangles face the +Y normal
+        for t in indices.chunks(3) {
+            let (a, b, c) = (vertices[t[0] as usize].position, vertices[t[1] as usize].position, vertices[t[2] as usize].position);
+            assert!((b - a).cross(c - a).y > 0.0);
+        }
+    }
+}
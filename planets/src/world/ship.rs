@@ -0,0 +1,281 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use cgmath as cgm;
+use cgmath::prelude::*;
+
+use crate::engine::scene::node::{Node, NodeContent, NodeMutRef, UpdateCallResult};
+
+pub type ShipMutRef = Rc<RefCell<Ship>>;
+type FlightModelMutRef = Rc<RefCell<FlightModel>>;
+
+/// Ship local axes: forward is -Z, up is +Y and right is +X
+pub const SHIP_FORWARD: cgm::Vector3<f64> = cgm::Vector3 { x: 0.0, y: 0.0, z: -1.0 };
+
+/// Physical properties of a ship
+#[derive(Clone, Debug)]
+pub struct ShipParams {
+    pub mass: f64,
+    // Force of the main engine at full throttle
+    pub max_thrust: f64,
+    // Torque of RCS thrusters around each axis
+    pub rcs_torque: f64,
+    // Moment of inertia around each axis
+    pub moment_of_inertia: f64,
+}
+
+/// Pilot input. Values are in [-1; 1] range.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShipControls {
+    // Positive accelerates forward, negative fires retro thrusters
+    pub throttle: f64,
+    // Pitch up, yaw left and roll counter clockwise, as rotations around ship X, Y and Z axes
+    pub rotation: cgm::Vector3<f64>,
+}
+
+impl Default for ShipControls {
+    fn default() -> Self {
+        ShipControls {
+            throttle: 0.0,
+            rotation: cgm::Vector3::zero(),
+        }
+    }
+}
+
+/// Point mass attracting the ship
+#[derive(Clone, Debug)]
+pub struct GravitySource {
+    pub position: cgm::Vector3<f64>,
+    pub gravitational_parameter: f64,
+}
+
+/// Kinematic state of a ship in world space
+#[derive(Clone, Debug)]
+pub struct ShipState {
+    pub position: cgm::Vector3<f64>,
+    pub velocity: cgm::Vector3<f64>,
+    pub orientation: cgm::Quaternion<f64>,
+    // Angular velocity in ship local axes
+    pub angular_velocity: cgm::Vector3<f64>,
+}
+
+impl ShipState {
+    pub fn get_transform(&self) -> cgm::Matrix4<f64> {
+        cgm::Matrix4::from_translation(self.position) * cgm::Matrix4::from(self.orientation)
+    }
+}
+
+/// Newtonian flight model. Thrust and gravity change velocity, RCS torque changes angular velocity.
+/// Without rotation input RCS damps rotation, so the ship holds its attitude.
+pub struct FlightModel {
+    pub params: ShipParams,
+    pub state: ShipState,
+    pub controls: ShipControls,
+}
+
+impl FlightModel {
+    pub fn new(params: ShipParams, state: ShipState) -> Self {
+        FlightModel {
+            params,
+            state,
+            controls: ShipControls::default(),
+        }
+    }
+
+    /// Gravitational acceleration at the given position
+    pub fn get_gravity(position: &cgm::Vector3<f64>, gravity_sources: &[GravitySource]) -> cgm::Vector3<f64> {
+        gravity_sources.iter().fold(cgm::Vector3::zero(), |acceleration, source| {
+            let offset = source.position - position;
+            let distance2 = offset.magnitude2();
+            if distance2 > 0.0 {
+                acceleration + offset * (source.gravitational_parameter / (distance2 * distance2.sqrt()))
+            } else {
+                acceleration
+            }
+        })
+    }
+
+    /// Advance the ship by the given time with semi-implicit Euler integration
+    pub fn step(&mut self, dt: f64, gravity_sources: &[GravitySource]) {
+        let state = &mut self.state;
+        let throttle = self.controls.throttle.clamp(-1.0, 1.0);
+        let thrust = state.orientation.rotate_vector(SHIP_FORWARD) * (throttle * self.params.max_thrust / self.params.mass);
+        let acceleration = thrust + FlightModel::get_gravity(&state.position, gravity_sources);
+        state.velocity += acceleration * dt;
+        state.position += state.velocity * dt;
+
+        let max_angular_acceleration = self.params.rcs_torque / self.params.moment_of_inertia;
+        let rotation = self.controls.rotation.map(|r| r.clamp(-1.0, 1.0));
+        for axis in 0..3 {
+            let change = if rotation[axis] != 0.0 {
+                rotation[axis] * max_angular_acceleration * dt
+            } else {
+                // Stop rotation around idle axes without overshooting
+                let stop = max_angular_acceleration * dt;
+                (-state.angular_velocity[axis]).max(-stop).min(stop)
+            };
+            state.angular_velocity[axis] += change;
+        }
+
+        let angle = state.angular_velocity.magnitude() * dt;
+        if angle > 0.0 {
+            let delta = cgm::Quaternion::from_axis_angle(state.angular_velocity.normalize(), cgm::Rad(angle));
+            state.orientation = (state.orientation * delta).normalize();
+        }
+    }
+}
+
+// Body attracting the ship with its node placement taken every step
+struct GravityBody {
+    node: NodeMutRef,
+    gravitational_parameter: f64,
+}
+
+/// Controllable ship moved by its flight model every simulation step
+pub struct Ship {
+    node: NodeMutRef,
+    flight_model: FlightModelMutRef,
+}
+
+impl Ship {
+    pub fn new_mut_ref(params: ShipParams, state: ShipState) -> ShipMutRef {
+        Rc::new(RefCell::new(Ship::new(params, state)))
+    }
+
+    pub fn new(params: ShipParams, state: ShipState) -> Self {
+        let node = Rc::new(RefCell::new(Node::with_content(NodeContent::Transform(state.get_transform()))));
+        let flight_model = Rc::new(RefCell::new(FlightModel::new(params, state)));
+
+        let ship = Ship {
+            node,
+            flight_model,
+        };
+        ship.set_gravity_bodies(vec![]);
+
+        ship
+    }
+
+    /// Attract the ship to the given body nodes. Bodies are expected to be attached to the scene root.
+    pub fn set_gravity_bodies(&self, bodies: Vec<(NodeMutRef, f64)>) {
+        let bodies: Vec<GravityBody> = bodies.into_iter()
+            .map(|(node, gravitational_parameter)| GravityBody { node, gravitational_parameter })
+            .collect();
+        let flight_model = Rc::clone(&self.flight_model);
+        self.node.borrow_mut().simulation_call = Some(Box::new(move |_, simulation| {
+            let gravity_sources: Vec<GravitySource> = bodies.iter()
+                .filter_map(|body| {
+                    let node = body.node.try_borrow().ok()?;
+                    let transform = node.get_simulated_transform().or_else(|| node.get_local_transform(1.0))?;
+                    Some(GravitySource {
+                        position: transform.w.truncate(),
+                        gravitational_parameter: body.gravitational_parameter,
+                    })
+                })
+                .collect();
+            let mut flight_model = flight_model.borrow_mut();
+            flight_model.step(simulation.get_step(), &gravity_sources);
+            UpdateCallResult {
+                transform: Some(flight_model.state.get_transform()),
+                pre_update_action: None,
+            }
+        }));
+    }
+
+    /// Attach ship model placed in ship local axes
+    pub fn set_model(&self, model: NodeMutRef) {
+        self.node.borrow_mut().add_child(model);
+    }
+
+    pub fn set_controls(&self, controls: ShipControls) {
+        self.flight_model.borrow_mut().controls = controls;
+    }
+
+    #[allow(dead_code)]
+    pub fn get_state(&self) -> ShipState {
+        self.flight_model.borrow().state.clone()
+    }
+
+    pub fn get_node(&self) -> &NodeMutRef {
+        &self.node
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath as cgm;
+    use cgmath::prelude::*;
+
+    use super::{FlightModel, GravitySource, ShipControls, ShipParams, ShipState};
+
+    const DT: f64 = 1.0 / 60.0;
+
+    fn flight_model(position: cgm::Vector3<f64>, velocity: cgm::Vector3<f64>) -> FlightModel {
+        let params = ShipParams {
+            mass: 1000.0,
+            max_thrust: 20000.0,
+            rcs_torque: 500.0,
+            moment_of_inertia: 1000.0,
+        };
+        let state = ShipState {
+            position,
+            velocity,
+            orientation: cgm::Quaternion::one(),
+            angular_velocity: cgm::Vector3::zero(),
+        };
+        FlightModel::new(params, state)
+    }
+
+    // Run scripted controls, each for the given number of steps
+    fn fly(ship: &mut FlightModel, script: &[(ShipControls, usize)], gravity_sources: &[GravitySource]) {
+        for (controls, steps) in script {
+            ship.controls = controls.clone();
+            for _ in 0..*steps {
+                ship.step(DT, gravity_sources);
+            }
+        }
+    }
+
+    #[test]
+    fn thrust_accelerates_along_nose_and_coasts() {
+        let mut ship = flight_model(cgm::Vector3::zero(), cgm::Vector3::zero());
+        let burn = ShipControls { throttle: 1.0, ..Default::default() };
+        fly(&mut ship, &[(burn, 60), (ShipControls::default(), 60)], &[]);
+
+        // One second at 20 m/s², then coasting
+        assert!((ship.state.velocity - cgm::Vector3::new(0.0, 0.0, -20.0)).magnitude() < 1e-9);
+        assert!((ship.state.position.z + 30.0).abs() < 0.5);
+    }
+
+    #[test]
+    fn rcs_turns_ship_and_holds_attitude() {
+        let mut ship = flight_model(cgm::Vector3::zero(), cgm::Vector3::zero());
+        let pitch_up = ShipControls { rotation: cgm::Vector3::new(1.0, 0.0, 0.0), ..Default::default() };
+        fly(&mut ship, &[(pitch_up, 60), (ShipControls::default(), 120)], &[]);
+
+        assert_eq!(ship.state.angular_velocity, cgm::Vector3::zero());
+        let nose = ship.state.orientation.rotate_vector(super::SHIP_FORWARD);
+        assert!(nose.y > 0.1 && nose.z < 0.0);
+
+        // Thrust now pushes upwards too
+        fly(&mut ship, &[(ShipControls { throttle: 1.0, ..Default::default() }, 10)], &[]);
+        assert!(ship.state.velocity.y > 0.0);
+    }
+
+    #[test]
+    fn gravity_keeps_circular_orbit() {
+        let gravitational_parameter = 1.0e5;
+        let radius: f64 = 150.0;
+        let speed = (gravitational_parameter / radius).sqrt();
+        let planet = GravitySource { position: cgm::Vector3::new(0.0, -55.0, 0.0), gravitational_parameter };
+        let mut ship = flight_model(planet.position + cgm::Vector3::new(radius, 0.0, 0.0), cgm::Vector3::new(0.0, 0.0, -speed));
+
+        let period = 2.0 * std::f64::consts::PI * radius / speed;
+        let steps = (period / DT) as usize;
+        fly(&mut ship, &[(ShipControls::default(), steps / 2)], std::slice::from_ref(&planet));
+
+        let offset = ship.state.position - planet.position;
+        assert!((offset.magnitude() - radius).abs() < 0.01 * radius);
+        // Half an orbit later the ship is on the other side
+        assert!(offset.x < -0.99 * radius);
+    }
+}
@@ -0,0 +1,206 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use cgmath as cgm;
+
+use crate::engine::geometry::{Geometry, Vertex};
+use crate::vulkan::resources::manager::ResourceManager;
+
+pub type StarfieldMutRef = Rc<RefCell<Starfield>>;
+
+// Magnitude rendered with unit brightness
+const REFERENCE_MAGNITUDE: f32 = 1.0;
+
+/// Catalog star in equatorial coordinates
+#[derive(Clone, Debug)]
+pub struct Star {
+    // Unit direction in equatorial frame: X to vernal equinox, Z to celestial north pole
+    pub direction: cgm::Vector3<f32>,
+    pub magnitude: f32,
+    // B-V color index
+    pub color_index: f32,
+}
+
+impl Star {
+    pub fn new(right_ascension: cgm::Deg<f32>, declination: cgm::Deg<f32>, magnitude: f32, color_index: f32) -> Self {
+        let (sin_ra, cos_ra) = cgm::Rad::from(right_ascension).0.sin_cos();
+        let (sin_dec, cos_dec) = cgm::Rad::from(declination).0.sin_cos();
+
+        Star {
+            direction: cgm::Vector3::new(cos_dec * cos_ra, cos_dec * sin_ra, sin_dec),
+            magnitude,
+            color_index,
+        }
+    }
+
+    /// Linear brightness relative to a star of the reference magnitude
+    pub fn get_brightness(&self) -> f32 {
+        10.0_f32.powf(-0.4 * (self.magnitude - REFERENCE_MAGNITUDE))
+    }
+
+    /// Effective temperature in Kelvin estimated from B-V color index (Ballesteros 2012)
+    pub fn get_temperature(&self) -> f32 {
+        let bv = self.color_index;
+        4600.0 * (1.0 / (0.92 * bv + 1.7) + 1.0 / (0.92 * bv + 0.62))
+    }
+
+    /// Linear RGB color of a black body of the star temperature normalized to the brightest channel
+    pub fn get_color(&self) -> cgm::Vector3<f32> {
+        temperature_to_rgb(self.get_temperature())
+    }
+}
+
+// Fit of black body colors for 1000K-40000K by Tanner Helland
+fn temperature_to_rgb(temperature: f32) -> cgm::Vector3<f32> {
+    let t = temperature.clamp(1000.0, 40000.0) / 100.0;
+    let red = if t <= 66.0 {
+        255.0
+    } else {
+        329.698_73 * (t - 60.0).powf(-0.133_204_76)
+    };
+    let green = if t <= 66.0 {
+        99.470_8 * t.ln() - 161.119_57
+    } else {
+        288.122_16 * (t - 60.0).powf(-0.075_514_85)
+    };
+    let blue = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.517_73 * (t - 10.0).ln() - 305.044_8
+    };
+
+    let srgb = cgm::Vector3::new(red, green, blue).map(|c| c.clamp(0.0, 255.0) / 255.0);
+    srgb.map(|c| if c <= 0.040_45 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) })
+}
+
+pub struct StarCatalog {
+    pub stars: Vec<Star>,
+}
+
+impl StarCatalog {
+    /// Load catalog from CSV with right ascension (degrees), declination (degrees), magnitude and B-V
+    /// color index columns. Lines starting with '#' and a header line are skipped.
+    pub fn load_csv(path: &str) -> Result<StarCatalog, String> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => StarCatalog::parse_csv(&text).map_err(|e| format!("Failed to parse star catalog {}: {}", path, e)),
+            Err(e) => Err(format!("Failed to read star catalog {}: {}", path, e)),
+        }
+    }
+
+    pub fn parse_csv(text: &str) -> Result<StarCatalog, String> {
+        let mut stars = vec![];
+        let mut header_allowed = true;
+        for (line_idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let values: Vec<Result<f32, _>> = line.split(',').map(|v| v.trim().parse::<f32>()).collect();
+            if values.len() < 4 {
+                return Err(format!("line {} has {} columns, 4 expected", line_idx + 1, values.len()));
+            }
+            match (&values[0], &values[1], &values[2], &values[3]) {
+                (Ok(ra), Ok(dec), Ok(magnitude), Ok(color_index)) => {
+                    stars.push(Star::new(cgm::Deg(*ra), cgm::Deg(*dec), *magnitude, *color_index));
+                },
+                _ if header_allowed => {},
+                _ => return Err(format!("line {} contains invalid numbers", line_idx + 1)),
+            }
+            header_allowed = false;
+        }
+
+        Ok(StarCatalog { stars })
+    }
+}
+
+/// Stars rendered as point sprites behind the scene. Each vertex position holds star direction,
+/// normal holds its linear color and uv.x holds its brightness.
+pub struct Starfield {
+    geometry: Geometry,
+    // Rotation of catalog equatorial frame to world frame
+    orientation: cgm::Matrix3<f64>,
+}
+
+impl Starfield {
+    pub fn new_mut_ref(resource_manager: &mut ResourceManager, catalog: &StarCatalog) -> StarfieldMutRef {
+        Rc::new(RefCell::new(Starfield::new(resource_manager, catalog)))
+    }
+
+    pub fn new(resource_manager: &mut ResourceManager, catalog: &StarCatalog) -> Self {
+        let vertices: Vec<Vertex> = catalog.stars.iter().map(|star| Vertex {
+            position: star.direction,
+            normal: star.get_color(),
+            uv: cgm::Vector2::new(star.get_brightness(), 0.0),
+        }).collect();
+        let indices = (0..vertices.len() as i32).collect();
+        let geometry = Geometry::new(resource_manager, vertices, indices, &String::from("Starfield"));
+
+        Starfield {
+            geometry,
+            orientation: Starfield::equatorial_to_world(),
+        }
+    }
+
+    /// Celestial north pole points to world +Y and vernal equinox to world +X
+    pub fn equatorial_to_world() -> cgm::Matrix3<f64> {
+        cgm::Matrix3::new(
+            1.0, 0.0, 0.0,
+            0.0, 0.0, -1.0,
+            0.0, 1.0, 0.0,
+        )
+    }
+
+    #[allow(dead_code)]
+    pub fn set_orientation(&mut self, orientation: cgm::Matrix3<f64>) {
+        self.orientation = orientation;
+    }
+
+    pub fn get_orientation(&self) -> &cgm::Matrix3<f64> {
+        &self.orientation
+    }
+
+    pub fn get_geometry(&self) -> &Geometry {
+        &self.geometry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath as cgm;
+    use cgmath::prelude::*;
+
+    use super::{Star, StarCatalog, Starfield};
+
+    #[test]
+    fn catalog_parses_and_maps_to_world() {
+        let csv = "ra,dec,mag,ci\n# Polaris\n37.95,89.26,1.98,0.60\n101.29,-16.72,-1.46,0.00\n";
+        let catalog = StarCatalog::parse_csv(csv).unwrap();
+        assert_eq!(catalog.stars.len(), 2);
+
+        let polaris = Starfield::equatorial_to_world() * catalog.stars[0].direction.cast::<f64>().unwrap();
+        assert!(polaris.dot(cgm::Vector3::unit_y()) > 0.999);
+        assert!((catalog.stars[1].direction.magnitude() - 1.0).abs() < 1e-5);
+
+        assert!(StarCatalog::parse_csv("10,20,1.0\n").is_err());
+    }
+
+    #[test]
+    fn brightness_and_color_follow_catalog_values() {
+        let star = |magnitude, color_index| Star::new(cgm::Deg(0.0), cgm::Deg(0.0), magnitude, color_index);
+
+        // 5 magnitudes are a factor of 100
+        let ratio = star(0.0, 0.0).get_brightness() / star(5.0, 0.0).get_brightness();
+        assert!((ratio - 100.0).abs() < 1e-2);
+
+        let hot = star(1.0, -0.3);
+        let sun_like = star(1.0, 0.65);
+        let cool = star(1.0, 1.8);
+        assert!(hot.get_temperature() > 10000.0);
+        assert!((sun_like.get_temperature() - 5800.0).abs() < 300.0);
+        assert!(hot.get_color().z > hot.get_color().x);
+        assert!(cool.get_color().x > cool.get_color().z);
+    }
+}
@@ -0,0 +1,186 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use cgmath as cgm;
+use cgmath::prelude::*;
+
+use crate::engine::geometry::{Geometry, Vertex};
+use crate::engine::material::Material;
+use crate::engine::scene::node::{Node, NodeContent, NodeMutRef};
+use crate::vulkan::drawable::{Drawable, DrawableMutRef, DrawType};
+use crate::vulkan::resources::manager::ResourceManager;
+use crate::vulkan::resources::objects::ObjectDescriptions;
+use crate::world::terrain::quadtree::ChunkKey;
+
+/// Surface of one chunk. Vertex positions are relative to the chunk center to keep f32 precision.
+pub struct ChunkMesh {
+    // Chunk center on the sphere in planet local space
+    pub center: cgm::Vector3<f64>,
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<i32>,
+}
+
+/// Generate grid of resolution x resolution quads on the sphere with a skirt along the chunk border.
+/// Skirt hangs down by skirt_depth and hides cracks between chunks of different LOD levels.
+pub fn generate_chunk_mesh(
+    key: &ChunkKey,
+    radius: f64,
+    resolution: u32,
+    skirt_depth: f64,
+    height: &dyn Fn(&cgm::Vector3<f64>) -> f64,
+) -> ChunkMesh {
+    let res = resolution as i32;
+    let (u0, v0) = key.get_origin();
+    let step = key.get_size() / resolution as f64;
+    let center = key.get_center_direction() * (radius + height(&key.get_center_direction()));
+
+    // Sample positions with one extra ring around the grid to calculate normals at the border
+    let ring_size = res + 3;
+    let mut samples = Vec::with_capacity((ring_size * ring_size) as usize);
+    for j in -1..=res + 1 {
+        for i in -1..=res + 1 {
+            let direction = key.face.sphere_direction(u0 + i as f64 * step, v0 + j as f64 * step);
+            samples.push(direction * (radius + height(&direction)));
+        }
+    }
+    let sample = |i: i32, j: i32| samples[((j + 1) * ring_size + i + 1) as usize];
+
+    let grid_size = res + 1;
+    let mut vertices = Vec::with_capacity((grid_size * grid_size + 4 * res) as usize);
+    for j in 0..=res {
+        for i in 0..=res {
+            let du = sample(i + 1, j) - sample(i - 1, j);
+            let dv = sample(i, j + 1) - sample(i, j - 1);
+            vertices.push(Vertex {
+                position: (sample(i, j) - center).cast().unwrap_or(cgm::Vector3::zero()),
+                normal: du.cross(dv).normalize().cast().unwrap_or(cgm::Vector3::unit_y()),
+                uv: cgm::Vector2::new(i as f32 / res as f32, j as f32 / res as f32),
+            });
+        }
+    }
+
+    let grid_index = |i: i32, j: i32| j * grid_size + i;
+    let mut indices = Vec::with_capacity((res * res * 6 + 4 * res * 6) as usize);
+    for j in 0..res {
+        for i in 0..res {
+            let a = grid_index(i, j);
+            let b = grid_index(i + 1, j);
+            let c = grid_index(i + 1, j + 1);
+            let d = grid_index(i, j + 1);
+            indices.extend_from_slice(&[a, b, c, a, c, d]);
+        }
+    }
+
+    // Border vertices in counter clockwise order as seen from outside the planet
+    let mut border = Vec::with_capacity(4 * res as usize);
+    border.extend((0..res).map(|i| grid_index(i, 0)));
+    border.extend((0..res).map(|j| grid_index(res, j)));
+    border.extend((1..=res).rev().map(|i| grid_index(i, res)));
+    border.extend((1..=res).rev().map(|j| grid_index(0, j)));
+
+    let skirt_offset = vertices.len() as i32;
+    for &b in &border {
+        let vertex = &vertices[b as usize];
+        let position = vertex.position.cast::<f64>().unwrap_or(cgm::Vector3::zero()) + center;
+        let skirt_position = position - position.normalize() * skirt_depth - center;
+        vertices.push(Vertex {
+            position: skirt_position.cast().unwrap_or(cgm::Vector3::zero()),
+            normal: vertex.normal,
+            uv: vertex.uv,
+        });
+    }
+
+    for k in 0..border.len() {
+        let next = (k + 1) % border.len();
+        let (a, b) = (border[k], border[next]);
+        let (skirt_a, skirt_b) = (skirt_offset + k as i32, skirt_offset + next as i32);
+        indices.extend_from_slice(&[a, skirt_a, b, b, skirt_a, skirt_b]);
+    }
+
+    ChunkMesh {
+        center,
+        vertices,
+        indices,
+    }
+}
+
+/// Chunk uploaded to GPU and ready to be attached to the scene graph
+pub struct TerrainChunk {
+    drawable: DrawableMutRef,
+    // Transform node placing chunk at its center with drawable instance as a child
+    node: NodeMutRef,
+}
+
+impl TerrainChunk {
+    pub fn new(
+        resource_manager: &mut ResourceManager,
+        object_descriptions: &mut ObjectDescriptions,
+        key: &ChunkKey,
+        mesh: ChunkMesh,
+        draw_type: DrawType,
+    ) -> Self {
+        let label = format!("TerrainChunk({:?} {} {} {})", key.face, key.level, key.x, key.y);
+        let geometry = Geometry::new(resource_manager, mesh.vertices, mesh.indices, &label);
+        let drawable = Rc::new(RefCell::new(Drawable::new(object_descriptions, draw_type, geometry, Material::new())));
+
+        let instance_node = Rc::new(RefCell::new(Node::with_content(NodeContent::DrawableInstance(
+            Drawable::create_instance(&drawable),
+        ))));
+        let mut node = Node::with_content(NodeContent::Transform(cgm::Matrix4::from_translation(mesh.center)));
+        node.add_child(instance_node);
+
+        TerrainChunk {
+            drawable,
+            node: Rc::new(RefCell::new(node)),
+        }
+    }
+
+    pub fn get_node(&self) -> &NodeMutRef {
+        &self.node
+    }
+
+    pub fn get_drawable(&self) -> &DrawableMutRef {
+        &self.drawable
+    }
+
+    /// Release ray tracing object description of the chunk. Chunk must not be used afterwards.
+    pub fn destroy(&mut self, object_descriptions: &mut ObjectDescriptions) {
+        object_descriptions.remove_object(self.drawable.borrow().get_object_id());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath as cgm;
+    use cgmath::prelude::*;
+
+    use super::generate_chunk_mesh;
+    use crate::world::terrain::quadtree::{ChunkKey, CubeFace};
+
+    #[test]
+    fn chunk_mesh_lies_on_sphere() {
+        let key = ChunkKey::root(CubeFace::NegativeY).children()[2];
+        let resolution = 8;
+        let radius = 6_371_000.0;
+        let mesh = generate_chunk_mesh(&key, radius, resolution, 100.0, &|_| 0.0);
+
+        let grid_vertices = ((resolution + 1) * (resolution + 1)) as usize;
+        assert_eq!(mesh.vertices.len(), grid_vertices + 4 * resolution as usize);
+        assert_eq!(mesh.indices.len(), (resolution * resolution * 6 + 4 * resolution * 6) as usize);
+        assert!(mesh.indices.iter().all(|&i| (i as usize) < mesh.vertices.len()));
+
+        for (i, vertex) in mesh.vertices.iter().enumerate() {
+            let position = vertex.position.cast::<f64>().unwrap() + mesh.center;
+            let expected_radius = if i < grid_vertices { radius } else { radius - 100.0 };
+            assert!((position.magnitude() - expected_radius).abs() < 1.0);
+            assert!(vertex.normal.cast::<f64>().unwrap().dot(position.normalize()) > 0.99);
+        }
+
+        // Triangles have to face away from the planet center
+        let triangle = &mesh.indices[0..3];
+        let p: Vec<cgm::Vector3<f64>> = triangle.iter()
+            .map(|&i| mesh.vertices[i as usize].position.cast::<f64>().unwrap() + mesh.center)
+            .collect();
+        assert!((p[1] - p[0]).cross(p[2] - p[0]).dot(p[0]) > 0.0);
+    }
+}
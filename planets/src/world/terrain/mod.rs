@@ -0,0 +1,332 @@
+pub mod chunk;
+pub mod quadtree;
+pub mod surface;
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use cgmath as cgm;
+use cgmath::prelude::*;
+
+use crate::engine::scene::node::{Node, NodeContent, NodeMutRef, UpdateCallResult};
+use crate::vulkan::device::Device;
+use crate::vulkan::drawable::DrawType;
+use crate::vulkan::resources::manager::ResourceManager;
+use crate::vulkan::resources::objects::ObjectDescriptions;
+use crate::world::impostor::{self, Impostor, ImpostorSettings};
+use crate::world::atmosphere::{Atmosphere, AtmosphereMutRef, AtmosphereParams};
+use crate::world::orbit::OrbitalElements;
+use crate::world::rings::PlanetRingsMutRef;
+use crate::world::terrain::chunk::{generate_chunk_mesh, TerrainChunk};
+use crate::world::terrain::quadtree::{ChunkKey, LodSettings, LodSurface, LodViewer};
+use crate::world::terrain::surface::{HeightMap, PlanetSurfaceMutRef};
+
+/// Terrain elevation above planet radius for a unit direction from planet center
+pub type HeightCall = Box<dyn Fn(&cgm::Vector3<f64>) -> f64>;
+
+pub type PlanetTerrainMutRef = Rc<RefCell<PlanetTerrain>>;
+
+const DEFAULT_CHUNKS_PER_FRAME: usize = 4;
+
+/// Planet surface built of cube sphere faces split into quadtrees of chunks.
+/// Chunks are refined by screen space error and generated a few per frame. Until a chunk is generated
+/// its closest generated ancestor is displayed instead.
+pub struct PlanetTerrain {
+    radius: f64,
+    // Gravitational constant times planet mass. Zero for bodies without gravity.
+    gravitational_parameter: f64,
+    // Maximal absolute value returned by height call
+    max_height: f64,
+    height_call: HeightCall,
+    lod_settings: LodSettings,
+    // Maximal number of chunks generated per frame
+    chunks_per_frame: usize,
+    // Planet node. Its transform is treated as planet world transform.
+    node: NodeMutRef,
+    // Child of planet node holding displayed chunks
+    chunks_node: NodeMutRef,
+    chunks: HashMap<ChunkKey, TerrainChunk>,
+    displayed: Vec<ChunkKey>,
+    atmosphere: Option<AtmosphereMutRef>,
+    rings: Option<PlanetRingsMutRef>,
+    surface: Option<PlanetSurfaceMutRef>,
+    // Billboard replacing the chunks when the planet is small on screen, created on first use
+    impostor: Option<Impostor>,
+    impostor_attached: bool,
+}
+
+impl PlanetTerrain {
+    pub fn new_mut_ref(radius: f64, position: &cgm::Vector3<f64>) -> PlanetTerrainMutRef {
+        Rc::new(RefCell::new(PlanetTerrain::new(radius, position)))
+    }
+
+    pub fn new(radius: f64, position: &cgm::Vector3<f64>) -> Self {
+        let chunks_node = Rc::new(RefCell::new(Node::with_content(NodeContent::Group)));
+        let mut node = Node::with_content(NodeContent::Transform(cgm::Matrix4::from_translation(*position)));
+        node.add_child(Rc::clone(&chunks_node));
+
+        PlanetTerrain {
+            radius,
+            gravitational_parameter: 0.0,
+            max_height: 0.0,
+            height_call: Box::new(|_| 0.0),
+            lod_settings: LodSettings::default(),
+            chunks_per_frame: DEFAULT_CHUNKS_PER_FRAME,
+            node: Rc::new(RefCell::new(node)),
+            chunks_node,
+            chunks: HashMap::new(),
+            displayed: vec![],
+            atmosphere: None,
+            rings: None,
+            surface: None,
+            impostor: None,
+            impostor_attached: false,
+        }
+    }
+
+    /// Set elevation source. Already generated chunks are not regenerated.
+    pub fn set_height_call(&mut self, height_call: HeightCall, max_height: f64) {
+        self.height_call = height_call;
+        self.max_height = max_height;
+    }
+
+    /// Drive elevation by an equirectangular heightmap. Already generated chunks are not regenerated.
+    pub fn set_height_map(&mut self, height_map: HeightMap) {
+        let max_height = height_map.get_max_abs_height();
+        self.set_height_call(Box::new(move |direction| height_map.sample(direction)), max_height);
+    }
+
+    #[allow(dead_code)]
+    pub fn set_lod_settings(&mut self, lod_settings: LodSettings) {
+        self.lod_settings = lod_settings;
+    }
+
+    #[allow(dead_code)]
+    pub fn set_chunks_per_frame(&mut self, chunks_per_frame: usize) {
+        self.chunks_per_frame = chunks_per_frame;
+    }
+
+    /// Give planet an atmosphere. Its lookup tables are computed once it is rendered.
+    pub fn set_atmosphere(&mut self, params: AtmosphereParams) {
+        self.atmosphere = Some(Atmosphere::new_mut_ref(params));
+    }
+
+    pub fn get_atmosphere(&self) -> Option<&AtmosphereMutRef> {
+        self.atmosphere.as_ref()
+    }
+
+    /// Attach rings to the planet node, replacing previous ones
+    pub fn set_rings(&mut self, rings: PlanetRingsMutRef) {
+        let mut node = self.node.borrow_mut();
+        if let Some(previous) = self.rings.take() {
+            node.remove_child(previous.borrow().get_node());
+        }
+        node.add_child(Rc::clone(rings.borrow().get_node()));
+        self.rings = Some(rings);
+    }
+
+    pub fn get_rings(&self) -> Option<&PlanetRingsMutRef> {
+        self.rings.as_ref()
+    }
+
+    /// Texture planet with equirectangular surface maps. Its chunks are drawn by the terrain pipeline afterwards.
+    pub fn set_surface(&mut self, surface: PlanetSurfaceMutRef) {
+        for chunk in self.chunks.values() {
+            chunk.get_drawable().borrow_mut().draw_type = DrawType::Terrain;
+        }
+        self.surface = Some(surface);
+    }
+
+    pub fn get_surface(&self) -> Option<&PlanetSurfaceMutRef> {
+        self.surface.as_ref()
+    }
+
+    /// Write surface map placement for the current frame. Must be called after scene transforms are updated.
+    pub fn update_surface(&self, device: &Device, origin: &cgm::Vector3<f64>) {
+        if let (Some(surface), NodeContent::Transform(transform)) = (&self.surface, &self.node.borrow().content) {
+            surface.borrow().update(device, transform, origin);
+        }
+    }
+
+    /// Draw displayed chunks. Used for planets with surface maps, others are drawn with the rest of opaque geometry.
+    pub fn write_draw_commands(&self, device: &Device, cmd_buffer: &ash::vk::CommandBuffer) {
+        for key in &self.displayed {
+            if let Some(chunk) = self.chunks.get(key) {
+                chunk.get_drawable().borrow().write_draw_commands(device, cmd_buffer);
+            }
+        }
+    }
+
+    /// Tilt planet rotation axis, and with it the rings, around the X axis
+    pub fn set_axial_tilt<A: Into<cgm::Rad<f64>>>(&mut self, tilt: A) {
+        let center = self.get_center();
+        self.node.borrow_mut().content = NodeContent::Transform(
+            cgm::Matrix4::from_translation(center) * cgm::Matrix4::from_angle_x(tilt.into()),
+        );
+    }
+
+    /// Move planet along the orbit around the given world position every simulation step. Axial tilt is kept.
+    pub fn set_orbit(&mut self, elements: OrbitalElements, focus: cgm::Vector3<f64>) {
+        let mut node = self.node.borrow_mut();
+        if let NodeContent::Transform(t) = &mut node.content {
+            t.w = (focus + elements.position_at(0.0)).extend(1.0);
+        }
+        node.simulation_call = Some(Box::new(move |node, simulation| {
+            let mut transform = match &node.content {
+                NodeContent::Transform(t) => *t,
+                _ => cgm::Matrix4::identity(),
+            };
+            transform.w = (focus + elements.position_at(simulation.get_elapsed())).extend(1.0);
+            UpdateCallResult {
+                transform: Some(transform),
+                pre_update_action: None,
+            }
+        }));
+    }
+
+    /// World position of planet center
+    pub fn get_center(&self) -> cgm::Vector3<f64> {
+        match &self.node.borrow().content {
+            NodeContent::Transform(t) => t.w.truncate(),
+            _ => cgm::Vector3::zero(),
+        }
+    }
+
+    pub fn get_node(&self) -> &NodeMutRef {
+        &self.node
+    }
+
+    #[allow(dead_code)]
+    pub fn get_radius(&self) -> f64 {
+        self.radius
+    }
+
+    pub fn set_gravitational_parameter(&mut self, gravitational_parameter: f64) {
+        self.gravitational_parameter = gravitational_parameter;
+    }
+
+    pub fn get_gravitational_parameter(&self) -> f64 {
+        self.gravitational_parameter
+    }
+
+    /// Refine chunks for the viewer at the given world position, generate some of the missing ones and
+    /// attach chunks to be displayed to the terrain node. Distant planets get an impostor instead.
+    pub fn update(
+        &mut self,
+        viewer_position: &cgm::Vector3<f64>,
+        projection_scale: f64,
+        impostor_settings: &ImpostorSettings,
+        resource_manager: &mut ResourceManager,
+        object_descriptions: &mut ObjectDescriptions,
+    ) {
+        let planet_transform = match &self.node.borrow().content {
+            NodeContent::Transform(t) => *t,
+            _ => cgm::Matrix4::identity(),
+        };
+        let local_position = planet_transform.invert().unwrap_or(cgm::Matrix4::identity())
+            * viewer_position.extend(1.0);
+        let angular_size = impostor::angular_size(self.radius, local_position.truncate().magnitude());
+        self.update_impostor(impostor_settings.is_impostor_visible(angular_size), resource_manager, object_descriptions);
+        if !impostor_settings.is_geometry_visible(angular_size) {
+            // Keep generated chunks for the way back
+            self.chunks_node.borrow_mut().clear_children();
+            self.displayed.clear();
+            return;
+        }
+
+        let viewer = LodViewer {
+            position: local_position.truncate(),
+            projection_scale,
+        };
+        let surface = LodSurface {
+            radius: self.radius,
+            max_height: self.max_height,
+        };
+
+        let wanted = quadtree::select_chunks(&surface, &viewer, &self.lod_settings);
+        self.generate_missing(&wanted, &surface, &viewer, resource_manager, object_descriptions);
+
+        let displayed = quadtree::resolve_displayed_chunks(&wanted, &self.displayed, |key| self.chunks.contains_key(key));
+        {
+            let mut node = self.chunks_node.borrow_mut();
+            node.clear_children();
+            for key in &displayed {
+                node.add_child(Rc::clone(self.chunks[key].get_node()));
+            }
+        }
+        self.displayed = displayed;
+
+        self.evict_unused(&wanted, object_descriptions);
+    }
+
+    fn update_impostor(&mut self, visible: bool, resource_manager: &mut ResourceManager, object_descriptions: &mut ObjectDescriptions) {
+        if visible == self.impostor_attached {
+            return;
+        }
+
+        let radius = self.radius;
+        let impostor = self.impostor.get_or_insert_with(|| Impostor::new(resource_manager, object_descriptions, radius));
+        if visible {
+            self.node.borrow_mut().add_child(Rc::clone(impostor.get_node()));
+        } else {
+            self.node.borrow_mut().remove_child(impostor.get_node());
+        }
+        self.impostor_attached = visible;
+    }
+
+    fn generate_missing(
+        &mut self,
+        wanted: &[ChunkKey],
+        surface: &LodSurface,
+        viewer: &LodViewer,
+        resource_manager: &mut ResourceManager,
+        object_descriptions: &mut ObjectDescriptions,
+    ) {
+        // Coarse chunks first, so the surface gets closed quickly, then the closest ones
+        let mut missing: Vec<(ChunkKey, f64)> = wanted.iter()
+            .filter(|key| !self.chunks.contains_key(key))
+            .map(|key| (*key, quadtree::chunk_distance(key, surface, viewer)))
+            .collect();
+        missing.sort_by(|(a, a_distance), (b, b_distance)| {
+            a.level.cmp(&b.level).then(a_distance.partial_cmp(b_distance).unwrap_or(std::cmp::Ordering::Equal))
+        });
+
+        for (key, _) in missing.into_iter().take(self.chunks_per_frame) {
+            let skirt_depth = quadtree::chunk_geometric_error(&key, surface, &self.lod_settings)
+                + self.max_height / (1u64 << key.level) as f64;
+            let mesh = generate_chunk_mesh(&key, self.radius, self.lod_settings.resolution, skirt_depth, &*self.height_call);
+            let draw_type = if self.surface.is_some() { DrawType::Terrain } else { DrawType::Opaque };
+            let chunk = TerrainChunk::new(resource_manager, object_descriptions, &key, mesh, draw_type);
+            self.chunks.insert(key, chunk);
+        }
+    }
+
+    // Keep displayed and wanted chunks together with their ancestors, so zooming out is immediate
+    fn evict_unused(&mut self, wanted: &[ChunkKey], object_descriptions: &mut ObjectDescriptions) {
+        let mut keep: HashSet<ChunkKey> = self.displayed.iter().cloned().collect();
+        for key in wanted {
+            let mut current = Some(*key);
+            while let Some(k) = current {
+                keep.insert(k);
+                current = k.parent();
+            }
+        }
+
+        self.chunks.retain(|key, chunk| {
+            if keep.contains(key) {
+                true
+            } else {
+                chunk.destroy(object_descriptions);
+                false
+            }
+        });
+    }
+}
+
+impl Drop for PlanetTerrain {
+    fn drop(&mut self) {
+        self.chunks_node.borrow_mut().clear_children();
+        self.node.borrow_mut().clear_children();
+    }
+}
@@ -0,0 +1,315 @@
+use std::collections::HashSet;
+use std::f64::consts::FRAC_PI_2;
+
+use cgmath as cgm;
+use cgmath::prelude::*;
+
+/// Face of the cube that is projected onto the planet sphere
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum CubeFace {
+    PositiveX,
+    NegativeX,
+    PositiveY,
+    NegativeY,
+    PositiveZ,
+    NegativeZ,
+}
+
+impl CubeFace {
+    pub const ALL: [CubeFace; 6] = [
+        CubeFace::PositiveX,
+        CubeFace::NegativeX,
+        CubeFace::PositiveY,
+        CubeFace::NegativeY,
+        CubeFace::PositiveZ,
+        CubeFace::NegativeZ,
+    ];
+
+    /// Point on the unit cube for face coordinates in range [-1; 1].
+    /// Cross product of u and v directions points outwards for every face.
+    pub fn cube_point(&self, u: f64, v: f64) -> cgm::Vector3<f64> {
+        match self {
+            CubeFace::PositiveX => cgm::Vector3::new(1.0, v, -u),
+            CubeFace::NegativeX => cgm::Vector3::new(-1.0, v, u),
+            CubeFace::PositiveY => cgm::Vector3::new(u, 1.0, -v),
+            CubeFace::NegativeY => cgm::Vector3::new(u, -1.0, v),
+            CubeFace::PositiveZ => cgm::Vector3::new(u, v, 1.0),
+            CubeFace::NegativeZ => cgm::Vector3::new(-u, v, -1.0),
+        }
+    }
+
+    /// Direction from planet center for face coordinates in range [-1; 1]
+    pub fn sphere_direction(&self, u: f64, v: f64) -> cgm::Vector3<f64> {
+        self.cube_point(u, v).normalize()
+    }
+}
+
+/// Address of a chunk in the quadtree of a cube face.
+/// On level L face is split into 2^L x 2^L chunks, x and y index them along u and v directions.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct ChunkKey {
+    pub face: CubeFace,
+    pub level: u32,
+    pub x: u32,
+    pub y: u32,
+}
+
+impl ChunkKey {
+    pub fn root(face: CubeFace) -> Self {
+        ChunkKey { face, level: 0, x: 0, y: 0 }
+    }
+
+    pub fn children(&self) -> [ChunkKey; 4] {
+        let child = |dx, dy| ChunkKey {
+            face: self.face,
+            level: self.level + 1,
+            x: self.x * 2 + dx,
+            y: self.y * 2 + dy,
+        };
+
+        [child(0, 0), child(1, 0), child(0, 1), child(1, 1)]
+    }
+
+    pub fn parent(&self) -> Option<ChunkKey> {
+        if self.level == 0 {
+            return None;
+        }
+
+        Some(ChunkKey {
+            face: self.face,
+            level: self.level - 1,
+            x: self.x / 2,
+            y: self.y / 2,
+        })
+    }
+
+    pub fn is_ancestor_of(&self, other: &ChunkKey) -> bool {
+        if self.face != other.face || self.level >= other.level {
+            return false;
+        }
+
+        let shift = other.level - self.level;
+        other.x >> shift == self.x && other.y >> shift == self.y
+    }
+
+    /// Size of the chunk in face coordinates
+    pub fn get_size(&self) -> f64 {
+        2.0 / (1u64 << self.level) as f64
+    }
+
+    /// Face coordinates of the chunk corner with minimal u and v
+    pub fn get_origin(&self) -> (f64, f64) {
+        let size = self.get_size();
+        (-1.0 + self.x as f64 * size, -1.0 + self.y as f64 * size)
+    }
+
+    /// Direction from planet center to the chunk center
+    pub fn get_center_direction(&self) -> cgm::Vector3<f64> {
+        let (u, v) = self.get_origin();
+        let half_size = self.get_size() * 0.5;
+        self.face.sphere_direction(u + half_size, v + half_size)
+    }
+
+    /// Approximate length of chunk edge on the sphere of the given radius
+    pub fn get_arc_length(&self, radius: f64) -> f64 {
+        radius * FRAC_PI_2 / (1u64 << self.level) as f64
+    }
+}
+
+/// Parameters of chunk refinement
+#[derive(Copy, Clone)]
+pub struct LodSettings {
+    // Number of quads along one chunk edge
+    pub resolution: u32,
+    // Deepest quadtree level chunks are refined to
+    pub max_level: u32,
+    // Chunks are split while their error on screen is larger than this number of pixels
+    pub max_screen_error: f64,
+}
+
+impl Default for LodSettings {
+    fn default() -> Self {
+        LodSettings {
+            resolution: 32,
+            max_level: 16,
+            max_screen_error: 4.0,
+        }
+    }
+}
+
+/// Camera as seen from the planet for LOD selection
+pub struct LodViewer {
+    // Camera position in planet local space
+    pub position: cgm::Vector3<f64>,
+    // Pixels per unit of size at unit distance: viewport height / (2 * tan(fov_y / 2))
+    pub projection_scale: f64,
+}
+
+/// Surface of a planet to select chunks for
+pub struct LodSurface {
+    pub radius: f64,
+    // Maximal deviation of terrain from the sphere of planet radius
+    pub max_height: f64,
+}
+
+/// Geometric error of the chunk: distance between neighbouring vertices
+pub fn chunk_geometric_error(key: &ChunkKey, surface: &LodSurface, settings: &LodSettings) -> f64 {
+    key.get_arc_length(surface.radius) / settings.resolution as f64
+}
+
+/// Distance from viewer to the bounding sphere of the chunk
+pub fn chunk_distance(key: &ChunkKey, surface: &LodSurface, viewer: &LodViewer) -> f64 {
+    let center = key.get_center_direction() * surface.radius;
+    // Half diagonal of the chunk plus the terrain height it may contain
+    let bounding_radius = key.get_arc_length(surface.radius) * std::f64::consts::FRAC_1_SQRT_2 + surface.max_height;
+
+    ((viewer.position - center).magnitude() - bounding_radius).max(f64::EPSILON)
+}
+
+/// Select chunks covering the whole planet at detail required by the viewer
+pub fn select_chunks(surface: &LodSurface, viewer: &LodViewer, settings: &LodSettings) -> Vec<ChunkKey> {
+    let mut selected = vec![];
+    for face in CubeFace::ALL {
+        select_chunks_recursive(&ChunkKey::root(face), surface, viewer, settings, &mut selected);
+    }
+
+    selected
+}
+
+fn select_chunks_recursive(
+    key: &ChunkKey,
+    surface: &LodSurface,
+    viewer: &LodViewer,
+    settings: &LodSettings,
+    selected: &mut Vec<ChunkKey>,
+) {
+    let screen_error = chunk_geometric_error(key, surface, settings) * viewer.projection_scale
+        / chunk_distance(key, surface, viewer);
+
+    if key.level < settings.max_level && screen_error > settings.max_screen_error {
+        for child in &key.children() {
+            select_chunks_recursive(child, surface, viewer, settings, selected);
+        }
+    } else {
+        selected.push(*key);
+    }
+}
+
+/// Choose chunks to display out of the wanted ones given which chunks are already generated.
+/// Missing chunks are replaced by their closest generated ancestor, so the surface stays closed while
+/// children are generated. Chunks covered by such ancestor are hidden to avoid overlapping.
+/// Without generated ancestor missing chunk is replaced by its descendants displayed previously.
+pub fn resolve_displayed_chunks<F>(wanted: &[ChunkKey], previous: &[ChunkKey], is_generated: F) -> Vec<ChunkKey>
+    where F: Fn(&ChunkKey) -> bool {
+    let mut ready = vec![];
+    let mut fallbacks = HashSet::new();
+
+    for key in wanted {
+        if is_generated(key) {
+            ready.push(*key);
+            continue;
+        }
+
+        let mut ancestor = key.parent();
+        while let Some(a) = ancestor {
+            if is_generated(&a) {
+                fallbacks.insert(a);
+                break;
+            }
+            ancestor = a.parent();
+        }
+
+        if ancestor.is_none() {
+            ready.extend(previous.iter().filter(|p| key.is_ancestor_of(p) && is_generated(p)));
+        }
+    }
+
+    // Fallbacks may be nested as well when chunks of different levels are missing
+    let is_covered = |key: &ChunkKey| fallbacks.iter().any(|f| f.is_ancestor_of(key));
+    let mut displayed: Vec<ChunkKey> = fallbacks.iter().filter(|key| !is_covered(key)).cloned().collect();
+    displayed.extend(ready.into_iter().filter(|key| !is_covered(key)));
+
+    displayed
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use cgmath as cgm;
+    use cgmath::prelude::*;
+
+    use super::*;
+
+    fn surface() -> LodSurface {
+        LodSurface { radius: 1000.0, max_height: 0.0 }
+    }
+
+    #[test]
+    fn cube_faces_point_outwards() {
+        for face in CubeFace::ALL {
+            let center = face.cube_point(0.0, 0.0);
+            let du = face.cube_point(1.0, 0.0) - center;
+            let dv = face.cube_point(0.0, 1.0) - center;
+            assert!(du.cross(dv).dot(center) > 0.0);
+        }
+    }
+
+    #[test]
+    fn chunk_hierarchy() {
+        let root = ChunkKey::root(CubeFace::PositiveZ);
+        let children = root.children();
+        let grandchild = children[3].children()[0];
+
+        assert_eq!(grandchild, ChunkKey { face: CubeFace::PositiveZ, level: 2, x: 2, y: 2 });
+        assert_eq!(grandchild.parent(), Some(children[3]));
+        assert!(root.is_ancestor_of(&grandchild));
+        assert!(!children[0].is_ancestor_of(&grandchild));
+        assert!(!grandchild.is_ancestor_of(&grandchild));
+        assert_eq!(grandchild.get_origin(), (0.0, 0.0));
+        assert_eq!(grandchild.get_size(), 0.5);
+    }
+
+    #[test]
+    fn chunks_refine_towards_viewer() {
+        let settings = LodSettings { resolution: 16, max_level: 8, max_screen_error: 2.0 };
+        let viewer = LodViewer {
+            position: cgm::Vector3::new(0.0, 0.0, 1010.0),
+            projection_scale: 1000.0,
+        };
+        let chunks = select_chunks(&surface(), &viewer, &settings);
+
+        let nearest = chunks.iter()
+            .max_by(|a, b| a.get_center_direction().z.partial_cmp(&b.get_center_direction().z).unwrap())
+            .unwrap();
+        let far_side_level = chunks.iter().filter(|c| c.face == CubeFace::NegativeZ).map(|c| c.level).max().unwrap();
+        assert_eq!(nearest.level, settings.max_level);
+        assert!(far_side_level < nearest.level);
+
+        // Selected chunks must cover each face exactly once
+        for face in CubeFace::ALL {
+            let area: f64 = chunks.iter().filter(|c| c.face == face).map(|c| c.get_size() * c.get_size()).sum();
+            assert!((area - 4.0).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn missing_chunks_fall_back_to_ancestor() {
+        let root = ChunkKey::root(CubeFace::PositiveX);
+        let children = root.children();
+        let generated: HashSet<ChunkKey> = [root, children[0], children[1]].iter().cloned().collect();
+
+        let displayed = resolve_displayed_chunks(&children, &[], |key| generated.contains(key));
+        assert_eq!(displayed, vec![root]);
+
+        let generated: HashSet<ChunkKey> = children.iter().cloned().collect();
+        let displayed = resolve_displayed_chunks(&children, &[root], |key| generated.contains(key));
+        assert_eq!(displayed.len(), 4);
+
+        // Merging back keeps children until parent is generated
+        let displayed = resolve_displayed_chunks(&[root], &children, |key| generated.contains(key));
+        assert_eq!(displayed.len(), 4);
+        let displayed = resolve_displayed_chunks(&[root], &[], |key| generated.contains(key));
+        assert!(displayed.is_empty());
+    }
+}
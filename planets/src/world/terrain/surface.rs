@@ -0,0 +1,182 @@
+use std::cell::RefCell;
+use std::f64::consts::PI;
+use std::rc::Rc;
+
+use cgmath as cgm;
+use cgmath::prelude::*;
+
+use crate::vulkan::device::{Device, MAX_FRAMES_IN_FLIGHT};
+use crate::vulkan::img::image::ImageMutRef;
+use crate::vulkan::mem::StructBufferData;
+use crate::vulkan::resources::manager::ResourceManager;
+use crate::vulkan::uniform_buffer::UniformBufferObject;
+
+pub type PlanetSurfaceMutRef = Rc<RefCell<PlanetSurface>>;
+
+/// Equirectangular texture coordinates of a unit direction in planet local space. North pole is +Y,
+/// longitude 0 is +X in the middle of the map and grows eastwards, in the direction of planet rotation
+/// from +X to -Z. Must match planetUv() in terrain.frag.
+pub fn planet_uv(direction: &cgm::Vector3<f64>) -> cgm::Vector2<f64> {
+    let u = 0.5 + (-direction.z).atan2(direction.x) / (2.0 * PI);
+    let v = direction.y.clamp(-1.0, 1.0).acos() / PI;
+
+    cgm::Vector2::new(u, v)
+}
+
+/// Elevation map in equirectangular projection
+pub struct HeightMap {
+    width: u32,
+    height: u32,
+    // Normalized samples row by row from the north pole down
+    samples: Vec<f32>,
+    // Elevation of black and white samples
+    min_height: f64,
+    max_height: f64,
+}
+
+impl HeightMap {
+    /// Load grayscale heightmap. 16 bit PNGs keep their full precision, other images are converted.
+    pub fn load(path: &str, min_height: f64, max_height: f64) -> Result<HeightMap, String> {
+        let image = image::open(path).map_err(|e| format!("Could not load heightmap {}: {}", path, e))?;
+        let luma = image.into_luma16();
+        let (width, height) = luma.dimensions();
+        let samples = luma.into_raw().into_iter().map(|s| s as f32 / u16::MAX as f32).collect();
+
+        HeightMap::new(width, height, samples, min_height, max_height)
+    }
+
+    pub fn new(width: u32, height: u32, samples: Vec<f32>, min_height: f64, max_height: f64) -> Result<HeightMap, String> {
+        if width == 0 || height == 0 || samples.len() != (width * height) as usize {
+            return Err(format!("Heightmap of {}x{} has {} samples", width, height, samples.len()));
+        }
+
+        Ok(HeightMap {
+            width,
+            height,
+            samples,
+            min_height,
+            max_height,
+        })
+    }
+
+    /// Bilinearly filtered elevation for a unit direction in planet local space. Longitude wraps around
+    /// the seam, latitude is clamped to the first and last rows at the poles.
+    pub fn sample(&self, direction: &cgm::Vector3<f64>) -> f64 {
+        let uv = planet_uv(direction);
+        let x = uv.x * self.width as f64 - 0.5;
+        let y = (uv.y * self.height as f64 - 0.5).max(0.0).min((self.height - 1) as f64);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+
+        let texel = |x: f64, y: f64| {
+            let x = (x as i64).rem_euclid(self.width as i64) as u32;
+            let y = (y as u32).min(self.height - 1);
+            self.samples[(y * self.width + x) as usize] as f64
+        };
+        let top = texel(x0, y0) + (texel(x0 + 1.0, y0) - texel(x0, y0)) * fx;
+        let bottom = texel(x0, y0 + 1.0) + (texel(x0 + 1.0, y0 + 1.0) - texel(x0, y0 + 1.0)) * fx;
+        let normalized = top + (bottom - top) * fy;
+
+        self.min_height + (self.max_height - self.min_height) * normalized
+    }
+
+    /// Largest absolute elevation the map can return
+    pub fn get_max_abs_height(&self) -> f64 {
+        self.min_height.abs().max(self.max_height.abs())
+    }
+}
+
+#[repr(C)]
+struct SurfaceUBOInterface {
+    // Render origin relative world space to planet local space
+    world_to_planet: cgm::Matrix4<f32>,
+}
+
+/// Equirectangular surface maps of a planet sampled by the terrain pipeline with a latitude/longitude
+/// lookup of every fragment, so chunks need no texture coordinates and the map wraps without a seam.
+pub struct PlanetSurface {
+    albedo: ImageMutRef,
+    // Per frame in flight, written by update()
+    ubo: Vec<UniformBufferObject>,
+}
+
+impl PlanetSurface {
+    pub fn new_mut_ref(resource_manager: &mut ResourceManager, albedo: ImageMutRef) -> PlanetSurfaceMutRef {
+        Rc::new(RefCell::new(PlanetSurface::new(resource_manager, albedo)))
+    }
+
+    pub fn new(resource_manager: &mut ResourceManager, albedo: ImageMutRef) -> Self {
+        let ubo_data = SurfaceUBOInterface {
+            world_to_planet: cgm::Matrix4::identity(),
+        };
+        let ubo_data = StructBufferData::new(&ubo_data);
+        let ubo = (0..MAX_FRAMES_IN_FLIGHT)
+            .map(|i| UniformBufferObject::new_with_data(resource_manager, &ubo_data, format!("PlanetSurface{}", i).as_str()))
+            .collect();
+
+        PlanetSurface {
+            albedo,
+            ubo,
+        }
+    }
+
+    /// Write planet placement relative to the render origin for the current frame
+    pub fn update(&self, device: &Device, planet_transform: &cgm::Matrix4<f64>, origin: &cgm::Vector3<f64>) {
+        let mut relative = *planet_transform;
+        relative.w = (relative.w.truncate() - origin).extend(relative.w.w);
+        let world_to_planet = relative.invert().unwrap_or(cgm::Matrix4::identity());
+        let ubo_data = SurfaceUBOInterface {
+            world_to_planet: world_to_planet.cast().unwrap_or(cgm::Matrix4::identity()),
+        };
+        self.ubo[device.get_image_idx()].buffer.borrow().update_data(device, &StructBufferData::new(&ubo_data), 0);
+    }
+
+    pub fn get_albedo(&self) -> &ImageMutRef {
+        &self.albedo
+    }
+
+    pub fn get_ubo(&self, image_idx: usize) -> &UniformBufferObject {
+        &self.ubo[image_idx]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath as cgm;
+    use cgmath::prelude::*;
+
+    use super::{planet_uv, HeightMap};
+
+    #[test]
+    fn planet_uv_follows_latitude_and_longitude() {
+        let uv = planet_uv(&cgm::Vector3::unit_x());
+        assert!((uv.x - 0.5).abs() < 1e-12 && (uv.y - 0.5).abs() < 1e-12);
+        // 90° east is a quarter of the map to the right
+        assert!((planet_uv(&-cgm::Vector3::unit_z()).x - 0.75).abs() < 1e-12);
+        assert!(planet_uv(&cgm::Vector3::unit_y()).y.abs() < 1e-12);
+        assert!((planet_uv(&-cgm::Vector3::unit_y()).y - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn heightmap_wraps_at_seam_and_clamps_at_poles() {
+        // 4x2 map, western half low, eastern half high in the northern row
+        let samples = vec![
+            0.0, 0.0, 1.0, 1.0,
+            0.5, 0.5, 0.5, 0.5,
+        ];
+        let map = HeightMap::new(4, 2, samples, -100.0, 300.0).unwrap();
+        assert_eq!(map.get_max_abs_height(), 300.0);
+
+        // Both sides of the seam blend the first and the last columns
+        let seam = cgm::Vector3::new(-1.0, 1.0, 1e-9).normalize();
+        let seam_other_side = cgm::Vector3::new(-1.0, 1.0, -1e-9).normalize();
+        assert!((map.sample(&seam) - map.sample(&seam_other_side)).abs() < 1e-6);
+
+        // Pole takes the top row only
+        let pole = map.sample(&cgm::Vector3::unit_y());
+        assert!((-100.0..=300.0).contains(&pole));
+        assert!((map.sample(&-cgm::Vector3::unit_y()) - 100.0).abs() < 1e-9);
+
+        assert!(HeightMap::new(4, 2, vec![0.0; 7], 0.0, 1.0).is_err());
+    }
+}